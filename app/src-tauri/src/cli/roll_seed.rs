@@ -71,9 +71,10 @@ impl Subcommand for ThisCommand {
   }
 }
 
-async fn run(matches: &ArgMatches, _context: &mut Context) -> Result<()> {
+async fn run(matches: &ArgMatches, context: &mut Context) -> Result<()> {
   let app_settings = load_app_settings()?;
   if let Some((seed, score)) = roll_seed(
+    context.servers.clone(),
     app_settings.factorio.clone(),
     matches
       .get_one::<String>("map")
@@ -113,7 +113,7 @@ async fn run(matches: &ArgMatches, context: &mut Context) -> Result<()> {
   if instance_state.world.is_some() {
     info!("started!");
     // Store the instance in context to keep processes alive
-    *context.instance_state.write().await = Some(instance_state);
+    *context.instance_state().write().await = Some(instance_state);
     // start_webserver(rcon, websocket_server, open_browser, world).await;
   }
   Ok(())
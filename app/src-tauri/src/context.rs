@@ -1,7 +1,10 @@
 use crate::paths;
 use crate::settings::{load_app_settings, SharedAppSettings};
 use factorio_bot_core::miette::{IntoDiagnostic, Result};
-use factorio_bot_core::process::process_control::{FactorioInstance, SharedFactorioInstance};
+use factorio_bot_core::process::process_control::SharedFactorioInstance;
+use factorio_bot_core::process::server_registry::{
+  FactorioServerRegistry, SharedFactorioServerRegistry, DEFAULT_SERVER_NAME,
+};
 use std::fs::create_dir_all;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -12,7 +15,9 @@ pub type SharedRestApiHandle = SharedJoinShandle<Result<()>>;
 
 #[derive(Clone)]
 pub struct Context {
-  pub instance_state: SharedFactorioInstance,
+  pub servers: SharedFactorioServerRegistry,
+  /// server targeted by repl commands
+  pub server: String,
   pub app_settings: SharedAppSettings,
   pub restapi_handle: SharedRestApiHandle,
 }
@@ -29,11 +34,17 @@ impl Context {
     create_dir_all(paths::workspace_dir()).into_diagnostic()?;
 
     let context = Context {
-      instance_state: FactorioInstance::new_shared(),
+      servers: FactorioServerRegistry::new_shared(),
+      server: DEFAULT_SERVER_NAME.to_owned(),
       restapi_handle: Arc::new(RwLock::new(None)),
       app_settings: load_app_settings()?.into_shared(),
     };
 
     Ok(context)
   }
+
+  /// instance of the currently targeted server
+  pub fn instance_state(&self) -> SharedFactorioInstance {
+    self.servers.get_or_insert(&self.server)
+  }
 }
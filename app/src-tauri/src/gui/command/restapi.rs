@@ -6,7 +6,7 @@
 use crate::context::SharedJoinShandle;
 use crate::settings::SharedAppSettings;
 use factorio_bot_core::miette::Result;
-use factorio_bot_core::process::process_control::SharedFactorioInstance;
+use factorio_bot_core::process::server_registry::SharedFactorioServerRegistry;
 #[cfg(feature = "restapi")]
 use factorio_bot_restapi::webserver;
 use tauri::State;
@@ -16,7 +16,7 @@ use tauri::State;
 #[tauri::command]
 pub async fn start_restapi(
   app_settings: State<'_, SharedAppSettings>,
-  instance_state: State<'_, SharedFactorioInstance>,
+  servers: State<'_, SharedFactorioServerRegistry>,
   restapi_handle: State<'_, SharedJoinShandle<Result<()>>>,
 ) -> Result<(), String> {
   #[cfg(feature = "restapi")]
@@ -25,9 +25,9 @@ pub async fn start_restapi(
       return Err("already started".into());
     }
     let app_settings = app_settings.inner().clone();
-    let instance_state = instance_state.inner().clone();
+    let servers = servers.inner().clone();
     let app_settings = app_settings.read().await;
-    let webserver = webserver::start(app_settings.restapi.clone(), instance_state);
+    let webserver = webserver::start(app_settings.restapi.clone(), servers);
     let handle = tokio::task::spawn(webserver);
     let mut restapi_handle = restapi_handle.write().await;
     *restapi_handle = Some(handle);
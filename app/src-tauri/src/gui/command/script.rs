@@ -4,6 +4,7 @@ use crate::scripting::{prepare_workspace_scripts, run_script, run_script_file};
 use crate::settings::SharedAppSettings;
 use factorio_bot_core::paris::warn;
 use factorio_bot_core::process::process_control::SharedFactorioInstance;
+use factorio_bot_core::process::server_registry::SharedFactorioServerRegistry;
 use factorio_bot_core::types::PrimeVueTreeNode;
 use tauri::State;
 
@@ -16,6 +17,7 @@ use tauri::State;
 pub async fn execute_script(
   app_settings: State<'_, SharedAppSettings>,
   instance_state: State<'_, SharedFactorioInstance>,
+  servers: State<'_, SharedFactorioServerRegistry>,
   path: String,
 ) -> Result<(String, String), String> {
   if let Some(instance_state) = &*instance_state.read().await {
@@ -26,7 +28,7 @@ pub async fn execute_script(
         world.entity_graph.connect().unwrap();
         let world = world.clone();
         let rcon = instance_state.rcon.clone();
-        let mut planner = Planner::new(world, Some(rcon));
+        let mut planner = Planner::new(world, Some(rcon)).with_servers(servers.inner().clone());
         let app_settings = &app_settings.read().await;
         let bot_count = app_settings.factorio.client_count;
         let (stdout, stderr) = run_script_file(&mut planner, &path[1..], bot_count, true)
@@ -53,6 +55,7 @@ pub async fn execute_script(
 pub async fn execute_code(
   app_settings: State<'_, SharedAppSettings>,
   instance_state: State<'_, SharedFactorioInstance>,
+  servers: State<'_, SharedFactorioServerRegistry>,
   language: String,
   code: String,
 ) -> Result<(String, String), String> {
@@ -64,7 +67,7 @@ pub async fn execute_code(
         world.entity_graph.connect().unwrap();
         let world = world.clone();
         let rcon = instance_state.rcon.clone();
-        let mut planner = Planner::new(world, Some(rcon));
+        let mut planner = Planner::new(world, Some(rcon)).with_servers(servers.inner().clone());
         let bot_count = app_settings.read().await.factorio.client_count;
         let (stdout, stderr) = run_script(&mut planner, &language, &code, None, bot_count, true)
           .await
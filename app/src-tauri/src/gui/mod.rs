@@ -21,7 +21,8 @@ pub fn start(context: Context) -> Result<()> {
     .plugin(tauri_plugin_dialog::init())
     .plugin(tauri_plugin_updater::Builder::new().build())
     .manage(context.app_settings)
-    .manage(context.instance_state)
+    .manage(context.instance_state())
+    .manage(context.servers)
     .manage(context.restapi_handle)
     .invoke_handler(tauri::generate_handler![
       command::is_restapi_started,
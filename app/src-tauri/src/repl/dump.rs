@@ -1,10 +1,12 @@
 use crate::context::Context;
 use crate::repl::{Error, Subcommand};
+use factorio_bot_core::factorio::world_snapshot::save_snapshot;
 use factorio_bot_core::miette::{IntoDiagnostic, Result};
-use factorio_bot_core::paris::error;
+use factorio_bot_core::paris::{error, info};
 use reedline_repl_rs::clap::builder::PossibleValue;
 use reedline_repl_rs::clap::{builder::PossibleValuesParser, Arg, ArgMatches, Command};
 use reedline_repl_rs::Repl;
+use std::path::Path;
 use std::str::FromStr;
 use strum::{EnumIter, EnumMessage, EnumString, IntoEnumIterator, IntoStaticStr};
 
@@ -25,7 +27,8 @@ async fn run(matches: ArgMatches, context: &mut Context) -> Result<Option<String
     save_path => Some(save_path),
   };
 
-  let instance_state = context.instance_state.read().await;
+  let instance_state = context.instance_state();
+  let instance_state = instance_state.read().await;
   if let Some(instance_state) = instance_state.as_ref() {
     match command {
       DumpType::World => {
@@ -35,6 +38,18 @@ async fn run(matches: ArgMatches, context: &mut Context) -> Result<Option<String
           error!("no factorio world found??");
         }
       }
+      DumpType::Snapshot => {
+        if let Some(world) = instance_state.world.as_ref() {
+          if let Some(save_path) = save_path {
+            save_snapshot(world, Path::new(save_path))?;
+            info!("saved snapshot to <bright-blue>{}</>", save_path);
+          } else {
+            error!("failed: missing --save, e.g. next to the save as saves/level.world");
+          }
+        } else {
+          error!("no factorio world found??");
+        }
+      }
       DumpType::EntityPrototypes => {
         if let Some(world) = instance_state.world.as_ref() {
           world.dump_entitiy_prototypes(save_path)?;
@@ -56,6 +71,18 @@ async fn run(matches: ArgMatches, context: &mut Context) -> Result<Option<String
           error!("no factorio world found??");
         }
       }
+      DumpType::ParserMetrics => {
+        if let Some(world) = instance_state.world.as_ref() {
+          world.dump_parser_metrics(save_path)?;
+          info!(
+            "{} failed and {} unknown server messages",
+            world.parser_metrics.total_failed(),
+            world.parser_metrics.total_unknown()
+          );
+        } else {
+          error!("no factorio world found??");
+        }
+      }
     }
   } else {
     error!("no factorio instance running");
@@ -68,12 +95,16 @@ async fn run(matches: ArgMatches, context: &mut Context) -> Result<Option<String
 enum DumpType {
   #[strum(message = "dump complete internal factorio world representation (very big)")]
   World,
+  #[strum(message = "save compact binary snapshot of the world which `load` reads again")]
+  Snapshot,
   #[strum(message = "dump entity prototypes")]
   EntityPrototypes,
   #[strum(message = "dump item prototypes")]
   ItemPrototypes,
   #[strum(message = "dump recipes")]
   Recipes,
+  #[strum(message = "dump counters of parsed, failed and unknown server messages")]
+  ParserMetrics,
 }
 
 impl Subcommand for ThisCommand {
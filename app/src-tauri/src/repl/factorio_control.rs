@@ -10,6 +10,7 @@ use reedline_repl_rs::clap::{builder::PossibleValuesParser, Arg, ArgAction, ArgM
 use reedline_repl_rs::crossterm::event::{KeyCode, KeyModifiers};
 use reedline_repl_rs::reedline::ReedlineEvent;
 use reedline_repl_rs::Repl;
+use std::path::PathBuf;
 use std::str::FromStr;
 use strum::{EnumIter, EnumMessage, EnumString, IntoEnumIterator, IntoStaticStr};
 
@@ -48,6 +49,8 @@ async fn run(matches: ArgMatches, context: &mut Context) -> Result<Option<String
       );
       let wait_until_finished = matches.get_flag("wait_until_finished");
       let recreate = matches.get_flag("new");
+      let trace_path = matches.get_one::<String>("trace").map(PathBuf::from);
+      let snapshot_path = matches.get_one::<String>("snapshot").map(PathBuf::from);
       drop(app_settings);
       subcommand_start(
         context,
@@ -58,9 +61,21 @@ async fn run(matches: ArgMatches, context: &mut Context) -> Result<Option<String
         map_exchange_string,
         wait_until_finished,
         recreate,
+        trace_path,
+        snapshot_path,
       )
       .await?
     }
+    Action::Replay => {
+      let verbose: bool = matches.get_flag("verbose");
+      match matches.get_one::<String>("trace") {
+        Some(trace_path) => subcommand_replay(context, PathBuf::from(trace_path), verbose).await?,
+        None => {
+          error!("failed: missing --trace");
+          None
+        }
+      }
+    }
     Action::Status => subcommand_status(context).await?,
     Action::ToggleVerbose => subcommand_toggle_verbose(context).await?,
     Action::Add => subcommand_add(context).await?,
@@ -79,9 +94,12 @@ async fn subcommand_start(
   map_exchange_string: Option<String>,
   wait_until_finished: bool,
   recreate: bool,
+  trace_path: Option<PathBuf>,
+  snapshot_path: Option<PathBuf>,
 ) -> Result<Option<String>, Error> {
   {
-    let instance_state = context.instance_state.read().await;
+    let instance_state = context.instance_state();
+    let instance_state = instance_state.read().await;
     if instance_state.is_some() {
       error!("failed: already started");
       return Ok(None);
@@ -101,12 +119,18 @@ async fn subcommand_start(
     } else {
       FactorioStartCondition::Initialized
     },
+    trace_path,
+    snapshot_path,
     ..FactorioParams::default()
   };
+  let params = context
+    .servers
+    .server_params(&context.server, &app_settings.factorio, params);
 
   match FactorioInstance::start(&app_settings.factorio, params).await {
     Ok(new_instance_state) => {
-      let mut instance_state = context.instance_state.write().await;
+      let instance_state = context.instance_state();
+      let mut instance_state = instance_state.write().await;
       *instance_state = Some(new_instance_state);
       drop(instance_state);
     }
@@ -122,8 +146,32 @@ async fn subcommand_start(
   Ok(None)
 }
 
+async fn subcommand_replay(
+  context: &mut Context,
+  trace_path: PathBuf,
+  verbose: bool,
+) -> Result<Option<String>, Error> {
+  let instance_state = context.instance_state();
+  let mut instance_state = instance_state.write().await;
+  if instance_state.is_some() {
+    error!("failed: already started");
+    return Ok(None);
+  }
+  match FactorioInstance::replay(&trace_path, !verbose) {
+    Ok(new_instance_state) => {
+      *instance_state = Some(new_instance_state);
+      info!("replaying <bright-blue>{:?}</>", trace_path);
+    }
+    Err(err) => {
+      error!("failed to load trace: {:?}", err);
+    }
+  }
+  Ok(None)
+}
+
 async fn subcommand_stop(context: &mut Context) -> Result<Option<String>, Error> {
-  let mut instance_state = context.instance_state.write().await;
+  let instance_state = context.instance_state();
+  let mut instance_state = instance_state.write().await;
   if instance_state.is_none() {
     error!("failed: not started");
     return Ok(None);
@@ -134,7 +182,8 @@ async fn subcommand_stop(context: &mut Context) -> Result<Option<String>, Error>
 }
 
 async fn subcommand_status(context: &mut Context) -> Result<Option<String>, Error> {
-  let instance_state = context.instance_state.read().await;
+  let instance_state = context.instance_state();
+  let instance_state = instance_state.read().await;
   if let Some(instance_state) = instance_state.as_ref() {
     info!(
       "started {} with {} clients @ Port {} with RCON {}",
@@ -147,6 +196,12 @@ async fn subcommand_status(context: &mut Context) -> Result<Option<String>, Erro
       instance_state.server_port.unwrap_or(0),
       instance_state.rcon_port
     );
+    for lane in instance_state.rcon.lane_metrics() {
+      info!(
+        "  rcon {}: {}/{} in flight, {} queued, {} sent, waited {}ms max",
+        lane.priority, lane.in_flight, lane.limit, lane.queued, lane.sent, lane.max_wait_ms
+      );
+    }
   } else {
     info!("factorio not started");
     return Ok(None);
@@ -155,7 +210,8 @@ async fn subcommand_status(context: &mut Context) -> Result<Option<String>, Erro
 }
 
 async fn subcommand_add(context: &mut Context) -> Result<Option<String>, Error> {
-  let instance_state = context.instance_state.write().await;
+  let instance_state = context.instance_state();
+  let instance_state = instance_state.write().await;
   if instance_state.is_none() {
     error!("failed: not started");
     return Ok(None);
@@ -165,7 +221,8 @@ async fn subcommand_add(context: &mut Context) -> Result<Option<String>, Error>
 }
 
 async fn subcommand_toggle_verbose(context: &mut Context) -> Result<Option<String>, Error> {
-  let instance_state = context.instance_state.read().await;
+  let instance_state = context.instance_state();
+  let instance_state = instance_state.read().await;
   if let Some(instance_state) = instance_state.as_ref() {
     let mut silent = instance_state.silent.write();
     *silent = !*silent;
@@ -212,6 +269,8 @@ enum Action {
   ToggleVerbose,
   #[strum(message = "start additional clients")]
   Add,
+  #[strum(message = "replay recorded trace instead of starting factorio")]
+  Replay,
 }
 
 impl Subcommand for ThisCommand {
@@ -284,6 +343,20 @@ impl Subcommand for ThisCommand {
               .action(ArgAction::SetTrue)
               .help("log server output to console"),
           )
+          .arg(
+            Arg::new("trace")
+              .long("trace")
+              .value_name("file")
+              .required(false)
+              .help("record rcon traffic to file on start, file to read on replay"),
+          )
+          .arg(
+            Arg::new("snapshot")
+              .long("snapshot")
+              .value_name("file")
+              .required(false)
+              .help("load world from snapshot instead of discovering it on start"),
+          )
           .arg(
             Arg::new("wait_until_finished")
               .short('w')
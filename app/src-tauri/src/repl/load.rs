@@ -0,0 +1,51 @@
+use crate::context::Context;
+use crate::repl::{Error, Subcommand};
+use factorio_bot_core::factorio::world_snapshot::load_snapshot;
+use factorio_bot_core::paris::{error, info};
+use reedline_repl_rs::clap::{Arg, ArgMatches, Command};
+use reedline_repl_rs::Repl;
+use std::path::Path;
+
+async fn run(matches: ArgMatches, context: &mut Context) -> Result<Option<String>, Error> {
+  let path = matches
+    .get_one::<String>("path")
+    .map(std::string::String::as_str)
+    .expect("required");
+
+  let instance_state = context.instance_state();
+  let instance_state = instance_state.read().await;
+  if let Some(instance_state) = instance_state.as_ref() {
+    if let Some(world) = instance_state.world.as_ref() {
+      world.restore(load_snapshot(Path::new(path))?)?;
+      info!("loaded snapshot from <bright-blue>{}</>", path);
+    } else {
+      error!("no factorio world found??");
+    }
+  } else {
+    error!("no factorio instance running, use `factorio start --snapshot` instead");
+  }
+  Ok(None)
+}
+
+impl Subcommand for ThisCommand {
+  fn name(&self) -> &'static str {
+    "load"
+  }
+  fn build_command(&self, repl: Repl<Context, Error>) -> Repl<Context, Error> {
+    repl.with_command_async(
+      Command::new(self.name())
+        .about("replace world with snapshot written by `dump snapshot`")
+        .arg(
+          Arg::new("path")
+            .required(true)
+            .help("path of snapshot file"),
+        ),
+      |args, context| Box::pin(run(args, context)),
+    )
+  }
+}
+
+struct ThisCommand {}
+pub fn build() -> Box<dyn Subcommand> {
+  Box::new(ThisCommand {})
+}
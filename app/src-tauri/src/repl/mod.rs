@@ -3,18 +3,21 @@ mod factorio_control;
 mod get_setting;
 #[cfg(all(debug_assertions, feature = "gui"))]
 mod gui;
+mod load;
 mod quit;
 mod rcon_send;
 #[cfg(feature = "restapi")]
 mod restapi_control;
 #[cfg(feature = "lua")]
 mod run_script;
+mod server;
 mod set_setting;
 
 use crate::context::Context;
 use crate::{paths, APP_ABOUT, APP_NAME};
 use factorio_bot_core::miette;
 use factorio_bot_core::miette::{miette, IntoDiagnostic};
+use factorio_bot_core::process::server_registry::DEFAULT_SERVER_NAME;
 use reedline_repl_rs::{yansi::Paint, Repl};
 use std::fmt;
 
@@ -26,17 +29,19 @@ fn subcommands() -> Vec<Box<dyn Subcommand>> {
     #[cfg(feature = "lua")]
     run_script::build(),
     rcon_send::build(),
+    server::build(),
     #[cfg(feature = "restapi")]
     restapi_control::build(),
     set_setting::build(),
     get_setting::build(),
     quit::build(),
     dump::build(),
+    load::build(),
   ]
 }
 
 pub async fn start(context: Context) -> miette::Result<()> {
-  let instance_state = context.instance_state.clone();
+  let servers = context.servers.clone();
   let mut repl: Repl<Context, Error> = Repl::new(context)
     .with_name(APP_NAME)
     .with_description(APP_ABOUT)
@@ -48,16 +53,17 @@ pub async fn start(context: Context) -> miette::Result<()> {
     repl = subcommand.build_command(repl);
   }
   repl.run_async().await.into_diagnostic()?;
-  let mut instance_state = instance_state.write().await;
-  if let Some(instance_state) = instance_state.take() {
-    instance_state.stop()?;
-  }
+  servers.stop_all().await?;
   Ok(())
 }
 
 async fn update_prompt(context: &mut Context) -> Result<Option<String>> {
-  let instance_state = context.instance_state.read().await;
+  let instance_state = context.instance_state();
+  let instance_state = instance_state.read().await;
   let mut prompt = "repl".to_owned();
+  if context.server != DEFAULT_SERVER_NAME {
+    prompt += &Paint::yellow(format!(" {}", context.server)).to_string();
+  }
   if instance_state.is_some() {
     prompt += &Paint::blue(" [running]").bold().to_string();
   }
@@ -3,12 +3,8 @@ use crate::repl::{Error, Subcommand};
 use reedline_repl_rs::clap::{ArgMatches, Command};
 use reedline_repl_rs::Repl;
 
-#[allow(clippy::unused_async)]
 async fn run(_matches: ArgMatches, context: &mut Context) -> Result<Option<String>, Error> {
-  let mut instance_state = context.instance_state.write().await;
-  if let Some(instance_state) = instance_state.take() {
-    instance_state.stop().expect("failed to stop");
-  }
+  context.servers.stop_all().await.expect("failed to stop");
   std::process::exit(0);
 }
 
@@ -9,9 +9,10 @@ async fn run(matches: ArgMatches, context: &mut Context) -> Result<Option<String
     .get_one::<String>("rcon-command")
     .expect("Required arg validated by clap")
     .to_owned();
-  let instance_state = context.instance_state.read().await;
+  let instance_state = context.instance_state();
+  let instance_state = instance_state.read().await;
   if instance_state.is_some() {
-    let instance_state = context.instance_state.clone();
+    let instance_state = context.instance_state();
     let instance_state = instance_state.read().await;
     if let Some(instance_state) = instance_state.as_ref() {
       let rcon = instance_state.rcon.clone();
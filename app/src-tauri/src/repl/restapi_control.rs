@@ -20,8 +20,8 @@ async fn run(matches: ArgMatches, context: &mut Context) -> Result<Option<String
   match action {
     Action::Start => {
       let app_settings = context.app_settings.read().await;
-      let instance_state = context.instance_state.clone();
-      let webserver = webserver::start(app_settings.restapi.clone(), instance_state);
+      let servers = context.servers.clone();
+      let webserver = webserver::start(app_settings.restapi.clone(), servers);
       let handle = tokio::task::spawn(webserver);
       let mut restapi_handle = context.restapi_handle.write().await;
       *restapi_handle = Some(handle);
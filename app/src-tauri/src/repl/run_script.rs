@@ -17,15 +17,17 @@ async fn run(matches: ArgMatches, context: &mut Context) -> Result<Option<String
     .expect("Has default value")
     .parse()
     .into_diagnostic()?;
-  let instance_state = context.instance_state.read().await;
+  let instance_state = context.instance_state();
+  let instance_state = instance_state.read().await;
   if instance_state.is_some() {
-    let instance_state = context.instance_state.clone();
+    let instance_state = context.instance_state();
     let instance_state = instance_state.read().await;
     if let Some(instance_state) = instance_state.as_ref() {
       let mut planner = Planner::new(
         instance_state.world.clone().unwrap(),
         Some(instance_state.rcon.clone()),
-      );
+      )
+      .with_servers(context.servers.clone());
       if let Err(err) = run_script_file(&mut planner, &filename, bot_count, false).await {
         error!("failed to execute: {:?}", err);
       }
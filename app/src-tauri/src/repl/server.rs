@@ -0,0 +1,103 @@
+use crate::context::Context;
+use crate::repl::{Error, Subcommand};
+use factorio_bot_core::miette::{IntoDiagnostic, Result};
+use factorio_bot_core::paris::{error, info};
+use factorio_bot_core::process::server_registry::DEFAULT_SERVER_NAME;
+use reedline_repl_rs::clap::builder::PossibleValue;
+use reedline_repl_rs::clap::{builder::PossibleValuesParser, Arg, ArgMatches, Command};
+use reedline_repl_rs::Repl;
+use std::str::FromStr;
+use strum::{EnumIter, EnumMessage, EnumString, IntoEnumIterator, IntoStaticStr};
+
+async fn run(matches: ArgMatches, context: &mut Context) -> Result<Option<String>, Error> {
+  let action = Action::from_str(
+    matches
+      .get_one::<String>("action")
+      .map(std::string::String::as_str)
+      .expect("Has default value"),
+  )
+  .into_diagnostic()?;
+  let name = matches
+    .get_one::<String>("name")
+    .map(std::string::String::as_str)
+    .unwrap_or(DEFAULT_SERVER_NAME)
+    .to_owned();
+  match action {
+    Action::List => {
+      for server in context.servers.names() {
+        let instance_state = context.servers.get_or_insert(&server);
+        let running = instance_state.read().await.is_some();
+        info!(
+          "{}{} {}",
+          if server == context.server { "* " } else { "  " },
+          server,
+          if running { "[running]" } else { "[stopped]" }
+        );
+      }
+    }
+    Action::Use => {
+      context.servers.get_or_insert(&name);
+      info!("now targeting server <bright-blue>{}</>", name);
+      context.server = name;
+    }
+    Action::Remove => {
+      if name == context.server {
+        error!("failed: can't remove targeted server");
+        return Ok(None);
+      }
+      match context.servers.remove(&name) {
+        Some(instance_state) => {
+          if let Some(instance_state) = instance_state.write().await.take() {
+            instance_state.stop()?;
+          }
+          info!("removed server <bright-blue>{}</>", name);
+        }
+        None => error!("failed: unknown server {}", name),
+      }
+    }
+  }
+  Ok(None)
+}
+
+#[derive(EnumString, EnumMessage, EnumIter, IntoStaticStr)]
+#[strum(serialize_all = "kebab-case")]
+enum Action {
+  #[strum(message = "list all servers")]
+  List,
+  #[strum(message = "target given server with all following commands")]
+  Use,
+  #[strum(message = "stop and forget given server")]
+  Remove,
+}
+
+impl Subcommand for ThisCommand {
+  fn name(&self) -> &'static str {
+    "server"
+  }
+  fn build_command(&self, repl: Repl<Context, Error>) -> Repl<Context, Error> {
+    repl.with_command_async(
+      Command::new(self.name())
+        .about("manage multiple factorio servers")
+        .arg(
+          Arg::new("action")
+            .default_value(Into::<&str>::into(Action::List))
+            .value_parser(PossibleValuesParser::new(Action::iter().map(|action| {
+              let message = action.get_message().unwrap();
+              PossibleValue::new(Into::<&str>::into(action)).help(message)
+            })))
+            .help("what action to take"),
+        )
+        .arg(
+          Arg::new("name")
+            .required(false)
+            .help("name of the server, defaults to the default server"),
+        ),
+      |args, context| Box::pin(run(args, context)),
+    )
+  }
+}
+
+struct ThisCommand {}
+pub fn build() -> Box<dyn Subcommand> {
+  Box::new(ThisCommand {})
+}
@@ -120,11 +120,11 @@ export type FactorioFluidBoxPrototype = { pipe_connections: FactorioFluidBoxConn
 export type FactorioFluidBoxConnection = { max_underground_distance: number | null; connection_type: string | null; positions: Position [] };
 export type FactorioBlueprintInfo = { label: string; blueprint: string; width: number; height: number; rect: Rect; data: object };
 export type PlayerChangedDistanceEvent = { player_id: PlayerId; build_distance: number; reach_distance: number; drop_item_distance: number; item_pickup_distance: number; loot_pickup_distance: number; resource_reach_distance: number };
-export type PlayerChangedPositionEvent = { player_id: PlayerId; position: Position };
+export type PlayerChangedPositionEvent = { player_id: PlayerId; position: Position; surface: string | null };
 export type PlayerChangedMainInventoryEvent = { player_id: PlayerId; main_inventory: InventoryItemWithQuality [] };
 export type PlayerLeftEvent = { player_id: PlayerId };
 export type RequestEntity = { name: string; position: Position };
-export type FactorioTile = { name: string; player_collidable: boolean; position: Position; color: number [] | null };
+export type FactorioTile = { name: string; player_collidable: boolean; position: Position; color: number [] | null; surface: string | null };
 export type FactorioTechnology = { name: string; enabled: boolean; upgrade: boolean; researched: boolean; prerequisites: string [] | null; research_unit_ingredients: FactorioIngredient []; research_unit_count: number; research_unit_energy: number; order: string; level: number; valid: boolean };
 export type FactorioForce = { name: string; force_id: number; current_research: string | null; research_progress: number | null; technologies: { [key: string]: FactorioTechnology } };
 export type InventoryResponse = { name: string; position: Position; output_inventory: InventoryItemWithQuality [] | null; fuel_inventory: InventoryItemWithQuality [] | null };
@@ -134,7 +134,7 @@ export type PlaceEntitiesResult = { player: FactorioPlayer; entities: FactorioEn
 export type FactorioIngredient = { name: string; ingredient_type: string; amount: number };
 export type FactorioProduct = { name: string; product_type: string; amount: number; probability: number };
 export type FactorioPlayer = { player_id: PlayerId; position: Position; main_inventory: { [key: string]: number };
- build_distance: number; reach_distance: number; drop_item_distance: number; item_pickup_distance: number; loot_pickup_distance: number; resource_reach_distance: number };
+ build_distance: number; reach_distance: number; drop_item_distance: number; item_pickup_distance: number; loot_pickup_distance: number; resource_reach_distance: number; surface: string | null };
 export type ChunkPosition = { x: number; y: number };
 export type Position = { x: number; y: number };
 export type Rect = { left_top: Position; right_bottom: Position };
@@ -142,8 +142,8 @@ export type FactorioChunk = { entities: FactorioEntity [] };
 export type ChunkObject = { name: string; position: Position; direction: string; bounding_box: Rect; output_inventory: InventoryItemWithQuality [] | null; fuel_inventory: InventoryItemWithQuality [] | null };
 export type ChunkResource = { name: string; position: Position };
 export type FactorioGraphic = { entity_name: string; image_path: string; width: number; height: number };
-export type FactorioEntity = { name: string; entity_type: string; position: Position; bounding_box: Rect; direction: number; drop_position: Position | null; pickup_position: Position | null; output_inventory: InventoryItemWithQuality [] | null; fuel_inventory: InventoryItemWithQuality [] | null; amount: number | null; recipe: string | null; ghost_name: string | null; ghost_type: string | null };
-export type FactorioEntityPrototype = { name: string; entity_type: string; collision_mask: string [] | null; collision_box: Rect; mine_result: { [key: string]: number } | null; mining_time: number | null; mining_speed: number | null; crafting_speed: number | null; max_underground_distance: number | null; fluidbox_prototypes: FactorioFluidBoxPrototype [] | null };
+export type FactorioEntity = { name: string; entity_type: string; position: Position; bounding_box: Rect; direction: number; drop_position: Position | null; pickup_position: Position | null; output_inventory: InventoryItemWithQuality [] | null; fuel_inventory: InventoryItemWithQuality [] | null; amount: number | null; recipe: string | null; ghost_name: string | null; ghost_type: string | null; surface: string | null; quality: string | null; force: string | null };
+export type FactorioEntityPrototype = { name: string; entity_type: string; collision_mask: string [] | null; collision_box: Rect; mine_result: { [key: string]: number } | null; mining_time: number | null; mining_speed: number | null; crafting_speed: number | null; max_underground_distance: number | null; fluidbox_prototypes: FactorioFluidBoxPrototype [] | null; supply_area_distance: number | null; max_wire_distance: number | null; energy_source: string | null; energy_usage: number | null; max_energy_production: number | null; electric_buffer_size: number | null; pumping_speed: number | null; fluid_usage: number | null };
 export type FactorioItemPrototype = { name: string; item_type: string; stack_size: number; fuel_value: number; place_result: string; group: string; subgroup: string };
 export type FactorioResult = { success: boolean; output: string [] };
 export type PrimeVueTreeNode = { key: string; label: string; leaf: boolean; children: PrimeVueTreeNode [] };
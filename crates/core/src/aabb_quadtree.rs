@@ -8,7 +8,8 @@
 use euclid::{Point2D, Rect as EuclidRect, Size2D};
 use fnv::FnvHashMap;
 use smallvec::{Array, SmallVec};
-use std::cmp::Ord;
+use std::cmp::{Ord, Ordering};
+use std::collections::{BinaryHeap, HashSet};
 
 type Rect<S> = EuclidRect<f32, S>;
 type Point<S> = Point2D<f32, S>;
@@ -206,6 +207,12 @@ impl<T, S, A: Array<Item = (ItemId, Rect<S>)>> QuadTree<T, S, A> {
         self.elements.get(&id).map(|(a, _)| a)
     }
 
+    /// Retrieves an element together with its bounding box
+    /// by looking it up from the ItemId.
+    pub fn get_with_box(&self, id: ItemId) -> Option<&(T, Rect<S>)> {
+        self.elements.get(&id)
+    }
+
     /// Returns an iterator of (element, bounding-box, id)
     /// for each element whose bounding box intersects
     /// with `bounding_box`.
@@ -279,6 +286,100 @@ impl<T, S, A: Array<Item = (ItemId, Rect<S>)>> QuadTree<T, S, A> {
     pub fn bounding_box(&self) -> Rect<S> {
         self.root.bounding_box()
     }
+
+    /// Returns up to `k` (id, distance) pairs of the elements
+    /// accepted by `filter`, nearest first.
+    ///
+    /// The distance is measured from `point` to the closest
+    /// point of the bounding box, nodes are visited best first.
+    pub fn nearest<F>(&self, point: Point<S>, k: usize, mut filter: F) -> Vec<(ItemId, f32)>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let mut found = vec![];
+        let mut seen: HashSet<ItemId> = HashSet::new();
+        let mut heap = BinaryHeap::new();
+        heap.push(Candidate {
+            distance: distance_to_rect(point, self.root.bounding_box()),
+            kind: CandidateKind::Node(&self.root),
+        });
+        while let Some(Candidate { distance, kind }) = heap.pop() {
+            if found.len() >= k {
+                break;
+            }
+            let elements = match kind {
+                CandidateKind::Item(item_id) => {
+                    if seen.insert(item_id) && filter(&self.elements[&item_id].0) {
+                        found.push((item_id, distance));
+                    }
+                    continue;
+                }
+                CandidateKind::Node(QuadNode::Branch {
+                    in_all, children, ..
+                }) => {
+                    for (child_aabb, child) in children {
+                        heap.push(Candidate {
+                            distance: distance_to_rect(point, *child_aabb),
+                            kind: CandidateKind::Node(child),
+                        });
+                    }
+                    in_all
+                }
+                CandidateKind::Node(QuadNode::Leaf { elements, .. }) => elements,
+            };
+            for &(item_id, item_aabb) in elements.iter() {
+                heap.push(Candidate {
+                    distance: distance_to_rect(point, item_aabb),
+                    kind: CandidateKind::Item(item_id),
+                });
+            }
+        }
+        found
+    }
+
+    /// Returns (id, distance) pairs of the elements whose
+    /// bounding box the segment from `from` to `to` crosses,
+    /// ordered by the distance from `from` at which the
+    /// segment enters them.
+    pub fn segment_query(&self, from: Point<S>, to: Point<S>) -> Vec<(ItemId, f32)> {
+        let mut found: Vec<(ItemId, f32)> = vec![];
+        self.root.segment_query(from, to, &mut found);
+        found.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        found.dedup_by_key(|(item_id, _)| *item_id);
+        found
+    }
+}
+
+/// node or element waiting to be visited by `nearest`, closest first
+struct Candidate<'a, S, A: Array<Item = (ItemId, Rect<S>)>> {
+    distance: f32,
+    kind: CandidateKind<'a, S, A>,
+}
+
+enum CandidateKind<'a, S, A: Array<Item = (ItemId, Rect<S>)>> {
+    Node(&'a QuadNode<S, A>),
+    Item(ItemId),
+}
+
+impl<S, A: Array<Item = (ItemId, Rect<S>)>> PartialEq for Candidate<'_, S, A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<S, A: Array<Item = (ItemId, Rect<S>)>> Eq for Candidate<'_, S, A> {}
+
+impl<S, A: Array<Item = (ItemId, Rect<S>)>> PartialOrd for Candidate<'_, S, A> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<S, A: Array<Item = (ItemId, Rect<S>)>> Ord for Candidate<'_, S, A> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed to pop the smallest distance from the max-heap
+        other.distance.total_cmp(&self.distance)
+    }
 }
 
 impl<S, A: Array<Item = (ItemId, Rect<S>)>> QuadNode<S, A> {
@@ -289,6 +390,27 @@ impl<S, A: Array<Item = (ItemId, Rect<S>)>> QuadNode<S, A> {
         }
     }
 
+    fn segment_query(&self, from: Point<S>, to: Point<S>, found: &mut Vec<(ItemId, f32)>) {
+        let elements = match self {
+            QuadNode::Branch {
+                in_all, children, ..
+            } => {
+                for (child_aabb, child) in children {
+                    if segment_entry(from, to, *child_aabb).is_some() {
+                        child.segment_query(from, to, found);
+                    }
+                }
+                in_all
+            }
+            QuadNode::Leaf { elements, .. } => elements,
+        };
+        for &(item_id, item_aabb) in elements.iter() {
+            if let Some(distance) = segment_entry(from, to, item_aabb) {
+                found.push((item_id, distance));
+            }
+        }
+    }
+
     fn new_leaf(aabb: Rect<S>, depth: usize) -> QuadNode<S, A> {
         QuadNode::Leaf {
             aabb,
@@ -471,7 +593,7 @@ impl<S, A: Array<Item = (ItemId, Rect<S>)>> QuadNode<S, A> {
                 Ok(())
             })
             .ok();
-            elements.sort_by(|(id1, _), (id2, _)| id1.cmp(id2));
+            elements.sort_by_key(|(id, _)| *id);
             elements.dedup();
             *self = QuadNode::Leaf {
                 aabb,
@@ -550,6 +672,38 @@ fn midpoint<S>(rect: Rect<S>) -> Point<S> {
     origin + half
 }
 
+fn distance_to_rect<S>(point: Point<S>, rect: Rect<S>) -> f32 {
+    let dx = (rect.min_x() - point.x).max(point.x - rect.max_x()).max(0.);
+    let dy = (rect.min_y() - point.y).max(point.y - rect.max_y()).max(0.);
+    (dx * dx + dy * dy).sqrt()
+}
+
+/// distance along the segment at which it enters `rect`, slab method
+fn segment_entry<S>(from: Point<S>, to: Point<S>, rect: Rect<S>) -> Option<f32> {
+    let delta = to - from;
+    let mut enter: f32 = 0.;
+    let mut leave: f32 = 1.;
+    for (origin, delta, min, max) in [
+        (from.x, delta.x, rect.min_x(), rect.max_x()),
+        (from.y, delta.y, rect.min_y(), rect.max_y()),
+    ] {
+        if delta.abs() < f32::EPSILON {
+            if origin < min || origin > max {
+                return None;
+            }
+        } else {
+            let a = (min - origin) / delta;
+            let b = (max - origin) / delta;
+            enter = enter.max(a.min(b));
+            leave = leave.min(a.max(b));
+            if enter > leave {
+                return None;
+            }
+        }
+    }
+    Some(enter * delta.length())
+}
+
 fn my_intersects<S>(a: Rect<S>, b: Rect<S>) -> bool {
     a.intersects(&b) || a.contains(b.origin) || a.contains(b.max())
 }
@@ -651,6 +805,48 @@ fn test_boundary_conditions() {
     ));
 }
 
+#[test]
+fn test_nearest_and_segment_query() {
+    use euclid::*;
+
+    type Tree = QuadTree<u32, f32, [(ItemId, Rect<f32, f32>); 4]>;
+    let mut tree: Tree = QuadTree::new(
+        Rect::new(point2(-100.0, -100.0), vec2(200.0, 200.0).to_size()),
+        true,
+        2,
+        4,
+        8,
+        16,
+    );
+    for i in 0..20 {
+        tree.insert_with_box(
+            i,
+            Rect::new(point2(i as f32 * 5.0, 0.0), vec2(1.0, 1.0).to_size()),
+        );
+    }
+    let nearest = tree.nearest(point2(22.0, 0.5), 3, |_| true);
+    let names: Vec<u32> = nearest
+        .iter()
+        .map(|(id, _)| *tree.get(*id).unwrap())
+        .collect();
+    assert_eq!(names, vec![4, 5, 3]);
+    assert_eq!(nearest[0].1, 1.0);
+    let odd = tree.nearest(point2(22.0, 0.5), 2, |i| i % 2 == 1);
+    assert_eq!(*tree.get(odd[0].0).unwrap(), 5);
+    assert_eq!(*tree.get(odd[1].0).unwrap(), 3);
+
+    let crossed = tree.segment_query(point2(12.0, 0.5), point2(-3.0, 0.5));
+    let names: Vec<u32> = crossed
+        .iter()
+        .map(|(id, _)| *tree.get(*id).unwrap())
+        .collect();
+    assert_eq!(names, vec![2, 1, 0]);
+    assert_eq!(crossed[0].1, 1.0);
+    assert!(tree
+        .segment_query(point2(0.0, 2.0), point2(50.0, 3.0))
+        .is_empty());
+}
+
 impl<T: ::std::fmt::Debug, S, A: Array<Item = (ItemId, Rect<S>)>> ::std::fmt::Debug
     for QuadTree<T, S, A>
 {
@@ -3,3 +3,15 @@ pub const MODS_FOLDERNAME: &str = "mods";
 pub const SERVER_SETTINGS_FILENAME: &str = "server-settings.json";
 pub const MAP_GEN_SETTINGS_FILENAME: &str = "map-gen-settings.json";
 pub const MAP_SETTINGS_FILENAME: &str = "map-settings.json";
+
+/// surface every world api falls back to if no surface is given
+pub const DEFAULT_SURFACE: &str = "nauvis";
+
+/// quality of items and entities if none is given
+pub const DEFAULT_QUALITY: &str = "normal";
+
+/// force of the bots, entities of other forces except neutral ones are hostile
+pub const PLAYER_FORCE: &str = "player";
+
+/// force of trees, rocks, resources and fish
+pub const NEUTRAL_FORCE: &str = "neutral";
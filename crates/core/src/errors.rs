@@ -1,7 +1,7 @@
 // False positive warnings from thiserror/miette derive macros using struct fields in format strings
 #![allow(unused_assignments)]
 
-use crate::types::PlayerId;
+use crate::types::{PlayerId, Position};
 use miette::Diagnostic;
 use thiserror::Error;
 
@@ -120,6 +120,34 @@ pub struct RconError {
     pub message: String,
 }
 
+#[derive(Error, Debug, Diagnostic)]
+#[error("lua evaluation failed: {message}")]
+#[diagnostic(code(factorio::rcon::eval_failed), help("fix lua snippet"))]
+pub struct RconEvalFailed {
+    pub message: String,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("replayed command differs from trace, expected '{expected}' but got '{actual}'")]
+#[diagnostic(
+    code(factorio::rcon::replay_mismatch),
+    help("replay with the same script and inputs")
+)]
+pub struct RconReplayMismatch {
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("no recorded commands left to replay '{command}'")]
+#[diagnostic(
+    code(factorio::rcon::replay_exhausted),
+    help("replay with the same script and inputs")
+)]
+pub struct RconReplayExhausted {
+    pub command: String,
+}
+
 #[derive(Error, Debug, Diagnostic)]
 #[error("no action result received in time")]
 #[diagnostic(code(factorio::workspace::not_found), help("read logs"))]
@@ -180,3 +208,259 @@ pub struct InsufficientResources {
     pub required: u32,
     pub available: u32,
 }
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("failed to parse server output '{action}': {reason}")]
+#[diagnostic(code(factorio::output::parse_failed), help("line excerpt: {excerpt}"))]
+pub struct OutputParseFailed {
+    pub action: String,
+    pub excerpt: String,
+    pub reason: String,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("unknown server output '{action}'")]
+#[diagnostic(
+    code(factorio::output::unknown_action),
+    help("line excerpt: {excerpt}")
+)]
+pub struct OutputUnknownAction {
+    pub action: String,
+    pub excerpt: String,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("unknown factorio server '{name}'")]
+#[diagnostic(
+    code(factorio::server::not_found),
+    help("list registered servers or start one with that name")
+)]
+pub struct ServerNotFound {
+    pub name: String,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("factorio server '{name}' is not started")]
+#[diagnostic(code(factorio::server::not_started), help("start the server first"))]
+pub struct ServerNotStarted {
+    pub name: String,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("path leads through danger zone of {name} at {position}")]
+#[diagnostic(
+    code(factorio::path::dangerous),
+    help("clear the enemies first or pick a goal further away from them")
+)]
+pub struct PathThroughDangerZone {
+    pub name: String,
+    pub position: Position,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("no walking path from {start} to {goal}")]
+#[diagnostic(
+    code(factorio::path::not_found),
+    help("the goal may be enclosed or not scanned yet, factorio's pathfinder is used instead")
+)]
+pub struct PathNotFound {
+    pub start: Position,
+    pub goal: Position,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("no {entity_name} route from {start} to {goal}")]
+#[diagnostic(
+    code(factorio::route::not_found),
+    help("free some space, allow longer undergrounds or shrink the reserved zones")
+)]
+pub struct RouteNotFound {
+    pub entity_name: String,
+    pub start: Position,
+    pub goal: Position,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("blueprints need at least one entity")]
+#[diagnostic(
+    code(factorio::blueprint::empty),
+    help("check the layout generated any entities")
+)]
+pub struct BlueprintEmpty {}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("blueprint books are not supported")]
+#[diagnostic(
+    code(factorio::blueprint::book_unsupported),
+    help("pass a single blueprint out of the book")
+)]
+pub struct BlueprintBookUnsupported {}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("no players given to build the blueprint")]
+#[diagnostic(
+    code(factorio::blueprint::no_players),
+    help("pass at least one player id")
+)]
+pub struct BlueprintNoPlayers {}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("wire from entity {from} to {to} but the blueprint only has {count} entities")]
+#[diagnostic(
+    code(factorio::blueprint::invalid_wire),
+    help("wires reference entities by their index in the entity list")
+)]
+pub struct BlueprintWireInvalid {
+    pub from: usize,
+    pub to: usize,
+    pub count: usize,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("no {drill} fits on the {resource} patch")]
+#[diagnostic(
+    code(factorio::outpost::empty),
+    help("try a smaller drill or clear the buildings on the patch")
+)]
+pub struct OutpostEmpty {
+    pub drill: String,
+    pub resource: String,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("no {resource} patch found")]
+#[diagnostic(
+    code(factorio::outpost::no_patch),
+    help("explore the map further or check the resource name")
+)]
+pub struct OutpostNoPatch {
+    pub resource: String,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("no prototype found for {name}")]
+#[diagnostic(
+    code(factorio::layout::unknown_entity),
+    help("check the entity names in the layout options")
+)]
+pub struct LayoutUnknownEntity {
+    pub name: String,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("no recipe found for {name}")]
+#[diagnostic(
+    code(factorio::production::recipe_not_found),
+    help("smelting columns need an input with a smelting recipe")
+)]
+pub struct ProductionRecipeNotFound {
+    pub name: String,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("{recipe} can't be made on an assembler line: {reason}")]
+#[diagnostic(
+    code(factorio::production::recipe_unsupported),
+    help("lines feed up to two item ingredients from one belt")
+)]
+pub struct ProductionRecipeUnsupported {
+    pub recipe: String,
+    pub reason: String,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("{items_per_minute} items per minute exceed the {capacity} a {belt} carries")]
+#[diagnostic(
+    code(factorio::production::belt_overloaded),
+    help("split the block into several lines or use a faster belt")
+)]
+pub struct ProductionBeltOverloaded {
+    pub belt: String,
+    pub items_per_minute: f64,
+    pub capacity: f64,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("{items_per_minute} items per minute per machine exceed the {capacity} its {inserter}s move")]
+#[diagnostic(
+    code(factorio::production::inserter_overloaded),
+    help("use a faster inserter or a slower machine")
+)]
+pub struct ProductionInserterOverloaded {
+    pub inserter: String,
+    pub items_per_minute: f64,
+    pub capacity: f64,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("flow graph predicts {predicted} {item} per minute but {expected} are expected")]
+#[diagnostic(
+    code(factorio::production::validation_failed),
+    help("some machines of the generated layout are not connected or their inserters are too slow")
+)]
+pub struct ProductionValidationFailed {
+    pub item: String,
+    pub predicted: f64,
+    pub expected: f64,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("{boilers} boilers need more water than one {offshore_pump} pumps, at most {max} fit")]
+#[diagnostic(
+    code(factorio::power_plant::too_large),
+    help("build several power plants or fewer boilers")
+)]
+pub struct PowerPlantTooLarge {
+    pub boilers: u32,
+    pub offshore_pump: String,
+    pub max: u32,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("no room for the power plant at any of {options} offshore pump positions")]
+#[diagnostic(
+    code(factorio::power_plant::no_site),
+    help("search for offshore pump positions at another shore or raise max_distance")
+)]
+pub struct PowerPlantNoSite {
+    pub options: usize,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("no pole line from the power plant to {base}")]
+#[diagnostic(
+    code(factorio::power_plant::pole_unreachable),
+    help("buildings or water block every pole position towards the base")
+)]
+pub struct PowerPlantPoleUnreachable {
+    pub base: Position,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("{path} is not a world snapshot")]
+#[diagnostic(
+    code(factorio::snapshot::invalid),
+    help("snapshots are written with `dump snapshot`")
+)]
+pub struct SnapshotInvalid {
+    pub path: String,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("world snapshot has version {version} but version {expected} is required")]
+#[diagnostic(
+    code(factorio::snapshot::version_mismatch),
+    help("snapshots can't be converted, discover the world again and write a new one")
+)]
+pub struct SnapshotVersionMismatch {
+    pub version: u32,
+    pub expected: u32,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("bot {player_id} was killed by {cause}")]
+#[diagnostic(code(factorio::executor::bot_killed), help("avoid danger zones"))]
+pub struct BotKilled {
+    pub player_id: PlayerId,
+    pub cause: String,
+}
@@ -0,0 +1,296 @@
+use crate::errors::{BlueprintEmpty, BlueprintWireInvalid};
+use crate::factorio::util::calculate_distance;
+use crate::types::{BlueprintWire, EntityType, FactorioEntity, FactorioEntityPrototype, WireColor};
+use dashmap::DashMap;
+use factorio_blueprint::objects::{
+    Blueprint, ConnectionData, ConnectionPoint, Entity, EntityConnections,
+    EntityType as BlueprintEntityType, ItemRequest, Position as BlueprintPosition,
+};
+use factorio_blueprint::{BlueprintCodec, Container};
+use miette::{IntoDiagnostic, Result};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+
+/// poles placed by hand connect to at most this many other poles
+const MAX_POLE_NEIGHBOURS: usize = 5;
+
+/// version 1.1.0.0, the last version of the format written here
+const BLUEPRINT_VERSION: u64 = (1 << 48) | (1 << 32);
+
+/// Encodes entities as blueprint string.
+///
+/// Positions are moved around the origin by whole tiles, ghosts are encoded as the
+/// entity they stand for. Electric poles get copper wires to the nearest poles in reach
+/// like when placed by hand, circuit wires reference entities by index.
+///
+/// The string uses the Factorio 1.1 format that `factorio_blueprint` decodes, with
+/// `neighbours` and `connections` instead of the 2.0 `wires` array and an item map for
+/// module requests. Factorio 2.0 migrates it on import, but the format has no quality,
+/// so every entity and module is requested at normal quality.
+pub fn encode_blueprint(
+    prototypes: &DashMap<String, FactorioEntityPrototype>,
+    label: &str,
+    entities: &[FactorioEntity],
+    wires: &[BlueprintWire],
+) -> Result<String> {
+    if entities.is_empty() {
+        return Err(BlueprintEmpty {}.into());
+    }
+    let min_x = entities
+        .iter()
+        .map(|e| e.position.x())
+        .fold(f64::MAX, f64::min);
+    let max_x = entities
+        .iter()
+        .map(|e| e.position.x())
+        .fold(f64::MIN, f64::max);
+    let min_y = entities
+        .iter()
+        .map(|e| e.position.y())
+        .fold(f64::MAX, f64::min);
+    let max_y = entities
+        .iter()
+        .map(|e| e.position.y())
+        .fold(f64::MIN, f64::max);
+    let center_x = ((min_x + max_x) / 2.).floor();
+    let center_y = ((min_y + max_y) / 2.).floor();
+
+    let mut connections: HashMap<usize, ConnectionPoint> = HashMap::new();
+    for wire in wires {
+        if wire.from >= entities.len() || wire.to >= entities.len() {
+            return Err(BlueprintWireInvalid {
+                from: wire.from,
+                to: wire.to,
+                count: entities.len(),
+            }
+            .into());
+        }
+        for (from, to) in [(wire.from, wire.to), (wire.to, wire.from)] {
+            let point = connections.entry(from).or_insert(ConnectionPoint {
+                red: None,
+                green: None,
+            });
+            let list = match wire.color {
+                WireColor::Red => &mut point.red,
+                WireColor::Green => &mut point.green,
+            };
+            list.get_or_insert_with(Vec::new).push(ConnectionData {
+                entity_id: entity_number(to),
+                circuit_id: None,
+            });
+        }
+    }
+
+    let blueprint = Blueprint {
+        label: label.to_owned(),
+        entities: entities
+            .iter()
+            .enumerate()
+            .map(|(index, entity)| -> Result<Entity> {
+                Ok(Entity {
+                    entity_number: entity_number(index),
+                    name: if entity.entity_type == "entity-ghost" {
+                        entity
+                            .ghost_name
+                            .clone()
+                            .unwrap_or_else(|| entity.name.clone())
+                    } else {
+                        entity.name.clone()
+                    },
+                    position: blueprint_position(
+                        entity.position.x() - center_x,
+                        entity.position.y() - center_y,
+                    )?,
+                    direction: (entity.direction != 0).then_some(entity.direction),
+                    orientation: None,
+                    connections: connections.remove(&index).map(|point| {
+                        EntityConnections::StringIdx(HashMap::from([("1".to_owned(), point)]))
+                    }),
+                    control_behavior: None,
+                    items: entity
+                        .modules
+                        .as_ref()
+                        .map(|modules| ItemRequest::Compact(modules.clone().into_iter().collect())),
+                    recipe: entity.recipe.clone(),
+                    bar: None,
+                    inventory: None,
+                    infinity_settings: None,
+                    type_: match entity.belt_to_ground_type.as_deref() {
+                        Some("input") => Some(BlueprintEntityType::Input),
+                        Some("output") => Some(BlueprintEntityType::Output),
+                        _ => None,
+                    },
+                    input_priority: None,
+                    output_priority: None,
+                    filter: None,
+                    filters: None,
+                    filter_mode: None,
+                    override_stack_size: None,
+                    drop_position: None,
+                    pickup_position: None,
+                    request_filters: None,
+                    request_from_buffers: None,
+                    parameters: None,
+                    alert_parameters: None,
+                    auto_launch: None,
+                    variation: None,
+                    color: None,
+                    station: None,
+                })
+            })
+            .collect::<Result<_>>()?,
+        version: BLUEPRINT_VERSION,
+        ..Default::default()
+    };
+
+    // copper wires are not part of the blueprint objects, so they are added to the json
+    let mut value = serde_json::to_value(Container::Blueprint(blueprint)).into_diagnostic()?;
+    if let Some(Value::Array(blueprint_entities)) = value.pointer_mut("/blueprint/entities") {
+        for (index, neighbours) in pole_neighbours(prototypes, entities) {
+            blueprint_entities[index]["neighbours"] = neighbours
+                .into_iter()
+                .map(|neighbour| Value::from(neighbour + 1))
+                .collect();
+        }
+    }
+    let mut out = Vec::new();
+    BlueprintCodec::encode_writer(&mut out, |writer| {
+        serde_json::to_writer(writer, &value).map_err(|err| err.into())
+    })
+    .into_diagnostic()?;
+    String::from_utf8(out).into_diagnostic()
+}
+
+/// factorio_blueprint uses another noisy_float version, so its positions are deserialized
+fn blueprint_position(x: f64, y: f64) -> Result<BlueprintPosition> {
+    serde_json::from_value(serde_json::json!({ "x": x, "y": y })).into_diagnostic()
+}
+
+fn entity_number(index: usize) -> NonZeroUsize {
+    NonZeroUsize::new(index + 1).unwrap()
+}
+
+/// copper wire neighbours by entity index, nearest poles first
+fn pole_neighbours(
+    prototypes: &DashMap<String, FactorioEntityPrototype>,
+    entities: &[FactorioEntity],
+) -> HashMap<usize, Vec<usize>> {
+    let poles: Vec<(usize, &FactorioEntity, f64)> = entities
+        .iter()
+        .enumerate()
+        .filter(|(_, entity)| entity.entity_type == EntityType::ElectricPole.to_string())
+        .filter_map(|(index, entity)| {
+            prototypes
+                .get(&entity.name)
+                .and_then(|prototype| prototype.max_wire_distance)
+                .map(|reach| (index, entity, reach))
+        })
+        .collect();
+    let mut pairs: Vec<(f64, usize, usize)> = vec![];
+    for (a, (index_a, pole_a, reach_a)) in poles.iter().enumerate() {
+        for (index_b, pole_b, reach_b) in poles.iter().skip(a + 1) {
+            let distance = calculate_distance(&pole_a.position, &pole_b.position);
+            if distance <= reach_a.min(*reach_b) {
+                pairs.push((distance, *index_a, *index_b));
+            }
+        }
+    }
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut neighbours: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (_, a, b) in pairs {
+        let count = |index: usize| neighbours.get(&index).map_or(0, |list| list.len());
+        if count(a) < MAX_POLE_NEIGHBOURS && count(b) < MAX_POLE_NEIGHBOURS {
+            neighbours.entry(a).or_default().push(b);
+            neighbours.entry(b).or_default().push(a);
+        }
+    }
+    neighbours
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixture_entity_prototypes;
+    use crate::types::Position;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_encode_blueprint() {
+        let prototypes = fixture_entity_prototypes();
+        if let Some(mut pole) = prototypes.get_mut("small-electric-pole") {
+            pole.max_wire_distance = Some(7.5);
+        }
+        let prototypes = Arc::new(prototypes);
+        let entities = vec![
+            FactorioEntity {
+                name: "assembling-machine-2".into(),
+                entity_type: "assembling-machine".into(),
+                position: Position::new(10.5, 20.5),
+                recipe: Some("iron-gear-wheel".into()),
+                modules: Some(BTreeMap::from([("speed-module".into(), 2)])),
+                ..Default::default()
+            },
+            FactorioEntity {
+                name: "underground-belt".into(),
+                entity_type: "underground-belt".into(),
+                position: Position::new(12.5, 20.5),
+                direction: 2,
+                belt_to_ground_type: Some("output".into()),
+                ..Default::default()
+            },
+            FactorioEntity {
+                name: "small-electric-pole".into(),
+                entity_type: "electric-pole".into(),
+                position: Position::new(12.5, 18.5),
+                ..Default::default()
+            },
+            FactorioEntity {
+                name: "small-electric-pole".into(),
+                entity_type: "electric-pole".into(),
+                position: Position::new(16.5, 18.5),
+                ..Default::default()
+            },
+        ];
+        let wires = vec![BlueprintWire {
+            from: 2,
+            to: 3,
+            color: WireColor::Red,
+        }];
+        let blueprint = encode_blueprint(&prototypes, "test", &entities, &wires).unwrap();
+
+        let mut json = Value::Null;
+        BlueprintCodec::decode_reader(blueprint.as_bytes(), |reader| {
+            json = serde_json::from_reader(reader)?;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(json["blueprint"]["label"], "test");
+        assert_eq!(json["blueprint"]["version"], 281479271677952u64);
+        assert_eq!(
+            json["blueprint"]["entities"][2]["neighbours"],
+            serde_json::json!([4])
+        );
+        assert_eq!(
+            json["blueprint"]["entities"][3]["connections"]["1"]["red"][0]["entity_id"],
+            3
+        );
+
+        let Container::Blueprint(decoded) = BlueprintCodec::decode_string(&blueprint).unwrap()
+        else {
+            panic!("expected a blueprint");
+        };
+        let decoded: Vec<FactorioEntity> = decoded
+            .entities
+            .into_iter()
+            .map(|entity| FactorioEntity::from_blueprint_entity(entity, prototypes.clone()))
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(decoded[0].position, Position::new(-2.5, 1.5));
+        assert_eq!(decoded[0].recipe, entities[0].recipe);
+        assert_eq!(decoded[0].modules, entities[0].modules);
+        assert_eq!(decoded[1].direction, 2);
+        assert_eq!(decoded[1].belt_to_ground_type.as_deref(), Some("output"));
+    }
+}
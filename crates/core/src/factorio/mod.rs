@@ -1,4 +1,13 @@
+pub mod blueprint;
 pub mod factorio_planner;
+pub mod outpost;
+pub mod power_plant;
+pub mod production;
 pub mod rcon;
+pub mod rcon_lanes;
+pub mod rcon_trace;
+pub mod router;
+pub mod site_finder;
 pub mod util;
 pub mod world;
+pub mod world_snapshot;
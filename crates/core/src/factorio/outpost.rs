@@ -0,0 +1,356 @@
+use crate::errors::OutpostEmpty;
+use crate::factorio::blueprint::encode_blueprint;
+use crate::factorio::util::{entity_tile_size, footprint_blocked};
+use crate::graph::entity_graph::EntityGraph;
+use crate::graph::flow_graph::FlowGraph;
+use crate::types::{
+    Direction, EntityName, FactorioEntity, OutpostLayout, OutpostOptions, OutpostStrategy, Pos,
+    Position, Rect, ResourcePatch,
+};
+use miette::Result;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+const DEFAULT_POLE: &str = "small-electric-pole";
+/// drills side by side between two pole columns, a pole powers one drill on each side
+const DRILLS_PER_POLE_GAP: i32 = 2;
+
+/// Generates a mining outpost covering `patch`.
+///
+/// Drills are placed in rows facing a shared belt which runs west, or with `furnace`
+/// set in single rows inserting directly into one furnace each. Drills need ore under
+/// their footprint and are left out where buildings block them. Electric drills get
+/// poles in gap columns, every drill borders one. The expected ore per minute comes from running
+/// the flow graph over the generated entities.
+pub fn plan_outpost(
+    entity_graph: &EntityGraph,
+    patch: &ResourcePatch,
+    options: &OutpostOptions,
+) -> Result<OutpostLayout> {
+    let prototypes = entity_graph.entity_prototypes();
+    let drill = options
+        .drill
+        .clone()
+        .unwrap_or_else(|| EntityName::ElectricMiningDrill.to_string());
+    let belt = options
+        .belt
+        .clone()
+        .unwrap_or_else(|| EntityName::TransportBelt.to_string());
+    let pole = options
+        .pole
+        .clone()
+        .unwrap_or_else(|| DEFAULT_POLE.to_owned());
+
+    let drill_size = entity_tile_size(&prototypes, &drill)?;
+    let powered = match prototypes
+        .get(&drill)
+        .and_then(|prototype| prototype.energy_source.clone())
+    {
+        Some(energy_source) => energy_source == "electric",
+        None => drill != EntityName::BurnerMiningDrill.to_string(),
+    };
+    let furnace_size = match &options.furnace {
+        Some(furnace) => Some(entity_tile_size(&prototypes, furnace)?),
+        None => {
+            entity_tile_size(&prototypes, &belt)?;
+            None
+        }
+    };
+    if powered {
+        entity_tile_size(&prototypes, &pole)?;
+    }
+    let mining_area = if drill == EntityName::BurnerMiningDrill.to_string() {
+        drill_size
+    } else {
+        drill_size + 2
+    };
+    let (group, gap) = match (options.strategy, powered) {
+        (OutpostStrategy::Overlap, true) => (DRILLS_PER_POLE_GAP, 1),
+        (OutpostStrategy::Overlap, false) => (1, 0),
+        (OutpostStrategy::Coverage, true) => (1, (mining_area - drill_size).max(1)),
+        (OutpostStrategy::Coverage, false) => (1, mining_area - drill_size),
+    };
+
+    let left = patch.rect.left_top.x().floor() as i32;
+    let top = patch.rect.left_top.y().floor() as i32;
+    let right = patch.rect.right_bottom.x().floor() as i32 + 1;
+    let bottom = patch.rect.right_bottom.y().floor() as i32 + 1;
+    let mut columns: Vec<i32> = vec![];
+    let mut gaps: Vec<i32> = vec![];
+    let mut x = left;
+    if group > 1 && gap > 0 {
+        // the first drill of a group is powered from the gap on its left, the last
+        // one from the gap on its right
+        gaps.push(x);
+        x += gap;
+    }
+    while x < right {
+        let mut placed = 0;
+        while placed < group && x < right {
+            columns.push(x);
+            x += drill_size;
+            placed += 1;
+        }
+        // a cut off group is already powered from its left gap
+        if gap > 0 && (group == 1 || placed == group) {
+            gaps.push(x);
+            x += gap;
+        }
+    }
+    let unit_height = match furnace_size {
+        Some(furnace_size) => drill_size + furnace_size,
+        None => 2 * drill_size + 1,
+    };
+
+    let ore: HashSet<Pos> = patch.elements.iter().map(|p| p.into()).collect();
+    let mut drills: Vec<FactorioEntity> = vec![];
+    let mut outputs: Vec<FactorioEntity> = vec![];
+    let mut poles: Vec<FactorioEntity> = vec![];
+    let mut unit_top = top;
+    while unit_top < bottom {
+        let rows = match furnace_size {
+            Some(_) => vec![(unit_top, Direction::South)],
+            None => vec![
+                (unit_top, Direction::South),
+                (unit_top + drill_size + 1, Direction::North),
+            ],
+        };
+        let mut drop_tiles: Vec<Pos> = vec![];
+        for (row_top, direction) in rows {
+            let mut row_columns: Vec<i32> = vec![];
+            for &column in &columns {
+                let footprint = Rect::new(
+                    &Position::new(column as f64, row_top as f64),
+                    &Position::new((column + drill_size) as f64, (row_top + drill_size) as f64),
+                );
+                if !covers_ore(&ore, column, row_top, drill_size)
+                    || footprint_blocked(entity_graph, &footprint)
+                {
+                    continue;
+                }
+                let position = footprint.center();
+                let drop_position = position.add(&drill_drop(drill_size).turn(direction));
+                drop_tiles.push((&drop_position).into());
+                drills.push(FactorioEntity::from_prototype(
+                    &drill,
+                    position,
+                    Some(direction),
+                    None,
+                    Some(drop_position),
+                    prototypes.clone(),
+                )?);
+                row_columns.push(column);
+            }
+            if powered {
+                let pole_y = (row_top + drill_size / 2) as f64 + 0.5;
+                for &gap_column in &gaps {
+                    if row_columns.iter().any(|&column| {
+                        column + drill_size == gap_column || column == gap_column + gap
+                    }) {
+                        poles.push(FactorioEntity::from_prototype(
+                            &pole,
+                            Position::new(gap_column as f64 + 0.5, pole_y),
+                            None,
+                            None,
+                            None,
+                            prototypes.clone(),
+                        )?);
+                    }
+                }
+            }
+        }
+        match (&options.furnace, furnace_size) {
+            (Some(furnace), Some(furnace_size)) => {
+                let half = furnace_size as f64 / 2.;
+                for tile in drop_tiles {
+                    outputs.push(FactorioEntity::from_prototype(
+                        furnace,
+                        Position::new(tile.0 as f64 + half, tile.1 as f64 + half),
+                        None,
+                        None,
+                        None,
+                        prototypes.clone(),
+                    )?);
+                }
+            }
+            _ => {
+                let belt_y = (unit_top + drill_size) as f64 + 0.5;
+                if let (Some(min_x), Some(max_x)) = (
+                    drop_tiles.iter().map(|tile| tile.0).min(),
+                    drop_tiles.iter().map(|tile| tile.0).max(),
+                ) {
+                    for x in min_x..=max_x {
+                        outputs.push(FactorioEntity::from_prototype(
+                            &belt,
+                            Position::new(x as f64 + 0.5, belt_y),
+                            Some(Direction::West),
+                            None,
+                            None,
+                            prototypes.clone(),
+                        )?);
+                    }
+                }
+            }
+        }
+        unit_top += unit_height;
+    }
+    if drills.is_empty() {
+        return Err(OutpostEmpty {
+            drill,
+            resource: patch.name.clone(),
+        }
+        .into());
+    }
+
+    let drill_count = drills.len() as u32;
+    let mut entities = drills;
+    entities.append(&mut outputs);
+    entities.append(&mut poles);
+    let ore_per_minute = ore_per_minute(entity_graph, patch, &entities)?;
+    let blueprint = encode_blueprint(
+        &prototypes,
+        &format!("{} outpost", patch.name),
+        &entities,
+        &[],
+    )?;
+    Ok(OutpostLayout {
+        entities,
+        blueprint,
+        drill_count,
+        ore_per_minute,
+    })
+}
+
+/// drop position of a drill facing north, the tile right in front of its centre
+fn drill_drop(drill_size: i32) -> Position {
+    Position::new(
+        if drill_size % 2 == 0 { -0.5 } else { 0. },
+        -(drill_size as f64) / 2. - 0.5,
+    )
+}
+
+fn covers_ore(ore: &HashSet<Pos>, left: i32, top: i32, size: i32) -> bool {
+    (left..left + size).any(|x| (top..top + size).any(|y| ore.contains(&Pos(x, y))))
+}
+
+/// builds a separate graph of the patch and the layout to model the drill output
+fn ore_per_minute(
+    entity_graph: &EntityGraph,
+    patch: &ResourcePatch,
+    entities: &[FactorioEntity],
+) -> Result<f64> {
+    let graph = EntityGraph::new(entity_graph.entity_prototypes(), entity_graph.recipes());
+    let mut all: Vec<FactorioEntity> = patch
+        .elements
+        .iter()
+        .map(|element| {
+            FactorioEntity::new_resource(
+                &Position::new(element.x() + 0.5, element.y() + 0.5),
+                Direction::North,
+                &patch.name,
+            )
+        })
+        .collect();
+    all.extend(entities.iter().cloned());
+    graph.add(all, None)?;
+    graph.connect()?;
+    let flow_graph = FlowGraph::new(Arc::new(graph));
+    flow_graph.update()?;
+    let inner = flow_graph.inner_graph();
+    let per_second: f64 = inner
+        .edge_indices()
+        .filter(|edge| {
+            inner
+                .edge_endpoints(*edge)
+                .is_some_and(|(source, _)| inner[source].miner_ore.is_some())
+        })
+        .flat_map(|edge| inner[edge].rates())
+        .filter(|(name, _)| *name == patch.name)
+        .map(|(_, rate)| rate)
+        .sum();
+    Ok(per_second * 60.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::power_graph::PowerGraph;
+    use crate::test_utils::entity_graph_from;
+
+    #[test]
+    fn test_plan_outpost() {
+        let mut resources = vec![];
+        for x in 0..10 {
+            for y in 0..10 {
+                resources.push(FactorioEntity::new_resource(
+                    &Position::new(x as f64 + 0.5, y as f64 + 0.5),
+                    Direction::North,
+                    &EntityName::IronOre.to_string(),
+                ));
+            }
+        }
+        let entity_graph = entity_graph_from(resources).unwrap();
+        let patch = entity_graph
+            .resource_patches(&EntityName::IronOre.to_string())
+            .remove(0);
+        let count = |layout: &OutpostLayout, entity_type: &str| {
+            layout
+                .entities
+                .iter()
+                .filter(|entity| entity.entity_type == entity_type)
+                .count()
+        };
+
+        let layout = plan_outpost(&entity_graph, &patch, &OutpostOptions::default()).unwrap();
+        assert_eq!(layout.drill_count, 9);
+        assert_eq!(count(&layout, "transport-belt"), 16);
+        assert_eq!(count(&layout, "electric-pole"), 6);
+        assert_eq!(layout.ore_per_minute, 270.);
+        assert!(!layout.blueprint.is_empty());
+        assert_drills_powered(&layout);
+
+        let coverage = plan_outpost(
+            &entity_graph,
+            &patch,
+            &OutpostOptions {
+                strategy: OutpostStrategy::Coverage,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(coverage.drill_count, 6);
+        assert_eq!(coverage.ore_per_minute, 180.);
+        assert_drills_powered(&coverage);
+
+        let furnaces = plan_outpost(
+            &entity_graph,
+            &patch,
+            &OutpostOptions {
+                drill: Some(EntityName::BurnerMiningDrill.to_string()),
+                furnace: Some(EntityName::StoneFurnace.to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(furnaces.drill_count, 15);
+        assert_eq!(count(&furnaces, "furnace"), 15);
+        assert_eq!(count(&furnaces, "electric-pole"), 0);
+        assert_eq!(furnaces.ore_per_minute, 225.);
+    }
+    fn assert_drills_powered(layout: &OutpostLayout) {
+        let entity_graph = Arc::new(entity_graph_from(layout.entities.clone()).unwrap());
+        let power_graph = PowerGraph::new(entity_graph);
+        power_graph.update().unwrap();
+        for drill in layout
+            .entities
+            .iter()
+            .filter(|entity| entity.entity_type == "mining-drill")
+        {
+            assert!(
+                power_graph.network_at(&drill.position).is_some(),
+                "drill at {} is outside every network",
+                drill.position
+            );
+        }
+    }
+}
@@ -0,0 +1,532 @@
+use crate::errors::{
+    PowerPlantNoSite, PowerPlantPoleUnreachable, PowerPlantTooLarge, ProductionValidationFailed,
+};
+use crate::factorio::blueprint::encode_blueprint;
+use crate::factorio::production::COAL_FUEL_VALUE;
+use crate::factorio::router::{LogisticsRouter, RouteEndpoint, RouteEntities, RouteRequest};
+use crate::factorio::site_finder::find_sites;
+use crate::factorio::util::{
+    calculate_distance, entity_tile_size, footprint_blocked, move_position,
+};
+use crate::graph::entity_graph::EntityGraph;
+use crate::graph::flow_graph::{
+    FlowGraph, BOILER_STEAM_OUTPUT, OFFSHORE_PUMP_PUMPING_SPEED, STEAM_ENERGY_PER_UNIT,
+    STEAM_ENGINE_FLUID_USAGE,
+};
+use crate::types::{
+    Direction, EntityName, EntityType, FactorioEntity, FactorioEntityPrototype, FactorioTile, Pos,
+    Position, PowerPlantLayout, PowerPlantOptions, Rect, SiteConstraints,
+};
+use dashmap::DashMap;
+use miette::Result;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+const DEFAULT_PIPE_TO_GROUND: &str = "pipe-to-ground";
+const DEFAULT_POLE: &str = "small-electric-pole";
+/// offshore pump positions tried, nearest to the base first
+const PUMP_CANDIDATES: usize = 10;
+/// maximum distance of the boilers from the pump if the options give none
+const DEFAULT_MAX_DISTANCE: f64 = 32.;
+/// small electric pole, used if the prototype has no wire distance
+const POLE_WIRE_DISTANCE: f64 = 7.5;
+/// boilers are 3x2 and steam engines 3x5 with their pipe connections on the short sides
+const BOILER_HEIGHT: i32 = 2;
+const STEAM_ENGINE_HEIGHT: i32 = 5;
+/// a boiler and the gap column west of it with the water pipe and the poles
+const COLUMN_WIDTH: i32 = 4;
+
+/// Generates a power plant at the best of `pump_options`.
+///
+/// Pump positions are tried nearest to `base` first. Boilers stand side by side on free
+/// land near the pump with their steam engines stacked north of them, the one tile gaps
+/// between boilers carry the water and the poles. The pipe from the pump is routed around
+/// buildings and water, a line of poles leads to within wire reach of `base`.
+/// `pump_direction` points from the water to the land like for
+/// `find_offshore_pump_placement_options`, the pump faces the water. The power is
+/// predicted by running the flow graph over the layout.
+pub fn plan_power_plant(
+    entity_graph: &EntityGraph,
+    pump_options: &[Position],
+    pump_direction: Direction,
+    base: &Position,
+    options: &PowerPlantOptions,
+) -> Result<PowerPlantLayout> {
+    let spec = PlantSpec::new(entity_graph.entity_prototypes(), options)?;
+    let mut candidates: Vec<&Position> = pump_options.iter().collect();
+    candidates.sort_by(|a, b| calculate_distance(a, base).total_cmp(&calculate_distance(b, base)));
+    let mut last_error = None;
+    for candidate in candidates.into_iter().take(PUMP_CANDIDATES) {
+        let entities = match spec.build(entity_graph, candidate.into(), pump_direction, base) {
+            Ok(Some(entities)) => entities,
+            Ok(None) => continue,
+            Err(err) => {
+                last_error = Some(err);
+                continue;
+            }
+        };
+        let expected = spec.steam_engines() as f64 * spec.engine_usage;
+        let predicted = steam_consumed(entity_graph, &entities)?;
+        if (predicted - expected).abs() > 1e-6 {
+            last_error = Some(
+                ProductionValidationFailed {
+                    item: EntityName::Steam.to_string(),
+                    predicted: predicted * 60.0,
+                    expected: expected * 60.0,
+                }
+                .into(),
+            );
+            continue;
+        }
+        let blueprint = encode_blueprint(&spec.prototypes, "power plant", &entities, &[])?;
+        return Ok(PowerPlantLayout {
+            offshore_pump_position: entities[0].position.clone(),
+            entities,
+            blueprint,
+            boiler_count: spec.boilers,
+            steam_engine_count: spec.steam_engines(),
+            power: predicted * STEAM_ENERGY_PER_UNIT,
+            fuel_per_minute: spec.boilers as f64 * spec.boiler_steam * STEAM_ENERGY_PER_UNIT
+                / COAL_FUEL_VALUE
+                * 60.,
+        });
+    }
+    Err(last_error.unwrap_or_else(|| {
+        PowerPlantNoSite {
+            options: pump_options.len(),
+        }
+        .into()
+    }))
+}
+
+/// Entity names and ratios of a power plant
+struct PlantSpec {
+    prototypes: Arc<DashMap<String, FactorioEntityPrototype>>,
+    offshore_pump: String,
+    boiler: String,
+    steam_engine: String,
+    pipe: String,
+    pipe_to_ground: String,
+    pole: String,
+    boilers: u32,
+    engines_per_boiler: u32,
+    /// steam per second of one boiler
+    boiler_steam: f64,
+    /// steam per second of one steam engine
+    engine_usage: f64,
+    wire_distance: f64,
+    max_distance: f64,
+}
+
+impl PlantSpec {
+    fn new(
+        prototypes: Arc<DashMap<String, FactorioEntityPrototype>>,
+        options: &PowerPlantOptions,
+    ) -> Result<PlantSpec> {
+        let offshore_pump = options
+            .offshore_pump
+            .clone()
+            .unwrap_or_else(|| EntityName::OffshorePump.to_string());
+        let boiler = options
+            .boiler
+            .clone()
+            .unwrap_or_else(|| EntityName::Boiler.to_string());
+        let steam_engine = options
+            .steam_engine
+            .clone()
+            .unwrap_or_else(|| EntityName::SteamEngine.to_string());
+        let pipe = options
+            .pipe
+            .clone()
+            .unwrap_or_else(|| EntityName::Pipe.to_string());
+        let pipe_to_ground = options
+            .pipe_to_ground
+            .clone()
+            .unwrap_or_else(|| DEFAULT_PIPE_TO_GROUND.to_owned());
+        let pole = options
+            .pole
+            .clone()
+            .unwrap_or_else(|| DEFAULT_POLE.to_owned());
+        for name in [
+            &offshore_pump,
+            &boiler,
+            &steam_engine,
+            &pipe,
+            &pipe_to_ground,
+            &pole,
+        ] {
+            entity_tile_size(&prototypes, name)?;
+        }
+
+        let value = |name: &str, value: fn(&FactorioEntityPrototype) -> Option<f64>| {
+            prototypes.get(name).and_then(|prototype| value(&prototype))
+        };
+        let boiler_steam = value(&boiler, |p| p.energy_usage)
+            .map_or(BOILER_STEAM_OUTPUT, |watts| watts / STEAM_ENERGY_PER_UNIT);
+        let engine_usage =
+            value(&steam_engine, |p| p.fluid_usage).unwrap_or(STEAM_ENGINE_FLUID_USAGE);
+        let pumping_speed =
+            value(&offshore_pump, |p| p.pumping_speed).unwrap_or(OFFSHORE_PUMP_PUMPING_SPEED);
+        let wire_distance = value(&pole, |p| p.max_wire_distance).unwrap_or(POLE_WIRE_DISTANCE);
+
+        let boilers = options.boilers.unwrap_or(1).max(1);
+        let max_boilers = (pumping_speed / boiler_steam).floor() as u32;
+        if boilers > max_boilers {
+            return Err(PowerPlantTooLarge {
+                boilers,
+                offshore_pump,
+                max: max_boilers,
+            }
+            .into());
+        }
+        Ok(PlantSpec {
+            offshore_pump,
+            boiler,
+            steam_engine,
+            pipe,
+            pipe_to_ground,
+            pole,
+            boilers,
+            engines_per_boiler: ((boiler_steam / engine_usage).floor() as u32).max(1),
+            boiler_steam,
+            engine_usage,
+            wire_distance,
+            max_distance: options.max_distance.unwrap_or(DEFAULT_MAX_DISTANCE),
+            prototypes,
+        })
+    }
+
+    fn steam_engines(&self) -> u32 {
+        self.boilers * self.engines_per_boiler
+    }
+
+    /// Entities for the pump on water tile `pump_tile`, the pump first. `None` if there
+    /// is no room for the boilers or no way for the pipe.
+    fn build(
+        &self,
+        entity_graph: &EntityGraph,
+        pump_tile: Pos,
+        pump_direction: Direction,
+        base: &Position,
+    ) -> Result<Option<Vec<FactorioEntity>>> {
+        let pump_position = Position::new(pump_tile.0 as f64 + 0.5, pump_tile.1 as f64 + 0.5);
+        let pump_output = move_position(&pump_position, pump_direction, 1.);
+        let width = COLUMN_WIDTH * self.boilers as i32;
+        let height = BOILER_HEIGHT + STEAM_ENGINE_HEIGHT * self.engines_per_boiler as i32;
+        // with a free tile around the block the pipe and poles can always reach it, plus
+        // one more in the north as pipes there would leak into the steam engines
+        let Some(site) = find_sites(
+            entity_graph,
+            width as u32 + 2,
+            height as u32 + 3,
+            &SiteConstraints {
+                near: Some(pump_output.clone()),
+                max_distance: Some(self.max_distance),
+                allow_trees: true,
+                ..Default::default()
+            },
+            1,
+        )
+        .into_iter()
+        .next() else {
+            return Ok(None);
+        };
+        let left = site.rect.left_top.x().floor() as i32 + 1;
+        let top = site.rect.left_top.y().floor() as i32 + 2;
+        let bottom = top + height;
+
+        let mut block: Vec<FactorioEntity> = vec![];
+        let mut poles: Vec<Position> = vec![];
+        for column in 0..self.boilers as i32 {
+            let gap = (left + column * COLUMN_WIDTH) as f64 + 0.5;
+            let center = gap + 2.;
+            block.push(self.entity(
+                &self.boiler,
+                Position::new(center, (bottom - 1) as f64),
+                Direction::North,
+            )?);
+            for engine in 0..self.engines_per_boiler as i32 {
+                let engine_y = (bottom - BOILER_HEIGHT - engine * STEAM_ENGINE_HEIGHT) as f64
+                    - STEAM_ENGINE_HEIGHT as f64 / 2.;
+                block.push(self.entity(
+                    &self.steam_engine,
+                    Position::new(center, engine_y),
+                    Direction::North,
+                )?);
+                poles.push(Position::new(gap, engine_y));
+            }
+            block.push(self.entity(
+                &self.pipe,
+                Position::new(gap, bottom as f64 - 0.5),
+                Direction::North,
+            )?);
+        }
+        let pump = self.entity(
+            &self.offshore_pump,
+            pump_position,
+            pump_direction.opposite(),
+        )?;
+
+        let reserved = Rect::new(
+            &Position::new(left as f64, (top - 1) as f64),
+            &Position::new((left + width) as f64, bottom as f64),
+        );
+        // the pipe ends west of the first gap
+        let Some(pipes) = self.route_pipe(
+            entity_graph,
+            &pump,
+            &block,
+            &reserved,
+            &pump_output,
+            &Position::new(left as f64 - 0.5, bottom as f64 - 0.5),
+        ) else {
+            return Ok(None);
+        };
+        let mut entities = vec![pump];
+        entities.extend(pipes);
+        entities.append(&mut block);
+
+        let occupied = occupied_tiles(&entities);
+        let nearest_pole = poles
+            .iter()
+            .min_by(|a, b| calculate_distance(a, base).total_cmp(&calculate_distance(b, base)))
+            .cloned()
+            .expect("at least one boiler");
+        poles.extend(self.pole_line(entity_graph, &occupied, nearest_pole, base)?);
+        for pole in poles {
+            entities.push(self.entity(&self.pole, pole, Direction::North)?);
+        }
+        Ok(Some(entities))
+    }
+
+    fn route_pipe(
+        &self,
+        entity_graph: &EntityGraph,
+        pump: &FactorioEntity,
+        block: &[FactorioEntity],
+        reserved: &Rect,
+        from: &Position,
+        to: &Position,
+    ) -> Option<Vec<FactorioEntity>> {
+        let area = Rect::new(
+            &Position::new(from.x().min(to.x()), from.y().min(to.y())),
+            &Position::new(from.x().max(to.x()), from.y().max(to.y())),
+        );
+        let area = Rect::new(
+            &Position::new(area.left_top.x() - 20., area.left_top.y() - 20.),
+            &Position::new(area.right_bottom.x() + 20., area.right_bottom.y() + 20.),
+        );
+        // trees and rocks in the way get mined when building
+        let mut blockers: Vec<FactorioEntity> = entity_graph
+            .entities_in(&area)
+            .into_iter()
+            .filter(|entity| {
+                entity.entity_type != EntityType::Tree.to_string()
+                    && entity.entity_type != EntityType::SimpleEntity.to_string()
+            })
+            .collect();
+        blockers.push(pump.clone());
+        blockers.extend(block.iter().cloned());
+        let tiles: Vec<FactorioTile> = entity_graph
+            .tile_tree()
+            .query(area.into())
+            .into_iter()
+            .filter(|(tile, _, _)| tile.player_collidable)
+            .map(|(tile, _, _)| tile.clone())
+            .collect();
+        LogisticsRouter::new(
+            self.prototypes.clone(),
+            RouteEntities::new(&self.prototypes, &self.pipe, &self.pipe_to_ground),
+            &blockers,
+            &tiles,
+        )
+        .with_reserved_zones(std::slice::from_ref(reserved))
+        .route(&RouteRequest {
+            from: RouteEndpoint::new(from.clone(), None),
+            to: RouteEndpoint::new(to.clone(), Some(Direction::East)),
+        })
+        .ok()
+    }
+
+    /// Poles from `from` towards `base`, each as close to `base` as the wire reaches,
+    /// until `base` is in reach
+    fn pole_line(
+        &self,
+        entity_graph: &EntityGraph,
+        occupied: &HashSet<Pos>,
+        from: Position,
+        base: &Position,
+    ) -> Result<Vec<Position>> {
+        let reach = self.wire_distance;
+        let steps = reach.floor() as i32;
+        let mut current = from;
+        let mut poles = vec![];
+        while calculate_distance(&current, base) > reach {
+            let tile: Pos = (&current).into();
+            let next = (-steps..=steps)
+                .flat_map(|dy| (-steps..=steps).map(move |dx| Pos(tile.0 + dx, tile.1 + dy)))
+                .filter(|pos| !occupied.contains(pos))
+                .map(|pos| Position::new(pos.0 as f64 + 0.5, pos.1 as f64 + 0.5))
+                .filter(|position| {
+                    calculate_distance(&current, position) <= reach
+                        && !footprint_blocked(
+                            entity_graph,
+                            &Rect::new(
+                                &Position::new(position.x() - 0.5, position.y() - 0.5),
+                                &Position::new(position.x() + 0.5, position.y() + 0.5),
+                            ),
+                        )
+                })
+                .min_by(|a, b| calculate_distance(a, base).total_cmp(&calculate_distance(b, base)));
+            match next {
+                Some(next)
+                    if calculate_distance(&next, base) < calculate_distance(&current, base) =>
+                {
+                    poles.push(next.clone());
+                    current = next;
+                }
+                _ => {
+                    return Err(PowerPlantPoleUnreachable { base: base.clone() }.into());
+                }
+            }
+        }
+        Ok(poles)
+    }
+
+    fn entity(
+        &self,
+        name: &str,
+        position: Position,
+        direction: Direction,
+    ) -> Result<FactorioEntity> {
+        FactorioEntity::from_prototype(
+            name,
+            position,
+            Some(direction),
+            None,
+            None,
+            self.prototypes.clone(),
+        )
+    }
+}
+
+fn occupied_tiles(entities: &[FactorioEntity]) -> HashSet<Pos> {
+    let mut tiles = HashSet::new();
+    for entity in entities {
+        let rect = &entity.bounding_box;
+        for y in rect.left_top.y().floor() as i32..=rect.right_bottom.y().floor() as i32 {
+            for x in rect.left_top.x().floor() as i32..=rect.right_bottom.x().floor() as i32 {
+                tiles.insert(Pos(x, y));
+            }
+        }
+    }
+    tiles
+}
+
+/// steam per second used by the steam engines as modelled by a flow graph of the layout
+fn steam_consumed(entity_graph: &EntityGraph, entities: &[FactorioEntity]) -> Result<f64> {
+    let graph = EntityGraph::new(entity_graph.entity_prototypes(), entity_graph.recipes());
+    graph.add(entities.to_vec(), None)?;
+    graph.connect()?;
+    let flow_graph = FlowGraph::new(Arc::new(graph));
+    flow_graph.update()?;
+    let inner = flow_graph.inner_graph();
+    let steam = EntityName::Steam.to_string();
+    let rate = |index, direction| -> f64 {
+        inner
+            .edges_directed(index, direction)
+            .flat_map(|edge| edge.weight().rates())
+            .filter(|(name, _)| *name == steam)
+            .map(|(_, rate)| rate)
+            .sum()
+    };
+    Ok(inner
+        .node_indices()
+        .filter(|index| inner[*index].entity_type == EntityType::Generator)
+        .map(|index| {
+            rate(index, petgraph::Direction::Incoming) - rate(index, petgraph::Direction::Outgoing)
+        })
+        .sum())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{entity_graph_from, spawn_water};
+
+    #[test]
+    fn test_plan_power_plant() {
+        let entity_graph = entity_graph_from(vec![]).unwrap();
+        let mut tiles = vec![];
+        spawn_water(
+            &mut tiles,
+            Rect::new(&Position::new(-5., -10.), &Position::new(4., -1.)),
+        );
+        entity_graph.add_tiles(tiles, None).unwrap();
+        let base = Position::new(0., 40.);
+        let count = |layout: &PowerPlantLayout, name: &str| {
+            layout
+                .entities
+                .iter()
+                .filter(|entity| entity.name == name)
+                .count()
+        };
+
+        let layout = plan_power_plant(
+            &entity_graph,
+            &[Position::new(-2., -1.), Position::new(1., -1.)],
+            Direction::South,
+            &base,
+            &PowerPlantOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(layout.offshore_pump_position, Position::new(1.5, -0.5));
+        assert_eq!(layout.boiler_count, 1);
+        assert_eq!(layout.steam_engine_count, 2);
+        assert_eq!(count(&layout, "boiler"), 1);
+        assert_eq!(count(&layout, "steam-engine"), 2);
+        assert_eq!(layout.power, 1_800_000.);
+        assert_eq!(layout.fuel_per_minute, 27.);
+        assert!(count(&layout, "pipe") > 0);
+        let poles: Vec<&FactorioEntity> = layout
+            .entities
+            .iter()
+            .filter(|entity| entity.name == "small-electric-pole")
+            .collect();
+        assert!(poles
+            .iter()
+            .any(|pole| calculate_distance(&pole.position, &base) <= POLE_WIRE_DISTANCE));
+        // everything but the pump stays off the water
+        let occupied = occupied_tiles(&layout.entities[1..]);
+        assert!(!occupied
+            .iter()
+            .any(|pos| (-5..=4).contains(&pos.0) && (-10..=-1).contains(&pos.1)));
+        assert!(!layout.blueprint.is_empty());
+
+        let larger = plan_power_plant(
+            &entity_graph,
+            &[Position::new(1., -1.)],
+            Direction::South,
+            &base,
+            &PowerPlantOptions {
+                boilers: Some(3),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(count(&larger, "steam-engine"), 6);
+        assert_eq!(larger.power, 5_400_000.);
+
+        assert!(plan_power_plant(
+            &entity_graph,
+            &[Position::new(1., -1.)],
+            Direction::South,
+            &base,
+            &PowerPlantOptions {
+                boilers: Some(21),
+                ..Default::default()
+            },
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,628 @@
+use crate::errors::{
+    ProductionBeltOverloaded, ProductionInserterOverloaded, ProductionRecipeNotFound,
+    ProductionRecipeUnsupported, ProductionValidationFailed,
+};
+use crate::factorio::blueprint::encode_blueprint;
+use crate::factorio::util::entity_tile_size;
+use crate::graph::entity_graph::EntityGraph;
+use crate::graph::flow_graph::{inserter_throughput, FlowGraph, FlowRates};
+use crate::types::{
+    AssemblerLineOptions, Direction, EntityName, EntityType, FactorioEntity,
+    FactorioEntityPrototype, FactorioRecipe, Position, ProductionBlock, SmeltingColumnOptions,
+};
+use dashmap::DashMap;
+use miette::Result;
+use num_traits::ToPrimitive;
+use petgraph::algo::has_path_connecting;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+const DEFAULT_ASSEMBLING_MACHINE: &str = "assembling-machine-2";
+const DEFAULT_POLE: &str = "small-electric-pole";
+/// tried from slow to fast if no inserter is given
+const DEFAULT_INSERTERS: [&str; 4] = [
+    "inserter",
+    "fast-inserter",
+    "bulk-inserter",
+    "stack-inserter",
+];
+/// burned by stone and steel furnaces without an energy usage in their prototype
+const FURNACE_ENERGY_USAGE: f64 = 90_000.;
+pub(crate) const COAL_FUEL_VALUE: f64 = 4_000_000.;
+/// used if the prototype has no energy source
+const BURNER_ENTITIES: [&str; 4] = [
+    "burner-mining-drill",
+    "burner-inserter",
+    "stone-furnace",
+    "steel-furnace",
+];
+
+/// Generates a column of furnaces smelting `input`.
+///
+/// Furnaces are stacked southwards at `position` between an input belt on the west and
+/// an output belt on the east, each with as many inserters on both sides as it takes to
+/// keep up. Burner furnaces get coal on the input belt. The layout is checked by feeding the inputs into a flow graph
+/// of it and comparing the predicted output with the recipe.
+pub fn smelting_column(
+    prototypes: Arc<DashMap<String, FactorioEntityPrototype>>,
+    recipes: Arc<DashMap<String, FactorioRecipe>>,
+    position: &Position,
+    options: &SmeltingColumnOptions,
+) -> Result<ProductionBlock> {
+    let furnace = options
+        .furnace
+        .clone()
+        .unwrap_or_else(|| EntityName::StoneFurnace.to_string());
+    let recipe = recipes
+        .iter()
+        .find(|recipe| {
+            recipe.category == "smelting"
+                && recipe.ingredients.as_ref().is_some_and(|ingredients| {
+                    ingredients.len() == 1 && ingredients[0].name == options.input
+                })
+        })
+        .map(|recipe| recipe.clone())
+        .ok_or_else(|| ProductionRecipeNotFound {
+            name: options.input.clone(),
+        })?;
+    let mut fuel = BTreeMap::new();
+    if is_burner(&prototypes, &furnace) {
+        let energy_usage = prototypes
+            .get(&furnace)
+            .and_then(|prototype| prototype.energy_usage)
+            .unwrap_or(FURNACE_ENERGY_USAGE);
+        fuel.insert(
+            EntityName::Coal.to_string(),
+            energy_usage / COAL_FUEL_VALUE * 60.,
+        );
+    }
+    let block = ColumnSpec::new(
+        &prototypes,
+        &furnace,
+        None,
+        options.count,
+        options.belt.clone(),
+        options.inserter.clone(),
+        options.pole.clone(),
+    )?;
+    block.build(
+        prototypes,
+        recipes,
+        position,
+        &recipe,
+        fuel,
+        &format!("{} smelting", options.input),
+    )
+}
+
+/// Generates a line of assembling machines making `recipe` at the target rate.
+///
+/// The machine count is the target divided by what one machine makes at its crafting
+/// speed, rounded up. Up to two item ingredients share the input belt, one on each lane.
+/// Like smelting columns, the line is checked against a flow graph of it.
+pub fn assembler_line(
+    prototypes: Arc<DashMap<String, FactorioEntityPrototype>>,
+    recipes: Arc<DashMap<String, FactorioRecipe>>,
+    position: &Position,
+    options: &AssemblerLineOptions,
+) -> Result<ProductionBlock> {
+    let recipe = recipes
+        .get(&options.recipe)
+        .map(|recipe| recipe.clone())
+        .ok_or_else(|| ProductionRecipeNotFound {
+            name: options.recipe.clone(),
+        })?;
+    let unsupported = |reason: &str| ProductionRecipeUnsupported {
+        recipe: recipe.name.clone(),
+        reason: reason.to_owned(),
+    };
+    let ingredients = recipe.ingredients.clone().unwrap_or_default();
+    if ingredients
+        .iter()
+        .any(|ingredient| ingredient.ingredient_type == "fluid")
+        || recipe
+            .products
+            .iter()
+            .any(|product| product.product_type == "fluid")
+    {
+        return Err(unsupported("fluids need pipes").into());
+    }
+    if ingredients.len() > 2 {
+        return Err(unsupported("more than two ingredients").into());
+    }
+    let product = recipe
+        .products
+        .iter()
+        .find(|product| product.name == recipe.name)
+        .or_else(|| recipe.products.first())
+        .ok_or_else(|| unsupported("no products"))?;
+
+    let machine = options
+        .machine
+        .clone()
+        .unwrap_or_else(|| DEFAULT_ASSEMBLING_MACHINE.to_owned());
+    let per_machine = product.amount as f64 * crafting_speed(&prototypes, &machine) * 60.
+        / recipe.energy.to_f64().unwrap();
+    let count = (options.items_per_minute / per_machine).ceil().max(1.) as u32;
+    let block = ColumnSpec::new(
+        &prototypes,
+        &machine,
+        Some(recipe.name.clone()),
+        count,
+        options.belt.clone(),
+        options.inserter.clone(),
+        options.pole.clone(),
+    )?;
+    block.build(
+        prototypes,
+        recipes,
+        position,
+        &recipe,
+        BTreeMap::new(),
+        &format!("{} assembly", recipe.name),
+    )
+}
+
+/// machines stacked between an input and an output belt
+struct ColumnSpec {
+    machine: String,
+    machine_size: i32,
+    recipe: Option<String>,
+    count: u32,
+    belt: String,
+    inserter: Option<String>,
+    pole: Option<String>,
+}
+
+impl ColumnSpec {
+    fn new(
+        prototypes: &DashMap<String, FactorioEntityPrototype>,
+        machine: &str,
+        recipe: Option<String>,
+        count: u32,
+        belt: Option<String>,
+        inserter: Option<String>,
+        pole: Option<String>,
+    ) -> Result<ColumnSpec> {
+        let belt = belt.unwrap_or_else(|| EntityName::TransportBelt.to_string());
+        let pole = pole.unwrap_or_else(|| DEFAULT_POLE.to_owned());
+        entity_tile_size(prototypes, &belt)?;
+        if let Some(inserter) = &inserter {
+            entity_tile_size(prototypes, inserter)?;
+        }
+        let powered = !is_burner(prototypes, machine)
+            || inserter
+                .as_ref()
+                .is_none_or(|inserter| !is_burner(prototypes, inserter));
+        if powered {
+            entity_tile_size(prototypes, &pole)?;
+        }
+        Ok(ColumnSpec {
+            machine: machine.to_owned(),
+            machine_size: entity_tile_size(prototypes, machine)?,
+            recipe,
+            count,
+            belt,
+            inserter,
+            pole: powered.then_some(pole),
+        })
+    }
+
+    /// Inserter and how many of it each machine needs on the input and the output side.
+    ///
+    /// Without a given inserter the slowest one which fits into `slots` per side is picked.
+    fn inserters(
+        &self,
+        prototypes: &DashMap<String, FactorioEntityPrototype>,
+        input_per_minute: f64,
+        output_per_minute: f64,
+        slots: usize,
+    ) -> Result<(String, usize, usize)> {
+        let candidates: Vec<String> = match &self.inserter {
+            Some(inserter) => vec![inserter.clone()],
+            None => DEFAULT_INSERTERS
+                .iter()
+                .filter(|name| prototypes.contains_key(**name))
+                .map(|name| name.to_string())
+                .collect(),
+        };
+        let per_minute = input_per_minute.max(output_per_minute);
+        let mut capacity = 0.;
+        for inserter in &candidates {
+            let items_per_minute = inserter_throughput(prototypes, inserter) * 60.;
+            let needed = |rate: f64| (rate / items_per_minute - 1e-9).ceil().max(1.) as usize;
+            if needed(per_minute) <= slots {
+                return Ok((
+                    inserter.clone(),
+                    needed(input_per_minute),
+                    needed(output_per_minute),
+                ));
+            }
+            capacity = items_per_minute * slots as f64;
+        }
+        Err(ProductionInserterOverloaded {
+            inserter: candidates
+                .last()
+                .cloned()
+                .unwrap_or_else(|| EntityName::Inserter.to_string()),
+            items_per_minute: per_minute,
+            capacity,
+        }
+        .into())
+    }
+
+    fn build(
+        &self,
+        prototypes: Arc<DashMap<String, FactorioEntityPrototype>>,
+        recipes: Arc<DashMap<String, FactorioRecipe>>,
+        position: &Position,
+        recipe: &FactorioRecipe,
+        fuel: BTreeMap<String, f64>,
+        label: &str,
+    ) -> Result<ProductionBlock> {
+        let size = self.machine_size;
+        let left = position.x().floor() as i32;
+        let top = position.y().floor() as i32;
+        let output_x = left + 3 + size;
+        let entity = |name: &str,
+                      x: f64,
+                      y: f64,
+                      direction: Option<Direction>,
+                      pickup: Option<Position>,
+                      drop: Option<Position>| {
+            FactorioEntity::from_prototype(
+                name,
+                Position::new(x, y),
+                direction,
+                pickup,
+                drop,
+                prototypes.clone(),
+            )
+        };
+
+        let crafts_per_minute = self.count as f64 * crafting_speed(&prototypes, &self.machine)
+            / recipe.energy.to_f64().unwrap()
+            * 60.;
+        let mut inputs = fuel
+            .into_iter()
+            .map(|(name, per_machine)| (name, per_machine * self.count as f64))
+            .collect::<BTreeMap<String, f64>>();
+        for ingredient in recipe.ingredients.iter().flatten() {
+            *inputs.entry(ingredient.name.clone()).or_default() +=
+                ingredient.amount as f64 * crafts_per_minute;
+        }
+        let expected: BTreeMap<String, f64> = recipe
+            .products
+            .iter()
+            .map(|product| {
+                (
+                    product.name.clone(),
+                    product.amount as f64 * crafts_per_minute,
+                )
+            })
+            .collect();
+        let capacity = belt_capacity(&self.belt);
+        for items_per_minute in [inputs.values().sum(), expected.values().sum::<f64>()] {
+            if items_per_minute > capacity {
+                return Err(ProductionBeltOverloaded {
+                    belt: self.belt.clone(),
+                    items_per_minute,
+                    capacity,
+                }
+                .into());
+            }
+        }
+
+        // from the middle of the machine southwards, then north of it but not on the pole
+        let inserter_rows: Vec<i32> = (size / 2..size)
+            .chain(0..size / 2)
+            .filter(|y| self.pole.is_none() || *y != 0)
+            .collect();
+        let (inserter, input_inserters, output_inserters) = self.inserters(
+            &prototypes,
+            inputs.values().sum::<f64>() / self.count as f64,
+            expected.values().sum::<f64>() / self.count as f64,
+            inserter_rows.len(),
+        )?;
+
+        let mut entities: Vec<FactorioEntity> = vec![];
+        for index in 0..self.count as i32 {
+            let row = top + index * size;
+            let mut machine = entity(
+                &self.machine,
+                (left + 2) as f64 + size as f64 / 2.,
+                row as f64 + size as f64 / 2.,
+                None,
+                None,
+                None,
+            )?;
+            machine.recipe = self.recipe.clone();
+            entities.push(machine);
+            // inserters drop eastwards, they are turned west
+            for (x, inserter_count) in [
+                (left + 1, input_inserters),
+                (left + 2 + size, output_inserters),
+            ] {
+                for y in inserter_rows.iter().take(inserter_count) {
+                    let inserter_y = (row + y) as f64 + 0.5;
+                    entities.push(entity(
+                        &inserter,
+                        x as f64 + 0.5,
+                        inserter_y,
+                        Some(Direction::West),
+                        Some(Position::new(x as f64 - 0.5, inserter_y)),
+                        Some(Position::new(x as f64 + 1.5, inserter_y)),
+                    )?);
+                }
+                if let Some(pole) = &self.pole {
+                    entities.push(entity(
+                        pole,
+                        x as f64 + 0.5,
+                        row as f64 + 0.5,
+                        None,
+                        None,
+                        None,
+                    )?);
+                }
+            }
+        }
+        for y in top..top + self.count as i32 * size {
+            for x in [left, output_x] {
+                entities.push(entity(
+                    &self.belt,
+                    x as f64 + 0.5,
+                    y as f64 + 0.5,
+                    Some(Direction::South),
+                    None,
+                    None,
+                )?);
+            }
+        }
+        let input_position = Position::new(left as f64 + 0.5, top as f64 + 0.5);
+        let output_position = Position::new(
+            output_x as f64 + 0.5,
+            (top + self.count as i32 * size) as f64 - 0.5,
+        );
+
+        let supply: FlowRates = inputs
+            .iter()
+            .map(|(name, items_per_minute)| (name.clone(), items_per_minute / 60.))
+            .collect();
+        let outputs = predicted_outputs(
+            prototypes.clone(),
+            recipes,
+            &entities,
+            &input_position,
+            supply,
+            &output_position,
+        )?;
+        for (item, expected) in &expected {
+            let predicted = outputs.get(item).copied().unwrap_or_default();
+            if (predicted - expected).abs() > 1e-6 {
+                return Err(ProductionValidationFailed {
+                    item: item.clone(),
+                    predicted,
+                    expected: *expected,
+                }
+                .into());
+            }
+        }
+        Ok(ProductionBlock {
+            blueprint: encode_blueprint(&prototypes, label, &entities, &[])?,
+            entities,
+            machine_count: self.count,
+            input_position,
+            output_position,
+            inputs,
+            outputs,
+        })
+    }
+}
+
+/// items per minute the machines put out which reach the end of the output belt,
+/// no more than their output inserters move
+fn predicted_outputs(
+    prototypes: Arc<DashMap<String, FactorioEntityPrototype>>,
+    recipes: Arc<DashMap<String, FactorioRecipe>>,
+    entities: &[FactorioEntity],
+    input_position: &Position,
+    supply: FlowRates,
+    output_position: &Position,
+) -> Result<BTreeMap<String, f64>> {
+    let entity_graph = Arc::new(EntityGraph::new(prototypes, recipes));
+    entity_graph.add(entities.to_vec(), None)?;
+    entity_graph.connect()?;
+    let flow_graph = FlowGraph::new(entity_graph.clone());
+    flow_graph.add_supply(input_position, supply);
+    flow_graph.update()?;
+
+    let mut outputs: BTreeMap<String, f64> = BTreeMap::new();
+    let Some(output_index) = entity_graph.node_at(output_position) else {
+        return Ok(outputs);
+    };
+    let inner = entity_graph.inner_graph();
+    let flow_inner = flow_graph.inner_graph();
+    for entity in entities.iter().filter(|entity| {
+        entity.entity_type == EntityType::Furnace.to_string()
+            || entity.entity_type == EntityType::AssemblingMachine.to_string()
+    }) {
+        let (Some(entity_index), Some(flow_index)) = (
+            entity_graph.node_at(&entity.position),
+            flow_graph.node_at(&entity.position),
+        ) else {
+            continue;
+        };
+        if !has_path_connecting(&*inner, entity_index, output_index, None) {
+            continue;
+        }
+        for edge in flow_inner.edges_directed(flow_index, petgraph::Direction::Outgoing) {
+            for (name, rate) in edge.weight().rates() {
+                *outputs.entry(name).or_default() += rate * 60.;
+            }
+        }
+    }
+    Ok(outputs)
+}
+
+fn is_burner(prototypes: &DashMap<String, FactorioEntityPrototype>, name: &str) -> bool {
+    match prototypes
+        .get(name)
+        .and_then(|prototype| prototype.energy_source.clone())
+    {
+        Some(energy_source) => energy_source == "burner",
+        None => BURNER_ENTITIES.contains(&name),
+    }
+}
+
+fn crafting_speed(prototypes: &DashMap<String, FactorioEntityPrototype>, name: &str) -> f64 {
+    prototypes
+        .get(name)
+        .and_then(|prototype| prototype.crafting_speed)
+        .unwrap_or(1.)
+}
+
+/// items per minute on both lanes
+fn belt_capacity(belt: &str) -> f64 {
+    match belt {
+        "fast-transport-belt" => 1800.,
+        "express-transport-belt" => 2700.,
+        "turbo-transport-belt" => 3600.,
+        _ => 900.,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{fixture_entity_prototypes, fixture_recipes};
+
+    #[test]
+    fn test_production_blocks() {
+        let prototypes = Arc::new(fixture_entity_prototypes());
+        let recipes = Arc::new(fixture_recipes());
+        let count = |block: &ProductionBlock, entity_type: &str| {
+            block
+                .entities
+                .iter()
+                .filter(|entity| entity.entity_type == entity_type)
+                .count()
+        };
+
+        let column = smelting_column(
+            prototypes.clone(),
+            recipes.clone(),
+            &Position::new(10., 20.),
+            &SmeltingColumnOptions {
+                input: EntityName::IronOre.to_string(),
+                count: 4,
+                furnace: None,
+                belt: None,
+                inserter: None,
+                pole: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(column.machine_count, 4);
+        assert_eq!(count(&column, "furnace"), 4);
+        assert_eq!(count(&column, "inserter"), 8);
+        assert_eq!(count(&column, "transport-belt"), 16);
+        assert_eq!(column.input_position, Position::new(10.5, 20.5));
+        assert_eq!(column.output_position, Position::new(15.5, 27.5));
+        assert_eq!(column.outputs["iron-plate"], 75.);
+        assert_eq!(column.inputs["iron-ore"], 75.);
+        assert!((column.inputs["coal"] - 5.4).abs() < 1e-9);
+
+        let options = AssemblerLineOptions {
+            recipe: "iron-gear-wheel".into(),
+            items_per_minute: 200.,
+            machine: None,
+            belt: None,
+            inserter: None,
+            pole: None,
+        };
+        let line = assembler_line(
+            prototypes.clone(),
+            recipes.clone(),
+            &Position::new(0., 0.),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(line.machine_count, 3);
+        assert_eq!(count(&line, "assembling-machine"), 3);
+        // 180 plates per minute and machine need two fast inserters, one takes the gears
+        assert_eq!(count(&line, "inserter"), 9);
+        assert!(line
+            .entities
+            .iter()
+            .filter(|entity| entity.entity_type == "inserter")
+            .all(|entity| entity.name == "fast-inserter"));
+        assert_eq!(line.outputs["iron-gear-wheel"], 270.);
+        assert_eq!(line.inputs["iron-plate"], 540.);
+
+        let slow_inserters = assembler_line(
+            prototypes.clone(),
+            recipes.clone(),
+            &Position::new(0., 0.),
+            &AssemblerLineOptions {
+                inserter: Some(EntityName::Inserter.to_string()),
+                ..options.clone()
+            },
+        );
+        assert!(slow_inserters.is_err());
+
+        let overloaded = assembler_line(
+            prototypes,
+            recipes,
+            &Position::new(0., 0.),
+            &AssemblerLineOptions {
+                items_per_minute: 600.,
+                ..options
+            },
+        );
+        assert!(overloaded.is_err());
+    }
+
+    #[test]
+    fn test_inserter_bottleneck() {
+        let prototypes = Arc::new(fixture_entity_prototypes());
+        let recipes = Arc::new(fixture_recipes());
+        let line = assembler_line(
+            prototypes.clone(),
+            recipes.clone(),
+            &Position::new(0., 0.),
+            &AssemblerLineOptions {
+                recipe: "iron-gear-wheel".into(),
+                items_per_minute: 90.,
+                machine: None,
+                belt: None,
+                inserter: None,
+                pole: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(line.outputs["iron-gear-wheel"], 90.);
+
+        // a basic inserter swings 0.014 turns per tick, 50.4 gears per minute
+        let mut entities = line.entities.clone();
+        for entity in &mut entities {
+            if entity.entity_type == "inserter" {
+                entity.name = EntityName::Inserter.to_string();
+            }
+        }
+        let supply: FlowRates = line
+            .inputs
+            .iter()
+            .map(|(name, items_per_minute)| (name.clone(), items_per_minute / 60.))
+            .collect();
+        let outputs = predicted_outputs(
+            prototypes,
+            recipes,
+            &entities,
+            &line.input_position,
+            supply,
+            &line.output_position,
+        )
+        .unwrap();
+        assert!((outputs["iron-gear-wheel"] - 50.4).abs() < 1e-9);
+    }
+}
@@ -1,19 +1,24 @@
+use crate::constants::DEFAULT_QUALITY;
 use crate::errors::{
-    RconError, RconNoWaterFound, RconPlayerBlockesAllPlacement, RconPlayerBlockesPlacement,
-    RconPlayerNotFound, RconRadiusLimitReached, RconTimeout, RconUnexpectedEmptyResponse,
-    RconUnexpectedOutput,
+    RconError, RconEvalFailed, RconNoWaterFound, RconPlayerBlockesAllPlacement,
+    RconPlayerBlockesPlacement, RconPlayerNotFound, RconRadiusLimitReached, RconTimeout,
+    RconUnexpectedEmptyResponse, RconUnexpectedOutput,
 };
+use crate::factorio::rcon_lanes::{RconLaneMetrics, RconLanes, RconPriority};
+use crate::factorio::rcon_trace::{RconRecorder, RconReplayer};
+use crate::factorio::router::{LogisticsRouter, RouteEntities, RouteRequest};
 use crate::factorio::util::{
-    blueprint_build_area, build_entity_path, calculate_distance, hashmap_to_lua, map_blocked_tiles,
-    move_pos, move_position, position_to_lua, rect_to_lua, span_rect, str_to_lua, value_to_lua,
-    vec_to_lua, vector_add, vector_multiply, vector_normalize, vector_substract,
+    blueprint_build_area, build_entity_path, calculate_distance, expand_rect, hashmap_to_lua,
+    map_blocked_tiles, move_pos, move_position, position_to_lua, rect_to_lua, span_rect,
+    str_to_lua, surface_to_lua, value_to_lua, vec_to_lua, vector_add, vector_multiply,
+    vector_normalize, vector_substract,
 };
 use crate::factorio::world::FactorioWorld;
 use crate::num_traits::FromPrimitive;
 use crate::settings::FactorioSettings;
 use crate::types::{
-    ActionId, AreaFilter, Direction, FactorioEntity, FactorioForce, FactorioTile,
-    InventoryResponse, PlayerId, Pos, Position, Rect, RequestEntity,
+    ActionId, AreaFilter, Direction, EntityDiff, FactorioEntity, FactorioForce, FactorioTile,
+    InventoryResponse, PlayerId, Pos, Position, Rect, RequestEntity, ThroughputSampling,
 };
 use miette::{IntoDiagnostic, Result};
 use paris::info;
@@ -30,9 +35,38 @@ use unicode_segmentation::UnicodeSegmentation;
 
 const RCON_INTERFACE: &str = "botbridge";
 
+/// Wraps snippet so its return value is always printed as `{"result": ...}` or `{"error": "..."}`
+fn eval_command(lua_snippet: &str) -> String {
+    format!(
+        "/silent-command local ok, json = pcall(function() \
+         return helpers.table_to_json({{result = (function() {}\n end)()}}) end) \
+         rcon.print(ok and json or helpers.table_to_json({{error = tostring(json)}}))",
+        lua_snippet
+    )
+}
+
+fn parse_eval_result(json: &str) -> Result<Value> {
+    let mut value: Value = serde_json::from_str(json).map_err(|_| RconUnexpectedOutput {
+        output: json.to_owned(),
+    })?;
+    if let Some(error) = value.get("error") {
+        return Err(RconEvalFailed {
+            message: error.as_str().unwrap_or_default().to_owned(),
+        }
+        .into());
+    }
+    Ok(value
+        .get_mut("result")
+        .map(Value::take)
+        .unwrap_or(Value::Null))
+}
+
 pub struct FactorioRcon {
     pool: Option<bb8::Pool<ConnectionManager>>,
     silent: Arc<RwLock<bool>>,
+    lanes: RconLanes,
+    recorder: RwLock<Option<Arc<RconRecorder>>>,
+    replayer: Option<Arc<RconReplayer>>,
 }
 
 #[cfg_attr(test, mockall::automock)]
@@ -44,43 +78,91 @@ impl FactorioRcon {
             settings.port
         );
         let manager = ConnectionManager::new(&address, &settings.pass);
+        let lanes = RconLanes::default();
         Ok(FactorioRcon {
             pool: Some(
                 bb8::Pool::builder()
-                    .max_size(15)
+                    .max_size(lanes.pool_size())
                     .build(manager)
                     .await
                     .into_diagnostic()?,
             ),
             silent,
+            lanes,
+            recorder: RwLock::new(None),
+            replayer: None,
         })
     }
 
+    /// Create a FactorioRcon instance which answers all commands from a recorded trace
+    pub fn new_replay(replayer: Arc<RconReplayer>, silent: Arc<RwLock<bool>>) -> Self {
+        FactorioRcon {
+            pool: None,
+            silent,
+            lanes: RconLanes::default(),
+            recorder: RwLock::new(None),
+            replayer: Some(replayer),
+        }
+    }
+
+    /// Queueing metrics of every priority class
+    pub fn lane_metrics(&self) -> Vec<RconLaneMetrics> {
+        self.lanes.metrics()
+    }
+
+    /// Record all following commands and their responses to given trace
+    pub fn record_to(&self, recorder: Arc<RconRecorder>) {
+        *self.recorder.write() = Some(recorder);
+    }
+
     /// Create a FactorioRcon instance without any connection which would fail if used
     /// Why? because LuaRconBuilder requires FactorioRcon which i didnt want to change to an option.
     pub fn new_empty() -> Self {
         FactorioRcon {
             pool: None,
             silent: Arc::new(RwLock::new(true)),
+            lanes: RconLanes::default(),
+            recorder: RwLock::new(None),
+            replayer: None,
         }
     }
 
-    /// Executes a couple of commands that need to be send to a newly started factorio server
-    pub async fn initialize_server(&self) -> Result<()> {
+    /// Executes a couple of commands that need to be send to a newly started factorio server.
+    /// With `skip_discovery` the chunks are not written out, the world was loaded from a snapshot.
+    pub async fn initialize_server(&self, skip_discovery: bool) -> Result<()> {
         self.silent_print("").await.expect("failed to silent print");
-        self.whoami("server").await.expect("failed to whoami");
+        if skip_discovery {
+            self.remote_call("whoami", vec![str_to_lua("server"), String::from("true")])
+                .await
+                .expect("failed to whoami");
+        } else {
+            self.whoami("server").await.expect("failed to whoami");
+        }
         self.send("/silent-command game.surfaces[1].always_day=true")
             .await
             .expect("always day");
         Ok(())
     }
 
-    /// Sends raw command to factorio server
+    /// Sends raw command to factorio server as query
     pub async fn send(&self, command: &str) -> Result<Option<Vec<String>>> {
+        self.send_with_priority(command, RconPriority::Query).await
+    }
+
+    /// Sends raw command to factorio server once a connection of given priority class is free
+    pub async fn send_with_priority(
+        &self,
+        command: &str,
+        priority: RconPriority,
+    ) -> Result<Option<Vec<String>>> {
         let silent = *self.silent.read();
         if !silent {
             info!("<cyan>rcon</>  ⮜ <green>{}</>", command);
         }
+        if let Some(replayer) = self.replayer.as_ref() {
+            return replayer.send(command);
+        }
+        let permit = self.lanes.acquire(priority).await;
         // let started = Instant::now();
         let mut conn = self.pool.as_ref().unwrap().get().await.into_diagnostic()?;
         let result = conn
@@ -88,23 +170,28 @@ impl FactorioRcon {
             .await
             .into_diagnostic()?;
         drop(conn);
+        drop(permit);
         // info!("send took {} ms", started.elapsed().as_millis());
-        if !result.is_empty() {
+        let response = if !result.is_empty() {
             if !silent {
                 info!(
                     "<cyan>rcon</>  ⮞ <green>{}</>",
                     &result[0..result.len() - 1]
                 );
             }
-            Ok(Some(
+            Some(
                 result[0..result.len() - 1]
                     .split('\n')
                     .map(|str| str.to_owned())
                     .collect(),
-            ))
+            )
         } else {
-            Ok(None)
+            None
+        };
+        if let Some(recorder) = self.recorder.read().as_ref() {
+            recorder.record_command(command, &response);
         }
+        Ok(response)
     }
 
     /// Calls a lua function exported by BotBridge
@@ -112,22 +199,42 @@ impl FactorioRcon {
         &self,
         function_name: &str,
         args: Vec<String>,
+    ) -> Result<Option<Vec<String>>> {
+        self.remote_call_with_priority(
+            function_name,
+            args,
+            RconPriority::of_remote_call(function_name),
+        )
+        .await
+    }
+
+    async fn remote_call_with_priority(
+        &self,
+        function_name: &str,
+        args: Vec<String>,
+        priority: RconPriority,
     ) -> Result<Option<Vec<String>>> {
         let mut arg_string: String = args.join(", ");
         if !arg_string.is_empty() {
             arg_string = String::from(", ") + &arg_string;
         }
-        self.send(&format!(
-            "/silent-command remote.call('{}', '{}'{})",
-            RCON_INTERFACE, function_name, arg_string
-        ))
+        self.send_with_priority(
+            &format!(
+                "/silent-command remote.call('{}', '{}'{})",
+                RCON_INTERFACE, function_name, arg_string
+            ),
+            priority,
+        )
         .await
     }
 
     /// Take a screenshot -> but where?
     pub async fn screenshot(&self, width: i16, height: i16, depth: i8) -> Result<()> {
-        self.send(&format!("/screenshot {} {} {}", width, height, depth))
-            .await?;
+        self.send_with_priority(
+            &format!("/screenshot {} {} {}", width, height, depth),
+            RconPriority::Bulk,
+        )
+        .await?;
         Ok(())
     }
 
@@ -147,7 +254,8 @@ impl FactorioRcon {
 
     /// Save the current game on server
     pub async fn server_save(&self) -> Result<()> {
-        self.send("/server-save").await?;
+        self.send_with_priority("/server-save", RconPriority::Bulk)
+            .await?;
         Ok(())
     }
 
@@ -188,12 +296,13 @@ impl FactorioRcon {
         Ok(())
     }
 
-    /// Cheats in an Item in given quantity to given player
+    /// Cheats in an Item of given quality (normal if None) in given quantity to given player
     pub async fn cheat_item(
         &self,
         player_id: PlayerId,
         item_name: &str,
         item_count: u32,
+        quality: Option<String>,
     ) -> Result<()> {
         self.remote_call(
             "cheat_item",
@@ -201,6 +310,7 @@ impl FactorioRcon {
                 player_id.to_string(),
                 str_to_lua(item_name),
                 item_count.to_string(),
+                str_to_lua(quality.as_deref().unwrap_or(DEFAULT_QUALITY)),
             ],
         )
         .await?;
@@ -237,6 +347,7 @@ impl FactorioRcon {
         let player = player.unwrap();
         let distance = calculate_distance(&player.position, position);
         let build_distance = player.build_distance as f64;
+        let player_surface = player.surface.clone();
         drop(player); // wow, without this factorio (?) freezes (!)
         if distance > build_distance {
             warn!("too far away, moving first!");
@@ -245,7 +356,7 @@ impl FactorioRcon {
         }
         // TODO: move inventory players close too
 
-        let build_area = blueprint_build_area(world.entity_prototypes.clone(), &blueprint);
+        let build_area = blueprint_build_area(world.entity_prototypes.clone(), &blueprint)?;
         let width_2 = build_area.width() / 2.0;
         let height_2 = build_area.height() / 2.0;
         let build_area = Rect {
@@ -253,7 +364,12 @@ impl FactorioRcon {
             right_bottom: Position::new(position.x() + width_2, position.y() + height_2),
         };
         let build_area_entities = self
-            .find_entities_filtered(&AreaFilter::Rect(build_area.clone()), None, None)
+            .find_entities_filtered(
+                &AreaFilter::Rect(build_area.clone()),
+                None,
+                None,
+                player_surface,
+            )
             .await?;
 
         for entity in build_area_entities {
@@ -403,6 +519,19 @@ impl FactorioRcon {
         Ok(Some(serde_json::from_str(json.as_str()).into_diagnostic()?))
     }
 
+    /// Evaluates a lua snippet on the server and returns its json serialized result.
+    ///
+    /// The snippet is used as function body, so it has to `return` a value to get
+    /// anything back. Errors raised while running the snippet or serializing its
+    /// result are returned as `RconEvalFailed`.
+    pub async fn eval(&self, lua_snippet: &str) -> Result<Value> {
+        let lines = self.send(&eval_command(lua_snippet)).await?;
+        match lines.and_then(|mut lines| lines.pop()) {
+            Some(json) => parse_eval_result(&json),
+            None => Err(RconUnexpectedEmptyResponse {}.into()),
+        }
+    }
+
     async fn sleep_for_action_result(
         &self,
         world: &Arc<FactorioWorld>,
@@ -519,6 +648,7 @@ impl FactorioRcon {
     pub async fn inventory_contents_at(
         &self,
         entities: Vec<RequestEntity>,
+        surface: Option<String>,
     ) -> Result<Vec<Option<InventoryResponse>>> {
         let positions: Vec<String> = entities
             .into_iter()
@@ -537,7 +667,10 @@ impl FactorioRcon {
             .collect();
 
         let lines = self
-            .remote_call("inventory_contents_at", vec![vec_to_lua(positions)])
+            .remote_call(
+                "inventory_contents_at",
+                vec![vec_to_lua(positions), surface_to_lua(&surface)],
+            )
             .await?;
         if lines.is_none() {
             return Err(RconUnexpectedEmptyResponse {}.into());
@@ -565,6 +698,7 @@ impl FactorioRcon {
         item_name: String,
         entity_position: Position,
         direction: u8,
+        quality: Option<String>,
         world: &Arc<FactorioWorld>,
     ) -> Result<FactorioEntity> {
         let player = world.players.get(&player_id);
@@ -573,6 +707,7 @@ impl FactorioRcon {
         }
         let player = player.unwrap();
         let player_position = player.position.clone();
+        let player_surface = player.surface.clone();
         let build_distance = player.build_distance as f64;
         drop(player); // wow, without this factorio (?) freezes (!)
         let distance = calculate_distance(&player_position, &entity_position);
@@ -589,6 +724,7 @@ impl FactorioRcon {
                     str_to_lua(&item_name),
                     position_to_lua(&entity_position),
                     direction.to_string(),
+                    str_to_lua(quality.as_deref().unwrap_or(DEFAULT_QUALITY)),
                 ],
             )
             .await?;
@@ -612,10 +748,10 @@ impl FactorioRcon {
                             5.0,
                         );
                         if self
-                            .is_area_empty(&AreaFilter::PositionRadius((
-                                test_position.clone(),
-                                Some(2.0),
-                            )))
+                            .is_area_empty(
+                                &AreaFilter::PositionRadius((test_position.clone(), Some(2.0))),
+                                player_surface.clone(),
+                            )
                             .await?
                         {
                             self.move_player(world, player_id, &test_position, Some(1.0))
@@ -678,6 +814,7 @@ impl FactorioRcon {
         inventory_type: u32,
         item_name: String,
         item_count: u32,
+        quality: Option<String>,
         world: &Arc<FactorioWorld>,
     ) -> Result<()> {
         let player = world.players.get(&player_id);
@@ -698,6 +835,10 @@ impl FactorioRcon {
         let mut items: HashMap<String, String> = HashMap::new();
         items.insert(String::from("name"), str_to_lua(&item_name));
         items.insert(String::from("count"), item_count.to_string());
+        items.insert(
+            String::from("quality"),
+            str_to_lua(quality.as_deref().unwrap_or(DEFAULT_QUALITY)),
+        );
         let lines = self
             .remote_call(
                 "insert_to_inventory",
@@ -728,6 +869,7 @@ impl FactorioRcon {
         inventory_type: u32,
         item_name: String,
         item_count: u32,
+        quality: Option<String>,
         world: &Arc<FactorioWorld>,
     ) -> Result<()> {
         let player = world.players.get(&player_id);
@@ -747,6 +889,10 @@ impl FactorioRcon {
         let mut items: HashMap<String, String> = HashMap::new();
         items.insert(String::from("name"), str_to_lua(&item_name));
         items.insert(String::from("count"), item_count.to_string());
+        items.insert(
+            String::from("quality"),
+            str_to_lua(quality.as_deref().unwrap_or(DEFAULT_QUALITY)),
+        );
         let lines = self
             .remote_call(
                 "remove_from_inventory",
@@ -768,12 +914,18 @@ impl FactorioRcon {
         Ok(())
     }
 
-    pub async fn is_area_empty(&self, area_filter: &AreaFilter) -> Result<bool> {
-        let entities = self.find_entities_filtered(area_filter, None, None).await?;
+    pub async fn is_area_empty(
+        &self,
+        area_filter: &AreaFilter,
+        surface: Option<String>,
+    ) -> Result<bool> {
+        let entities = self
+            .find_entities_filtered(area_filter, None, None, surface.clone())
+            .await?;
         if !entities.is_empty() {
             return Ok(false);
         }
-        let tiles = self.find_tiles_filtered(area_filter, None).await?;
+        let tiles = self.find_tiles_filtered(area_filter, None, surface).await?;
         for tile in tiles {
             if tile.player_collidable {
                 return Ok(false);
@@ -805,6 +957,7 @@ impl FactorioRcon {
         area_filter: &AreaFilter,
         search_name: Option<String>,
         search_type: Option<String>,
+        surface: Option<String>,
     ) -> Result<Vec<FactorioEntity>> {
         let mut args: HashMap<String, String> = HashMap::new();
         match area_filter {
@@ -827,8 +980,15 @@ impl FactorioRcon {
         if let Some(entity_type) = search_type {
             args.insert(String::from("type"), str_to_lua(&entity_type));
         }
+        if let Some(surface) = surface {
+            args.insert(String::from("surface"), str_to_lua(&surface));
+        }
         let result = self
-            .remote_call("find_entities_filtered", vec![hashmap_to_lua(args)])
+            .remote_call_with_priority(
+                "find_entities_filtered",
+                vec![hashmap_to_lua(args)],
+                RconPriority::of_area_filter(area_filter),
+            )
             .await?;
         if result.is_none() {
             return Err(RconUnexpectedEmptyResponse {}.into());
@@ -864,6 +1024,7 @@ impl FactorioRcon {
         &self,
         area_filter: &AreaFilter,
         name: Option<String>,
+        surface: Option<String>,
     ) -> Result<Vec<FactorioTile>> {
         let mut args: HashMap<String, String> = HashMap::new();
         match area_filter {
@@ -883,8 +1044,15 @@ impl FactorioRcon {
         if let Some(name) = name {
             args.insert(String::from("name"), str_to_lua(&name));
         }
+        if let Some(surface) = surface {
+            args.insert(String::from("surface"), str_to_lua(&surface));
+        }
         let result = self
-            .remote_call("find_tiles_filtered", vec![hashmap_to_lua(args)])
+            .remote_call_with_priority(
+                "find_tiles_filtered",
+                vec![hashmap_to_lua(args)],
+                RconPriority::of_area_filter(area_filter),
+            )
             .await?;
         if result.is_none() {
             return Err(RconUnexpectedEmptyResponse {}.into());
@@ -897,6 +1065,70 @@ impl FactorioRcon {
         serde_json::from_str(json.as_str()).into_diagnostic()
     }
 
+    /// Scans `rect` again and replaces everything the world knows about it,
+    /// returns which entities were added, removed or changed since the last scan.
+    pub async fn rescan(
+        &self,
+        world: &Arc<FactorioWorld>,
+        rect: &Rect,
+        surface: Option<String>,
+    ) -> Result<EntityDiff> {
+        let area_filter = AreaFilter::Rect(rect.clone());
+        let entities = self
+            .find_entities_filtered(&area_filter, None, None, surface.clone())
+            .await?;
+        let tiles = self
+            .find_tiles_filtered(&area_filter, None, surface.clone())
+            .await?;
+        world.rescan(surface.as_deref(), rect, entities, tiles)
+    }
+
+    /// Counts the items belts, splitters and inserters in `rect` move during `seconds`
+    /// and attaches the measured throughput to the flow graph of the surface.
+    pub async fn sample_throughput(
+        &self,
+        world: &Arc<FactorioWorld>,
+        rect: &Rect,
+        seconds: u32,
+        surface: Option<String>,
+    ) -> Result<ThroughputSampling> {
+        let ticks = seconds.max(1) * 60;
+        let surface_arg = surface_to_lua(&surface);
+        let result = self
+            .remote_call(
+                "async_sample_throughput",
+                vec![rect_to_lua(rect), ticks.to_string(), surface_arg],
+            )
+            .await?;
+        if result.is_none() {
+            return Err(RconUnexpectedEmptyResponse {}.into());
+        }
+        let result = result.unwrap().pop().unwrap();
+        let request_id: u32 = match result.parse() {
+            Ok(request_id) => request_id,
+            Err(_) => return Err(RconError { message: result }.into()),
+        };
+        let wait_start = Instant::now();
+        let timeout = Duration::from_secs(seconds as u64 + 30);
+        let json = loop {
+            sleep(Duration::from_millis(50)).await;
+            if let Some((_, json)) = world.throughput_samplings.remove(&request_id) {
+                break json;
+            }
+            if wait_start.elapsed() > timeout {
+                return Err(RconTimeout {}.into());
+            }
+        };
+        let mut sampling: ThroughputSampling =
+            serde_json::from_str(json.as_str()).into_diagnostic()?;
+        sampling.surface = surface;
+        world
+            .surface(sampling.surface.as_deref())
+            .flow_graph
+            .record_throughput(&sampling);
+        Ok(sampling)
+    }
+
     async fn async_request_player_path(
         &self,
         player_id: PlayerId,
@@ -928,6 +1160,7 @@ impl FactorioRcon {
         start: &Position,
         goal: &Position,
         radius: Option<f64>,
+        surface: &Option<String>,
     ) -> Result<u32> {
         let radius = match radius {
             Some(radius) => radius.to_string(),
@@ -936,7 +1169,12 @@ impl FactorioRcon {
         let result = self
             .remote_call(
                 "async_request_path",
-                vec![position_to_lua(start), position_to_lua(goal), radius],
+                vec![
+                    position_to_lua(start),
+                    position_to_lua(goal),
+                    radius,
+                    surface_to_lua(surface),
+                ],
             )
             .await?;
         if result.is_none() {
@@ -969,10 +1207,24 @@ impl FactorioRcon {
         goal: &Position,
         radius: Option<f64>,
     ) -> Result<Vec<Position>> {
+        let (start, surface) = match world.players.get(&player_id) {
+            Some(player) => (Some(player.position.clone()), player.surface.clone()),
+            None => (None, None),
+        };
+        if let Some(start) = start {
+            match world.walk_path(surface.as_deref(), &start, goal, radius) {
+                Ok(path) => return Ok(path),
+                Err(err) => warn!("{}, asking factorio", err),
+            }
+        }
         let id = self
             .async_request_player_path(player_id, goal, radius)
             .await?;
-        match self.sleep_for_path_request_result(world, id).await {
+        match self
+            .sleep_for_path_request_result(world, id)
+            .await
+            .and_then(|path| world.avoid_danger(surface.as_deref(), path))
+        {
             Ok(path) => Ok(path),
             Err(err) => {
                 warn!(
@@ -994,7 +1246,11 @@ impl FactorioRcon {
                     let id = self
                         .async_request_player_path(player_id, &new_goal, radius)
                         .await?;
-                    if let Ok(result) = self.sleep_for_path_request_result(world, id).await {
+                    if let Ok(result) = self
+                        .sleep_for_path_request_result(world, id)
+                        .await
+                        .and_then(|path| world.avoid_danger(surface.as_deref(), path))
+                    {
                         return Ok(result);
                     }
                     direction = direction.rotate_clockwise();
@@ -1010,9 +1266,20 @@ impl FactorioRcon {
         start: &Position,
         goal: &Position,
         radius: Option<f64>,
+        surface: Option<String>,
     ) -> Result<Vec<Position>> {
-        let id = self.async_request_path(start, goal, radius).await?;
-        match self.sleep_for_path_request_result(world, id).await {
+        match world.walk_path(surface.as_deref(), start, goal, radius) {
+            Ok(path) => return Ok(path),
+            Err(err) => warn!("{}, asking factorio", err),
+        }
+        let id = self
+            .async_request_path(start, goal, radius, &surface)
+            .await?;
+        match self
+            .sleep_for_path_request_result(world, id)
+            .await
+            .and_then(|path| world.avoid_danger(surface.as_deref(), path))
+        {
             Ok(path) => Ok(path),
             Err(err) => {
                 warn!(
@@ -1030,8 +1297,14 @@ impl FactorioRcon {
                     let new_goal =
                         vector_add(goal, &vector_multiply(&direction, radius.unwrap_or(10.0)));
 
-                    let id = self.async_request_path(start, &new_goal, radius).await?;
-                    if let Ok(result) = self.sleep_for_path_request_result(world, id).await {
+                    let id = self
+                        .async_request_path(start, &new_goal, radius, &surface)
+                        .await?;
+                    if let Ok(result) = self
+                        .sleep_for_path_request_result(world, id)
+                        .await
+                        .and_then(|path| world.avoid_danger(surface.as_deref(), path))
+                    {
                         return Ok(result);
                     }
                     direction = direction.rotate_clockwise();
@@ -1113,13 +1386,19 @@ impl FactorioRcon {
         from_position: &Position,
         to_position: &Position,
         to_direction: Direction,
+        surface: Option<String>,
     ) -> Result<Vec<FactorioEntity>> {
         let build_rect = span_rect(from_position, to_position, 20.0);
         let entities = self
-            .find_entities_filtered(&AreaFilter::Rect(build_rect.clone()), None, None)
+            .find_entities_filtered(
+                &AreaFilter::Rect(build_rect.clone()),
+                None,
+                None,
+                surface.clone(),
+            )
             .await?;
         let tiles = self
-            .find_tiles_filtered(&AreaFilter::Rect(build_rect), Some("water".into()))
+            .find_tiles_filtered(&AreaFilter::Rect(build_rect), Some("water".into()), surface)
             .await?;
 
         build_entity_path(
@@ -1137,6 +1416,54 @@ impl FactorioRcon {
         )
     }
 
+    /// Routes belt or pipe lines in parallel around the entities and water of the area,
+    /// see `LogisticsRouter`
+    pub async fn plan_routes(
+        &self,
+        world: &Arc<FactorioWorld>,
+        entity_name: &str,
+        underground_entity_name: &str,
+        requests: &[RouteRequest],
+        reserved_zones: &[Rect],
+        surface: Option<String>,
+    ) -> Result<Vec<Vec<FactorioEntity>>> {
+        let mut build_rect: Option<Rect> = None;
+        for request in requests {
+            let rect = span_rect(&request.from.position, &request.to.position, 20.0);
+            match build_rect.as_mut() {
+                Some(build_rect) => expand_rect(build_rect, &rect),
+                None => build_rect = Some(rect),
+            }
+        }
+        let Some(build_rect) = build_rect else {
+            return Ok(vec![]);
+        };
+        let entities = self
+            .find_entities_filtered(
+                &AreaFilter::Rect(build_rect.clone()),
+                None,
+                None,
+                surface.clone(),
+            )
+            .await?;
+        let tiles = self
+            .find_tiles_filtered(&AreaFilter::Rect(build_rect), Some("water".into()), surface)
+            .await?;
+        let route_entities = RouteEntities::new(
+            &world.entity_prototypes,
+            entity_name,
+            underground_entity_name,
+        );
+        LogisticsRouter::new(
+            world.entity_prototypes.clone(),
+            route_entities,
+            &entities,
+            &tiles,
+        )
+        .with_reserved_zones(reserved_zones)
+        .route_bus(requests)
+    }
+
     pub async fn action_start_crafting(
         &self,
         action_id: ActionId,
@@ -1166,6 +1493,7 @@ impl FactorioRcon {
         world: &Arc<FactorioWorld>,
         search_center: Position,
         pump_direction: Direction,
+        surface: Option<String>,
     ) -> Result<Vec<Pos>> {
         for radius in 3..10 {
             let tiles = self
@@ -1175,6 +1503,7 @@ impl FactorioRcon {
                         Some((radius * 100) as f64),
                     )),
                     Some("water".into()),
+                    surface.clone(),
                 )
                 .await?;
             if tiles.is_empty() {
@@ -1282,3 +1611,32 @@ impl RconSettings {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_eval_command() {
+        assert_eq!(
+            eval_command("return game.tick"),
+            "/silent-command local ok, json = pcall(function() \
+             return helpers.table_to_json({result = (function() return game.tick\n end)()}) end) \
+             rcon.print(ok and json or helpers.table_to_json({error = tostring(json)}))"
+        );
+    }
+
+    #[test]
+    fn test_parse_eval_result() {
+        assert_eq!(parse_eval_result(r#"{"result":42}"#).unwrap(), json!(42));
+        assert_eq!(
+            parse_eval_result(r#"{"result":{"a":[1,2]}}"#).unwrap(),
+            json!({"a": [1, 2]})
+        );
+        // nil results drop the key entirely
+        assert_eq!(parse_eval_result("{}").unwrap(), Value::Null);
+        assert!(parse_eval_result(r#"{"error":"attempt to index nil"}"#).is_err());
+        assert!(parse_eval_result("not json").is_err());
+    }
+}
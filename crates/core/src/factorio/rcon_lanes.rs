@@ -0,0 +1,255 @@
+use crate::types::AreaFilter;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Instant;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// 4x4 chunks
+const BULK_SCAN_AREA: f64 = 128.0 * 128.0;
+
+/// Priority class of a rcon command, every class gets its own share of connections
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema, EnumIter, Display,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum RconPriority {
+    /// bot actions like walking, mining, crafting or placing which are waited on
+    Realtime,
+    /// small queries and everything without an explicit class
+    Query,
+    /// long running scans over large areas, saves and screenshots
+    Bulk,
+}
+
+impl RconPriority {
+    /// Priority of a BotBridge remote call by function name
+    pub fn of_remote_call(function_name: &str) -> RconPriority {
+        match function_name {
+            "action_start_walk_waypoints"
+            | "action_start_mining"
+            | "action_start_crafting"
+            | "async_request_player_path"
+            | "async_request_path"
+            | "place_entity"
+            | "insert_to_inventory"
+            | "remove_from_inventory"
+            | "revive_ghost" => RconPriority::Realtime,
+            "place_blueprint" | "cheat_blueprint" | "parse_map_exchange_string" => {
+                RconPriority::Bulk
+            }
+            _ => RconPriority::Query,
+        }
+    }
+
+    /// Searches covering more than a few chunks are scans, smaller ones are queries
+    pub fn of_area_filter(area_filter: &AreaFilter) -> RconPriority {
+        let area = match area_filter {
+            AreaFilter::Rect(rect) => rect.width() * rect.height(),
+            AreaFilter::PositionRadius((_, Some(radius))) => 4.0 * radius * radius,
+            AreaFilter::PositionRadius((_, None)) => 0.0,
+        };
+        if area > BULK_SCAN_AREA {
+            RconPriority::Bulk
+        } else {
+            RconPriority::Query
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            RconPriority::Realtime => 0,
+            RconPriority::Query => 1,
+            RconPriority::Bulk => 2,
+        }
+    }
+}
+
+/// Snapshot of queueing metrics of one priority class
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RconLaneMetrics {
+    pub priority: RconPriority,
+    /// max number of commands of this class in flight at once
+    pub limit: usize,
+    pub in_flight: usize,
+    /// commands currently waiting for a free slot
+    pub queued: usize,
+    pub sent: u64,
+    pub total_wait_ms: u64,
+    pub max_wait_ms: u64,
+}
+
+struct RconLane {
+    limit: usize,
+    semaphore: Semaphore,
+    queued: AtomicUsize,
+    sent: AtomicU64,
+    total_wait_ms: AtomicU64,
+    max_wait_ms: AtomicU64,
+}
+
+impl RconLane {
+    fn new(limit: usize) -> RconLane {
+        RconLane {
+            limit,
+            semaphore: Semaphore::new(limit),
+            queued: AtomicUsize::new(0),
+            sent: AtomicU64::new(0),
+            total_wait_ms: AtomicU64::new(0),
+            max_wait_ms: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Counts a waiting command, also when the waiting future gets dropped
+struct QueuedGuard<'a>(&'a AtomicUsize);
+
+impl<'a> QueuedGuard<'a> {
+    fn new(queued: &'a AtomicUsize) -> Self {
+        queued.fetch_add(1, Ordering::SeqCst);
+        QueuedGuard(queued)
+    }
+}
+
+impl Drop for QueuedGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Per class concurrency limits in front of the rcon connection pool.
+///
+/// The limits add up to the pool size, so commands of one class can never take
+/// connections reserved for another class: a long running world scan may only
+/// delay other scans but never bot actions.
+pub struct RconLanes {
+    lanes: [RconLane; 3],
+}
+
+impl RconLanes {
+    pub fn new(realtime_limit: usize, query_limit: usize, bulk_limit: usize) -> RconLanes {
+        RconLanes {
+            lanes: [
+                RconLane::new(realtime_limit),
+                RconLane::new(query_limit),
+                RconLane::new(bulk_limit),
+            ],
+        }
+    }
+
+    /// Number of connections needed to serve all lanes at once
+    pub fn pool_size(&self) -> u32 {
+        self.lanes.iter().map(|lane| lane.limit as u32).sum()
+    }
+
+    /// Waits for a free slot in the lane of given priority
+    pub async fn acquire(&self, priority: RconPriority) -> SemaphorePermit<'_> {
+        let lane = &self.lanes[priority.index()];
+        let started = Instant::now();
+        let queued = QueuedGuard::new(&lane.queued);
+        let permit = lane
+            .semaphore
+            .acquire()
+            .await
+            .expect("lane semaphore never closed");
+        drop(queued);
+        let waited = started.elapsed().as_millis() as u64;
+        lane.sent.fetch_add(1, Ordering::Relaxed);
+        lane.total_wait_ms.fetch_add(waited, Ordering::Relaxed);
+        lane.max_wait_ms.fetch_max(waited, Ordering::Relaxed);
+        permit
+    }
+
+    pub fn metrics(&self) -> Vec<RconLaneMetrics> {
+        use strum::IntoEnumIterator;
+        RconPriority::iter()
+            .map(|priority| {
+                let lane = &self.lanes[priority.index()];
+                RconLaneMetrics {
+                    priority,
+                    limit: lane.limit,
+                    in_flight: lane.limit - lane.semaphore.available_permits(),
+                    queued: lane.queued.load(Ordering::SeqCst),
+                    sent: lane.sent.load(Ordering::Relaxed),
+                    total_wait_ms: lane.total_wait_ms.load(Ordering::Relaxed),
+                    max_wait_ms: lane.max_wait_ms.load(Ordering::Relaxed),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Default for RconLanes {
+    fn default() -> Self {
+        RconLanes::new(6, 6, 3)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Position, Rect};
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    async fn test_bulk_does_not_block_realtime() {
+        let lanes = RconLanes::new(1, 1, 2);
+        assert_eq!(lanes.pool_size(), 4);
+        let _bulk1 = lanes.acquire(RconPriority::Bulk).await;
+        let _bulk2 = lanes.acquire(RconPriority::Bulk).await;
+        assert!(
+            timeout(Duration::from_millis(20), lanes.acquire(RconPriority::Bulk))
+                .await
+                .is_err()
+        );
+        let realtime = timeout(
+            Duration::from_millis(20),
+            lanes.acquire(RconPriority::Realtime),
+        )
+        .await
+        .expect("realtime lane is free");
+
+        let metrics = lanes.metrics();
+        assert_eq!(metrics[0].priority, RconPriority::Realtime);
+        assert_eq!(metrics[0].in_flight, 1);
+        assert_eq!(metrics[2].in_flight, 2);
+        assert_eq!(metrics[2].sent, 2);
+        assert_eq!(metrics[2].queued, 0);
+        drop(realtime);
+        assert_eq!(lanes.metrics()[0].in_flight, 0);
+    }
+
+    #[test]
+    fn test_of_area_filter() {
+        let center = Position::new(0.0, 0.0);
+        assert_eq!(
+            RconPriority::of_area_filter(&AreaFilter::PositionRadius((center.clone(), Some(10.0)))),
+            RconPriority::Query
+        );
+        assert_eq!(
+            RconPriority::of_area_filter(&AreaFilter::PositionRadius((center, Some(300.0)))),
+            RconPriority::Bulk
+        );
+        assert_eq!(
+            RconPriority::of_area_filter(&AreaFilter::Rect(Rect::from_wh(512.0, 512.0))),
+            RconPriority::Bulk
+        );
+    }
+
+    #[test]
+    fn test_of_remote_call() {
+        assert_eq!(
+            RconPriority::of_remote_call("action_start_mining"),
+            RconPriority::Realtime
+        );
+        assert_eq!(
+            RconPriority::of_remote_call("cheat_blueprint"),
+            RconPriority::Bulk
+        );
+        assert_eq!(
+            RconPriority::of_remote_call("player_force"),
+            RconPriority::Query
+        );
+    }
+}
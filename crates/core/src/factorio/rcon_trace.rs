@@ -0,0 +1,299 @@
+use crate::errors::{RconReplayExhausted, RconReplayMismatch};
+use crate::factorio::world::FactorioWorld;
+use crate::process::output_parser::OutputParser;
+use miette::{IntoDiagnostic, Result};
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Single line of a rcon trace file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TraceEntry {
+    /// command sent via rcon with the response it got
+    Command {
+        elapsed_ms: u64,
+        command: String,
+        response: Option<Vec<String>>,
+    },
+    /// BotBridge event parsed from server stdout
+    Event {
+        elapsed_ms: u64,
+        tick: u64,
+        action: String,
+        rest: String,
+    },
+    /// server finished initial discovery, world graphs got connected
+    Initialized { elapsed_ms: u64 },
+}
+
+/// Writes rcon commands, responses and server events to a trace file, one json object per line.
+///
+/// Every entry is flushed immediately so the trace survives crashes of the script under test.
+pub struct RconRecorder {
+    started: Instant,
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl RconRecorder {
+    pub fn create(path: &Path) -> Result<RconRecorder> {
+        Ok(RconRecorder {
+            started: Instant::now(),
+            writer: Mutex::new(BufWriter::new(File::create(path).into_diagnostic()?)),
+        })
+    }
+
+    pub fn record_command(&self, command: &str, response: &Option<Vec<String>>) {
+        self.write(&TraceEntry::Command {
+            elapsed_ms: self.elapsed_ms(),
+            command: command.to_owned(),
+            response: response.clone(),
+        });
+    }
+
+    pub fn record_event(&self, tick: u64, action: &str, rest: &str) {
+        self.write(&TraceEntry::Event {
+            elapsed_ms: self.elapsed_ms(),
+            tick,
+            action: action.to_owned(),
+            rest: rest.to_owned(),
+        });
+    }
+
+    pub fn record_initialized(&self) {
+        self.write(&TraceEntry::Initialized {
+            elapsed_ms: self.elapsed_ms(),
+        });
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    fn write(&self, entry: &TraceEntry) {
+        let mut writer = self.writer.lock();
+        let result = serde_json::to_writer(&mut *writer, entry)
+            .into_diagnostic()
+            .and_then(|_| writer.write_all(b"\n").into_diagnostic())
+            .and_then(|_| writer.flush().into_diagnostic());
+        if let Err(err) = result {
+            error!("<red>failed to write rcon trace</>: {:?}", err);
+        }
+    }
+}
+
+pub fn read_trace(path: &Path) -> Result<Vec<TraceEntry>> {
+    let file = File::open(path).into_diagnostic()?;
+    let mut entries = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line.into_diagnostic()?;
+        if !line.trim().is_empty() {
+            entries.push(serde_json::from_str(&line).into_diagnostic()?);
+        }
+    }
+    Ok(entries)
+}
+
+/// Answers rcon commands from a recorded trace instead of a live server.
+///
+/// Recorded server events are fed into the world in the order they were recorded:
+/// everything up to the first command on creation, and after every command all
+/// events up to the next one.
+///
+/// Bots run concurrently, so commands of different bots may arrive in another order
+/// than recorded. Every command is answered by the earliest recorded command with the
+/// same text that was not replayed yet, events recorded before it are fed first.
+pub struct RconReplayer {
+    entries: Mutex<VecDeque<TraceEntry>>,
+    output_parser: Mutex<OutputParser>,
+    world: Arc<FactorioWorld>,
+}
+
+impl RconReplayer {
+    pub fn new(entries: Vec<TraceEntry>) -> RconReplayer {
+        let output_parser = OutputParser::new();
+        let world = output_parser.world();
+        let replayer = RconReplayer {
+            entries: Mutex::new(entries.into()),
+            output_parser: Mutex::new(output_parser),
+            world,
+        };
+        replayer.feed_events(&mut replayer.entries.lock());
+        replayer
+    }
+
+    pub fn load(path: &Path) -> Result<RconReplayer> {
+        Ok(Self::new(read_trace(path)?))
+    }
+
+    pub fn world(&self) -> Arc<FactorioWorld> {
+        self.world.clone()
+    }
+
+    /// Number of recorded commands not yet replayed
+    pub fn remaining_commands(&self) -> usize {
+        self.entries
+            .lock()
+            .iter()
+            .filter(|entry| matches!(entry, TraceEntry::Command { .. }))
+            .count()
+    }
+
+    pub fn send(&self, command: &str) -> Result<Option<Vec<String>>> {
+        let mut entries = self.entries.lock();
+        let index = entries.iter().position(|entry| {
+            matches!(entry, TraceEntry::Command { command: recorded, .. } if recorded == command)
+        });
+        let Some(index) = index else {
+            return match entries.front() {
+                Some(TraceEntry::Command {
+                    command: expected, ..
+                }) => Err(RconReplayMismatch {
+                    expected: expected.clone(),
+                    actual: command.to_owned(),
+                }
+                .into()),
+                _ => Err(RconReplayExhausted {
+                    command: command.to_owned(),
+                }
+                .into()),
+            };
+        };
+        // earlier commands of other bots stay pending, events in between are fed now
+        let mut before: VecDeque<TraceEntry> = entries.drain(..index).collect();
+        let response = match entries.pop_front() {
+            Some(TraceEntry::Command { response, .. }) => response,
+            _ => unreachable!("found above"),
+        };
+        let mut pending = VecDeque::new();
+        while !before.is_empty() {
+            self.feed_events(&mut before);
+            if let Some(command) = before.pop_front() {
+                pending.push_back(command);
+            }
+        }
+        pending.append(&mut entries);
+        *entries = pending;
+        self.feed_events(&mut entries);
+        Ok(response)
+    }
+
+    fn feed_events(&self, entries: &mut VecDeque<TraceEntry>) {
+        let mut output_parser = self.output_parser.lock();
+        while let Some(entry) = entries.front() {
+            match entry {
+                TraceEntry::Command { .. } => break,
+                TraceEntry::Event {
+                    tick, action, rest, ..
+                } => {
+                    if let Err(err) = output_parser.parse(*tick, action, rest) {
+                        error!("<red>failed to parse</> <bright-blue>'{}'</>", action);
+                        error!("<red>error: {:?}</>", err);
+                    }
+                }
+                TraceEntry::Initialized { .. } => {
+                    if let Err(err) = output_parser.on_init() {
+                        error!("<red>failed to initialize world</>: {:?}", err);
+                    }
+                }
+            }
+            entries.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(command: &str, response: Option<Vec<String>>) -> TraceEntry {
+        TraceEntry::Command {
+            elapsed_ms: 0,
+            command: command.into(),
+            response,
+        }
+    }
+
+    fn event(action: &str, rest: &str) -> TraceEntry {
+        TraceEntry::Event {
+            elapsed_ms: 0,
+            tick: 1,
+            action: action.into(),
+            rest: rest.into(),
+        }
+    }
+
+    #[test]
+    fn test_record_and_read() {
+        let path = std::env::temp_dir().join(format!("rcon-trace-{}.jsonl", std::process::id()));
+        let recorder = RconRecorder::create(&path).unwrap();
+        recorder.record_event(3, "action_completed", "ok 1");
+        recorder.record_initialized();
+        recorder.record_command("/c rcon.print(1)", &Some(vec!["1".into()]));
+        drop(recorder);
+        let entries = read_trace(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert!(
+            matches!(&entries[0], TraceEntry::Event { tick: 3, action, .. } if action == "action_completed")
+        );
+        assert!(matches!(&entries[1], TraceEntry::Initialized { .. }));
+        assert!(
+            matches!(&entries[2], TraceEntry::Command { command, response: Some(response), .. } if command == "/c rcon.print(1)" && response == &vec!["1".to_owned()])
+        );
+    }
+
+    #[test]
+    fn test_replay() {
+        let replayer = RconReplayer::new(vec![
+            event("action_completed", "ok 1"),
+            command("first", Some(vec!["a".into()])),
+            event("action_completed", "fail 2 player blocks"),
+            command("second", None),
+        ]);
+        // events before the first command are fed on creation
+        assert_eq!(replayer.world().actions.get(&1).unwrap().as_str(), "ok");
+        assert_eq!(replayer.remaining_commands(), 2);
+
+        assert!(replayer.send("other").is_err());
+        assert_eq!(replayer.send("first").unwrap(), Some(vec!["a".into()]));
+        assert_eq!(
+            replayer.world().actions.get(&2).unwrap().as_str(),
+            "player blocks"
+        );
+        assert_eq!(replayer.send("second").unwrap(), None);
+        assert!(replayer.send("third").is_err());
+    }
+
+    #[test]
+    fn test_replay_interleaved() {
+        let trace = vec![
+            command("bot 1 walk", Some(vec!["1".into()])),
+            event("action_completed", "ok 1"),
+            command("bot 2 walk", Some(vec!["2".into()])),
+            event("action_completed", "ok 2"),
+            command("bot 1 walk", Some(vec!["3".into()])),
+        ];
+
+        // recorded order
+        let replayer = RconReplayer::new(trace.clone());
+        assert_eq!(replayer.send("bot 1 walk").unwrap(), Some(vec!["1".into()]));
+        assert_eq!(replayer.send("bot 2 walk").unwrap(), Some(vec!["2".into()]));
+        assert_eq!(replayer.send("bot 1 walk").unwrap(), Some(vec!["3".into()]));
+        assert_eq!(replayer.remaining_commands(), 0);
+
+        // bot 2 first, the events recorded before its command are fed with it
+        let replayer = RconReplayer::new(trace);
+        assert_eq!(replayer.send("bot 2 walk").unwrap(), Some(vec!["2".into()]));
+        assert!(replayer.world().actions.get(&1).is_some());
+        assert!(replayer.world().actions.get(&2).is_none());
+        assert_eq!(replayer.send("bot 1 walk").unwrap(), Some(vec!["1".into()]));
+        assert!(replayer.world().actions.get(&2).is_some());
+        assert_eq!(replayer.send("bot 1 walk").unwrap(), Some(vec!["3".into()]));
+        assert!(replayer.send("bot 2 walk").is_err());
+    }
+}
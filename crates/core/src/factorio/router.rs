@@ -0,0 +1,674 @@
+use crate::errors::{RconSourcePositionBlocked, RconTargetPositionBlocked, RouteNotFound};
+use crate::factorio::util::{add_to_rect_turned, move_pos};
+use crate::types::{
+    Direction, EntityType, FactorioEntity, FactorioEntityPrototype, FactorioTile, Pos, Position,
+    Rect,
+};
+use dashmap::DashMap;
+use miette::Result;
+use num_traits::{FromPrimitive, ToPrimitive};
+use pathfinding::prelude::astar;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// tiles around the endpoints a route may use
+const ROUTE_MARGIN: i32 = 20;
+/// cost of a tile on the surface, underground connections cost `UNDERGROUND_COST` per tile
+const STEP_COST: u32 = 1;
+const UNDERGROUND_COST: u32 = 3;
+/// extra cost of bus tiles which don't run alongside the previous line
+const BUS_DETOUR_COST: u32 = 1;
+
+/// Side of a belt looking in its direction, items stay on the lane they were put on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BeltLane {
+    Left,
+    Right,
+}
+
+impl BeltLane {
+    /// direction of this side of a belt facing `direction`
+    pub fn side(&self, direction: Direction) -> Direction {
+        match self {
+            BeltLane::Left => direction.clockwise().opposite(),
+            BeltLane::Right => direction.clockwise(),
+        }
+    }
+}
+
+impl FromStr for BeltLane {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "left" => Ok(BeltLane::Left),
+            "right" => Ok(BeltLane::Right),
+            _ => Err(format!("invalid belt lane '{s}', expected left or right")),
+        }
+    }
+}
+
+/// Entities a route is built with, belts with underground belts or pipes with pipes to ground
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteEntities {
+    pub name: String,
+    pub entity_type: String,
+    pub underground_name: String,
+    pub underground_type: String,
+    /// longest distance between the two ends of an underground connection
+    pub underground_max: u8,
+}
+
+impl RouteEntities {
+    /// Takes types and underground distance from the prototypes if known
+    pub fn new(
+        prototypes: &DashMap<String, FactorioEntityPrototype>,
+        name: &str,
+        underground_name: &str,
+    ) -> Self {
+        let entity_type = prototypes
+            .get(name)
+            .map(|prototype| prototype.entity_type.clone())
+            .unwrap_or_else(|| EntityType::TransportBelt.to_string());
+        let underground = prototypes.get(underground_name);
+        let underground_type = underground
+            .as_ref()
+            .map(|prototype| prototype.entity_type.clone())
+            .unwrap_or_else(|| {
+                if entity_type == EntityType::Pipe.to_string() {
+                    EntityType::PipeToGround.to_string()
+                } else {
+                    EntityType::UndergroundBelt.to_string()
+                }
+            });
+        let underground_max = underground
+            .as_ref()
+            .and_then(|prototype| prototype.max_underground_distance)
+            .unwrap_or(5);
+        RouteEntities {
+            name: name.to_owned(),
+            entity_type,
+            underground_name: underground_name.to_owned(),
+            underground_type,
+            underground_max,
+        }
+    }
+
+    pub fn is_pipe(&self) -> bool {
+        self.underground_type == EntityType::PipeToGround.to_string()
+    }
+}
+
+/// Start or end of a route
+///
+/// At the start `direction` is the direction items or fluids move into `position`, with a
+/// `lane` they are sideloaded onto that lane of the first belt. At the end the last belt is
+/// placed at `position` facing `direction`, with a `lane` `position` is an existing belt
+/// facing `direction` and the route sideloads onto that lane of it. Pipes ignore lanes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteEndpoint {
+    pub position: Position,
+    pub direction: Option<Direction>,
+    pub lane: Option<BeltLane>,
+}
+
+impl RouteEndpoint {
+    pub fn new(position: Position, direction: Option<Direction>) -> Self {
+        RouteEndpoint {
+            position,
+            direction,
+            lane: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteRequest {
+    pub from: RouteEndpoint,
+    pub to: RouteEndpoint,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct RouteNode {
+    pos: Pos,
+    /// direction of travel into `pos`, only unknown at the start
+    direction: Option<u8>,
+    /// arrived through an underground connection
+    underground: bool,
+}
+
+/// A* router for belts and pipes on the tile grid.
+///
+/// Routes never cross existing entities or reserved zones, but may pass below them
+/// with undergrounds as long as no other underground of the same kind on the same
+/// axis could pair with one of its ends. Belts stay out of tiles existing belts,
+/// splitters and underground exits move items into, pipes keep a tile away from
+/// existing pipes and tanks so fluids don't mix. Every routed line blocks the
+/// following ones.
+pub struct LogisticsRouter {
+    prototypes: Arc<DashMap<String, FactorioEntityPrototype>>,
+    entities: RouteEntities,
+    blocked: HashSet<Pos>,
+    belt_outputs: HashSet<Pos>,
+    fluid_neighbours: HashSet<Pos>,
+    /// underground ends of the route entities, true if on the north/south axis
+    undergrounds: HashMap<Pos, bool>,
+}
+
+impl LogisticsRouter {
+    pub fn new(
+        prototypes: Arc<DashMap<String, FactorioEntityPrototype>>,
+        entities: RouteEntities,
+        block_entities: &[FactorioEntity],
+        block_tiles: &[FactorioTile],
+    ) -> Self {
+        let mut router = LogisticsRouter {
+            prototypes,
+            entities,
+            blocked: HashSet::new(),
+            belt_outputs: HashSet::new(),
+            fluid_neighbours: HashSet::new(),
+            undergrounds: HashMap::new(),
+        };
+        for tile in block_tiles {
+            if tile.player_collidable {
+                router.blocked.insert((&tile.position).into());
+            }
+        }
+        for entity in block_entities {
+            router.add_entity(entity);
+        }
+        router
+    }
+
+    /// Keeps routes out of given areas, undergrounds may still pass below them
+    pub fn with_reserved_zones(mut self, zones: &[Rect]) -> Self {
+        for zone in zones {
+            for y in zone.left_top.y().floor() as i32..zone.right_bottom.y().ceil() as i32 {
+                for x in zone.left_top.x().floor() as i32..zone.right_bottom.x().ceil() as i32 {
+                    self.blocked.insert(Pos(x, y));
+                }
+            }
+        }
+        self
+    }
+
+    /// Routes a single line
+    pub fn route(&mut self, request: &RouteRequest) -> Result<Vec<FactorioEntity>> {
+        Ok(self.route_line(request, None)?.0)
+    }
+
+    /// Routes lines one after another, each following the previous one where possible
+    /// so they run in parallel like a main bus
+    pub fn route_bus(&mut self, requests: &[RouteRequest]) -> Result<Vec<Vec<FactorioEntity>>> {
+        let spacing = if self.entities.is_pipe() { 2 } else { 1 };
+        let mut lines = vec![];
+        let mut follow: Option<HashSet<Pos>> = None;
+        for request in requests {
+            let (entities, tiles) = self.route_line(request, follow.as_ref())?;
+            follow = Some(
+                tiles
+                    .iter()
+                    .flat_map(|tile| {
+                        Direction::orthogonal()
+                            .into_iter()
+                            .map(move |direction| move_pos(tile, direction, spacing))
+                    })
+                    .collect(),
+            );
+            lines.push(entities);
+        }
+        Ok(lines)
+    }
+
+    fn route_line(
+        &mut self,
+        request: &RouteRequest,
+        follow: Option<&HashSet<Pos>>,
+    ) -> Result<(Vec<FactorioEntity>, Vec<Pos>)> {
+        let is_pipe = self.entities.is_pipe();
+        let start: Pos = (&request.from.position).into();
+        // the first belt turns so the source sideloads onto the lane
+        let first_direction = match (request.from.direction, request.from.lane) {
+            (Some(direction), Some(lane)) if !is_pipe => Some(lane.side(direction)),
+            _ => None,
+        };
+        let (goal, goal_direction) = match (request.to.direction, request.to.lane) {
+            (Some(direction), Some(lane)) if !is_pipe => {
+                let side = lane.side(direction);
+                (
+                    move_pos(&(&request.to.position).into(), side, 1),
+                    Some(side.opposite()),
+                )
+            }
+            (direction, _) => ((&request.to.position).into(), direction),
+        };
+        // a belt behind the first one keeps it straight, otherwise it would curve
+        let rear = first_direction.map(|direction| move_pos(&start, direction.opposite(), 1));
+        let left_top = Pos(
+            start.0.min(goal.0) - ROUTE_MARGIN,
+            start.1.min(goal.1) - ROUTE_MARGIN,
+        );
+        let right_bottom = Pos(
+            start.0.max(goal.0) + ROUTE_MARGIN,
+            start.1.max(goal.1) + ROUTE_MARGIN,
+        );
+        let in_bounds = |pos: &Pos| {
+            pos.0 >= left_top.0
+                && pos.0 <= right_bottom.0
+                && pos.1 >= left_top.1
+                && pos.1 <= right_bottom.1
+        };
+        let is_free = |pos: &Pos| {
+            if !in_bounds(pos) || self.blocked.contains(pos) {
+                false
+            } else if *pos == start || *pos == goal {
+                true
+            } else if is_pipe {
+                !self.fluid_neighbours.contains(pos)
+            } else {
+                !self.belt_outputs.contains(pos)
+            }
+        };
+        if !is_free(&start) || rear.as_ref().is_some_and(|rear| !is_free(rear)) {
+            return Err(RconSourcePositionBlocked {}.into());
+        }
+        if !is_free(&goal) {
+            return Err(RconTargetPositionBlocked {}.into());
+        }
+        let detour = |pos: &Pos| match follow {
+            Some(follow) if !follow.contains(pos) => BUS_DETOUR_COST,
+            _ => 0,
+        };
+        let start_node = RouteNode {
+            pos: start.clone(),
+            direction: request.from.direction.map(|d| d.to_u8().unwrap()),
+            underground: false,
+        };
+        // direction of the last belt, single tile routes keep the sideloaded direction
+        let last_direction = |is_start: bool, arrival: Option<Direction>| {
+            goal_direction
+                .or(first_direction.filter(|_| is_start))
+                .or(arrival)
+        };
+        let underground_max = self.entities.underground_max as i32;
+        let path = astar(
+            &start_node,
+            |node| {
+                let mut options: Vec<(RouteNode, u32)> = vec![];
+                let is_start = *node == start_node;
+                let current = node.direction.and_then(Direction::from_u8);
+                for direction in Direction::orthogonal() {
+                    if is_start && first_direction.is_some_and(|first| first != direction) {
+                        continue;
+                    }
+                    if current.is_some_and(|current| direction == current.opposite()) {
+                        continue;
+                    }
+                    // underground exits only continue straight
+                    if node.underground && current != Some(direction) {
+                        continue;
+                    }
+                    let target = move_pos(&node.pos, direction, 1);
+                    if is_free(&target) {
+                        options.push((
+                            RouteNode {
+                                direction: direction.to_u8(),
+                                pos: target.clone(),
+                                underground: false,
+                            },
+                            STEP_COST + detour(&target),
+                        ));
+                    }
+                    // underground entrances need a straight connection, sideloading them
+                    // would only fill one lane
+                    if node.underground
+                        || (is_start && first_direction.is_some())
+                        || current.is_some_and(|current| current != direction)
+                        || self.underground_conflict(&node.pos, direction)
+                    {
+                        continue;
+                    }
+                    for length in 2..=underground_max {
+                        let target = move_pos(&node.pos, direction, length);
+                        if !in_bounds(&target) {
+                            break;
+                        }
+                        if is_free(&target) && !self.underground_conflict(&target, direction) {
+                            options.push((
+                                RouteNode {
+                                    direction: direction.to_u8(),
+                                    pos: target.clone(),
+                                    underground: true,
+                                },
+                                UNDERGROUND_COST * length as u32 + detour(&target),
+                            ));
+                        }
+                    }
+                }
+                options
+            },
+            |node| node.pos.distance(&goal) * STEP_COST,
+            |node| {
+                if node.pos != goal {
+                    return false;
+                }
+                if is_pipe {
+                    return true;
+                }
+                let is_start = *node == start_node;
+                if is_start
+                    && first_direction.is_some()
+                    && goal_direction.is_some()
+                    && first_direction != goal_direction
+                {
+                    return false;
+                }
+                let arrival = node.direction.and_then(Direction::from_u8);
+                match (arrival, last_direction(is_start, arrival)) {
+                    (Some(arrival), Some(last)) => {
+                        arrival != last.opposite() && (!node.underground || arrival == last)
+                    }
+                    _ => true,
+                }
+            },
+        );
+        let (path, _cost) = path.ok_or_else(|| RouteNotFound {
+            entity_name: self.entities.name.clone(),
+            start: request.from.position.clone(),
+            goal: request.to.position.clone(),
+        })?;
+
+        let mut result: Vec<FactorioEntity> = vec![];
+        let mut tiles: Vec<Pos> = vec![];
+        if let (Some(rear), Some(direction)) = (rear, first_direction) {
+            result.push(self.route_entity(
+                &self.entities.name,
+                &self.entities.entity_type,
+                &rear,
+                direction,
+            ));
+            tiles.push(rear);
+        }
+        for (index, node) in path.iter().enumerate() {
+            let next = path.get(index + 1);
+            let arrival = node.direction.and_then(Direction::from_u8);
+            let direction = match next {
+                Some(next) => next.direction.and_then(Direction::from_u8),
+                None if is_pipe => arrival,
+                None => last_direction(index == 0, arrival),
+            }
+            .unwrap_or_default();
+            let entrance = next.is_some_and(|next| next.underground);
+            let mut entity = if entrance || node.underground {
+                // pipes to ground face their connection on the surface
+                let direction = match (is_pipe, entrance) {
+                    (true, true) => direction.opposite(),
+                    (_, true) => direction,
+                    (_, false) => arrival.unwrap_or_default(),
+                };
+                self.route_entity(
+                    &self.entities.underground_name,
+                    &self.entities.underground_type,
+                    &node.pos,
+                    direction,
+                )
+            } else if is_pipe {
+                self.route_entity(
+                    &self.entities.name,
+                    &self.entities.entity_type,
+                    &node.pos,
+                    Direction::North,
+                )
+            } else {
+                self.route_entity(
+                    &self.entities.name,
+                    &self.entities.entity_type,
+                    &node.pos,
+                    direction,
+                )
+            };
+            if !is_pipe && (entrance || node.underground) {
+                entity.belt_to_ground_type =
+                    Some(if entrance { "input" } else { "output" }.to_owned());
+            }
+            if node.underground {
+                let previous = &path[index - 1].pos;
+                let length = previous.distance(&node.pos) as i32;
+                for offset in 1..length {
+                    tiles.push(move_pos(previous, arrival.unwrap_or_default(), offset));
+                }
+            }
+            tiles.push(node.pos.clone());
+            result.push(entity);
+        }
+        for entity in &result {
+            self.add_entity(entity);
+        }
+        Ok((result, tiles))
+    }
+
+    /// true if an underground end at `pos` facing `direction` could pair with an existing one
+    fn underground_conflict(&self, pos: &Pos, direction: Direction) -> bool {
+        let vertical = is_vertical(direction);
+        [direction, direction.opposite()].iter().any(|direction| {
+            (1..=self.entities.underground_max as i32).any(|length| {
+                self.undergrounds.get(&move_pos(pos, *direction, length)) == Some(&vertical)
+            })
+        })
+    }
+
+    fn add_entity(&mut self, entity: &FactorioEntity) {
+        let entity_type = EntityType::from_str(&entity.entity_type).ok();
+        if matches!(
+            entity_type,
+            Some(EntityType::Character | EntityType::Resource)
+        ) {
+            return;
+        }
+        let direction = Direction::from_u8(entity.direction).unwrap_or_default();
+        let tiles = self.entity_tiles(entity, direction);
+        match entity_type {
+            Some(EntityType::TransportBelt | EntityType::Splitter) => {
+                for tile in &tiles {
+                    self.belt_outputs.insert(move_pos(tile, direction, 1));
+                }
+            }
+            Some(EntityType::UndergroundBelt)
+                if entity.belt_to_ground_type.as_deref() != Some("input") =>
+            {
+                for tile in &tiles {
+                    self.belt_outputs.insert(move_pos(tile, direction, 1));
+                }
+            }
+            Some(entity_type) if entity_type.is_fluid_input() => {
+                for tile in &tiles {
+                    for side in Direction::orthogonal() {
+                        self.fluid_neighbours.insert(move_pos(tile, side, 1));
+                    }
+                }
+            }
+            _ => {}
+        }
+        if entity.name == self.entities.underground_name {
+            for tile in &tiles {
+                self.undergrounds
+                    .insert(tile.clone(), is_vertical(direction));
+            }
+        }
+        self.blocked.extend(tiles);
+    }
+
+    fn entity_tiles(&self, entity: &FactorioEntity, direction: Direction) -> Vec<Pos> {
+        match self.prototypes.get(&entity.name) {
+            Some(prototype) => {
+                let rect =
+                    add_to_rect_turned(&prototype.collision_box, &entity.position, direction);
+                let mut tiles = vec![];
+                for y in rect.left_top.y().floor() as i32..=rect.right_bottom.y().floor() as i32 {
+                    for x in rect.left_top.x().floor() as i32..=rect.right_bottom.x().floor() as i32
+                    {
+                        tiles.push(Pos(x, y));
+                    }
+                }
+                tiles
+            }
+            None => vec![(&entity.position).into()],
+        }
+    }
+
+    fn route_entity(
+        &self,
+        name: &str,
+        entity_type: &str,
+        pos: &Pos,
+        direction: Direction,
+    ) -> FactorioEntity {
+        let position = Position::new(pos.0 as f64 + 0.5, pos.1 as f64 + 0.5);
+        let collision_box = self
+            .prototypes
+            .get(name)
+            .map(|prototype| prototype.collision_box.clone())
+            .unwrap_or_else(|| Rect::from_wh(0.8, 0.8));
+        FactorioEntity {
+            name: name.to_owned(),
+            entity_type: entity_type.to_owned(),
+            bounding_box: add_to_rect_turned(&collision_box, &position, direction),
+            position,
+            direction: direction.to_u8().unwrap(),
+            ..Default::default()
+        }
+    }
+}
+
+fn is_vertical(direction: Direction) -> bool {
+    direction == Direction::North || direction == Direction::South
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixture_entity_prototypes;
+
+    fn entity(
+        name: &str,
+        entity_type: &str,
+        x: f64,
+        y: f64,
+        direction: Direction,
+    ) -> FactorioEntity {
+        FactorioEntity {
+            name: name.into(),
+            entity_type: entity_type.into(),
+            position: Position::new(x, y),
+            direction: direction.to_u8().unwrap(),
+            ..Default::default()
+        }
+    }
+
+    fn router(name: &str, underground_name: &str, block: &[FactorioEntity]) -> LogisticsRouter {
+        let prototypes = Arc::new(fixture_entity_prototypes());
+        let entities = RouteEntities::new(&prototypes, name, underground_name);
+        LogisticsRouter::new(prototypes, entities, block, &[])
+    }
+
+    #[test]
+    fn test_route_belt_under_wall() {
+        let wall: Vec<FactorioEntity> = (-30..30)
+            .map(|y| entity("stone-wall", "wall", 5.5, y as f64 + 0.5, Direction::North))
+            .collect();
+        let route = router("transport-belt", "underground-belt", &wall)
+            .route(&RouteRequest {
+                from: RouteEndpoint::new(Position::new(0.5, 0.5), Some(Direction::East)),
+                to: RouteEndpoint::new(Position::new(10.5, 0.5), Some(Direction::East)),
+            })
+            .unwrap();
+        let undergrounds: Vec<&FactorioEntity> = route
+            .iter()
+            .filter(|entity| entity.name == "underground-belt")
+            .collect();
+        assert_eq!(undergrounds.len(), 2);
+        assert_eq!(
+            undergrounds[0].belt_to_ground_type.as_deref(),
+            Some("input")
+        );
+        assert_eq!(
+            undergrounds[1].belt_to_ground_type.as_deref(),
+            Some("output")
+        );
+        assert!(undergrounds[0].position.x() < 5.5 && undergrounds[1].position.x() > 5.5);
+        assert!(route.iter().all(|entity| entity.direction == 2));
+        assert_eq!(route.first().unwrap().position, Position::new(0.5, 0.5));
+        assert_eq!(route.last().unwrap().position, Position::new(10.5, 0.5));
+    }
+
+    #[test]
+    fn test_route_bus_and_lanes() {
+        let target = entity(
+            "transport-belt",
+            "transport-belt",
+            12.5,
+            0.5,
+            Direction::North,
+        );
+        let mut router = router("transport-belt", "underground-belt", &[target]);
+        let lines = router
+            .route_bus(&[
+                RouteRequest {
+                    from: RouteEndpoint::new(Position::new(0.5, 5.5), Some(Direction::East)),
+                    to: RouteEndpoint::new(Position::new(10.5, 5.5), Some(Direction::East)),
+                },
+                RouteRequest {
+                    from: RouteEndpoint::new(Position::new(0.5, 6.5), Some(Direction::East)),
+                    to: RouteEndpoint::new(Position::new(10.5, 6.5), Some(Direction::East)),
+                },
+            ])
+            .unwrap();
+        assert_eq!(lines[0].len(), 11);
+        assert_eq!(lines[1].len(), 11);
+        assert!(lines[1].iter().all(|entity| entity.position.y() == 6.5));
+
+        // sideloads onto the left lane of the north facing target belt from the west
+        let line = router
+            .route(&RouteRequest {
+                from: RouteEndpoint::new(Position::new(2.5, 0.5), Some(Direction::East)),
+                to: RouteEndpoint {
+                    position: Position::new(12.5, 0.5),
+                    direction: Some(Direction::North),
+                    lane: Some(BeltLane::Left),
+                },
+            })
+            .unwrap();
+        let last = line.last().unwrap();
+        assert_eq!(last.position, Position::new(11.5, 0.5));
+        assert_eq!(last.direction, Direction::East.to_u8().unwrap());
+        // crossing the bus is only possible below it
+        let crossing = router
+            .route(&RouteRequest {
+                from: RouteEndpoint::new(Position::new(5.5, 2.5), Some(Direction::South)),
+                to: RouteEndpoint::new(Position::new(5.5, 9.5), Some(Direction::South)),
+            })
+            .unwrap();
+        assert!(crossing
+            .iter()
+            .filter(|entity| entity.position.y() > 4. && entity.position.y() < 8.)
+            .all(|entity| entity.name == "underground-belt"));
+    }
+
+    #[test]
+    fn test_route_pipes_apart() {
+        let pipe = entity("pipe", "pipe", 3.5, 1.5, Direction::North);
+        let route = router("pipe", "pipe-to-ground", &[pipe])
+            .route(&RouteRequest {
+                from: RouteEndpoint::new(Position::new(0.5, 0.5), None),
+                to: RouteEndpoint::new(Position::new(6.5, 0.5), None),
+            })
+            .unwrap();
+        let existing = Pos(3, 1);
+        assert!(route
+            .iter()
+            .all(|entity| Pos::from(&entity.position).distance(&existing) > 1));
+        assert_eq!(route.last().unwrap().position, Position::new(6.5, 0.5));
+    }
+}
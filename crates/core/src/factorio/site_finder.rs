@@ -0,0 +1,374 @@
+use crate::factorio::util::calculate_distance;
+use crate::graph::entity_graph::{EntityGraph, QuadTreeRect};
+use crate::types::{Direction, Position, Rect, SiteCandidate, SiteConstraints};
+use num_traits::ToPrimitive;
+
+/// search radius around `near` if the constraints give none
+const DEFAULT_SEARCH_RADIUS: f64 = 64.;
+/// score added per tree or rock which has to be mined first
+const OBSTRUCTION_COST: f64 = 2.;
+/// score taken off per resource tile covered
+const RESOURCE_TILE_BONUS: f64 = 0.1;
+
+/// Per tile facts about the search area, with summed area tables to count them for
+/// footprints in constant time
+struct SiteGrid {
+    left: i32,
+    top: i32,
+    width: usize,
+    height: usize,
+    blocked: Vec<u32>,
+    obstructed: Vec<u32>,
+    resources: Vec<u32>,
+    water_distance: Vec<f64>,
+}
+
+impl SiteGrid {
+    fn new(entity_graph: &EntityGraph, area: &Rect, constraints: &SiteConstraints) -> SiteGrid {
+        let left = area.left_top.x().floor() as i32;
+        let top = area.left_top.y().floor() as i32;
+        let width = (area.right_bottom.x().ceil() as i32 - left).max(0) as usize;
+        let height = (area.right_bottom.y().ceil() as i32 - top).max(0) as usize;
+        let mut grid = SiteGrid {
+            left,
+            top,
+            width,
+            height,
+            blocked: vec![0; width * height],
+            obstructed: vec![0; width * height],
+            resources: vec![0; width * height],
+            water_distance: vec![f64::INFINITY; width * height],
+        };
+        let query: QuadTreeRect = area.clone().into();
+        for (minable, rect, _) in entity_graph.blocked_tree().query(query) {
+            let cells = if *minable {
+                &mut grid.obstructed
+            } else {
+                &mut grid.blocked
+            };
+            fill(cells, left, top, width, height, &rect);
+        }
+        if let Some(resource) = constraints.resource.as_ref() {
+            for (name, rect, _) in entity_graph.resource_tree().query(query) {
+                if name == resource {
+                    fill(&mut grid.resources, left, top, width, height, &rect);
+                }
+            }
+        }
+        if constraints.max_water_distance.is_some() {
+            for (tile, _, _) in entity_graph.tile_tree().query(query) {
+                if tile.name.contains("water") {
+                    if let Some(index) = grid.index(
+                        tile.position.x().floor() as i32,
+                        tile.position.y().floor() as i32,
+                    ) {
+                        grid.water_distance[index] = 0.;
+                    }
+                }
+            }
+            grid.distance_transform();
+        }
+        grid.blocked = grid.summed(&grid.blocked);
+        grid.obstructed = grid.summed(&grid.obstructed);
+        grid.resources = grid.summed(&grid.resources);
+        grid
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        let (x, y) = (x - self.left, y - self.top);
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return None;
+        }
+        Some(y as usize * self.width + x as usize)
+    }
+
+    /// two pass chamfer distance transform, close enough to euclidean distances
+    fn distance_transform(&mut self) {
+        let diagonal = std::f64::consts::SQRT_2;
+        let (width, height) = (self.width as i32, self.height as i32);
+        let relax = |grid: &mut SiteGrid, x: i32, y: i32, neighbours: &[(i32, i32, f64)]| {
+            let index = (y * width + x) as usize;
+            for (dx, dy, cost) in neighbours {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx >= 0 && ny >= 0 && nx < width && ny < height {
+                    let other = grid.water_distance[(ny * width + nx) as usize] + cost;
+                    if other < grid.water_distance[index] {
+                        grid.water_distance[index] = other;
+                    }
+                }
+            }
+        };
+        let forward = [
+            (-1, 0, 1.),
+            (0, -1, 1.),
+            (-1, -1, diagonal),
+            (1, -1, diagonal),
+        ];
+        for y in 0..height {
+            for x in 0..width {
+                relax(self, x, y, &forward);
+            }
+        }
+        let backward = [(1, 0, 1.), (0, 1, 1.), (1, 1, diagonal), (-1, 1, diagonal)];
+        for y in (0..height).rev() {
+            for x in (0..width).rev() {
+                relax(self, x, y, &backward);
+            }
+        }
+    }
+
+    /// summed area table with an extra row and column of zeros
+    fn summed(&self, cells: &[u32]) -> Vec<u32> {
+        let stride = self.width + 1;
+        let mut sums = vec![0; stride * (self.height + 1)];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                sums[(y + 1) * stride + x + 1] = cells[y * self.width + x]
+                    + sums[y * stride + x + 1]
+                    + sums[(y + 1) * stride + x]
+                    - sums[y * stride + x];
+            }
+        }
+        sums
+    }
+
+    /// tiles of the footprint at grid offset `x`/`y` which are set in `sums`
+    fn count(&self, sums: &[u32], x: usize, y: usize, width: usize, height: usize) -> u32 {
+        let stride = self.width + 1;
+        sums[(y + height) * stride + x + width] + sums[y * stride + x]
+            - sums[y * stride + x + width]
+            - sums[(y + height) * stride + x]
+    }
+
+    /// distance from the footprint to the nearest water tile, touching water is 1
+    fn water_distance(&self, x: usize, y: usize, width: usize, height: usize) -> f64 {
+        let mut distance = f64::INFINITY;
+        for cy in y..y + height {
+            for cx in x..x + width {
+                distance = distance.min(self.water_distance[cy * self.width + cx]);
+            }
+        }
+        distance
+    }
+}
+
+/// marks the tiles covered by more than a sliver of `rect`
+fn fill(cells: &mut [u32], left: i32, top: i32, width: usize, height: usize, rect: &QuadTreeRect) {
+    let from_x = ((rect.min_x() + 0.01).floor() as i32 - left).max(0);
+    let to_x = ((rect.max_x() - 0.01).ceil() as i32 - left).min(width as i32);
+    let from_y = ((rect.min_y() + 0.01).floor() as i32 - top).max(0);
+    let to_y = ((rect.max_y() - 0.01).ceil() as i32 - top).min(height as i32);
+    for y in from_y..to_y {
+        for x in from_x..to_x {
+            cells[y as usize * width + x as usize] = 1;
+        }
+    }
+}
+
+/// Ranked places for a `width` x `height` footprint which meet `constraints`.
+///
+/// The search covers the resource patches of the wanted resource, or a square around
+/// `near`. Candidates never cover blocked tiles like water or buildings and do not
+/// overlap each other, lower scores are better.
+pub fn find_sites(
+    entity_graph: &EntityGraph,
+    width: u32,
+    height: u32,
+    constraints: &SiteConstraints,
+    limit: usize,
+) -> Vec<SiteCandidate> {
+    let near = constraints.near.clone().unwrap_or_default();
+    let radius = constraints.max_distance.unwrap_or(DEFAULT_SEARCH_RADIUS);
+    let around_near = Rect::new(
+        &Position::new(near.x() - radius, near.y() - radius),
+        &Position::new(near.x() + radius, near.y() + radius),
+    );
+    let areas: Vec<Rect> = match constraints.resource.as_ref() {
+        Some(resource) => entity_graph
+            .resource_patches(resource)
+            .into_iter()
+            .map(|patch| {
+                let margin = width.max(height) as f64;
+                Rect::new(
+                    &Position::new(
+                        patch.rect.left_top.x() - margin,
+                        patch.rect.left_top.y() - margin,
+                    ),
+                    &Position::new(
+                        patch.rect.right_bottom.x() + margin,
+                        patch.rect.right_bottom.y() + margin,
+                    ),
+                )
+            })
+            .filter(|area| constraints.max_distance.is_none() || overlaps(area, &around_near))
+            .collect(),
+        None => vec![around_near],
+    };
+    let mut footprints = vec![(Direction::North, width, height)];
+    if constraints.rotations && width != height {
+        footprints.push((Direction::East, height, width));
+    }
+
+    let mut candidates: Vec<SiteCandidate> = vec![];
+    for area in areas {
+        let water_margin = constraints.max_water_distance.unwrap_or(0.).ceil() + 1.;
+        let grid_area = Rect::new(
+            &Position::new(
+                area.left_top.x() - water_margin,
+                area.left_top.y() - water_margin,
+            ),
+            &Position::new(
+                area.right_bottom.x() + water_margin,
+                area.right_bottom.y() + water_margin,
+            ),
+        );
+        let grid = SiteGrid::new(entity_graph, &grid_area, constraints);
+        for (direction, width, height) in &footprints {
+            let (width, height) = (*width as usize, *height as usize);
+            if width > grid.width || height > grid.height {
+                continue;
+            }
+            for y in 0..=grid.height - height {
+                for x in 0..=grid.width - width {
+                    let left_top =
+                        Position::new((grid.left + x as i32) as f64, (grid.top + y as i32) as f64);
+                    let rect = Rect::new(
+                        &left_top,
+                        &Position::new(left_top.x() + width as f64, left_top.y() + height as f64),
+                    );
+                    let position = rect.center();
+                    let distance = calculate_distance(&position, &near);
+                    if !area.contains(&position)
+                        || constraints
+                            .max_distance
+                            .is_some_and(|max_distance| distance > max_distance)
+                        || grid.count(&grid.blocked, x, y, width, height) > 0
+                    {
+                        continue;
+                    }
+                    let obstructions = grid.count(&grid.obstructed, x, y, width, height);
+                    if obstructions > 0 && !constraints.allow_trees {
+                        continue;
+                    }
+                    let resource_tiles = grid.count(&grid.resources, x, y, width, height);
+                    if resource_tiles < constraints.min_resource_tiles {
+                        continue;
+                    }
+                    let water_distance = match constraints.max_water_distance {
+                        Some(max_water_distance) => {
+                            let water_distance = grid.water_distance(x, y, width, height);
+                            if water_distance > max_water_distance {
+                                continue;
+                            }
+                            Some(water_distance)
+                        }
+                        None => None,
+                    };
+                    let score = if constraints.near.is_some() {
+                        distance
+                    } else {
+                        0.
+                    } + obstructions as f64 * OBSTRUCTION_COST
+                        - resource_tiles as f64 * RESOURCE_TILE_BONUS;
+                    candidates.push(SiteCandidate {
+                        position,
+                        direction: direction.to_u8().unwrap(),
+                        rect,
+                        resource_tiles,
+                        obstructions,
+                        water_distance,
+                        score,
+                    });
+                }
+            }
+        }
+    }
+    candidates.sort_by(|a, b| a.score.total_cmp(&b.score));
+    let mut sites: Vec<SiteCandidate> = vec![];
+    for candidate in candidates {
+        if sites.len() >= limit {
+            break;
+        }
+        if !sites
+            .iter()
+            .any(|site| overlaps(&site.rect, &candidate.rect))
+        {
+            sites.push(candidate);
+        }
+    }
+    sites
+}
+
+fn overlaps(a: &Rect, b: &Rect) -> bool {
+    a.left_top.x() < b.right_bottom.x()
+        && b.left_top.x() < a.right_bottom.x()
+        && a.left_top.y() < b.right_bottom.y()
+        && b.left_top.y() < a.right_bottom.y()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixture_world;
+
+    #[test]
+    fn test_find_sites() {
+        let world = fixture_world();
+        let entity_graph = world.entity_graph.clone();
+
+        let sites = find_sites(
+            &entity_graph,
+            3,
+            3,
+            &SiteConstraints {
+                resource: Some("iron-ore".into()),
+                min_resource_tiles: 9,
+                near: Some(Position::new(0., 0.)),
+                ..Default::default()
+            },
+            3,
+        );
+        assert_eq!(sites.len(), 3);
+        for site in &sites {
+            assert_eq!(site.resource_tiles, 9);
+        }
+        assert!(sites[0].score <= sites[1].score);
+
+        let sites = find_sites(
+            &entity_graph,
+            2,
+            4,
+            &SiteConstraints {
+                max_water_distance: Some(2.),
+                near: Some(Position::new(30., 40.)),
+                rotations: true,
+                ..Default::default()
+            },
+            1,
+        );
+        assert_eq!(sites.len(), 1);
+        let site = &sites[0];
+        assert!(site.water_distance.unwrap() <= 2.);
+        assert!(site.rect.right_bottom.x() <= 38.);
+
+        // the trees are spread around -20/-20
+        let in_forest = SiteConstraints {
+            near: Some(Position::new(-20., -20.)),
+            max_distance: Some(2.),
+            ..Default::default()
+        };
+        assert!(find_sites(&entity_graph, 4, 4, &in_forest, 5).is_empty());
+        let sites = find_sites(
+            &entity_graph,
+            4,
+            4,
+            &SiteConstraints {
+                allow_trees: true,
+                ..in_forest
+            },
+            5,
+        );
+        assert!(!sites.is_empty());
+        assert!(sites.iter().all(|site| site.obstructions > 0));
+    }
+}
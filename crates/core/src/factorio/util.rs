@@ -1,5 +1,6 @@
-use crate::errors::{RconNoPathFound, RconSourcePositionBlocked, RconTargetPositionBlocked};
-use crate::graph::entity_graph::QuadTreeRect;
+use crate::errors::{BlueprintBookUnsupported, LayoutUnknownEntity};
+use crate::factorio::router::{LogisticsRouter, RouteEndpoint, RouteEntities, RouteRequest};
+use crate::graph::entity_graph::{EntityGraph, QuadTreeRect};
 use crate::types::{
     Direction, FactorioEntity, FactorioEntityPrototype, FactorioTile, Pos, Position, Rect,
 };
@@ -10,7 +11,6 @@ use human_sort::compare;
 use itertools::Itertools;
 use miette::{IntoDiagnostic, Result};
 use num_traits::ToPrimitive;
-use pathfinding::prelude::astar;
 use serde_json::Value;
 use std::cmp::Ordering;
 use std::collections::HashMap;
@@ -70,6 +70,14 @@ pub fn str_to_lua(str: &str) -> String {
     format!("'{}'", str)
 }
 
+/// surface name argument, `nil` selects the first surface
+pub fn surface_to_lua(surface: &Option<String>) -> String {
+    match surface {
+        Some(surface) => str_to_lua(surface),
+        None => String::from("nil"),
+    }
+}
+
 pub fn calculate_distance(pos1: &Position, pos2: &Position) -> f64 {
     let x = pos1.x() - pos2.x();
     let y = pos1.y() - pos2.y();
@@ -201,12 +209,12 @@ pub fn expand_rect(total_rect: &mut Rect, rect: &Rect) {
 pub fn blueprint_build_area(
     entity_prototypes: Arc<DashMap<String, FactorioEntityPrototype>>,
     blueprint: &str,
-) -> Rect {
-    let decoded = BlueprintCodec::decode_string(blueprint).expect("failed to decode blueprint");
+) -> Result<Rect> {
+    let decoded = BlueprintCodec::decode_string(blueprint).into_diagnostic()?;
     let mut build_area = Rect::new(&Position::new(999.0, 999.0), &Position::new(-999.0, -999.0));
     match decoded {
         BlueprintBook(_blueprint_book) => {
-            panic!("blueprint books are not supported!");
+            return Err(BlueprintBookUnsupported {}.into());
         }
         Blueprint(blueprint) => {
             for entity in blueprint.entities {
@@ -223,7 +231,7 @@ pub fn blueprint_build_area(
             }
         }
     };
-    build_area
+    Ok(build_area)
 }
 
 pub fn vector_length(vector: &Position) -> f64 {
@@ -260,6 +268,39 @@ pub fn span_rect(a: &Position, b: &Position, margin: f64) -> Rect {
     )
 }
 
+/// Width of an entity in whole tiles, layouts assume square entities
+pub fn entity_tile_size(
+    prototypes: &DashMap<String, FactorioEntityPrototype>,
+    name: &str,
+) -> Result<i32> {
+    prototypes
+        .get(name)
+        .map(|prototype| prototype.collision_box.width().ceil() as i32)
+        .ok_or_else(|| {
+            LayoutUnknownEntity {
+                name: name.to_owned(),
+            }
+            .into()
+        })
+}
+
+/// True if anything but trees and rocks, which get mined, is in the way of `footprint`
+pub fn footprint_blocked(entity_graph: &EntityGraph, footprint: &Rect) -> bool {
+    let inner = Rect::new(
+        &Position::new(footprint.left_top.x() + 0.1, footprint.left_top.y() + 0.1),
+        &Position::new(
+            footprint.right_bottom.x() - 0.1,
+            footprint.right_bottom.y() - 0.1,
+        ),
+    );
+    let query: QuadTreeRect = inner.into();
+    entity_graph
+        .blocked_tree()
+        .query(query)
+        .into_iter()
+        .any(|(minable, _, _)| !*minable)
+}
+
 #[allow(clippy::ptr_arg)]
 pub fn bounding_box(elements: &Vec<Position>) -> Option<Rect> {
     let min_max_positions = elements.iter().fold(
@@ -341,6 +382,7 @@ pub fn map_blocked_tiles(
     blocked
 }
 
+/// Routes one belt or pipe line, see `LogisticsRouter` for buses, lanes and reserved zones
 #[allow(clippy::too_many_arguments)]
 pub fn build_entity_path(
     entity_prototypes: Arc<DashMap<String, FactorioEntityPrototype>>,
@@ -355,118 +397,19 @@ pub fn build_entity_path(
     block_entities: Vec<FactorioEntity>,
     block_tiles: Vec<FactorioTile>,
 ) -> Result<Vec<FactorioEntity>> {
-    let from_position: Pos = from_position.into();
-    let to_position: Pos = to_position.into();
-    let blocked = map_blocked_tiles(
-        entity_prototypes,
-        &block_entities.iter().collect(),
-        &block_tiles.iter().collect(),
-    );
-    if blocked.contains_key(&from_position) {
-        return Err(RconSourcePositionBlocked {}.into());
-    }
-    if blocked.contains_key(&to_position) {
-        return Err(RconTargetPositionBlocked {}.into());
-    }
-    // info!("start pathfinding");
-    let path = astar(
-        &(from_position.clone(), from_position.clone(), from_position),
-        move |(last_last_pos, last_pos, current_pos)| {
-            let mut options: Vec<((Pos, Pos, Pos), i32)> = vec![];
-            let current_direction = relative_direction(last_pos, current_pos);
-            let last_dist = last_pos.distance(last_last_pos);
-            for direction in Direction::orthogonal() {
-                // we cannot move in the opposite direction after we have moved
-                if current_pos != last_pos && direction == current_direction.opposite() {
-                    continue;
-                }
-                // after underground we need to go straight
-                if last_dist > 1 && direction != current_direction {
-                    continue;
-                }
-                for length in 1..(underground_max + 1) {
-                    // after underground we cannot immediately underground again
-                    if last_dist > 1 && length > 1 {
-                        break;
-                    }
-                    let target: Pos = move_pos(current_pos, direction, length as i32);
-                    if !blocked.contains_key(&target) {
-                        options.push((
-                            (last_pos.clone(), current_pos.clone(), target),
-                            if length == 1 { 1 } else { length as i32 * 3 },
-                        ));
-                    }
-                    if last_pos != current_pos {
-                        // before underground we need a straight connection
-                        if last_dist < 2 && direction != current_direction {
-                            break;
-                        }
-                    }
-                }
-            }
-            options
+    let entities = RouteEntities {
+        name: entity_name.into(),
+        entity_type: entity_type.into(),
+        underground_name: underground_entity_name.into(),
+        underground_type: underground_entity_type.into(),
+        underground_max,
+    };
+    LogisticsRouter::new(entity_prototypes, entities, &block_entities, &block_tiles).route(
+        &RouteRequest {
+            from: RouteEndpoint::new(from_position.clone(), None),
+            to: RouteEndpoint::new(to_position.clone(), Some(to_direction)),
         },
-        |(_, _, pos)| (pos.distance(&to_position) / 3) as i32,
-        |(_, _, pos)| *pos == to_position,
-    );
-    // info!("finished pathfinding");
-    let mirror_direction = underground_entity_type == "pipe-to-ground";
-    match path {
-        Some((path, _cost)) => {
-            let mut result: Vec<FactorioEntity> = vec![];
-
-            for i in 0..path.len() {
-                let (_, last_pos, pos) = &path[i];
-                let next: Option<&Pos> = if i + 1 < path.len() {
-                    Some(&path[i + 1].2)
-                } else {
-                    None
-                };
-
-                let direction = if let Some(next) = next {
-                    relative_direction(pos, next)
-                } else {
-                    to_direction
-                };
-
-                let distance = if last_pos != pos {
-                    pos.distance(last_pos)
-                } else {
-                    1
-                };
-
-                if distance == 1 {
-                    result.push(FactorioEntity {
-                        name: entity_name.into(),
-                        entity_type: entity_type.into(),
-                        position: Position::new(pos.0 as f64, pos.1 as f64),
-                        direction: if mirror_direction {
-                            direction.opposite().to_u8().unwrap()
-                        } else {
-                            direction.to_u8().unwrap()
-                        },
-                        ..Default::default()
-                    });
-                } else {
-                    result[i - 1].name = underground_entity_name.into();
-                    result[i - 1].entity_type = underground_entity_type.into();
-                    result.push(FactorioEntity {
-                        name: underground_entity_name.into(),
-                        entity_type: underground_entity_type.into(),
-                        position: Position::new(pos.0 as f64, pos.1 as f64),
-                        direction: if mirror_direction {
-                            direction.to_u8().unwrap()
-                        } else {
-                            direction.opposite().to_u8().unwrap()
-                        },
-                        ..Default::default()
-                    });
-                }
-            }
-            Ok(result)
-        }
-        None => Err(RconNoPathFound {}.into()),
-    }
+    )
 }
 
 pub fn floor_position(position: &Position) -> Position {
@@ -478,6 +421,15 @@ pub fn position_equal(a: &Position, b: &Position) -> bool {
         && (a.y().floor() - b.y().floor()).abs() < f64::EPSILON
 }
 
+/// like `Rect::contains` but includes the left and top edge,
+/// so every position belongs to exactly one of multiple adjacent rects
+pub fn position_in_area(position: &Position, area: &Rect) -> bool {
+    position.x() >= area.left_top.x()
+        && position.x() < area.right_bottom.x()
+        && position.y() >= area.left_top.y()
+        && position.y() < area.right_bottom.y()
+}
+
 pub fn rect_fields(rect: &Rect) -> Vec<Position> {
     let mut res = vec![];
     for y in rect.left_top.y().floor() as i32..=rect.right_bottom.y().floor() as i32 {
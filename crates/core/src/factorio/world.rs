@@ -1,20 +1,70 @@
+use crate::constants::{DEFAULT_SURFACE, NEUTRAL_FORCE, PLAYER_FORCE};
+use crate::errors::{OutpostNoPatch, PathThroughDangerZone};
+use crate::factorio::blueprint::encode_blueprint;
+use crate::factorio::outpost::plan_outpost;
+use crate::factorio::power_plant::plan_power_plant;
+use crate::factorio::production::{assembler_line, smelting_column};
+use crate::factorio::site_finder::find_sites;
+use crate::factorio::util::{blueprint_build_area, position_in_area, vector_substract};
 use crate::graph::entity_graph::EntityGraph;
 use crate::graph::flow_graph::FlowGraph;
+use crate::graph::power_graph::PowerGraph;
+use crate::graph::walk_grid::find_walk_path;
+use crate::plan::walk_cost::{WalkCalibration, WalkCostModel};
+use crate::process::output_parser::OutputParserMetrics;
 use crate::types::{
-    FactorioEntity, FactorioEntityPrototype, FactorioForce, FactorioGraphic, FactorioItemPrototype,
-    FactorioPlayer, FactorioRecipe, FactorioTile, PlayerChangedDistanceEvent,
-    PlayerChangedMainInventoryEvent, PlayerChangedPositionEvent, PlayerId,
+    AssemblerLineOptions, BlueprintWire, ChunkEntities, ChunkPosition, DangerZone, Direction,
+    EntityAttackedEvent, EntityDiff, EntityStateChangedEvent, EntityType, FactorioEntity,
+    FactorioEntityPrototype, FactorioEntityState, FactorioForce, FactorioGraphic,
+    FactorioItemPrototype, FactorioPlayer, FactorioRecipe, FactorioTile, FactorioTilePrototype,
+    OutpostLayout, OutpostOptions, PlayerChangedDistanceEvent, PlayerChangedMainInventoryEvent,
+    PlayerChangedPositionEvent, PlayerId, PollutionChangedEvent, Pos, Position, PowerPlantLayout,
+    PowerPlantOptions, ProductionBlock, Rect, SiteCandidate, SiteConstraints,
+    SmeltingColumnOptions,
 };
 use dashmap::DashMap;
 use image::RgbaImage;
 use miette::{IntoDiagnostic, Result};
+use parking_lot::RwLock;
 use serde::de::{MapAccess, Visitor};
 use serde::ser::SerializeStruct;
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::BTreeMap;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::{fmt, fs};
-use tokio::sync::Mutex;
+use tokio::sync::{broadcast, Mutex};
+
+/// spawners are guarded by the biters and worms around them
+const SPAWNER_DANGER_RADIUS: f64 = 24.;
+/// range of behemoth worms, smaller worms reach less far
+const WORM_DANGER_RADIUS: f64 = 48.;
+const UNIT_DANGER_RADIUS: f64 = 16.;
+/// attack events kept for subscribers which fall behind
+const ATTACK_EVENT_BUFFER: usize = 64;
+
+/// Entity, tile and resource state of a single surface (planet or space platform)
+pub struct FactorioSurface {
+    pub entity_graph: Arc<EntityGraph>,
+    pub flow_graph: Arc<FlowGraph>,
+    pub power_graph: Arc<PowerGraph>,
+}
+
+impl FactorioSurface {
+    fn new(
+        entity_prototypes: Arc<DashMap<String, FactorioEntityPrototype>>,
+        recipes: Arc<DashMap<String, FactorioRecipe>>,
+    ) -> FactorioSurface {
+        let entity_graph = Arc::new(EntityGraph::new(entity_prototypes, recipes));
+        let flow_graph = Arc::new(FlowGraph::new(entity_graph.clone()));
+        let power_graph = Arc::new(PowerGraph::new(entity_graph.clone()));
+        FactorioSurface {
+            entity_graph,
+            flow_graph,
+            power_graph,
+        }
+    }
+}
 
 pub struct FactorioWorld {
     pub players: DashMap<PlayerId, FactorioPlayer>,
@@ -23,12 +73,31 @@ pub struct FactorioWorld {
     pub recipes: Arc<DashMap<String, FactorioRecipe>>,
     pub entity_prototypes: Arc<DashMap<String, FactorioEntityPrototype>>,
     pub item_prototypes: DashMap<String, FactorioItemPrototype>,
+    /// walking speed modifiers by tile name
+    pub tile_prototypes: DashMap<String, FactorioTilePrototype>,
+    /// measured walking speed of all players, see `WalkCostModel`
+    pub walk_calibration: RwLock<WalkCalibration>,
     pub image_cache: DashMap<String, Box<RgbaImage>>,
     pub actions: DashMap<u32, String>,
     pub path_requests: DashMap<u32, String>,
+    /// finished throughput samplings by request id, see `FactorioRcon::sample_throughput`
+    pub throughput_samplings: DashMap<u32, String>,
     pub next_action_id: Mutex<u32>,
+    /// graphs of the default surface
     pub entity_graph: Arc<EntityGraph>,
     pub flow_graph: Arc<FlowGraph>,
+    pub power_graph: Arc<PowerGraph>,
+    /// all surfaces besides the default surface, created when BotBridge reports their chunks
+    pub surfaces: DashMap<String, Arc<FactorioSurface>>,
+    /// inventories and status of machines and containers by surface and position
+    pub entity_states: DashMap<(String, Pos), FactorioEntityState>,
+    /// spawners, worms and enemy units by surface and position
+    pub enemies: DashMap<(String, Pos), FactorioEntity>,
+    /// pollution by surface and chunk, chunks without pollution are left out
+    pub pollution: DashMap<(String, ChunkPosition), f64>,
+    /// entities damaged or killed by the enemy force, see `subscribe_attacks`
+    pub attacks: broadcast::Sender<EntityAttackedEvent>,
+    pub parser_metrics: Arc<OutputParserMetrics>,
 }
 
 impl FactorioWorld {
@@ -54,6 +123,17 @@ impl FactorioWorld {
         Ok(())
     }
 
+    pub fn update_tile_prototypes(
+        &self,
+        tile_prototypes: Vec<FactorioTilePrototype>,
+    ) -> Result<()> {
+        for tile_prototype in tile_prototypes {
+            self.tile_prototypes
+                .insert(tile_prototype.name.clone(), tile_prototype);
+        }
+        Ok(())
+    }
+
     pub fn remove_player(&self, player_id: PlayerId) -> Result<()> {
         self.players.remove(&player_id);
         Ok(())
@@ -65,6 +145,7 @@ impl FactorioWorld {
             FactorioPlayer {
                 player_id: event.player_id,
                 position: existing_player.position.clone(),
+                surface: existing_player.surface.clone(),
                 main_inventory: existing_player.main_inventory.clone(),
                 build_distance: event.build_distance,
                 reach_distance: event.reach_distance,
@@ -72,6 +153,7 @@ impl FactorioWorld {
                 item_pickup_distance: event.item_pickup_distance,
                 loot_pickup_distance: event.loot_pickup_distance,
                 resource_reach_distance: event.resource_reach_distance,
+                movement_bonus: event.movement_bonus,
             }
         } else {
             FactorioPlayer {
@@ -82,6 +164,7 @@ impl FactorioWorld {
                 item_pickup_distance: event.item_pickup_distance,
                 loot_pickup_distance: event.loot_pickup_distance,
                 resource_reach_distance: event.resource_reach_distance,
+                movement_bonus: event.movement_bonus,
                 ..Default::default()
             }
         };
@@ -95,6 +178,7 @@ impl FactorioWorld {
             FactorioPlayer {
                 player_id: event.player_id,
                 position: event.position,
+                surface: event.surface,
                 main_inventory: existing_player.main_inventory.clone(),
                 build_distance: existing_player.build_distance,
                 reach_distance: existing_player.reach_distance,
@@ -102,11 +186,13 @@ impl FactorioWorld {
                 item_pickup_distance: existing_player.item_pickup_distance,
                 loot_pickup_distance: existing_player.loot_pickup_distance,
                 resource_reach_distance: existing_player.resource_reach_distance,
+                movement_bonus: existing_player.movement_bonus,
             }
         } else {
             FactorioPlayer {
                 player_id: event.player_id,
                 position: event.position,
+                surface: event.surface,
                 ..Default::default()
             }
         };
@@ -114,6 +200,24 @@ impl FactorioWorld {
         Ok(())
     }
 
+    /// Calibrates walking times with the position change of a player at `tick`,
+    /// must be called before `player_changed_position` updates the player.
+    pub fn record_walk_sample(&self, tick: u64, event: &PlayerChangedPositionEvent) {
+        let speed = {
+            let previous = self
+                .players
+                .get(&event.player_id)
+                .map(|player| player.position.clone())
+                .unwrap_or_else(|| event.position.clone());
+            let surface = self.surface(event.surface.as_deref());
+            let tiles = surface.entity_graph.tile_tree();
+            WalkCostModel::new(self, event.player_id).predicted_speed_at(&tiles, &previous)
+        };
+        self.walk_calibration
+            .write()
+            .record(event.player_id, tick, &event.position, speed);
+    }
+
     pub fn update_force(&self, force: FactorioForce) -> Result<()> {
         let name = force.name.clone();
         self.forces.insert(name, force);
@@ -127,27 +231,396 @@ impl FactorioWorld {
 
     pub fn on_some_entity_created(&self, entity: FactorioEntity) -> Result<()> {
         info!("XXX on_some_entity_created {:?}", &entity);
-        self.entity_graph.add(vec![entity], None)?;
+        if is_enemy(&entity) {
+            self.add_enemy(entity);
+            return Ok(());
+        }
+        self.get_or_insert_surface(Some(entity.surface()))
+            .entity_graph
+            .add(vec![entity], None)?;
         Ok(())
     }
 
     pub fn on_some_entity_deleted(&self, entity: FactorioEntity) -> Result<()> {
-        self.entity_graph.remove(&entity)?;
+        if is_enemy(&entity) {
+            self.enemies
+                .remove(&(entity.surface().to_owned(), (&entity.position).into()));
+            return Ok(());
+        }
+        self.surface(Some(entity.surface()))
+            .entity_graph
+            .remove(&entity)?;
+        self.entity_states
+            .remove(&(entity.surface().to_owned(), (&entity.position).into()));
         Ok(())
     }
 
+    pub fn on_entity_state_changed(&self, tick: u64, event: EntityStateChangedEvent) -> Result<()> {
+        let surface = event.surface.unwrap_or_else(|| DEFAULT_SURFACE.to_owned());
+        let inventories = event
+            .inventories
+            .into_iter()
+            .map(|(inventory, items)| {
+                let items = items.into_iter().fold(BTreeMap::new(), |mut acc, item| {
+                    *acc.entry(item.key()).or_insert(0) += item.count;
+                    acc
+                });
+                (inventory, items)
+            })
+            .collect();
+        self.entity_states.insert(
+            (surface, (&event.position).into()),
+            FactorioEntityState {
+                name: event.name,
+                position: event.position,
+                status: event.status,
+                inventories,
+                tick,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn on_entity_attacked(&self, event: EntityAttackedEvent) -> Result<()> {
+        // sending only fails if nobody is subscribed
+        let _ = self.attacks.send(event);
+        Ok(())
+    }
+
+    /// Receives every entity damaged or killed by the enemy force from now on
+    pub fn subscribe_attacks(&self) -> broadcast::Receiver<EntityAttackedEvent> {
+        self.attacks.subscribe()
+    }
+
+    pub fn on_pollution_changed(&self, event: PollutionChangedEvent) -> Result<()> {
+        let surface = event.surface.unwrap_or_else(|| DEFAULT_SURFACE.to_owned());
+        self.pollution.retain(|(name, _), _| *name != surface);
+        for chunk in event.chunks {
+            self.pollution.insert(
+                (
+                    surface.clone(),
+                    ChunkPosition {
+                        x: chunk.x,
+                        y: chunk.y,
+                    },
+                ),
+                chunk.pollution,
+            );
+        }
+        Ok(())
+    }
+
+    /// Pollution of the chunk containing given position
+    pub fn pollution_at(&self, surface: Option<&str>, position: &Position) -> f64 {
+        let chunk = ChunkPosition {
+            x: (position.x() / 32.).floor() as i32,
+            y: (position.y() / 32.).floor() as i32,
+        };
+        self.pollution
+            .get(&(surface.unwrap_or(DEFAULT_SURFACE).to_owned(), chunk))
+            .map_or(0., |pollution| *pollution)
+    }
+
+    fn add_enemy(&self, entity: FactorioEntity) {
+        self.enemies.insert(
+            (entity.surface().to_owned(), (&entity.position).into()),
+            entity,
+        );
+    }
+
+    /// Areas around known enemies on given surface
+    pub fn danger_zones(&self, surface: Option<&str>) -> Vec<DangerZone> {
+        let surface = surface.unwrap_or(DEFAULT_SURFACE);
+        self.enemies
+            .iter()
+            .filter(|enemy| enemy.key().0 == surface)
+            .filter_map(|enemy| {
+                let radius = match EntityType::from_str(&enemy.entity_type).ok()? {
+                    EntityType::UnitSpawner => SPAWNER_DANGER_RADIUS,
+                    EntityType::Turret => WORM_DANGER_RADIUS,
+                    _ => UNIT_DANGER_RADIUS,
+                };
+                Some(DangerZone {
+                    name: enemy.name.clone(),
+                    position: enemy.position.clone(),
+                    radius,
+                })
+            })
+            .collect()
+    }
+
+    pub fn danger_zone_at(&self, surface: Option<&str>, position: &Position) -> Option<DangerZone> {
+        self.danger_zones(surface)
+            .into_iter()
+            .find(|zone| zone.contains(position))
+    }
+
+    pub fn is_rect_dangerous(&self, surface: Option<&str>, rect: &Rect) -> bool {
+        self.danger_zones(surface)
+            .iter()
+            .any(|zone| zone.intersects_rect(rect))
+    }
+
+    /// Passes the path through if it stays out of all danger zones, fails otherwise.
+    /// This only checks paths found by factorio, `walk_path` routes around the zones.
+    pub fn avoid_danger(
+        &self,
+        surface: Option<&str>,
+        path: Vec<Position>,
+    ) -> Result<Vec<Position>> {
+        let zones = self.danger_zones(surface);
+        for (index, position) in path.iter().enumerate() {
+            let next = path.get(index + 1).unwrap_or(position);
+            if let Some(zone) = zones
+                .iter()
+                .find(|zone| zone.intersects_line(position, next))
+            {
+                return Err(PathThroughDangerZone {
+                    name: zone.name.clone(),
+                    position: zone.position.clone(),
+                }
+                .into());
+            }
+        }
+        Ok(path)
+    }
+
+    /// Walking path over the known blocked tiles of the surface which stays out of
+    /// danger zones, see `WalkGrid`
+    pub fn walk_path(
+        &self,
+        surface: Option<&str>,
+        start: &Position,
+        goal: &Position,
+        radius: Option<f64>,
+    ) -> Result<Vec<Position>> {
+        find_walk_path(
+            &self.surface(surface).entity_graph.blocked_tree(),
+            start,
+            goal,
+            radius,
+            &self.danger_zones(surface),
+        )
+    }
+
+    /// Seconds for given player to walk from `start` to within `radius` of `goal`,
+    /// along the native walking path or straight if none is found
+    pub fn walk_time(
+        &self,
+        player_id: PlayerId,
+        surface: Option<&str>,
+        start: &Position,
+        goal: &Position,
+        radius: Option<f64>,
+    ) -> f64 {
+        let path = self
+            .walk_path(surface, start, goal, radius)
+            .unwrap_or_else(|_| vec![goal.clone()]);
+        let surface = self.surface(surface);
+        let tiles = surface.entity_graph.tile_tree();
+        WalkCostModel::new(self, player_id).walk_time(&tiles, start, &path)
+    }
+
+    /// Up to `n` entities or resource tiles nearest to `position`, nearest first
+    pub fn nearest(
+        &self,
+        surface: Option<&str>,
+        position: &Position,
+        n: usize,
+        name: Option<&str>,
+        entity_type: Option<&str>,
+    ) -> Vec<FactorioEntity> {
+        self.surface(surface)
+            .entity_graph
+            .nearest(position, n, name, entity_type)
+    }
+
+    /// True if no building, tree, rock or water blocks the line from `from` to `to`
+    pub fn line_of_sight(&self, surface: Option<&str>, from: &Position, to: &Position) -> bool {
+        self.surface(surface).entity_graph.line_of_sight(from, to)
+    }
+
+    /// Entities crossed by the line from `from` to `to`, in order along it
+    pub fn entities_along(
+        &self,
+        surface: Option<&str>,
+        from: &Position,
+        to: &Position,
+    ) -> Vec<FactorioEntity> {
+        self.surface(surface).entity_graph.entities_along(from, to)
+    }
+
+    /// Entities by chunk up to `radius` chunks around `position`, ring by ring
+    pub fn chunk_rings(
+        &self,
+        surface: Option<&str>,
+        position: &Position,
+        radius: u32,
+    ) -> Vec<ChunkEntities> {
+        self.surface(surface)
+            .entity_graph
+            .chunk_rings(position, radius)
+    }
+
+    /// Ranked sites for a `width` x `height` footprint, see `find_sites`
+    pub fn find_sites(
+        &self,
+        surface: Option<&str>,
+        width: u32,
+        height: u32,
+        constraints: &SiteConstraints,
+        limit: usize,
+    ) -> Vec<SiteCandidate> {
+        find_sites(
+            &self.surface(surface).entity_graph,
+            width,
+            height,
+            constraints,
+            limit,
+        )
+    }
+
+    /// Ranked sites for the build area of `blueprint`.
+    ///
+    /// Positions are the blueprint origin, where to place it unrotated. Turned footprints
+    /// are not tried, `constraints.rotations` is ignored.
+    pub fn find_blueprint_sites(
+        &self,
+        surface: Option<&str>,
+        blueprint: &str,
+        constraints: &SiteConstraints,
+        limit: usize,
+    ) -> Result<Vec<SiteCandidate>> {
+        let build_area = blueprint_build_area(self.entity_prototypes.clone(), blueprint)?;
+        let constraints = SiteConstraints {
+            rotations: false,
+            ..constraints.clone()
+        };
+        Ok(self
+            .find_sites(
+                surface,
+                build_area.width().ceil() as u32,
+                build_area.height().ceil() as u32,
+                &constraints,
+                limit,
+            )
+            .into_iter()
+            .map(|site| SiteCandidate {
+                position: vector_substract(&site.rect.left_top, &build_area.left_top),
+                ..site
+            })
+            .collect())
+    }
+
+    /// Blueprint string for given entities, see `encode_blueprint`
+    pub fn make_blueprint(
+        &self,
+        label: &str,
+        entities: &[FactorioEntity],
+        wires: &[BlueprintWire],
+    ) -> Result<String> {
+        encode_blueprint(&self.entity_prototypes, label, entities, wires)
+    }
+
+    /// Mining outpost on the `resource` patch nearest to `near`, or the largest patch
+    pub fn plan_outpost(
+        &self,
+        surface: Option<&str>,
+        resource: &str,
+        near: Option<&Position>,
+        options: &OutpostOptions,
+    ) -> Result<OutpostLayout> {
+        let entity_graph = self.surface(surface).entity_graph.clone();
+        let mut patches = entity_graph.resource_patches(resource);
+        if let Some(near) = near {
+            patches.sort_by(|a, b| {
+                a.rect
+                    .center()
+                    .distance(near)
+                    .total_cmp(&b.rect.center().distance(near))
+            });
+        }
+        let patch = patches.first().ok_or_else(|| OutpostNoPatch {
+            resource: resource.to_owned(),
+        })?;
+        plan_outpost(&entity_graph, patch, options)
+    }
+
+    /// Steam power plant fed by an offshore pump on one of `pump_options`, wired towards `base`
+    pub fn plan_power_plant(
+        &self,
+        surface: Option<&str>,
+        pump_options: &[Position],
+        pump_direction: Direction,
+        base: &Position,
+        options: &PowerPlantOptions,
+    ) -> Result<PowerPlantLayout> {
+        let entity_graph = self.surface(surface).entity_graph.clone();
+        plan_power_plant(&entity_graph, pump_options, pump_direction, base, options)
+    }
+
+    /// Furnace column with its left top corner at `position`, see `smelting_column`
+    pub fn plan_smelting_column(
+        &self,
+        position: &Position,
+        options: &SmeltingColumnOptions,
+    ) -> Result<ProductionBlock> {
+        smelting_column(
+            self.entity_prototypes.clone(),
+            self.recipes.clone(),
+            position,
+            options,
+        )
+    }
+
+    /// Assembling machine line with its left top corner at `position`, see `assembler_line`
+    pub fn plan_assembler_line(
+        &self,
+        position: &Position,
+        options: &AssemblerLineOptions,
+    ) -> Result<ProductionBlock> {
+        assembler_line(
+            self.entity_prototypes.clone(),
+            self.recipes.clone(),
+            position,
+            options,
+        )
+    }
+
+    /// Last known state of the entity at or covering given position
+    pub fn entity_state(
+        &self,
+        surface: Option<&str>,
+        position: &Position,
+    ) -> Option<FactorioEntityState> {
+        let surface_name = surface.unwrap_or(DEFAULT_SURFACE);
+        if let Some(state) = self
+            .entity_states
+            .get(&(surface_name.to_owned(), position.into()))
+        {
+            return Some(state.clone());
+        }
+        let entity_graph = self.surface(surface).entity_graph.clone();
+        let entity = entity_graph
+            .entity_at(position)
+            .and_then(|id| entity_graph.entity_by_id(id))?;
+        self.entity_states
+            .get(&(surface_name.to_owned(), (&entity.position).into()))
+            .map(|state| state.clone())
+    }
+
     pub fn player_changed_main_inventory(
         &self,
         event: PlayerChangedMainInventoryEvent,
     ) -> Result<()> {
         // Convert Vec<InventoryItemWithQuality> to BTreeMap<String, u32>
-        // (sum counts by item name, ignore quality for now)
+        // keyed by item_key, so normal items keep their plain name
         let main_inventory: BTreeMap<String, u32> =
             event
                 .main_inventory
                 .into_iter()
                 .fold(BTreeMap::new(), |mut acc, item| {
-                    *acc.entry(item.name).or_insert(0) += item.count;
+                    *acc.entry(item.key()).or_insert(0) += item.count;
                     acc
                 });
 
@@ -156,6 +629,7 @@ impl FactorioWorld {
             FactorioPlayer {
                 player_id: event.player_id,
                 position: existing_player.position.clone(),
+                surface: existing_player.surface.clone(),
                 main_inventory,
                 build_distance: existing_player.build_distance,
                 reach_distance: existing_player.reach_distance,
@@ -163,6 +637,7 @@ impl FactorioWorld {
                 item_pickup_distance: existing_player.item_pickup_distance,
                 loot_pickup_distance: existing_player.loot_pickup_distance,
                 resource_reach_distance: existing_player.resource_reach_distance,
+                movement_bonus: existing_player.movement_bonus,
             }
         } else {
             FactorioPlayer {
@@ -190,16 +665,212 @@ impl FactorioWorld {
     }
 
     pub fn update_chunk_tiles(&self, tiles: Vec<FactorioTile>) -> Result<()> {
-        self.entity_graph.add_tiles(tiles, None)?; // FIXME: add clear rect from chunk_position
+        let mut by_surface: BTreeMap<String, Vec<FactorioTile>> = BTreeMap::new();
+        for tile in tiles {
+            by_surface
+                .entry(tile.surface().to_owned())
+                .or_default()
+                .push(tile);
+        }
+        for (surface, tiles) in by_surface {
+            self.get_or_insert_surface(Some(&surface))
+                .entity_graph
+                .add_tiles(tiles, None)?;
+        }
         Ok(())
     }
 
-    #[allow(clippy::map_clone)]
     pub fn update_chunk_entities(&self, entities: Vec<FactorioEntity>) -> Result<()> {
-        self.entity_graph.add(entities, None)?; // FIXME: add clear rect
+        let mut by_surface: BTreeMap<String, Vec<FactorioEntity>> = BTreeMap::new();
+        for entity in entities {
+            if is_enemy(&entity) {
+                self.add_enemy(entity);
+                continue;
+            }
+            by_surface
+                .entry(entity.surface().to_owned())
+                .or_default()
+                .push(entity);
+        }
+        for (surface, entities) in by_surface {
+            self.get_or_insert_surface(Some(&surface))
+                .entity_graph
+                .add(entities, None)?;
+        }
         Ok(())
     }
 
+    /// Replaces all tiles within `rect` on given surface
+    pub fn replace_chunk_tiles(
+        &self,
+        surface: Option<&str>,
+        rect: &Rect,
+        mut tiles: Vec<FactorioTile>,
+    ) -> Result<()> {
+        for tile in tiles.iter_mut() {
+            if tile.surface.is_none() {
+                tile.surface = surface.map(|s| s.to_owned());
+            }
+        }
+        self.get_or_insert_surface(surface)
+            .entity_graph
+            .add_tiles(tiles, Some(rect.clone()))
+    }
+
+    /// Replaces all entities and enemies positioned within `rect` on given surface,
+    /// returns what changed compared to the previously known state
+    pub fn replace_chunk_entities(
+        &self,
+        surface: Option<&str>,
+        rect: &Rect,
+        entities: Vec<FactorioEntity>,
+    ) -> Result<EntityDiff> {
+        let surface_name = surface.unwrap_or(DEFAULT_SURFACE);
+        self.enemies.retain(|(enemy_surface, _), enemy| {
+            enemy_surface != surface_name || !position_in_area(&enemy.position, rect)
+        });
+        let mut others: Vec<FactorioEntity> = vec![];
+        for mut entity in entities {
+            // area searches also return entities reaching into the rect, and players
+            if !position_in_area(&entity.position, rect)
+                || entity.entity_type == EntityType::Character.to_string()
+            {
+                continue;
+            }
+            if entity.surface.is_none() {
+                entity.surface = surface.map(|s| s.to_owned());
+            }
+            if is_enemy(&entity) {
+                self.add_enemy(entity);
+            } else {
+                others.push(entity);
+            }
+        }
+        let entity_graph = self.get_or_insert_surface(surface).entity_graph.clone();
+        let diff = entity_graph.diff(&others, rect);
+        entity_graph.add(others, Some(rect.clone()))?;
+        Ok(diff)
+    }
+
+    /// Replaces tiles and entities within `rect` with a fresh scan of it
+    /// and reconnects the graphs of the surface.
+    /// Used to resync after the map was changed without the bots noticing, e.g. by manual play.
+    pub fn rescan(
+        &self,
+        surface: Option<&str>,
+        rect: &Rect,
+        entities: Vec<FactorioEntity>,
+        tiles: Vec<FactorioTile>,
+    ) -> Result<EntityDiff> {
+        self.replace_chunk_tiles(surface, rect, tiles)?;
+        let diff = self.replace_chunk_entities(surface, rect, entities)?;
+        let graphs = self.surface(surface);
+        graphs.entity_graph.connect()?;
+        graphs.flow_graph.update()?;
+        graphs.power_graph.update()?;
+        Ok(diff)
+    }
+
+    /// Graphs of given surface, `None` selects the default surface.
+    /// Unknown surfaces are empty and not remembered, only reported chunks create surfaces.
+    pub fn surface(&self, name: Option<&str>) -> Arc<FactorioSurface> {
+        let name = name.unwrap_or(DEFAULT_SURFACE);
+        if name == DEFAULT_SURFACE {
+            return self.default_surface();
+        }
+        match self.surfaces.get(name) {
+            Some(surface) => surface.clone(),
+            None => Arc::new(FactorioSurface::new(
+                self.entity_prototypes.clone(),
+                self.recipes.clone(),
+            )),
+        }
+    }
+
+    /// Graphs of given surface like `surface`, unknown surfaces are created empty
+    fn get_or_insert_surface(&self, name: Option<&str>) -> Arc<FactorioSurface> {
+        let name = name.unwrap_or(DEFAULT_SURFACE);
+        if name == DEFAULT_SURFACE {
+            return self.default_surface();
+        }
+        self.surfaces
+            .entry(name.to_owned())
+            .or_insert_with(|| {
+                Arc::new(FactorioSurface::new(
+                    self.entity_prototypes.clone(),
+                    self.recipes.clone(),
+                ))
+            })
+            .clone()
+    }
+
+    fn default_surface(&self) -> Arc<FactorioSurface> {
+        Arc::new(FactorioSurface {
+            entity_graph: self.entity_graph.clone(),
+            flow_graph: self.flow_graph.clone(),
+            power_graph: self.power_graph.clone(),
+        })
+    }
+
+    /// Names of all known surfaces, the default surface first
+    pub fn surface_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.surfaces.iter().map(|s| s.key().clone()).collect();
+        names.sort();
+        names.insert(0, DEFAULT_SURFACE.to_owned());
+        names
+    }
+
+    /// Connects entity graphs and updates flow graphs of all surfaces
+    pub fn connect_surfaces(&self) -> Result<()> {
+        for name in self.surface_names() {
+            let surface = self.surface(Some(&name));
+            surface.entity_graph.connect()?;
+            surface.flow_graph.update()?;
+            surface.power_graph.update()?;
+        }
+        Ok(())
+    }
+
+    /// Takes over everything a loaded snapshot knows, replacing the graphs of all surfaces,
+    /// and connects the graphs again
+    pub fn restore(&self, snapshot: FactorioWorld) -> Result<()> {
+        for (player_id, player) in snapshot.players {
+            self.players.insert(player_id, player);
+        }
+        for (name, force) in snapshot.forces {
+            self.forces.insert(name, force);
+        }
+        for (name, graphic) in snapshot.graphics {
+            self.graphics.insert(name, graphic);
+        }
+        for recipe in snapshot.recipes.iter() {
+            self.recipes
+                .insert(recipe.key().clone(), recipe.value().clone());
+        }
+        for prototype in snapshot.entity_prototypes.iter() {
+            self.entity_prototypes
+                .insert(prototype.key().clone(), prototype.value().clone());
+        }
+        for (name, prototype) in snapshot.item_prototypes {
+            self.item_prototypes.insert(name, prototype);
+        }
+        for (name, prototype) in snapshot.tile_prototypes {
+            self.tile_prototypes.insert(name, prototype);
+        }
+        self.entity_graph.replace(&snapshot.entity_graph);
+        self.surfaces.clear();
+        for (name, surface) in snapshot.surfaces {
+            self.get_or_insert_surface(Some(&name))
+                .entity_graph
+                .replace(&surface.entity_graph);
+        }
+        self.enemies.clear();
+        for (key, enemy) in snapshot.enemies {
+            self.enemies.insert(key, enemy);
+        }
+        self.connect_surfaces()
+    }
+
     pub fn import(&mut self, world: Arc<FactorioWorld>) -> Result<()> {
         for player in world.players.iter() {
             self.players.insert(player.player_id, player.clone());
@@ -212,6 +883,10 @@ impl FactorioWorld {
             self.item_prototypes
                 .insert(item_prototype.name.clone(), item_prototype.clone());
         }
+        for tile_prototype in world.tile_prototypes.iter() {
+            self.tile_prototypes
+                .insert(tile_prototype.name.clone(), tile_prototype.clone());
+        }
         for recipe in world.recipes.iter() {
             self.recipes.insert(recipe.name.clone(), recipe.clone());
         }
@@ -234,6 +909,7 @@ impl FactorioWorld {
             Arc::new(DashMap::new());
         let entity_graph = Arc::new(EntityGraph::new(entity_prototypes.clone(), recipes.clone()));
         let flow_graph = Arc::new(FlowGraph::new(entity_graph.clone()));
+        let power_graph = Arc::new(PowerGraph::new(entity_graph.clone()));
         FactorioWorld {
             image_cache,
             players,
@@ -242,11 +918,21 @@ impl FactorioWorld {
             forces,
             entity_prototypes,
             item_prototypes,
+            tile_prototypes: DashMap::new(),
+            walk_calibration: Default::default(),
             actions: DashMap::new(),
             path_requests: DashMap::new(),
+            throughput_samplings: DashMap::new(),
             next_action_id: Mutex::new(1),
             entity_graph,
             flow_graph,
+            power_graph,
+            surfaces: DashMap::new(),
+            entity_states: DashMap::new(),
+            enemies: DashMap::new(),
+            pollution: DashMap::new(),
+            attacks: broadcast::channel(ATTACK_EVENT_BUFFER).0,
+            parser_metrics: Default::default(),
         }
     }
 
@@ -283,6 +969,18 @@ impl FactorioWorld {
         Ok(())
     }
 
+    pub fn dump_parser_metrics(&self, save_path: Option<&str>) -> Result<()> {
+        let content =
+            serde_json::to_string_pretty(&self.parser_metrics.report()).into_diagnostic()?;
+        if let Some(save_path) = save_path {
+            fs::write(save_path, &content).into_diagnostic()?;
+        } else {
+            println!("{content}");
+        }
+
+        Ok(())
+    }
+
     pub fn dump_recipes(&self, save_path: Option<&str>) -> Result<()> {
         let content = serde_json::to_string_pretty(&*self.recipes).into_diagnostic()?;
         if let Some(save_path) = save_path {
@@ -303,16 +1001,29 @@ impl Serialize for FactorioWorld {
     where
         S: Serializer,
     {
-        let mut state = serializer.serialize_struct("FactorioWorld", 9)?;
+        let mut state = serializer.serialize_struct("FactorioWorld", 12)?;
         state.serialize_field("players", &self.players)?;
         state.serialize_field("forces", &self.forces)?;
         state.serialize_field("graphics", &self.graphics)?;
         state.serialize_field("recipes", &*self.recipes)?;
         state.serialize_field("entity_prototypes", &*self.entity_prototypes)?;
         state.serialize_field("item_prototypes", &self.item_prototypes)?;
+        state.serialize_field("tile_prototypes", &self.tile_prototypes)?;
         state.serialize_field("actions", &self.actions)?;
         state.serialize_field("path_requests", &self.path_requests)?;
         state.serialize_field("entity_graph", &*self.entity_graph)?;
+        let surfaces: Vec<(String, Arc<FactorioSurface>)> = self
+            .surfaces
+            .iter()
+            .map(|surface| (surface.key().clone(), surface.value().clone()))
+            .collect();
+        let surfaces: BTreeMap<&str, &EntityGraph> = surfaces
+            .iter()
+            .map(|(name, surface)| (name.as_str(), &*surface.entity_graph))
+            .collect();
+        state.serialize_field("surfaces", &surfaces)?;
+        let enemies: Vec<FactorioEntity> = self.enemies.iter().map(|e| e.value().clone()).collect();
+        state.serialize_field("enemies", &enemies)?;
         state.end()
     }
 }
@@ -329,9 +1040,12 @@ impl<'de> Deserialize<'de> for FactorioWorld {
             Recipes,
             EntityPrototypes,
             ItemPrototypes,
+            TilePrototypes,
             Actions,
             PathRequests,
             EntityGraph,
+            Surfaces,
+            Enemies,
         }
 
         impl<'de> Deserialize<'de> for Field {
@@ -359,9 +1073,12 @@ impl<'de> Deserialize<'de> for FactorioWorld {
                             "recipes" => Ok(Field::Recipes),
                             "entity_prototypes" => Ok(Field::EntityPrototypes),
                             "item_prototypes" => Ok(Field::ItemPrototypes),
+                            "tile_prototypes" => Ok(Field::TilePrototypes),
                             "actions" => Ok(Field::Actions),
                             "path_requests" => Ok(Field::PathRequests),
                             "entity_graph" => Ok(Field::EntityGraph),
+                            "surfaces" => Ok(Field::Surfaces),
+                            "enemies" => Ok(Field::Enemies),
                             _ => Err(de::Error::unknown_field(value, FIELDS)),
                         }
                     }
@@ -390,9 +1107,12 @@ impl<'de> Deserialize<'de> for FactorioWorld {
                 let mut recipes = None;
                 let mut entity_prototypes = None;
                 let mut item_prototypes = None;
+                let mut tile_prototypes: Option<DashMap<String, FactorioTilePrototype>> = None;
                 let mut actions = None;
                 let mut path_requests = None;
                 let mut entity_graph = None;
+                let mut surfaces: Option<BTreeMap<String, EntityGraph>> = None;
+                let mut enemies: Option<Vec<FactorioEntity>> = None;
 
                 while let Some(key) = map.next_key()? {
                     match key {
@@ -432,6 +1152,12 @@ impl<'de> Deserialize<'de> for FactorioWorld {
                             }
                             item_prototypes = Some(map.next_value()?);
                         }
+                        Field::TilePrototypes => {
+                            if tile_prototypes.is_some() {
+                                return Err(de::Error::duplicate_field("tile_prototypes"));
+                            }
+                            tile_prototypes = Some(map.next_value()?);
+                        }
                         Field::Actions => {
                             if actions.is_some() {
                                 return Err(de::Error::duplicate_field("actions"));
@@ -450,6 +1176,18 @@ impl<'de> Deserialize<'de> for FactorioWorld {
                             }
                             entity_graph = Some(map.next_value()?);
                         }
+                        Field::Surfaces => {
+                            if surfaces.is_some() {
+                                return Err(de::Error::duplicate_field("surfaces"));
+                            }
+                            surfaces = Some(map.next_value()?);
+                        }
+                        Field::Enemies => {
+                            if enemies.is_some() {
+                                return Err(de::Error::duplicate_field("enemies"));
+                            }
+                            enemies = Some(map.next_value()?);
+                        }
                     }
                 }
                 let players = players.ok_or_else(|| de::Error::missing_field("players"))?;
@@ -468,6 +1206,36 @@ impl<'de> Deserialize<'de> for FactorioWorld {
 
                 let entity_graph: Arc<EntityGraph> = Arc::new(entity_graph);
                 let flow_graph = Arc::new(FlowGraph::new(entity_graph.clone()));
+                let power_graph = Arc::new(PowerGraph::new(entity_graph.clone()));
+                // dumps written before multi surface support only contain the default surface
+                let surfaces: DashMap<String, Arc<FactorioSurface>> = surfaces
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(name, entity_graph)| {
+                        let entity_graph = Arc::new(entity_graph);
+                        let flow_graph = Arc::new(FlowGraph::new(entity_graph.clone()));
+                        let power_graph = Arc::new(PowerGraph::new(entity_graph.clone()));
+                        (
+                            name,
+                            Arc::new(FactorioSurface {
+                                entity_graph,
+                                flow_graph,
+                                power_graph,
+                            }),
+                        )
+                    })
+                    .collect();
+                // enemies were added with the snapshot format
+                let enemies: DashMap<(String, Pos), FactorioEntity> = enemies
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|enemy| {
+                        (
+                            (enemy.surface().to_owned(), (&enemy.position).into()),
+                            enemy,
+                        )
+                    })
+                    .collect();
                 Ok(FactorioWorld {
                     players,
                     forces,
@@ -475,12 +1243,23 @@ impl<'de> Deserialize<'de> for FactorioWorld {
                     recipes: Arc::new(recipes),
                     entity_prototypes: Arc::new(entity_prototypes),
                     item_prototypes,
+                    // tile prototypes were added with walking time estimates
+                    tile_prototypes: tile_prototypes.unwrap_or_default(),
+                    walk_calibration: Default::default(),
                     image_cache: Default::default(),
                     actions,
                     path_requests,
+                    throughput_samplings: Default::default(),
                     next_action_id: Default::default(),
                     entity_graph,
                     flow_graph,
+                    power_graph,
+                    surfaces,
+                    entity_states: Default::default(),
+                    enemies,
+                    pollution: Default::default(),
+                    attacks: broadcast::channel(ATTACK_EVENT_BUFFER).0,
+                    parser_metrics: Default::default(),
                 })
             }
         }
@@ -492,20 +1271,45 @@ impl<'de> Deserialize<'de> for FactorioWorld {
             "recipes",
             "entity_prototypes",
             "item_prototypes",
+            "tile_prototypes",
             "actions",
             "path_requests",
             "entity_graph",
+            "surfaces",
+            "enemies",
         ];
         deserializer.deserialize_struct("FactorioWorld", FIELDS, FactorioWorldVisitor)
     }
 }
 
+/// Entities of any force but the player and neutral ones, by type if the force is unknown
+fn is_enemy(entity: &FactorioEntity) -> bool {
+    match entity.force.as_deref() {
+        Some(force) => force != PLAYER_FORCE && force != NEUTRAL_FORCE,
+        None => EntityType::from_str(&entity.entity_type).is_ok_and(|t| t.is_enemy()),
+    }
+}
+
 impl Clone for FactorioWorld {
     fn clone(&self) -> Self {
         let entity_prototypes = Arc::new((*self.entity_prototypes).clone());
         let recipes = Arc::new((*self.recipes).clone());
         let entity_graph = Arc::new((*self.entity_graph).clone());
         let _entity_graph = entity_graph.clone();
+        let surfaces = DashMap::new();
+        for surface in self.surfaces.iter() {
+            let entity_graph = Arc::new((*surface.entity_graph).clone());
+            let flow_graph = Arc::new(FlowGraph::new(entity_graph.clone()));
+            let power_graph = Arc::new(PowerGraph::new(entity_graph.clone()));
+            surfaces.insert(
+                surface.key().clone(),
+                Arc::new(FactorioSurface {
+                    entity_graph,
+                    flow_graph,
+                    power_graph,
+                }),
+            );
+        }
         FactorioWorld {
             entity_graph,
             recipes,
@@ -514,11 +1318,21 @@ impl Clone for FactorioWorld {
             forces: self.forces.clone(),
             graphics: self.graphics.clone(),
             item_prototypes: self.item_prototypes.clone(),
+            tile_prototypes: self.tile_prototypes.clone(),
+            walk_calibration: RwLock::new(self.walk_calibration.read().clone()),
             image_cache: self.image_cache.clone(),
             actions: self.actions.clone(),
             path_requests: self.path_requests.clone(),
+            throughput_samplings: self.throughput_samplings.clone(),
             next_action_id: Mutex::new(0),
-            flow_graph: Arc::new(FlowGraph::new(_entity_graph)),
+            flow_graph: Arc::new(FlowGraph::new(_entity_graph.clone())),
+            power_graph: Arc::new(PowerGraph::new(_entity_graph)),
+            surfaces,
+            entity_states: self.entity_states.clone(),
+            enemies: self.enemies.clone(),
+            pollution: self.pollution.clone(),
+            attacks: broadcast::channel(ATTACK_EVENT_BUFFER).0,
+            parser_metrics: Default::default(),
         }
     }
 
@@ -530,6 +1344,7 @@ impl Clone for FactorioWorld {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils::fixture_world;
 
     #[test]
     #[allow(clippy::redundant_clone)]
@@ -541,9 +1356,12 @@ mod tests {
             recipes: Arc::new(Default::default()),
             entity_prototypes: Arc::new(Default::default()),
             item_prototypes: Default::default(),
+            tile_prototypes: Default::default(),
+            walk_calibration: Default::default(),
             image_cache: Default::default(),
             actions: Default::default(),
             path_requests: Default::default(),
+            throughput_samplings: Default::default(),
             next_action_id: Default::default(),
             entity_graph: Arc::new(EntityGraph::new(
                 Arc::new(DashMap::new()),
@@ -553,8 +1371,141 @@ mod tests {
                 Arc::new(DashMap::new()),
                 Arc::new(DashMap::new()),
             )))),
+            power_graph: Arc::new(PowerGraph::new(Arc::new(EntityGraph::new(
+                Arc::new(DashMap::new()),
+                Arc::new(DashMap::new()),
+            )))),
+            surfaces: Default::default(),
+            entity_states: Default::default(),
+            enemies: Default::default(),
+            pollution: Default::default(),
+            attacks: broadcast::channel(ATTACK_EVENT_BUFFER).0,
+            parser_metrics: Default::default(),
         };
 
         let _cloned = world.clone();
     }
+
+    #[test]
+    fn test_replace_chunk_entities() {
+        use crate::types::Direction;
+        let world = FactorioWorld::new();
+        let chunk = Rect::new(&Position::new(0., 0.), &Position::new(32., 32.));
+        let belt = FactorioEntity::new_transport_belt(&Position::new(0.5, 0.5), Direction::South);
+        let furnace = FactorioEntity::new_stone_furnace(&Position::new(5., 5.), Direction::North);
+        let tree = FactorioEntity::new_tree(&Position::new(10.5, 10.5));
+        let outside =
+            FactorioEntity::new_transport_belt(&Position::new(40.5, 0.5), Direction::East);
+        let diff = world
+            .replace_chunk_entities(
+                None,
+                &chunk,
+                vec![belt.clone(), furnace.clone(), tree.clone(), outside],
+            )
+            .unwrap();
+        assert_eq!(diff.added, vec![belt.clone(), furnace.clone()]);
+        assert!(diff.removed.is_empty() && diff.changed.is_empty());
+        assert_eq!(world.entity_graph.blocked_tree().len(), 3);
+
+        // rescanning the same chunk must not duplicate anything
+        let diff = world
+            .replace_chunk_entities(
+                None,
+                &chunk,
+                vec![belt.clone(), furnace.clone(), tree.clone()],
+            )
+            .unwrap();
+        assert!(diff.is_empty());
+        assert_eq!(world.entity_graph.entities_in(&chunk).len(), 2);
+        assert_eq!(world.entity_graph.blocked_tree().len(), 3);
+
+        let rotated = FactorioEntity::new_transport_belt(&Position::new(0.5, 0.5), Direction::East);
+        let diff = world
+            .replace_chunk_entities(None, &chunk, vec![rotated.clone(), tree])
+            .unwrap();
+        assert_eq!(diff.changed, vec![rotated]);
+        assert_eq!(diff.removed, vec![furnace]);
+        assert!(diff.added.is_empty());
+        assert_eq!(world.entity_graph.entities_in(&chunk).len(), 1);
+        assert_eq!(world.entity_graph.blocked_tree().len(), 2);
+    }
+
+    #[test]
+    fn test_enemies_by_force() {
+        let world = FactorioWorld::new();
+        let chunk = Rect::new(&Position::new(0., 0.), &Position::new(128., 128.));
+        let turret = |x: f64, force: &str| FactorioEntity {
+            name: "small-worm-turret".into(),
+            entity_type: EntityType::Turret.to_string(),
+            position: Position::new(x, 5.),
+            bounding_box: Rect::new(&Position::new(x - 1., 4.), &Position::new(x + 1., 6.)),
+            force: Some(force.into()),
+            ..Default::default()
+        };
+        world
+            .replace_chunk_entities(
+                None,
+                &chunk,
+                vec![turret(5., "enemy"), turret(100., "player")],
+            )
+            .unwrap();
+        let zones = world.danger_zones(None);
+        assert_eq!(zones.len(), 1);
+        assert_eq!(zones[0].position, Position::new(5., 5.));
+        assert!(world
+            .danger_zone_at(None, &Position::new(100., 5.))
+            .is_none());
+    }
+
+    #[test]
+    fn test_find_blueprint_sites() {
+        let world = fixture_world();
+        let belt = |x: f64| FactorioEntity {
+            name: "transport-belt".into(),
+            entity_type: "transport-belt".into(),
+            position: Position::new(x, 0.5),
+            ..Default::default()
+        };
+        let blueprint = world
+            .make_blueprint("test", &[belt(0.5), belt(3.5)], &[])
+            .unwrap();
+        let constraints = SiteConstraints {
+            near: Some(Position::new(30., 40.)),
+            rotations: true,
+            ..Default::default()
+        };
+        let sites = world
+            .find_blueprint_sites(None, &blueprint, &constraints, 3)
+            .unwrap();
+        assert_eq!(sites.len(), 3);
+        for site in &sites {
+            assert_eq!(site.direction, Direction::North as u8);
+            // the belts are encoded at -1.5/0.5 and 1.5/0.5 around the origin
+            for offset in [Position::new(-1.5, 0.5), Position::new(1.5, 0.5)] {
+                assert!(site.rect.contains(&site.position.add(&offset)));
+            }
+        }
+        assert!(world
+            .find_blueprint_sites(None, "invalid", &constraints, 3)
+            .is_err());
+    }
+
+    #[test]
+    fn test_unknown_surface_not_created() {
+        let world = FactorioWorld::new();
+        assert!(world
+            .surface(Some("typo"))
+            .entity_graph
+            .tile_tree()
+            .iter()
+            .next()
+            .is_none());
+        assert_eq!(world.surface_names(), vec![DEFAULT_SURFACE]);
+
+        let chunk = Rect::new(&Position::new(0., 0.), &Position::new(32., 32.));
+        world
+            .replace_chunk_entities(Some("vulcanus"), &chunk, vec![])
+            .unwrap();
+        assert_eq!(world.surface_names(), vec![DEFAULT_SURFACE, "vulcanus"]);
+    }
 }
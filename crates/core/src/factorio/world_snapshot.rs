@@ -0,0 +1,102 @@
+use crate::errors::{SnapshotInvalid, SnapshotVersionMismatch};
+use crate::factorio::world::FactorioWorld;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use miette::{IntoDiagnostic, Result};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// first bytes of every snapshot file
+const SNAPSHOT_MAGIC: &[u8; 4] = b"FBWS";
+/// bump whenever the serialized world changes incompatibly
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Writes entities, tiles, resources, players, forces and graphs of all surfaces.
+///
+/// The file starts with `FBWS` and the little endian format version, followed by
+/// the gzip compressed CBOR encoded world.
+pub fn save_snapshot(world: &FactorioWorld, path: &Path) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path).into_diagnostic()?);
+    writer.write_all(SNAPSHOT_MAGIC).into_diagnostic()?;
+    writer
+        .write_all(&SNAPSHOT_VERSION.to_le_bytes())
+        .into_diagnostic()?;
+    let mut encoder = GzEncoder::new(writer, Compression::fast());
+    ciborium::into_writer(world, &mut encoder).into_diagnostic()?;
+    encoder
+        .finish()
+        .into_diagnostic()?
+        .flush()
+        .into_diagnostic()?;
+    Ok(())
+}
+
+/// Reads a snapshot written by `save_snapshot`, the graphs still need to be connected,
+/// see `FactorioWorld::restore`
+pub fn load_snapshot(path: &Path) -> Result<FactorioWorld> {
+    let mut reader = BufReader::new(File::open(path).into_diagnostic()?);
+    let mut magic = [0u8; 4];
+    let mut version = [0u8; 4];
+    if reader.read_exact(&mut magic).is_err()
+        || &magic != SNAPSHOT_MAGIC
+        || reader.read_exact(&mut version).is_err()
+    {
+        return Err(SnapshotInvalid {
+            path: path.to_string_lossy().into(),
+        }
+        .into());
+    }
+    let version = u32::from_le_bytes(version);
+    if version != SNAPSHOT_VERSION {
+        return Err(SnapshotVersionMismatch {
+            version,
+            expected: SNAPSHOT_VERSION,
+        }
+        .into());
+    }
+    ciborium::from_reader(GzDecoder::new(reader)).into_diagnostic()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixture_world;
+    use crate::types::Rect;
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let world = fixture_world();
+        let path = std::env::temp_dir().join("factorio-bot-test-snapshot.world");
+        save_snapshot(&world, &path).unwrap();
+
+        let loaded = FactorioWorld::new();
+        loaded.restore(load_snapshot(&path).unwrap()).unwrap();
+        let everything = Rect::from_wh(2000., 2000.);
+        assert_eq!(
+            loaded.entity_graph.entities_in(&everything),
+            world.entity_graph.entities_in(&everything)
+        );
+        assert_eq!(
+            loaded.entity_graph.tile_tree().len(),
+            world.entity_graph.tile_tree().len()
+        );
+        assert_eq!(
+            loaded.entity_graph.blocked_tree().len(),
+            world.entity_graph.blocked_tree().len()
+        );
+        assert_eq!(
+            loaded.entity_graph.graphviz_dot(),
+            world.entity_graph.graphviz_dot()
+        );
+        assert_eq!(loaded.recipes.len(), world.recipes.len());
+
+        std::fs::write(&path, b"FBWS\x63\0\0\0").unwrap();
+        let err = load_snapshot(&path).err().unwrap();
+        assert!(err.to_string().contains("version 99"));
+        std::fs::write(&path, b"{}").unwrap();
+        assert!(load_snapshot(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -187,6 +187,7 @@ impl Handler<PlayerDistanceChangedMessage> for FactorioWebSocketServer {
                 item_pickup_distance: msg.player.item_pickup_distance,
                 loot_pickup_distance: msg.player.loot_pickup_distance,
                 resource_reach_distance: msg.player.resource_reach_distance,
+                movement_bonus: msg.player.movement_bonus,
             })
             .expect("failed to serialize")
         ]));
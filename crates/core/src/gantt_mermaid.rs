@@ -155,28 +155,28 @@ mod tests {
     title Example diagram
     dateFormat HH:mm:ss
     axisFormat %H:%M:%S
-    test : milestone, m1, 00:03:38,0s
+    test : milestone, m1, 00:00:29,0s
     section Bot 1
     Process Start : 00:00:00,0s
     Start﹕ Mine Stuff : 0s
-    Walk to [10, 43] : 45s
+    Walk to [10, 43] : 5s
     Mining rock-huge : 3s
-    End : 3s
+    End : 1s
     Start﹕ Walk Around Stuff : 0s
-    Walk to [10, 3] : 40s
-    Walk to [10, 63] : 60s
-    Walk to [0, 0] : 64s
-    End : 3s
+    Walk to [10, 3] : 5s
+    Walk to [10, 63] : 7s
+    Walk to [0, 0] : 8s
+    End : 0s
     section Bot 2
     Process Start : 00:00:00,0s
     Start﹕ Mine Stuff : 0s
-    Walk to [20, 43] : 48s
+    Walk to [20, 43] : 6s
     Mining rock-huge : 3s
     End : 0s
     Start﹕ Walk Around Stuff : 0s
-    Walk to [20, 3] : 40s
-    Walk to [20, 63] : 60s
-    Walk to [0, 0] : 67s
+    Walk to [20, 3] : 5s
+    Walk to [20, 63] : 7s
+    Walk to [0, 0] : 8s
     End : 0s
 "#,
         );
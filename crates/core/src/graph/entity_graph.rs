@@ -1,11 +1,12 @@
 use crate::aabb_quadtree::{ItemId, QuadTree};
 use crate::factorio::util::{
-    add_to_rect, bounding_box, format_dotgraph, move_position, rect_fields, rect_floor,
+    add_to_rect, bounding_box, format_dotgraph, move_position, position_in_area, rect_fields,
+    rect_floor,
 };
-use crate::num_traits::FromPrimitive;
+use crate::num_traits::{FromPrimitive, ToPrimitive};
 use crate::types::{
-    Direction, EntityName, EntityType, FactorioEntity, FactorioEntityPrototype, FactorioRecipe,
-    FactorioTile, Pos, Position, Rect, ResourcePatch,
+    ChunkEntities, ChunkPosition, Direction, EntityDiff, EntityName, EntityType, FactorioEntity,
+    FactorioEntityPrototype, FactorioRecipe, FactorioTile, Pos, Position, Rect, ResourcePatch,
 };
 use dashmap::DashMap;
 use euclid::{Point2D, Rect as EuclidRect, Size2D};
@@ -125,6 +126,112 @@ impl EntityGraph {
         entities
     }
 
+    /// Up to `n` entities nearest to `search_center`, resource tiles included
+    pub fn nearest(
+        &self,
+        search_center: &Position,
+        n: usize,
+        search_name: Option<&str>,
+        search_type: Option<&str>,
+    ) -> Vec<FactorioEntity> {
+        let point: QuadTreePoint = search_center.clone().into();
+        let entity_tree = self.entity_tree.read();
+        let mut found: Vec<(FactorioEntity, f32)> = entity_tree
+            .nearest(point, n, |entity| {
+                search_name.is_none_or(|name| entity.name == name)
+                    && search_type.is_none_or(|entity_type| entity.entity_type == entity_type)
+            })
+            .into_iter()
+            .filter_map(|(item_id, distance)| {
+                entity_tree
+                    .get(item_id)
+                    .map(|entity| (entity.clone(), distance))
+            })
+            .collect();
+        if search_type.is_none_or(|entity_type| entity_type == EntityType::Resource.to_string()) {
+            let resource_tree = self.resource_tree.read();
+            found.extend(
+                resource_tree
+                    .nearest(point, n, |name| {
+                        search_name.is_none_or(|search| name == search)
+                    })
+                    .into_iter()
+                    .filter_map(|(item_id, distance)| {
+                        resource_tree.get_with_box(item_id).map(|(name, rect)| {
+                            let center: Position = rect.center().into();
+                            (
+                                FactorioEntity::new_resource(&center, Direction::North, name),
+                                distance,
+                            )
+                        })
+                    }),
+            );
+        }
+        found.sort_by(|a, b| a.1.total_cmp(&b.1));
+        found.truncate(n);
+        found.into_iter().map(|(entity, _)| entity).collect()
+    }
+
+    /// Entities crossed by the line from `from` to `to`, in order along it
+    pub fn entities_along(&self, from: &Position, to: &Position) -> Vec<FactorioEntity> {
+        let tree = self.entity_tree.read();
+        tree.segment_query(from.clone().into(), to.clone().into())
+            .into_iter()
+            .filter_map(|(item_id, _)| tree.get(item_id).cloned())
+            .collect()
+    }
+
+    /// True if nothing blocking like buildings, trees, rocks or water is on
+    /// the line between `from` and `to`, boxes containing either end are ignored
+    pub fn line_of_sight(&self, from: &Position, to: &Position) -> bool {
+        let from: QuadTreePoint = from.clone().into();
+        let to: QuadTreePoint = to.clone().into();
+        let tree = self.blocked_tree.read();
+        tree.segment_query(from, to)
+            .into_iter()
+            .all(|(item_id, _)| {
+                tree.get_with_box(item_id)
+                    .is_none_or(|(_, rect)| rect.contains(from) || rect.contains(to))
+            })
+    }
+
+    /// Entities by chunk for all chunks up to `radius` chunks around the one
+    /// containing `center`, ring by ring starting with the centre chunk
+    pub fn chunk_rings(&self, center: &Position, radius: u32) -> Vec<ChunkEntities> {
+        let center_x = (center.x() / 32.).floor() as i32;
+        let center_y = (center.y() / 32.).floor() as i32;
+        let tree = self.entity_tree.read();
+        let mut chunks = vec![];
+        for ring in 0..=radius as i32 {
+            for y in center_y - ring..=center_y + ring {
+                for x in center_x - ring..=center_x + ring {
+                    if (x - center_x).abs().max((y - center_y).abs()) != ring {
+                        continue;
+                    }
+                    let chunk_rect = Rect::new(
+                        &Position::new(x as f64 * 32., y as f64 * 32.),
+                        &Position::new((x + 1) as f64 * 32., (y + 1) as f64 * 32.),
+                    );
+                    let entities = tree
+                        .query(chunk_rect.clone().into())
+                        .into_iter()
+                        .filter(|(entity, _, _)| {
+                            (entity.position.x() / 32.).floor() as i32 == x
+                                && (entity.position.y() / 32.).floor() as i32 == y
+                        })
+                        .map(|(entity, _, _)| entity.clone())
+                        .collect();
+                    chunks.push(ChunkEntities {
+                        chunk: ChunkPosition { x, y },
+                        ring: ring as u32,
+                        entities,
+                    });
+                }
+            }
+        }
+        chunks
+    }
+
     pub fn resource_patches(&self, resource_name: &str) -> Vec<ResourcePatch> {
         let mut patches: Vec<ResourcePatch> = vec![];
         let mut positions_by_id: HashMap<Pos, Option<u32>> = HashMap::new();
@@ -175,19 +282,37 @@ impl EntityGraph {
                 id,
             });
         }
-        patches.sort_by(|a, b| b.elements.len().cmp(&a.elements.len()));
+        patches.sort_by_key(|patch| std::cmp::Reverse(patch.elements.len()));
         patches
     }
 
-    pub fn add_tiles(&self, tiles: Vec<FactorioTile>, _clear_rect: Option<Rect>) -> Result<()> {
+    /// Adds tiles, if `clear_rect` is given all tiles within are replaced
+    pub fn add_tiles(&self, tiles: Vec<FactorioTile>, clear_rect: Option<Rect>) -> Result<()> {
         let mut tree = self.tile_tree.write();
         let mut blocked = self.blocked_tree.write();
+        if let Some(clear_rect) = clear_rect {
+            let cleared: Vec<(ItemId, bool, QuadTreeRect)> = tree
+                .query(clear_rect.clone().into())
+                .iter()
+                .filter(|(tile, _, _)| position_in_area(&tile.position, &clear_rect))
+                .map(|(tile, rect, item_id)| (*item_id, tile.player_collidable, *rect))
+                .collect();
+            for (item_id, player_collidable, rect) in cleared {
+                tree.remove(item_id);
+                if player_collidable {
+                    let blocked_id = blocked
+                        .query(rect)
+                        .iter()
+                        .find(|(minable, other, _)| !**minable && *other == rect)
+                        .map(|(_, _, item_id)| *item_id);
+                    if let Some(blocked_id) = blocked_id {
+                        blocked.remove(blocked_id);
+                    }
+                }
+            }
+        }
         for tile in tiles {
-            let rect: QuadTreeRect = add_to_rect(
-                &Rect::from_wh(1., 1.),
-                &Position::new(tile.position.x() + 0.5, tile.position.y() + 0.5),
-            )
-            .into();
+            let rect = tile_rect(&tile.position);
             if tile.player_collidable {
                 let minable = false; // player_collidable tiles like water are not minable
                 blocked.insert_with_box(minable, rect);
@@ -214,7 +339,11 @@ impl EntityGraph {
         self.add(entities, None)
     }
 
-    pub fn add(&self, entities: Vec<FactorioEntity>, _clear_rect: Option<Rect>) -> Result<()> {
+    /// Adds entities, if `clear_rect` is given all entities within are replaced
+    pub fn add(&self, entities: Vec<FactorioEntity>, clear_rect: Option<Rect>) -> Result<()> {
+        if let Some(clear_rect) = clear_rect {
+            self.clear(&clear_rect);
+        }
         let mut resource_tree = self.resource_tree.write();
         for entity in &entities {
             if entity.entity_type == EntityType::Resource.to_string() {
@@ -267,91 +396,196 @@ impl EntityGraph {
             }
 
             if let Ok(entity_type) = EntityType::from_str(&entity.entity_type) {
-                match (entity.name.as_str(), &entity_type) {
-                    (_, EntityType::Furnace)
-                    | (_, EntityType::Inserter)
-                    | (_, EntityType::Boiler)
-                    | (_, EntityType::Lab)
-                    | (_, EntityType::OffshorePump)
-                    | (_, EntityType::MiningDrill)
-                    | (_, EntityType::StorageTank)
-                    | (_, EntityType::Container)
-                    | (_, EntityType::Splitter)
-                    | (_, EntityType::TransportBelt)
-                    | (_, EntityType::UndergroundBelt)
-                    | (_, EntityType::Pipe)
-                    | (_, EntityType::PipeToGround)
-                    | (_, EntityType::LogisticContainer)
-                    | (_, EntityType::AssemblingMachine)
-                    | ("rock-big", _)
-                    | ("rock-huge", _) => {
-                        if let Some(entity_id) = self.entity_at(&entity.position) {
-                            let tree = self.entity_tree.read();
-                            let block = tree.get(entity_id).unwrap();
-                            warn!(
-                                "failed to add {}@{} -> blocked by {}@{}",
-                                entity.name, entity.position, block.name, block.position
-                            );
-                            continue;
-                        }
-                        if let Some(entity_id) = {
-                            let mut tree = self.entity_tree.write();
-                            tree.insert(entity.clone())
-                        } {
-                            let miner_ore = if entity_type == EntityType::MiningDrill {
-                                let rect = rect_floor(&entity.bounding_box);
-                                let mut miner_ore: Option<String> = None;
-                                for resource in &[
-                                    EntityName::IronOre,
-                                    EntityName::CopperOre,
-                                    EntityName::Coal,
-                                    EntityName::Stone,
-                                    EntityName::CrudeOil,
-                                    EntityName::UraniumOre,
-                                ] {
-                                    let resource = resource.to_string();
-                                    let resource_found = rect_fields(&rect).iter().any(|p| {
-                                        self.resources
-                                            .get(&resource)
-                                            .and_then(|resources| {
-                                                if resources.contains(&p.into()) {
-                                                    Some(true)
-                                                } else {
-                                                    None
-                                                }
-                                            })
-                                            .is_some()
-                                    });
-                                    if resource_found {
-                                        miner_ore = Some(resource);
-                                        break;
-                                    }
-                                }
-                                if miner_ore.is_none() {
-                                    warn!(
-                                        "no ore found under miner {} @ {}",
-                                        entity.name, entity.position
-                                    );
+                if is_graph_entity(&entity.name, &entity_type) {
+                    if let Some(entity_id) = self.entity_at(&entity.position) {
+                        let tree = self.entity_tree.read();
+                        let block = tree.get(entity_id).unwrap();
+                        warn!(
+                            "failed to add {}@{} -> blocked by {}@{}",
+                            entity.name, entity.position, block.name, block.position
+                        );
+                        continue;
+                    }
+                    if let Some(entity_id) = {
+                        let mut tree = self.entity_tree.write();
+                        tree.insert(entity.clone())
+                    } {
+                        let miner_ore = if entity_type == EntityType::MiningDrill {
+                            let rect = rect_floor(&entity.bounding_box);
+                            let mut miner_ore: Option<String> = None;
+                            for resource in &[
+                                EntityName::IronOre,
+                                EntityName::CopperOre,
+                                EntityName::Coal,
+                                EntityName::Stone,
+                                EntityName::CrudeOil,
+                                EntityName::UraniumOre,
+                            ] {
+                                let resource = resource.to_string();
+                                let resource_found = rect_fields(&rect).iter().any(|p| {
+                                    self.resources
+                                        .get(&resource)
+                                        .and_then(|resources| {
+                                            if resources.contains(&p.into()) {
+                                                Some(true)
+                                            } else {
+                                                None
+                                            }
+                                        })
+                                        .is_some()
+                                });
+                                if resource_found {
+                                    miner_ore = Some(resource);
+                                    break;
                                 }
-                                miner_ore
-                            } else {
-                                None
-                            };
-                            let new_node = EntityNode::new(entity.clone(), miner_ore, entity_id);
-                            let mut inner = self.entity_graph.write();
-                            let new_node_index = inner.add_node(new_node);
-                            self.entity_nodes.insert(entity_id, new_node_index);
+                            }
+                            if miner_ore.is_none() {
+                                warn!(
+                                    "no ore found under miner {} @ {}",
+                                    entity.name, entity.position
+                                );
+                            }
+                            miner_ore
                         } else {
-                            warn!("failed to insert entity into quad tree");
-                        }
+                            None
+                        };
+                        let new_node = EntityNode::new(entity.clone(), miner_ore, entity_id);
+                        let mut inner = self.entity_graph.write();
+                        let new_node_index = inner.add_node(new_node);
+                        self.entity_nodes.insert(entity_id, new_node_index);
+                    } else {
+                        warn!("failed to insert entity into quad tree");
                     }
-                    _ => {}
                 }
             }
         }
         Ok(())
     }
 
+    /// Replaces entities, tiles, resources and their graph with the ones of `other`.
+    /// Prototypes and recipes are kept, they are shared with the world.
+    pub fn replace(&self, other: &EntityGraph) {
+        *self.entity_graph.write() = other.entity_graph.read().clone();
+        *self.blocked_tree.write() = other.blocked_tree.read().clone();
+        *self.entity_tree.write() = other.entity_tree.read().clone();
+        *self.tile_tree.write() = other.tile_tree.read().clone();
+        *self.resource_tree.write() = other.resource_tree.read().clone();
+        self.entity_nodes.clear();
+        for entry in other.entity_nodes.iter() {
+            self.entity_nodes.insert(*entry.key(), *entry.value());
+        }
+        self.resources.clear();
+        for entry in other.resources.iter() {
+            self.resources
+                .insert(entry.key().clone(), entry.value().clone());
+        }
+    }
+
+    /// Entities stored in the graph whose position lies within `rect`
+    pub fn entities_in(&self, rect: &Rect) -> Vec<FactorioEntity> {
+        let tree = self.entity_tree.read();
+        let entities = tree
+            .query(rect.clone().into())
+            .iter()
+            .filter(|(entity, _, _)| position_in_area(&entity.position, rect))
+            .map(|(entity, _, _)| (*entity).clone())
+            .collect();
+        entities
+    }
+
+    /// Compares a fresh scan of `rect` with the entities stored in the graph.
+    /// Entities which the graph does not store, like trees, are ignored.
+    pub fn diff(&self, entities: &[FactorioEntity], rect: &Rect) -> EntityDiff {
+        let mut known = self.entities_in(rect);
+        let mut diff = EntityDiff::default();
+        for entity in entities {
+            let stored = EntityType::from_str(&entity.entity_type)
+                .is_ok_and(|entity_type| is_graph_entity(&entity.name, &entity_type));
+            if !stored || !position_in_area(&entity.position, rect) {
+                continue;
+            }
+            match known
+                .iter()
+                .position(|other| other.name == entity.name && other.position == entity.position)
+            {
+                Some(index) => {
+                    let other = known.swap_remove(index);
+                    if other.direction != entity.direction
+                        || other.recipe != entity.recipe
+                        || other.quality != entity.quality
+                    {
+                        diff.changed.push(entity.clone());
+                    }
+                }
+                None => diff.added.push(entity.clone()),
+            }
+        }
+        diff.removed = known;
+        diff
+    }
+
+    /// Removes all entities, resources and blocking boxes positioned within `rect`.
+    /// Tiles are kept, water stays blocked.
+    fn clear(&self, rect: &Rect) {
+        let mut inner = self.entity_graph.write();
+        let mut entity_tree = self.entity_tree.write();
+        let entity_ids: Vec<ItemId> = entity_tree
+            .query(rect.clone().into())
+            .iter()
+            .filter(|(entity, _, _)| position_in_area(&entity.position, rect))
+            .map(|(_, _, item_id)| *item_id)
+            .collect();
+        for entity_id in entity_ids {
+            if let Some((_, node_index)) = self.entity_nodes.remove(&entity_id) {
+                // removing a node also removes its edges
+                inner.remove_node(node_index);
+            }
+            entity_tree.remove(entity_id);
+        }
+        drop(entity_tree);
+        drop(inner);
+
+        let mut blocked = self.blocked_tree.write();
+        let blocked_ids: Vec<ItemId> = blocked
+            .query(rect.clone().into())
+            .iter()
+            .filter(|(_, aabb, _)| {
+                let center = aabb.center();
+                position_in_area(&Position::new(center.x as f64, center.y as f64), rect)
+            })
+            .map(|(_, _, item_id)| *item_id)
+            .collect();
+        for item_id in blocked_ids {
+            blocked.remove(item_id);
+        }
+        let tiles = self.tile_tree.read();
+        for (tile, _, _) in tiles.query(rect.clone().into()) {
+            if tile.player_collidable && position_in_area(&tile.position, rect) {
+                blocked.insert_with_box(false, tile_rect(&tile.position));
+            }
+        }
+        drop(tiles);
+        drop(blocked);
+
+        let mut resource_tree = self.resource_tree.write();
+        let resource_ids: Vec<ItemId> = resource_tree
+            .query(rect.clone().into())
+            .iter()
+            .filter(|(_, aabb, _)| {
+                let center = aabb.center();
+                position_in_area(&Position::new(center.x as f64, center.y as f64), rect)
+            })
+            .map(|(_, _, item_id)| *item_id)
+            .collect();
+        for item_id in resource_ids {
+            resource_tree.remove(item_id);
+        }
+        for mut positions in self.resources.iter_mut() {
+            positions
+                .retain(|pos| !position_in_area(&Position::new(pos.0 as f64, pos.1 as f64), rect));
+        }
+    }
+
     pub fn condense(&self) -> EntityGraphInner {
         let _started = Instant::now();
         let mut graph = self.entity_graph.read().clone();
@@ -816,6 +1050,39 @@ impl EntityGraph {
                             }
                         }
                     }
+                    EntityType::Boiler
+                    | EntityType::Generator
+                    | EntityType::Pump
+                    | EntityType::AssemblingMachine
+                    | EntityType::MiningDrill => {
+                        for connection in self.fluid_connections(node) {
+                            if let Some(next_index) = self.node_at(&connection.position) {
+                                if next_index == node_index {
+                                    continue;
+                                }
+                                let next = inner.node_weight(next_index).unwrap();
+                                // only connect if the other side has a fluid box facing us
+                                if let Some(counterpart) = self
+                                    .fluid_connections(next)
+                                    .into_iter()
+                                    .find(|c| self.node_at(&c.position) == Some(node_index))
+                                {
+                                    if connection.is_output()
+                                        && counterpart.is_input()
+                                        && !inner.contains_edge(node_index, next_index)
+                                    {
+                                        edges_to_add.push((node_index, next_index, 1.));
+                                    }
+                                    if counterpart.is_output()
+                                        && connection.is_input()
+                                        && !inner.contains_edge(next_index, node_index)
+                                    {
+                                        edges_to_add.push((next_index, node_index, 1.));
+                                    }
+                                }
+                            }
+                        }
+                    }
                     _ => {}
                 }
             }
@@ -863,6 +1130,39 @@ impl EntityGraph {
             Some(results[0])
         }
     }
+    /// Positions the above ground pipe connections of the given entity connect to,
+    /// turned into the direction of the entity.
+    pub fn fluid_connections(&self, node: &EntityNode) -> Vec<FluidConnection> {
+        let mut connections = vec![];
+        if let Some(prototype) = self.entity_prototypes.get(&node.entity_name) {
+            if let Some(fluidboxes) = prototype.fluidbox_prototypes.as_ref() {
+                for (fluidbox_index, fluidbox) in fluidboxes.iter().enumerate() {
+                    if let Some(pipe_connections) = &*fluidbox.pipe_connections {
+                        for pipe_connection in pipe_connections {
+                            if pipe_connection.max_underground_distance.is_some()
+                                || pipe_connection.positions.is_empty()
+                            {
+                                continue;
+                            }
+                            let rotation = node.direction.to_usize().unwrap() / 2;
+                            let offset = &pipe_connection.positions
+                                [rotation % pipe_connection.positions.len()];
+                            connections.push(FluidConnection {
+                                position: node.position.add(offset),
+                                fluidbox_index,
+                                connection_type: pipe_connection
+                                    .connection_type
+                                    .clone()
+                                    .unwrap_or_else(|| "input-output".into()),
+                                production_type: fluidbox.production_type.clone(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        connections
+    }
     fn is_entity_belt_connectable(&self, node: &EntityNode, next: &EntityNode) -> bool {
         (next.entity_type == EntityType::TransportBelt
             || next.entity_type == EntityType::UndergroundBelt
@@ -1125,6 +1425,26 @@ impl Clone for EntityGraph {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct FluidConnection {
+    /// tile the connection leads to
+    pub position: Position,
+    pub fluidbox_index: usize,
+    /// input, output or input-output
+    pub connection_type: String,
+    /// production type of the owning fluid box, e.g. output for boiler steam
+    pub production_type: String,
+}
+
+impl FluidConnection {
+    pub fn is_input(&self) -> bool {
+        self.connection_type != "output"
+    }
+    pub fn is_output(&self) -> bool {
+        self.connection_type != "input"
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct EntityNode {
     pub bounding_box: Rect,
@@ -1183,9 +1503,48 @@ impl EntityNode {
     }
 }
 
+/// entity types which are stored in the entity quad tree and graph, everything else only blocks
+fn is_graph_entity(name: &str, entity_type: &EntityType) -> bool {
+    matches!(
+        (name, entity_type),
+        (_, EntityType::Furnace)
+            | (_, EntityType::Inserter)
+            | (_, EntityType::Boiler)
+            | (_, EntityType::Lab)
+            | (_, EntityType::OffshorePump)
+            | (_, EntityType::MiningDrill)
+            | (_, EntityType::StorageTank)
+            | (_, EntityType::Container)
+            | (_, EntityType::Splitter)
+            | (_, EntityType::TransportBelt)
+            | (_, EntityType::UndergroundBelt)
+            | (_, EntityType::Pipe)
+            | (_, EntityType::PipeToGround)
+            | (_, EntityType::LogisticContainer)
+            | (_, EntityType::AssemblingMachine)
+            | (_, EntityType::ElectricPole)
+            | (_, EntityType::Generator)
+            | (_, EntityType::SolarPanel)
+            | (_, EntityType::Accumulator)
+            | (_, EntityType::Radar)
+            | (_, EntityType::Pump)
+            | ("rock-big", _)
+            | ("rock-huge", _)
+    )
+}
+
+fn tile_rect(position: &Position) -> QuadTreeRect {
+    add_to_rect(
+        &Rect::from_wh(1., 1.),
+        &Position::new(position.x() + 0.5, position.y() + 0.5),
+    )
+    .into()
+}
+
 pub type EntityGraphInner = StableGraph<EntityNode, f64>;
 
 pub type QuadTreeRect = EuclidRect<f32, Rect>;
+pub type QuadTreePoint = Point2D<f32, Rect>;
 pub type BlockedQuadTree = QuadTree<bool, Rect, [(ItemId, QuadTreeRect); 4]>;
 pub type EntityQuadTree = QuadTree<FactorioEntity, Rect, [(ItemId, QuadTreeRect); 4]>;
 pub type TileQuadTree = QuadTree<FactorioTile, Rect, [(ItemId, QuadTreeRect); 4]>;
@@ -1244,6 +1603,72 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_spatial_queries() {
+        let mut entities = vec![
+            FactorioEntity::new_transport_belt(&Position::new(0.5, 0.5), Direction::East),
+            FactorioEntity::new_transport_belt(&Position::new(5.5, 0.5), Direction::East),
+            FactorioEntity::new_transport_belt(&Position::new(40.5, 0.5), Direction::East),
+        ];
+        for x in 10..13 {
+            entities.push(FactorioEntity::new_resource(
+                &Position::new(x as f64 + 0.5, 3.5),
+                Direction::North,
+                &EntityName::IronOre.to_string(),
+            ));
+        }
+        let graph = entity_graph_from(entities).unwrap();
+        let positions = |entities: Vec<FactorioEntity>| -> Vec<String> {
+            entities
+                .iter()
+                .map(|entity| format!("{} {}", entity.name, entity.position))
+                .collect()
+        };
+
+        assert_eq!(
+            positions(graph.nearest(&Position::new(8., 0.5), 3, None, None)),
+            vec![
+                "transport-belt [5.5, 0.5]",
+                "iron-ore [10.5, 3.5]",
+                "iron-ore [11.5, 3.5]"
+            ]
+        );
+        assert_eq!(
+            positions(graph.nearest(&Position::new(0., 0.), 2, Some("iron-ore"), None)),
+            vec!["iron-ore [10.5, 3.5]", "iron-ore [11.5, 3.5]"]
+        );
+        assert_eq!(
+            positions(graph.nearest(&Position::new(12., 3.5), 1, None, Some("transport-belt"))),
+            vec!["transport-belt [5.5, 0.5]"]
+        );
+
+        assert_eq!(
+            positions(graph.entities_along(&Position::new(50., 0.5), &Position::new(0., 0.5))),
+            vec![
+                "transport-belt [40.5, 0.5]",
+                "transport-belt [5.5, 0.5]",
+                "transport-belt [0.5, 0.5]"
+            ]
+        );
+        assert!(!graph.line_of_sight(&Position::new(3., 0.5), &Position::new(8., 0.5)));
+        assert!(graph.line_of_sight(&Position::new(3., 2.), &Position::new(8., 2.)));
+        assert!(graph.line_of_sight(&Position::new(0.5, 0.5), &Position::new(3., 0.5)));
+
+        let chunks = graph.chunk_rings(&Position::new(1., 1.), 1);
+        assert_eq!(chunks.len(), 9);
+        assert_eq!(chunks[0].chunk, ChunkPosition { x: 0, y: 0 });
+        assert_eq!(chunks[0].entities.len(), 2);
+        assert!(chunks[1..].iter().all(|chunk| chunk.ring == 1));
+        let east = chunks
+            .iter()
+            .find(|chunk| chunk.chunk == ChunkPosition { x: 1, y: 0 })
+            .unwrap();
+        assert_eq!(
+            positions(east.entities.clone()),
+            vec!["transport-belt [40.5, 0.5]"]
+        );
+    }
+
     #[test]
     fn test_splitters2() {
         let graph = entity_graph_from(vec![]).unwrap();
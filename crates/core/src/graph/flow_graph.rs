@@ -1,10 +1,10 @@
 use crate::aabb_quadtree::{ItemId, QuadTree};
 use crate::factorio::util::{add_to_rect, format_dotgraph};
-use crate::graph::entity_graph::{EntityGraph, EntityNode, QuadTreeRect};
+use crate::graph::entity_graph::{EntityGraph, EntityNode, FluidConnection, QuadTreeRect};
 use crate::num_traits::FromPrimitive;
 use crate::types::{
     Direction, EntityName, EntityType, FactorioEntity, FactorioEntityPrototype, FactorioRecipe,
-    Position, Rect,
+    Pos, Position, Quality, Rect, ThroughputSampling,
 };
 use dashmap::DashMap;
 use euclid::{Point2D, Size2D};
@@ -13,20 +13,38 @@ use num_traits::ToPrimitive;
 use paris::warn;
 use parking_lot::{RwLock, RwLockReadGuard};
 use petgraph::dot::{Config, Dot};
-use petgraph::graph::NodeIndex;
+use petgraph::graph::{EdgeIndex, NodeIndex};
 use petgraph::stable_graph::StableGraph;
+use petgraph::unionfind::UnionFind;
 use petgraph::visit::{depth_first_search, Bfs, Control, DfsEvent, EdgeRef};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Instant;
 
+/// vanilla offshore pump, used if the prototype has no pumping speed
+pub(crate) const OFFSHORE_PUMP_PUMPING_SPEED: f64 = 1200.;
+/// vanilla pump, used if the prototype has no pumping speed
+const PUMP_PUMPING_SPEED: f64 = 12000.;
+/// steam takes 0.2 kJ per unit and degree to be heated from 15 to 165 degrees
+pub(crate) const STEAM_ENERGY_PER_UNIT: f64 = 200. * 150.;
+/// vanilla boiler, used if the prototype has no energy usage
+pub(crate) const BOILER_STEAM_OUTPUT: f64 = 60.;
+/// vanilla steam engine, used if the prototype has no fluid usage
+pub(crate) const STEAM_ENGINE_FLUID_USAGE: f64 = 30.;
+/// vanilla inserter, used if the prototype has no rotation speed
+const INSERTER_ROTATION_SPEED: f64 = 0.014;
+
 pub struct FlowGraph {
     entity_graph: Arc<EntityGraph>,
     entity_prototypes: Arc<DashMap<String, FactorioEntityPrototype>>,
     recipes: Arc<DashMap<String, FactorioRecipe>>,
     flow_tree: RwLock<FlowQuadTree>,
     inner: RwLock<FlowGraphInner>,
+    /// items per minute which left an entity during the last sampling of it
+    measured: RwLock<HashMap<Pos, FlowRates>>,
+    /// entities fed from outside the graph, see `add_supply`
+    supplied: RwLock<Vec<NodeIndex>>,
 }
 
 impl Clone for FlowGraph {
@@ -37,6 +55,8 @@ impl Clone for FlowGraph {
             recipes: Arc::new((*self.recipes).clone()),
             flow_tree: RwLock::new((*self.flow_tree.read()).clone()),
             inner: RwLock::new((*self.inner.read()).clone()),
+            measured: RwLock::new((*self.measured.read()).clone()),
+            supplied: RwLock::new((*self.supplied.read()).clone()),
         }
     }
 
@@ -46,6 +66,8 @@ impl Clone for FlowGraph {
         self.recipes = Arc::new((*source.recipes).clone());
         self.flow_tree = RwLock::new((*source.flow_tree.read()).clone());
         self.inner = RwLock::new((*source.inner.read()).clone());
+        self.measured = RwLock::new((*source.measured.read()).clone());
+        self.supplied = RwLock::new((*source.supplied.read()).clone());
     }
 }
 
@@ -64,17 +86,45 @@ impl FlowGraph {
                 8,
             )),
             inner: RwLock::new(FlowGraphInner::new()),
+            measured: RwLock::new(HashMap::new()),
+            supplied: RwLock::new(vec![]),
         }
     }
 
+    /// Feeds items per second into the entity at `position` like a belt from outside the
+    /// graph, the next `update` follows the flow from there. Returns false if no entity
+    /// is at `position`.
+    pub fn add_supply(&self, position: &Position, rates: FlowRates) -> bool {
+        let Some(entity_index) = self.entity_graph.node_at(position) else {
+            return false;
+        };
+        let entity_node = self.entity_graph.node_weight(entity_index).unwrap();
+        let target = self.get_or_create_flow_node(&entity_node);
+        let mut inner = self.inner.write();
+        let source = inner.add_node(FlowNode {
+            position: entity_node.position.clone(),
+            direction: entity_node.direction,
+            entity_name: "supply".into(),
+            entity_type: entity_node.entity_type.clone(),
+            entity_id: None,
+            miner_ore: None,
+        });
+        inner.add_edge(source, target, FlowEdge::Single(rates));
+        self.supplied.write().push(entity_index);
+        true
+    }
+
     pub fn update(&self) -> Result<()> {
         let _started = Instant::now();
         let inner = self.entity_graph.inner_graph();
-        for entity_root_index in inner.externals(petgraph::Direction::Incoming) {
+        let supplied = self.supplied.read().clone();
+        // drills next to each other share fluid boxes, so they are not always externals
+        for entity_root_index in inner.node_indices() {
             let entity_root = inner.node_weight(entity_root_index).unwrap();
             if entity_root.entity_type == EntityType::OffshorePump
                 || (entity_root.entity_type == EntityType::MiningDrill
                     && entity_root.miner_ore.is_some())
+                || supplied.contains(&entity_root_index)
             {
                 let entity_graph = self.entity_graph.inner_graph();
                 depth_first_search(&*entity_graph, Some(entity_root_index), |event| {
@@ -82,6 +132,11 @@ impl FlowGraph {
                         let source_node = entity_graph.node_weight(source_node_index).unwrap();
                         let target_node = entity_graph.node_weight(target_node_index).unwrap();
                         match source_node.entity_type {
+                            EntityType::MiningDrill
+                                if target_node.entity_type == EntityType::MiningDrill =>
+                            {
+                                Control::Prune
+                            }
                             EntityType::MiningDrill => {
                                 let miner_ore = entity_root.miner_ore.as_ref().unwrap();
                                 let mining_speed = self
@@ -118,16 +173,100 @@ impl FlowGraph {
                                 // The rate at which resources are produced is given by:
                                 // Mining speed / Mining time = Production rate (in resource/sec)
                                 let production_rate = mining_speed / mining_time;
+                                let output = vec![(miner_ore.clone(), production_rate)];
                                 self.update_flow_edge(
-                                    FlowEdge::Single(vec![(miner_ore.clone(), production_rate)]),
+                                    if target_node.entity_type.is_fluid_input() {
+                                        FlowEdge::Fluid(output)
+                                    } else {
+                                        FlowEdge::Single(output)
+                                    },
                                     source_node,
                                     target_node,
                                 );
                                 Control::Continue
                             }
                             EntityType::OffshorePump => {
+                                let pumping_speed = self
+                                    .prototype_value(source_node, |p| p.pumping_speed)
+                                    .unwrap_or(OFFSHORE_PUMP_PUMPING_SPEED);
+                                self.update_flow_edge(
+                                    FlowEdge::Fluid(vec![(
+                                        EntityName::Water.to_string(),
+                                        pumping_speed,
+                                    )]),
+                                    source_node,
+                                    target_node,
+                                );
+                                Control::Continue
+                            }
+                            EntityType::Pump => {
+                                let incoming =
+                                    self.sum_incoming_edge_weights(&source_node.position);
+                                let pumping_speed = self
+                                    .prototype_value(source_node, |p| p.pumping_speed)
+                                    .unwrap_or(PUMP_PUMPING_SPEED);
                                 self.update_flow_edge(
-                                    FlowEdge::Single(vec![(EntityName::Water.to_string(), 1.)]),
+                                    FlowEdge::Fluid(self.limit_flowrate(&incoming, pumping_speed)),
+                                    source_node,
+                                    target_node,
+                                );
+                                Control::Continue
+                            }
+                            EntityType::Boiler => {
+                                // water passes through to the next boiler, steam leaves on top
+                                let incoming =
+                                    self.sum_incoming_edge_weights(&source_node.position);
+                                let water = incoming
+                                    .iter()
+                                    .filter(|(name, _)| *name == EntityName::Water.to_string())
+                                    .map(|(_, rate)| rate)
+                                    .sum::<f64>();
+                                let steam = water.min(
+                                    self.prototype_value(source_node, |p| p.energy_usage)
+                                        .map_or(BOILER_STEAM_OUTPUT, |watts| {
+                                            watts / STEAM_ENERGY_PER_UNIT
+                                        }),
+                                );
+                                match self.fluid_connection_to(source_node, target_node_index) {
+                                    Some(connection) if connection.production_type == "output" => {
+                                        self.update_flow_edge(
+                                            FlowEdge::Fluid(vec![(
+                                                EntityName::Steam.to_string(),
+                                                steam,
+                                            )]),
+                                            source_node,
+                                            target_node,
+                                        );
+                                        Control::Continue
+                                    }
+                                    Some(_) => {
+                                        self.update_flow_edge(
+                                            FlowEdge::Fluid(self.consume_flowrate(
+                                                &incoming,
+                                                &EntityName::Water.to_string(),
+                                                steam,
+                                            )),
+                                            source_node,
+                                            target_node,
+                                        );
+                                        Control::Continue
+                                    }
+                                    None => Control::Prune,
+                                }
+                            }
+                            EntityType::Generator => {
+                                // steam passes through to the next steam engine
+                                let incoming =
+                                    self.sum_incoming_edge_weights(&source_node.position);
+                                let fluid_usage = self
+                                    .prototype_value(source_node, |p| p.fluid_usage)
+                                    .unwrap_or(STEAM_ENGINE_FLUID_USAGE);
+                                self.update_flow_edge(
+                                    FlowEdge::Fluid(self.consume_flowrate(
+                                        &incoming,
+                                        &EntityName::Steam.to_string(),
+                                        fluid_usage,
+                                    )),
                                     source_node,
                                     target_node,
                                 );
@@ -140,16 +279,48 @@ impl FlowGraph {
 
                                 if let Some(recipe) = entity.recipe.as_ref() {
                                     if let Some(recipe) = self.recipes.get(recipe) {
+                                        let speed_multiplier = Quality::from_str(entity.quality())
+                                            .map_or(1.0, Quality::crafting_speed_multiplier)
+                                            * self
+                                                .prototype_value(source_node, |p| p.crafting_speed)
+                                                .unwrap_or(1.);
+                                        let energy = recipe.energy.to_f64().unwrap();
+                                        // fluid products leave through pipes, items through inserters
+                                        let fluid_target = self
+                                            .fluid_connection_to(source_node, target_node_index)
+                                            .is_some();
+                                        // several output inserters share the products
+                                        let outgoing_count = entity_graph
+                                            .edges_directed(
+                                                source_node_index,
+                                                petgraph::Direction::Outgoing,
+                                            )
+                                            .filter(|edge| {
+                                                self.fluid_connection_to(source_node, edge.target())
+                                                    .is_some()
+                                                    == fluid_target
+                                            })
+                                            .count()
+                                            .max(1);
                                         let mut output: FlowRates = vec![];
-                                        for product in recipe.products.iter() {
+                                        for product in recipe
+                                            .products
+                                            .iter()
+                                            .filter(|p| (p.product_type == "fluid") == fluid_target)
+                                        {
                                             // FIXME: only if enough input?
                                             output.push((
                                                 product.name.clone(),
-                                                product.amount as f64 / 3.2, // FIXME: correct amount based on assembler speed
+                                                product.amount as f64 / energy * speed_multiplier
+                                                    / outgoing_count as f64,
                                             ));
                                         }
                                         self.update_flow_edge(
-                                            FlowEdge::Single(output),
+                                            if fluid_target {
+                                                FlowEdge::Fluid(output)
+                                            } else {
+                                                FlowEdge::Single(output)
+                                            },
                                             source_node,
                                             target_node,
                                         );
@@ -192,24 +363,31 @@ impl FlowGraph {
                                 Stone and Steel Furnaces consume 0.0225 coal/second.
                                              */
 
+                                let crafting_speed = self
+                                    .prototype_value(source_node, |p| p.crafting_speed)
+                                    .unwrap_or(1.);
                                 let mut output: FlowRates = vec![];
                                 for (name, _rate) in &incoming {
-                                    if let Ok(name) = EntityName::from_str(name) {
+                                    if let Some((product, rate)) = self.smelting_product(name) {
+                                        output.push((product, rate * crafting_speed));
+                                    } else if let Ok(name) = EntityName::from_str(name) {
                                         match name {
                                             EntityName::IronOre => output.push((
                                                 EntityName::IronPlate.to_string(),
-                                                1. / 3.2,
+                                                crafting_speed / 3.2,
                                             )),
                                             EntityName::CopperOre => output.push((
                                                 EntityName::CopperPlate.to_string(),
-                                                1. / 3.2,
+                                                crafting_speed / 3.2,
                                             )),
                                             EntityName::Stone => output.push((
                                                 EntityName::StoneBrick.to_string(),
-                                                1. / 3.2,
+                                                crafting_speed / 3.2,
+                                            )),
+                                            EntityName::IronPlate => output.push((
+                                                EntityName::Steel.to_string(),
+                                                crafting_speed / 3.2,
                                             )),
-                                            EntityName::IronPlate => output
-                                                .push((EntityName::Steel.to_string(), 1. / 3.2)),
                                             EntityName::Coal => {}
                                             _ => warn!("invalid furnace input: {}", name),
                                         }
@@ -217,8 +395,34 @@ impl FlowGraph {
                                         warn!("invalid furnace input: {}", name);
                                     }
                                 }
+                                // several output inserters share the products
+                                let outgoing_count = entity_graph
+                                    .edges_directed(
+                                        source_node_index,
+                                        petgraph::Direction::Outgoing,
+                                    )
+                                    .count();
                                 self.update_flow_edge(
-                                    FlowEdge::Single(output),
+                                    FlowEdge::Single(
+                                        output
+                                            .into_iter()
+                                            .map(|(name, rate)| {
+                                                (name, rate / outgoing_count as f64)
+                                            })
+                                            .collect(),
+                                    ),
+                                    source_node,
+                                    target_node,
+                                );
+                                Control::Continue
+                            }
+                            EntityType::Pipe
+                            | EntityType::PipeToGround
+                            | EntityType::StorageTank => {
+                                let incoming =
+                                    self.sum_incoming_edge_weights(&source_node.position);
+                                self.update_flow_edge(
+                                    FlowEdge::Fluid(incoming),
                                     source_node,
                                     target_node,
                                 );
@@ -226,9 +430,6 @@ impl FlowGraph {
                             }
                             EntityType::Container
                             | EntityType::LogisticContainer
-                            | EntityType::PipeToGround
-                            | EntityType::StorageTank
-                            | EntityType::Pipe
                             | EntityType::Inserter => {
                                 // can have one incoming and one outgoing
                                 let incoming =
@@ -312,6 +513,16 @@ impl FlowGraph {
         source_entity_node: &EntityNode,
         target_entity_node: &EntityNode,
     ) {
+        // an inserter picks up no more than its hand moves
+        let flow = match flow {
+            FlowEdge::Single(rates) if target_entity_node.entity_type == EntityType::Inserter => {
+                FlowEdge::Single(self.limit_flowrate(
+                    &rates,
+                    inserter_throughput(&self.entity_prototypes, &target_entity_node.entity_name),
+                ))
+            }
+            flow => flow,
+        };
         let source_flow_idx = self.get_or_create_flow_node(source_entity_node);
         let target_flow_idx = self.get_or_create_flow_node(target_entity_node);
         self.inner
@@ -446,7 +657,7 @@ impl FlowGraph {
 
             if flow_node.direction == prev_node.direction || entity_edge_count == 1 {
                 match weight {
-                    FlowEdge::Single(vec) => {
+                    FlowEdge::Single(vec) | FlowEdge::Fluid(vec) => {
                         for (name, production_rate) in vec {
                             self.add_production_rate(
                                 &mut left,
@@ -469,7 +680,7 @@ impl FlowGraph {
                 }
             } else if flow_node.direction.clockwise().opposite() == prev_node.direction {
                 match weight {
-                    FlowEdge::Single(vec) => {
+                    FlowEdge::Single(vec) | FlowEdge::Fluid(vec) => {
                         for (name, production_rate) in vec {
                             self.add_production_rate(&mut right, (name.clone(), *production_rate));
                         }
@@ -485,7 +696,7 @@ impl FlowGraph {
                 }
             } else if flow_node.direction.clockwise() == prev_node.direction {
                 match weight {
-                    FlowEdge::Single(vec) => {
+                    FlowEdge::Single(vec) | FlowEdge::Fluid(vec) => {
                         for (name, production_rate) in vec {
                             self.add_production_rate(&mut left, (name.clone(), *production_rate));
                         }
@@ -524,6 +735,216 @@ impl FlowGraph {
         }
     }
 
+    /// scales all rates down so their sum stays below the given limit
+    #[allow(clippy::ptr_arg)]
+    fn limit_flowrate(&self, incoming: &FlowRates, limit: f64) -> FlowRates {
+        let total: f64 = incoming.iter().map(|(_, rate)| rate).sum();
+        if total <= limit {
+            incoming.clone()
+        } else {
+            incoming
+                .iter()
+                .map(|(name, rate)| (name.clone(), rate * limit / total))
+                .collect()
+        }
+    }
+
+    /// removes up to `amount` of the given flow, dropping everything used up
+    #[allow(clippy::ptr_arg)]
+    fn consume_flowrate(&self, incoming: &FlowRates, name: &str, amount: f64) -> FlowRates {
+        incoming
+            .iter()
+            .map(|(n, rate)| {
+                if n == name {
+                    (n.clone(), (rate - amount).max(0.))
+                } else {
+                    (n.clone(), *rate)
+                }
+            })
+            .filter(|(_, rate)| *rate > 0.)
+            .collect()
+    }
+
+    /// product of the smelting recipe which takes only `ingredient` and its rate per second
+    /// at crafting speed 1
+    fn smelting_product(&self, ingredient: &str) -> Option<(String, f64)> {
+        self.recipes.iter().find_map(|recipe| {
+            let ingredients = recipe.ingredients.as_ref()?;
+            if recipe.category != "smelting"
+                || ingredients.len() != 1
+                || ingredients[0].name != ingredient
+            {
+                return None;
+            }
+            let product = recipe.products.first()?;
+            Some((
+                product.name.clone(),
+                product.amount as f64 / recipe.energy.to_f64().unwrap(),
+            ))
+        })
+    }
+
+    fn prototype_value(
+        &self,
+        node: &EntityNode,
+        value: impl Fn(&FactorioEntityPrototype) -> Option<f64>,
+    ) -> Option<f64> {
+        self.entity_prototypes
+            .get(&node.entity_name)
+            .and_then(|prototype| value(&prototype))
+    }
+
+    /// pipe connection of `node` which leads into the entity at `target_index`
+    fn fluid_connection_to(
+        &self,
+        node: &EntityNode,
+        target_index: NodeIndex,
+    ) -> Option<FluidConnection> {
+        self.entity_graph
+            .fluid_connections(node)
+            .into_iter()
+            .find(|connection| {
+                self.entity_graph.node_at(&connection.position) == Some(target_index)
+            })
+    }
+
+    /// Fluid a fluid box of a machine produces or accepts, `None` if any fluid fits.
+    fn fluidbox_fluid(&self, node: &EntityNode, connection: &FluidConnection) -> Option<String> {
+        match node.entity_type {
+            EntityType::OffshorePump => Some(EntityName::Water.to_string()),
+            EntityType::MiningDrill if connection.production_type == "output" => {
+                node.miner_ore.clone()
+            }
+            EntityType::Boiler if connection.production_type == "output" => {
+                Some(EntityName::Steam.to_string())
+            }
+            EntityType::Boiler => Some(EntityName::Water.to_string()),
+            EntityType::Generator => Some(EntityName::Steam.to_string()),
+            EntityType::AssemblingMachine => {
+                // recipe fluids are assigned to fluid boxes of the same production type in order
+                let entity = self.entity_graph.entity_by_id(node.entity_id?)?;
+                let recipe = self.recipes.get(entity.recipe.as_ref()?)?;
+                let prototype = self.entity_prototypes.get(&node.entity_name)?;
+                let slot = prototype
+                    .fluidbox_prototypes
+                    .as_ref()?
+                    .iter()
+                    .take(connection.fluidbox_index)
+                    .filter(|fluidbox| fluidbox.production_type == connection.production_type)
+                    .count();
+                match connection.production_type.as_str() {
+                    "output" => recipe
+                        .products
+                        .iter()
+                        .filter(|product| product.product_type == "fluid")
+                        .nth(slot)
+                        .map(|product| product.name.clone()),
+                    "input" => recipe
+                        .ingredients
+                        .as_ref()?
+                        .iter()
+                        .filter(|ingredient| ingredient.ingredient_type == "fluid")
+                        .nth(slot)
+                        .map(|ingredient| ingredient.name.clone()),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Connected pipes, tanks and machine fluid boxes with the fluids flowing in
+    /// them, propagated from the machines which produce or accept those fluids.
+    pub fn fluid_networks(&self) -> Vec<FluidNetwork> {
+        let graph = self.entity_graph.inner_graph();
+        let mut elements: HashMap<(NodeIndex, Option<usize>), usize> = HashMap::new();
+        let mut element = |key: (NodeIndex, Option<usize>)| {
+            let next = elements.len();
+            *elements.entry(key).or_insert(next)
+        };
+        let mut unions: Vec<(usize, usize)> = vec![];
+        let mut fluids: Vec<(usize, String)> = vec![];
+        let mut positions: Vec<(usize, Position)> = vec![];
+        for node_index in graph.node_indices() {
+            let node = graph.node_weight(node_index).unwrap();
+            if node.entity_type.is_fluid_input() {
+                let a = element((node_index, None));
+                positions.push((a, node.position.clone()));
+                for next_index in graph.neighbors(node_index) {
+                    if graph
+                        .node_weight(next_index)
+                        .unwrap()
+                        .entity_type
+                        .is_fluid_input()
+                    {
+                        unions.push((a, element((next_index, None))));
+                    }
+                }
+                continue;
+            }
+            for connection in self.entity_graph.fluid_connections(node) {
+                let a = element((node_index, Some(connection.fluidbox_index)));
+                positions.push((a, node.position.clone()));
+                if let Some(fluid) = self.fluidbox_fluid(node, &connection) {
+                    fluids.push((a, fluid));
+                }
+                if let Some(next_index) = self.entity_graph.node_at(&connection.position) {
+                    if !graph.contains_edge(node_index, next_index)
+                        && !graph.contains_edge(next_index, node_index)
+                    {
+                        continue;
+                    }
+                    let next = graph.node_weight(next_index).unwrap();
+                    if next.entity_type.is_fluid_input() {
+                        unions.push((a, element((next_index, None))));
+                    } else if let Some(counterpart) = self.fluid_connection_to(next, node_index) {
+                        unions.push((a, element((next_index, Some(counterpart.fluidbox_index)))));
+                    }
+                }
+            }
+        }
+        let mut components = UnionFind::new(elements.len());
+        for (a, b) in unions {
+            components.union(a, b);
+        }
+        let mut networks: HashMap<usize, FluidNetwork> = HashMap::new();
+        for (element, position) in positions {
+            let network = networks.entry(components.find(element)).or_default();
+            if !network.entities.contains(&position) {
+                network.entities.push(position);
+            }
+        }
+        for (element, fluid) in fluids {
+            let network = networks.entry(components.find(element)).or_default();
+            if !network.fluids.contains(&fluid) {
+                network.fluids.push(fluid);
+            }
+        }
+        let mut networks: Vec<FluidNetwork> = networks
+            .into_values()
+            .filter(|network| network.entities.len() > 1)
+            .collect();
+        networks.sort_by(|a, b| {
+            (a.entities[0].x(), a.entities[0].y())
+                .partial_cmp(&(b.entities[0].x(), b.entities[0].y()))
+                .unwrap()
+        });
+        for (id, network) in networks.iter_mut().enumerate() {
+            network.id = id;
+            network.fluids.sort();
+        }
+        networks
+    }
+
+    /// Fluid networks where different fluids meet, e.g. a steam pipe touching
+    /// the water input of a boiler.
+    pub fn fluid_mismatches(&self) -> Vec<FluidNetwork> {
+        self.fluid_networks()
+            .into_iter()
+            .filter(|network| network.fluids.len() > 1)
+            .collect()
+    }
+
     fn sum_production_rates(&self, input: Vec<FlowRates>) -> FlowRates {
         let mut map: HashMap<String, f64> = HashMap::new();
         for vec in input {
@@ -549,7 +970,7 @@ impl FlowGraph {
         let mut rates: Vec<FlowRates> = vec![];
         for edge in incoming {
             match edge {
-                FlowEdge::Single(vec) => {
+                FlowEdge::Single(vec) | FlowEdge::Fluid(vec) => {
                     rates.push(vec);
                 }
                 FlowEdge::Double(left, right) => {
@@ -560,6 +981,66 @@ impl FlowGraph {
         }
         self.sum_production_rates(rates)
     }
+    /// Stores the items per minute each sampled entity moved, replacing earlier samplings.
+    pub fn record_throughput(&self, sampling: &ThroughputSampling) {
+        let mut measured = self.measured.write();
+        for entity in &sampling.entities {
+            measured.insert(
+                (&entity.position).into(),
+                entity.items_per_minute(sampling.ticks),
+            );
+        }
+    }
+
+    /// Measured items per minute along given edge, the source entity's throughput
+    /// is split evenly between its outgoing edges.
+    pub fn measured_flow(&self, edge_index: EdgeIndex) -> Option<FlowRates> {
+        self.measured_edge_flow(&self.inner.read(), edge_index)
+    }
+
+    fn measured_edge_flow(
+        &self,
+        graph: &FlowGraphInner,
+        edge_index: EdgeIndex,
+    ) -> Option<FlowRates> {
+        let (source, _target) = graph.edge_endpoints(edge_index)?;
+        let measured = self.measured.read();
+        let rates = measured.get(&(&graph.node_weight(source)?.position).into())?;
+        let outgoing = graph
+            .edges_directed(source, petgraph::Direction::Outgoing)
+            .count()
+            .max(1) as f64;
+        Some(
+            rates
+                .iter()
+                .map(|(name, rate)| (name.clone(), rate / outgoing))
+                .collect(),
+        )
+    }
+
+    /// Theoretical versus measured items per minute for every edge of the flow graph
+    pub fn throughput(&self) -> Vec<EdgeThroughput> {
+        let graph = self.inner.read();
+        graph
+            .edge_indices()
+            .map(|edge_index| {
+                let (source, target) = graph.edge_endpoints(edge_index).unwrap();
+                EdgeThroughput {
+                    source: graph.node_weight(source).unwrap().position.clone(),
+                    target: graph.node_weight(target).unwrap().position.clone(),
+                    theoretical: graph
+                        .edge_weight(edge_index)
+                        .unwrap()
+                        .rates()
+                        .into_iter()
+                        .map(|(name, rate)| (name, rate * 60.))
+                        .collect(),
+                    measured: self.measured_edge_flow(&graph, edge_index),
+                }
+            })
+            .collect()
+    }
+
     pub fn graphviz_dot(&self) -> String {
         format_dotgraph(
             Dot::with_config(&*self.inner.read(), &[Config::GraphContentOnly]).to_string(),
@@ -632,6 +1113,8 @@ impl std::fmt::Debug for FlowNode {
 pub enum FlowEdge {
     Single(Vec<(String, f64)>),
     Double(Vec<(String, f64)>, Vec<(String, f64)>),
+    /// fluids per second through pipes
+    Fluid(Vec<(String, f64)>),
 }
 
 impl std::fmt::Display for FlowEdge {
@@ -657,8 +1140,26 @@ impl FlowEdge {
                     .map(|(name, production_rate)| (name.clone(), production_rate / 2.))
                     .collect(),
             ),
+            FlowEdge::Fluid(vec) => FlowEdge::Fluid(
+                vec.iter()
+                    .map(|(name, production_rate)| (name.clone(), production_rate / 2.))
+                    .collect(),
+            ),
         }
     }
+
+    /// production rates per second of both lanes combined, sorted by name
+    pub fn rates(&self) -> FlowRates {
+        let mut map: BTreeMap<String, f64> = BTreeMap::new();
+        let lanes: Vec<&FlowRates> = match self {
+            FlowEdge::Single(vec) | FlowEdge::Fluid(vec) => vec![vec],
+            FlowEdge::Double(left, right) => vec![left, right],
+        };
+        for (name, production_rate) in lanes.into_iter().flatten() {
+            *map.entry(name.clone()).or_default() += production_rate;
+        }
+        map.into_iter().collect()
+    }
 }
 
 impl Default for FlowEdge {
@@ -667,15 +1168,67 @@ impl Default for FlowEdge {
     }
 }
 
+/// Pipes, tanks and machine fluid boxes which share their fluid
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct FluidNetwork {
+    pub id: usize,
+    pub entities: Vec<Position>,
+    /// more than one fluid means the network is mis-connected
+    pub fluids: Vec<String>,
+}
+
+/// Theoretical and measured items per minute along a flow graph edge
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct EdgeThroughput {
+    pub source: Position,
+    pub target: Position,
+    pub theoretical: FlowRates,
+    /// `None` if the source entity was never sampled
+    pub measured: Option<FlowRates>,
+}
+
+impl EdgeThroughput {
+    /// measured divided by theoretical throughput, `None` if unmeasured or without theoretical flow
+    pub fn utilization(&self) -> Option<f64> {
+        let theoretical: f64 = self.theoretical.iter().map(|(_, rate)| rate).sum();
+        let measured: f64 = self.measured.as_ref()?.iter().map(|(_, rate)| rate).sum();
+        if theoretical > 0. {
+            Some(measured / theoretical)
+        } else {
+            None
+        }
+    }
+}
+
 pub type FlowGraphInner = StableGraph<FlowNode, FlowEdge>;
 pub type FlowRate = (String, f64);
 pub type FlowRates = Vec<FlowRate>;
 
 pub type FlowQuadTree = QuadTree<NodeIndex, Rect, [(ItemId, QuadTreeRect); 4]>;
 
+/// Items per second an inserter moves, one hand full per turn there and back
+pub(crate) fn inserter_throughput(
+    prototypes: &DashMap<String, FactorioEntityPrototype>,
+    name: &str,
+) -> f64 {
+    let prototype = prototypes.get(name);
+    let rotation_speed = prototype
+        .as_ref()
+        .and_then(|prototype| prototype.rotation_speed)
+        .unwrap_or(INSERTER_ROTATION_SPEED);
+    let stack_size_bonus = prototype
+        .as_ref()
+        .and_then(|prototype| prototype.inserter_stack_size_bonus)
+        .unwrap_or(0);
+    rotation_speed * 60. * (1 + stack_size_bonus) as f64
+}
+
 #[cfg(test)]
 mod tests {
     use crate::test_utils::entity_graph_from;
+    use crate::types::EntityThroughput;
 
     use super::*;
 
@@ -791,4 +1344,134 @@ mod tests {
 "#,
         );
     }
+    #[test]
+    fn test_throughput() {
+        let entity_graph = entity_graph_from(vec![
+            FactorioEntity::new_resource(
+                &Position::new(0.5, -1.5),
+                Direction::South,
+                &EntityName::IronOre.to_string(),
+            ),
+            FactorioEntity::new_electric_mining_drill(&Position::new(0.5, -1.5), Direction::South),
+            FactorioEntity::new_transport_belt(&Position::new(0.5, 0.5), Direction::South),
+            FactorioEntity::new_transport_belt(&Position::new(1.5, 0.5), Direction::South),
+            FactorioEntity::new_splitter(&Position::new(1., 1.5), Direction::South),
+            FactorioEntity::new_transport_belt(&Position::new(0.5, 2.5), Direction::South),
+            FactorioEntity::new_transport_belt(&Position::new(1.5, 2.5), Direction::South),
+        ])
+        .unwrap();
+        let flow_graph = FlowGraph::new(Arc::new(entity_graph));
+        flow_graph.update().unwrap();
+        assert!(flow_graph
+            .throughput()
+            .iter()
+            .all(|edge| edge.measured.is_none()));
+
+        flow_graph.record_throughput(&ThroughputSampling {
+            surface: None,
+            ticks: 3600,
+            entities: vec![
+                EntityThroughput {
+                    name: EntityName::TransportBelt.to_string(),
+                    position: Position::new(0.5, 0.5),
+                    items: [(EntityName::IronOre.to_string(), 15)].into(),
+                },
+                EntityThroughput {
+                    name: EntityName::Splitter.to_string(),
+                    position: Position::new(1., 1.5),
+                    items: [(EntityName::IronOre.to_string(), 30)].into(),
+                },
+            ],
+        });
+        let throughput = flow_graph.throughput();
+        let belt = throughput
+            .iter()
+            .find(|edge| edge.source == Position::new(0.5, 0.5))
+            .unwrap();
+        assert_eq!(
+            belt.theoretical,
+            vec![(EntityName::IronOre.to_string(), 30.)]
+        );
+        assert_eq!(
+            belt.measured,
+            Some(vec![(EntityName::IronOre.to_string(), 15.)])
+        );
+        assert_eq!(belt.utilization(), Some(0.5));
+        let splitter: Vec<&EdgeThroughput> = throughput
+            .iter()
+            .filter(|edge| edge.source == Position::new(1., 1.5))
+            .collect();
+        assert_eq!(splitter.len(), 2);
+        for edge in splitter {
+            assert_eq!(edge.utilization(), Some(1.));
+        }
+        let miner = throughput
+            .iter()
+            .find(|edge| edge.source == Position::new(0.5, -1.5))
+            .unwrap();
+        assert_eq!(miner.utilization(), None);
+    }
+    #[test]
+    fn test_fluid_flow() {
+        let entity_graph = entity_graph_from(vec![
+            FactorioEntity::new_offshore_pump(&Position::new(1.5, 0.5), Direction::North),
+            FactorioEntity::new_pipe(&Position::new(1.5, 1.5)),
+            FactorioEntity::new_pipe(&Position::new(1.5, 2.5)),
+            FactorioEntity::new_pipe(&Position::new(1.5, 3.5)),
+            FactorioEntity::new_pipe(&Position::new(1.5, 4.5)),
+            FactorioEntity::new_boiler(&Position::new(3.5, 4.), Direction::North),
+            FactorioEntity::new_pipe(&Position::new(3.5, 2.5)),
+            FactorioEntity::new_steam_engine(&Position::new(3.5, -0.5), Direction::North),
+        ])
+        .unwrap();
+        let flow_graph = FlowGraph::new(Arc::new(entity_graph));
+        flow_graph.update().unwrap();
+        assert_eq!(
+            flow_graph.graphviz_dot_condensed(),
+            r#"digraph {
+    0 [ label = "offshore-pump at [1.5, 0.5]" ]
+    1 [ label = "pipe at [1.5, 1.5]" ]
+    4 [ label = "pipe at [1.5, 4.5]" ]
+    5 [ label = "boiler at [3.5, 4]" ]
+    6 [ label = "pipe at [3.5, 2.5]" ]
+    7 [ label = "steam-engine at [3.5, -0.5]" ]
+    0 -> 1 [ label = "Fluid([(\"water\", 1200.0)])" ]
+    1 -> 4 [ label = "Fluid([(\"water\", 1200.0)])" ]
+    4 -> 5 [ label = "Fluid([(\"water\", 1200.0)])" ]
+    5 -> 6 [ label = "Fluid([(\"steam\", 60.0)])" ]
+    6 -> 7 [ label = "Fluid([(\"steam\", 60.0)])" ]
+}
+"#,
+        );
+        let networks = flow_graph.fluid_networks();
+        assert_eq!(networks.len(), 2);
+        assert_eq!(networks[0].fluids, vec![EntityName::Water.to_string()]);
+        assert_eq!(networks[0].entities.len(), 6);
+        assert_eq!(networks[1].fluids, vec![EntityName::Steam.to_string()]);
+        assert_eq!(networks[1].entities.len(), 3);
+        assert!(flow_graph.fluid_mismatches().is_empty());
+    }
+    #[test]
+    fn test_fluid_mismatch() {
+        let entity_graph = entity_graph_from(vec![
+            FactorioEntity::new_offshore_pump(&Position::new(1.5, 0.5), Direction::North),
+            FactorioEntity::new_pipe(&Position::new(1.5, 1.5)),
+            FactorioEntity::new_pipe(&Position::new(1.5, 2.5)),
+            FactorioEntity::new_pipe(&Position::new(1.5, 3.5)),
+            FactorioEntity::new_pipe(&Position::new(1.5, 4.5)),
+            FactorioEntity::new_boiler(&Position::new(3.5, 4.), Direction::North),
+            FactorioEntity::new_pipe(&Position::new(3.5, 2.5)),
+            // leaks steam into the water line
+            FactorioEntity::new_pipe(&Position::new(2.5, 2.5)),
+        ])
+        .unwrap();
+        let flow_graph = FlowGraph::new(Arc::new(entity_graph));
+        let mismatches = flow_graph.fluid_mismatches();
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(
+            mismatches[0].fluids,
+            vec![EntityName::Steam.to_string(), EntityName::Water.to_string()]
+        );
+        assert!(mismatches[0].entities.contains(&Position::new(2.5, 2.5)));
+    }
 }
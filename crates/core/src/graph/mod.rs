@@ -1,3 +1,5 @@
 pub mod entity_graph;
 pub mod flow_graph;
+pub mod power_graph;
 pub mod task_graph;
+pub mod walk_grid;
@@ -0,0 +1,467 @@
+use crate::aabb_quadtree::ItemId;
+use crate::factorio::util::{add_to_rect, calculate_distance, format_dotgraph};
+use crate::graph::entity_graph::EntityGraph;
+use crate::types::{EntityType, FactorioEntity, FactorioEntityPrototype, Position, Rect};
+use dashmap::DashMap;
+use miette::Result;
+use parking_lot::{RwLock, RwLockReadGuard};
+use petgraph::dot::{Config, Dot};
+use petgraph::graph::NodeIndex;
+use petgraph::stable_graph::StableGraph;
+use petgraph::unionfind::UnionFind;
+use petgraph::visit::{EdgeRef, IntoEdgeReferences, NodeIndexable};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Electric networks of a surface: poles connected by wires and the
+/// producers, accumulators and consumers inside their supply areas
+pub struct PowerGraph {
+    entity_graph: Arc<EntityGraph>,
+    entity_prototypes: Arc<DashMap<String, FactorioEntityPrototype>>,
+    inner: RwLock<PowerGraphInner>,
+    networks: RwLock<Vec<PowerNetwork>>,
+}
+
+impl Clone for PowerGraph {
+    fn clone(&self) -> Self {
+        PowerGraph {
+            entity_graph: Arc::new((*self.entity_graph).clone()),
+            entity_prototypes: Arc::new((*self.entity_prototypes).clone()),
+            inner: RwLock::new((*self.inner.read()).clone()),
+            networks: RwLock::new((*self.networks.read()).clone()),
+        }
+    }
+}
+
+impl PowerGraph {
+    pub fn new(entity_graph: Arc<EntityGraph>) -> Self {
+        PowerGraph {
+            entity_prototypes: entity_graph.entity_prototypes(),
+            entity_graph,
+            inner: RwLock::new(PowerGraphInner::default()),
+            networks: RwLock::new(vec![]),
+        }
+    }
+
+    /// Rebuilds all networks from the entities currently in the entity graph
+    pub fn update(&self) -> Result<()> {
+        let tree = self.entity_graph.inner_tree();
+        let mut inner = PowerGraphInner::default();
+        let mut nodes: HashMap<ItemId, NodeIndex> = HashMap::new();
+        let mut poles: Vec<(NodeIndex, f64, f64)> = vec![];
+        for (entity_id, (entity, _rect)) in tree.iter() {
+            let prototype = match self.entity_prototypes.get(&entity.name) {
+                Some(prototype) => prototype,
+                None => continue,
+            };
+            let role = match PowerRole::of(&prototype) {
+                Some(role) => role,
+                None => continue,
+            };
+            let node = inner.add_node(PowerNode::new(entity, role));
+            nodes.insert(*entity_id, node);
+            if role == PowerRole::Pole {
+                poles.push((
+                    node,
+                    prototype.supply_area_distance.unwrap_or_default(),
+                    prototype.max_wire_distance.unwrap_or_default(),
+                ));
+            }
+        }
+
+        for (index, (pole, _, wire_distance)) in poles.iter().enumerate() {
+            for (other, _, other_wire_distance) in &poles[index + 1..] {
+                let distance = calculate_distance(&inner[*pole].position, &inner[*other].position);
+                if distance <= wire_distance.min(*other_wire_distance) {
+                    inner.add_edge(*pole, *other, PowerEdge::Wire(distance));
+                }
+            }
+        }
+        for (pole, supply_area_distance, _) in &poles {
+            let supply_area = add_to_rect(
+                &Rect::from_wh(supply_area_distance * 2., supply_area_distance * 2.),
+                &inner[*pole].position,
+            );
+            for (_entity, _rect, entity_id) in tree.query(supply_area.into()) {
+                if let Some(node) = nodes.get(&entity_id) {
+                    if inner[*node].role != PowerRole::Pole {
+                        inner.add_edge(*pole, *node, PowerEdge::Supply);
+                    }
+                }
+            }
+        }
+
+        // only wires join networks, an entity inside the supply areas of several
+        // networks is assigned to one of them below
+        let mut components = UnionFind::new(inner.node_bound());
+        for edge in inner.edge_references() {
+            if let PowerEdge::Wire(_) = edge.weight() {
+                components.union(edge.source().index(), edge.target().index());
+            }
+        }
+        let mut network_ids: HashMap<usize, usize> = HashMap::new();
+        for (pole, _, _) in &poles {
+            let next_id = network_ids.len();
+            network_ids
+                .entry(components.find(pole.index()))
+                .or_insert(next_id);
+        }
+        let mut networks: Vec<PowerNetwork> = (0..network_ids.len())
+            .map(|id| PowerNetwork {
+                id,
+                ..Default::default()
+            })
+            .collect();
+        let node_indices: Vec<NodeIndex> = inner.node_indices().collect();
+        for node_index in node_indices {
+            let network_id = if inner[node_index].role == PowerRole::Pole {
+                network_ids
+                    .get(&components.find(node_index.index()))
+                    .copied()
+            } else {
+                inner
+                    .edges_directed(node_index, petgraph::Direction::Incoming)
+                    .filter(|edge| *edge.weight() == PowerEdge::Supply)
+                    .filter_map(|edge| network_ids.get(&components.find(edge.source().index())))
+                    .min()
+                    .copied()
+            };
+            let node = &mut inner[node_index];
+            node.network = network_id;
+            if let Some(network_id) = network_id {
+                let prototype = self.entity_prototypes.get(&node.entity_name);
+                let prototype = prototype.as_deref();
+                let network = &mut networks[network_id];
+                match node.role {
+                    PowerRole::Pole => network.poles += 1,
+                    PowerRole::Producer => {
+                        network.producers += 1;
+                        network.supply += prototype
+                            .and_then(|p| p.max_energy_production)
+                            .unwrap_or_default();
+                    }
+                    PowerRole::Accumulator => {
+                        network.accumulators += 1;
+                        network.buffer += prototype
+                            .and_then(|p| p.electric_buffer_size)
+                            .unwrap_or_default();
+                    }
+                    PowerRole::Consumer => {
+                        network.consumers += 1;
+                        network.demand +=
+                            prototype.and_then(|p| p.energy_usage).unwrap_or_default();
+                    }
+                }
+            }
+        }
+        *self.inner.write() = inner;
+        *self.networks.write() = networks;
+        Ok(())
+    }
+
+    pub fn inner_graph(&self) -> RwLockReadGuard<'_, PowerGraphInner> {
+        self.inner.read()
+    }
+
+    pub fn networks(&self) -> Vec<PowerNetwork> {
+        self.networks.read().clone()
+    }
+
+    pub fn node_at(&self, position: &Position) -> Option<NodeIndex> {
+        let inner = self.inner.read();
+        inner.node_indices().find(|node_index| {
+            let node = &inner[*node_index];
+            node.position == *position || node.bounding_box.contains(position)
+        })
+    }
+
+    /// Network of the pole or powered entity at given position
+    pub fn network_at(&self, position: &Position) -> Option<PowerNetwork> {
+        let node_index = self.node_at(position)?;
+        let network_id = self.inner.read()[node_index].network?;
+        self.networks.read().get(network_id).cloned()
+    }
+
+    /// Consumers outside of every supply area or on a network without any production
+    pub fn unpowered(&self) -> Vec<PowerNode> {
+        let inner = self.inner.read();
+        let networks = self.networks.read();
+        inner
+            .node_weights()
+            .filter(|node| node.role == PowerRole::Consumer)
+            .filter(|node| match node.network {
+                Some(network_id) => networks[network_id].supply <= 0.,
+                None => true,
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub fn report(&self) -> PowerReport {
+        PowerReport {
+            networks: self.networks(),
+            unpowered: self.unpowered(),
+        }
+    }
+
+    pub fn graphviz_dot(&self) -> String {
+        format_dotgraph(
+            Dot::with_config(&*self.inner.read(), &[Config::GraphContentOnly]).to_string(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerRole {
+    Pole,
+    Producer,
+    Accumulator,
+    Consumer,
+}
+
+impl PowerRole {
+    /// `None` for entities which neither use nor distribute electricity
+    pub fn of(prototype: &FactorioEntityPrototype) -> Option<PowerRole> {
+        if prototype.entity_type == EntityType::ElectricPole.to_string()
+            || prototype.supply_area_distance.is_some()
+        {
+            Some(PowerRole::Pole)
+        } else if prototype.energy_source.as_deref() != Some("electric") {
+            None
+        } else if prototype.entity_type == EntityType::Accumulator.to_string() {
+            Some(PowerRole::Accumulator)
+        } else if prototype.max_energy_production.unwrap_or_default() > 0. {
+            Some(PowerRole::Producer)
+        } else {
+            Some(PowerRole::Consumer)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PowerNode {
+    pub entity_name: String,
+    pub position: Position,
+    pub bounding_box: Rect,
+    pub role: PowerRole,
+    /// index into `PowerGraph::networks`, `None` if outside of every supply area
+    pub network: Option<usize>,
+}
+
+impl PowerNode {
+    pub fn new(entity: &FactorioEntity, role: PowerRole) -> PowerNode {
+        PowerNode {
+            entity_name: entity.name.clone(),
+            position: entity.position.clone(),
+            bounding_box: entity.bounding_box.clone(),
+            role,
+            network: None,
+        }
+    }
+}
+
+impl std::fmt::Display for PowerNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {}", self.entity_name, self.position)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PowerEdge {
+    /// copper wire between two poles, weight is its length
+    Wire(f64),
+    /// pole supplying an entity inside its supply area
+    Supply,
+}
+
+impl std::fmt::Display for PowerEdge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PowerEdge::Wire(distance) => write!(f, "{:.1}", distance),
+            PowerEdge::Supply => f.write_str("supply"),
+        }
+    }
+}
+
+/// Expected supply and demand of one electric network in watts
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PowerNetwork {
+    pub id: usize,
+    pub poles: u32,
+    pub producers: u32,
+    pub accumulators: u32,
+    pub consumers: u32,
+    /// peak production of all generators and solar panels
+    pub supply: f64,
+    /// energy usage of all consumers working at full speed
+    pub demand: f64,
+    /// accumulator capacity in joules
+    pub buffer: f64,
+}
+
+impl PowerNetwork {
+    /// share of the demand which can be satisfied, machines slow down below 1
+    pub fn satisfaction(&self) -> f64 {
+        if self.demand <= 0. {
+            1.
+        } else {
+            (self.supply / self.demand).min(1.)
+        }
+    }
+
+    pub fn is_overloaded(&self) -> bool {
+        self.demand > self.supply
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PowerReport {
+    pub networks: Vec<PowerNetwork>,
+    pub unpowered: Vec<PowerNode>,
+}
+
+pub type PowerGraphInner = StableGraph<PowerNode, PowerEdge>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixture_entity_prototypes;
+    use crate::types::Direction;
+
+    fn electric_prototypes() -> Arc<DashMap<String, FactorioEntityPrototype>> {
+        let prototypes = fixture_entity_prototypes();
+        if let Some(mut pole) = prototypes.get_mut("small-electric-pole") {
+            pole.supply_area_distance = Some(2.5);
+            pole.max_wire_distance = Some(7.5);
+        }
+        if let Some(mut engine) = prototypes.get_mut("steam-engine") {
+            engine.energy_source = Some("electric".into());
+            engine.max_energy_production = Some(900_000.);
+        }
+        if let Some(mut assembler) = prototypes.get_mut("assembling-machine-1") {
+            assembler.energy_source = Some("electric".into());
+            assembler.energy_usage = Some(75_000.);
+        }
+        if let Some(mut furnace) = prototypes.get_mut("stone-furnace") {
+            furnace.energy_source = Some("burner".into());
+            furnace.energy_usage = Some(90_000.);
+        }
+        Arc::new(prototypes)
+    }
+
+    fn entity(
+        name: &str,
+        x: f64,
+        y: f64,
+        prototypes: &Arc<DashMap<String, FactorioEntityPrototype>>,
+    ) -> FactorioEntity {
+        FactorioEntity::from_prototype(
+            name,
+            Position::new(x, y),
+            Some(Direction::North),
+            None,
+            None,
+            prototypes.clone(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_power_networks() {
+        let prototypes = electric_prototypes();
+        let entity_graph = Arc::new(EntityGraph::new(
+            prototypes.clone(),
+            Arc::new(DashMap::new()),
+        ));
+        entity_graph
+            .add(
+                vec![
+                    entity("steam-engine", 1.5, 2.5, &prototypes),
+                    entity("small-electric-pole", 3.5, 0.5, &prototypes),
+                    entity("small-electric-pole", 9.5, 0.5, &prototypes),
+                    entity("assembling-machine-1", 11.5, 1.5, &prototypes),
+                    entity("assembling-machine-1", 30.5, 1.5, &prototypes),
+                    entity("stone-furnace", 8., 3., &prototypes),
+                    entity("small-electric-pole", 40.5, 0.5, &prototypes),
+                    entity("assembling-machine-1", 42.5, 1.5, &prototypes),
+                ],
+                None,
+            )
+            .unwrap();
+        let power_graph = PowerGraph::new(entity_graph);
+        power_graph.update().unwrap();
+
+        let networks = power_graph.networks();
+        assert_eq!(networks.len(), 2);
+        let network = power_graph
+            .network_at(&Position::new(11.5, 1.5))
+            .expect("assembler is powered");
+        assert_eq!(network.poles, 2);
+        assert_eq!(network.producers, 1);
+        assert_eq!(network.consumers, 1);
+        assert_eq!(network.supply, 900_000.);
+        assert_eq!(network.demand, 75_000.);
+        assert!(!network.is_overloaded());
+        assert!(power_graph.network_at(&Position::new(8., 3.)).is_none());
+
+        let mut unpowered: Vec<Position> = power_graph
+            .unpowered()
+            .into_iter()
+            .map(|node| node.position)
+            .collect();
+        unpowered.sort_by(|a, b| a.x().partial_cmp(&b.x()).unwrap());
+        assert_eq!(
+            unpowered,
+            vec![Position::new(30.5, 1.5), Position::new(42.5, 1.5)]
+        );
+    }
+
+    #[test]
+    fn test_unwired_poles_sharing_consumer() {
+        let prototypes = electric_prototypes();
+        let entity_graph = Arc::new(EntityGraph::new(
+            prototypes.clone(),
+            Arc::new(DashMap::new()),
+        ));
+        // both poles reach the assembler but are too far apart for a wire
+        entity_graph
+            .add(
+                vec![
+                    entity("steam-engine", -1.5, 0.5, &prototypes),
+                    entity("small-electric-pole", 1.5, 1.5, &prototypes),
+                    entity("assembling-machine-1", 4.5, 4.5, &prototypes),
+                    entity("small-electric-pole", 7.5, 7.5, &prototypes),
+                ],
+                None,
+            )
+            .unwrap();
+        let power_graph = PowerGraph::new(entity_graph);
+        power_graph.update().unwrap();
+
+        let networks = power_graph.networks();
+        assert_eq!(networks.len(), 2);
+        assert!(networks.iter().all(|network| network.poles == 1));
+        assert_eq!(
+            networks
+                .iter()
+                .map(|network| network.consumers)
+                .sum::<u32>(),
+            1
+        );
+        let powered = power_graph
+            .network_at(&Position::new(1.5, 1.5))
+            .expect("pole has a network");
+        assert_eq!(powered.producers, 1);
+        assert_eq!(powered.supply, 900_000.);
+        let unpowered = power_graph
+            .network_at(&Position::new(7.5, 7.5))
+            .expect("pole has a network");
+        assert_eq!(unpowered.producers, 0);
+        assert_eq!(unpowered.supply, 0.);
+        assert!(power_graph.network_at(&Position::new(4.5, 4.5)).is_some());
+    }
+}
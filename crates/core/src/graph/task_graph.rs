@@ -1,8 +1,9 @@
+use crate::constants::DEFAULT_QUALITY;
 use crate::factorio::util::format_dotgraph;
 use crate::gantt_mermaid::MermaidGanttBuilder;
 use crate::num_traits::FromPrimitive;
 use crate::types::{
-    Direction, FactorioEntity, InventoryItem, InventoryLocation, MineTarget, PlayerId,
+    item_key, Direction, FactorioEntity, InventoryItem, InventoryLocation, MineTarget, PlayerId,
     PositionRadius,
 };
 use miette::Result;
@@ -106,6 +107,7 @@ impl TaskGraph {
         task_node.outputs.push(ResourceFlow {
             item_name: target.name.clone(),
             count: target.count,
+            quality: DEFAULT_QUALITY.into(),
         });
         let node = self.inner.add_node(task_node);
         self.add_to_group(player_id, node, cost);
@@ -124,6 +126,7 @@ impl TaskGraph {
         task_node.inputs.push(ResourceFlow {
             item_name: entity.name.clone(),
             count: 1,
+            quality: entity.quality().into(),
         });
         let node = self.inner.add_node(task_node);
         self.add_to_group(player_id, node, cost);
@@ -146,6 +149,7 @@ impl TaskGraph {
         task_node.inputs.push(ResourceFlow {
             item_name: item.name.clone(),
             count: item.count,
+            quality: item.quality.clone(),
         });
         let node = self.inner.add_node(task_node);
         self.add_to_group(player_id, node, cost);
@@ -158,9 +162,15 @@ impl TaskGraph {
         location: InventoryLocation,
         item: InventoryItem,
     ) {
-        let node = self.inner.add_node(TaskNode::new_remove_from_inventory(
-            player_id, location, item, cost,
-        ));
+        let mut task_node =
+            TaskNode::new_remove_from_inventory(player_id, location, item.clone(), cost);
+        // Populate outputs - removed items end up in player's inventory
+        task_node.outputs.push(ResourceFlow {
+            item_name: item.name.clone(),
+            count: item.count,
+            quality: item.quality.clone(),
+        });
+        let node = self.inner.add_node(task_node);
         self.add_to_group(player_id, node, cost);
     }
 
@@ -193,9 +203,10 @@ impl TaskGraph {
 
                 // Check if producer outputs match consumer inputs
                 let has_matching_resource = producer.outputs.iter().any(|output| {
-                    consumer.inputs.iter().any(|input| {
-                        input.item_name == output.item_name
-                    })
+                    consumer
+                        .inputs
+                        .iter()
+                        .any(|input| input.key() == output.key())
                 });
 
                 if has_matching_resource {
@@ -237,27 +248,29 @@ impl TaskGraph {
             // Initialize player's resource tracker if needed
             let resources = player_resources.entry(player_id).or_default();
 
-            // Check if inputs are satisfied
+            // Check if inputs are satisfied, items of different quality don't mix
             for input in &node.inputs {
-                let available = resources.get(&input.item_name).copied().unwrap_or(0);
+                let key = input.key();
+                let available = resources.get(&key).copied().unwrap_or(0);
                 if available < input.count {
                     return Err(InsufficientResources {
                         task_name: node.name.clone(),
                         player_id,
-                        item_name: input.item_name.clone(),
+                        item_name: key,
                         required: input.count,
                         available,
                     }
                     .into());
                 }
                 // Consume the input
-                resources.insert(input.item_name.clone(), available - input.count);
+                resources.insert(key, available - input.count);
             }
 
             // Add outputs to available resources
             for output in &node.outputs {
-                let current = resources.get(&output.item_name).copied().unwrap_or(0);
-                resources.insert(output.item_name.clone(), current + output.count);
+                let key = output.key();
+                let current = resources.get(&key).copied().unwrap_or(0);
+                resources.insert(key, current + output.count);
             }
         }
 
@@ -407,6 +420,13 @@ impl TaskGraph {
 pub struct ResourceFlow {
     pub item_name: String,
     pub count: u32,
+    pub quality: String,
+}
+
+impl ResourceFlow {
+    pub fn key(&self) -> String {
+        item_key(&self.item_name, &self.quality)
+    }
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -458,7 +478,7 @@ impl TaskNode {
             Some(player_id),
             &format!(
                 "Craft {}{}",
-                item.name,
+                item.key(),
                 if item.count > 1 {
                     format!(" x {}", item.count)
                 } else {
@@ -498,7 +518,7 @@ impl TaskNode {
             Some(player_id),
             &format!(
                 "Place {} at {} ({:?})",
-                entity.name,
+                item_key(&entity.name, entity.quality()),
                 entity.position,
                 Direction::from_u8(entity.direction).unwrap()
             ),
@@ -516,7 +536,10 @@ impl TaskNode {
             Some(player_id),
             &format!(
                 "Insert {}x{} into {} at {}",
-                &item.name, &item.count, location.entity_name, location.position
+                item.key(),
+                &item.count,
+                location.entity_name,
+                location.position
             ),
             Some(TaskData::InsertToInventory(location, item)),
             cost,
@@ -532,7 +555,10 @@ impl TaskNode {
             Some(player_id),
             &format!(
                 "Remove {}x{} from {} at {}",
-                &item.name, &item.count, location.entity_name, location.position
+                item.key(),
+                &item.count,
+                location.entity_name,
+                location.position
             ),
             Some(TaskData::RemoveFromInventory(location, item)),
             cost,
@@ -655,4 +681,48 @@ mod tests {
 "##,
         );
     }
+
+    #[test]
+    fn test_resource_flow_keeps_quality_apart() {
+        let chest = InventoryLocation {
+            entity_name: "wooden-chest".into(),
+            position: Position::default(),
+            inventory_type: 1,
+        };
+        let mut task_graph = TaskGraph::new();
+        task_graph.group_start("quality");
+        task_graph.add_mine_node(
+            1,
+            3.,
+            MineTarget {
+                position: Position::default(),
+                count: 5,
+                name: "iron-ore".into(),
+            },
+        );
+        task_graph.add_insert_into_inventory_node(
+            1,
+            1.,
+            chest.clone(),
+            InventoryItem::with_quality("iron-ore", 5, "uncommon"),
+        );
+        task_graph.group_end();
+        let err = task_graph.validate_resource_flow().unwrap_err();
+        assert!(format!("{:?}", err).contains("iron-ore@uncommon"));
+
+        let mut task_graph = TaskGraph::new();
+        task_graph.group_start("quality");
+        task_graph.add_mine_node(
+            1,
+            3.,
+            MineTarget {
+                position: Position::default(),
+                count: 5,
+                name: "iron-ore".into(),
+            },
+        );
+        task_graph.add_insert_into_inventory_node(1, 1., chest, InventoryItem::new("iron-ore", 5));
+        task_graph.group_end();
+        task_graph.validate_resource_flow().unwrap();
+    }
 }
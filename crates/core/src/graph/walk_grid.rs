@@ -0,0 +1,411 @@
+use crate::errors::PathNotFound;
+use crate::graph::entity_graph::BlockedQuadTree;
+use crate::types::{DangerZone, Pos, Position, Rect};
+use miette::Result;
+use pathfinding::prelude::astar;
+
+/// tiles around start and goal the first search may use, doubled on every retry
+const WALK_MARGIN: f64 = 16.;
+const MAX_WALK_MARGIN: f64 = 256.;
+
+/// cost of a straight step, diagonal steps cost `DIAGONAL_COST`
+const STRAIGHT_COST: u32 = 1000;
+const DIAGONAL_COST: u32 = 1414;
+
+/// Walking path from `start` to `goal`, searched in the area around both which
+/// grows until a path is found or `MAX_WALK_MARGIN` is reached.
+pub fn find_walk_path(
+    blocked_tree: &BlockedQuadTree,
+    start: &Position,
+    goal: &Position,
+    radius: Option<f64>,
+    danger_zones: &[DangerZone],
+) -> Result<Vec<Position>> {
+    let mut margin = WALK_MARGIN;
+    while margin <= MAX_WALK_MARGIN {
+        let area = Rect::new(
+            &Position::new(
+                start.x().min(goal.x()) - margin,
+                start.y().min(goal.y()) - margin,
+            ),
+            &Position::new(
+                start.x().max(goal.x()) + margin,
+                start.y().max(goal.y()) + margin,
+            ),
+        );
+        let mut grid = WalkGrid::new(blocked_tree, &area, danger_zones);
+        // characters may stand partially inside the box of a neighbouring entity
+        grid.set_blocked(&start.into(), false);
+        if let Some(path) = grid.find_path(start, goal, radius) {
+            return Ok(path);
+        }
+        margin *= 2.;
+    }
+    Err(PathNotFound {
+        start: start.clone(),
+        goal: goal.clone(),
+    }
+    .into())
+}
+
+/// Tiles a character can walk on, rasterized from the blocked quadtree.
+///
+/// Paths are searched with jump point search on 8 directions, diagonal steps are
+/// only allowed if both adjacent tiles are free so characters never cut corners.
+pub struct WalkGrid {
+    left: i32,
+    top: i32,
+    width: i32,
+    height: i32,
+    blocked: Vec<bool>,
+}
+
+impl WalkGrid {
+    /// Marks every tile within `area` which touches a blocked box or a danger zone
+    pub fn new(blocked_tree: &BlockedQuadTree, area: &Rect, danger_zones: &[DangerZone]) -> Self {
+        let left = area.left_top.x().floor() as i32;
+        let top = area.left_top.y().floor() as i32;
+        let width = (area.right_bottom.x().ceil() as i32 - left).max(1);
+        let height = (area.right_bottom.y().ceil() as i32 - top).max(1);
+        let mut grid = WalkGrid {
+            left,
+            top,
+            width,
+            height,
+            blocked: vec![false; (width * height) as usize],
+        };
+        for (_minable, rect, _id) in blocked_tree.query(area.clone().into()) {
+            for y in rect.min_y().floor() as i32..rect.max_y().ceil() as i32 {
+                for x in rect.min_x().floor() as i32..rect.max_x().ceil() as i32 {
+                    grid.set_blocked(&Pos(x, y), true);
+                }
+            }
+        }
+        for zone in danger_zones
+            .iter()
+            .filter(|zone| zone.intersects_rect(area))
+        {
+            let radius = zone.radius.ceil() as i32;
+            let center: Pos = (&zone.position).into();
+            for y in center.1 - radius..=center.1 + radius {
+                for x in center.0 - radius..=center.0 + radius {
+                    let tile = Rect::new(
+                        &Position::new(x as f64, y as f64),
+                        &Position::new(x as f64 + 1., y as f64 + 1.),
+                    );
+                    if zone.intersects_rect(&tile) {
+                        grid.set_blocked(&Pos(x, y), true);
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    fn index(&self, pos: &Pos) -> Option<usize> {
+        let (x, y) = (pos.0 - self.left, pos.1 - self.top);
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            None
+        } else {
+            Some((y * self.width + x) as usize)
+        }
+    }
+
+    pub fn set_blocked(&mut self, pos: &Pos, blocked: bool) {
+        if let Some(index) = self.index(pos) {
+            self.blocked[index] = blocked;
+        }
+    }
+
+    /// tiles outside of the grid are never walkable
+    pub fn is_walkable(&self, pos: &Pos) -> bool {
+        self.index(pos).is_some_and(|index| !self.blocked[index])
+    }
+
+    fn is_free(&self, x: i32, y: i32) -> bool {
+        self.is_walkable(&Pos(x, y))
+    }
+
+    /// Waypoints from `start` to `goal` or to the first tile whose center is within
+    /// `radius` of it, without `start` itself. `None` if there is no such path within the grid.
+    pub fn find_path(
+        &self,
+        start: &Position,
+        goal: &Position,
+        radius: Option<f64>,
+    ) -> Option<Vec<Position>> {
+        let start_tile: Pos = start.into();
+        let goal_tile: Pos = goal.into();
+        let radius = radius.unwrap_or(0.);
+        let search = JumpPointSearch {
+            grid: self,
+            goal: goal.clone(),
+            goal_tile: goal_tile.clone(),
+            radius,
+        };
+        let (path, _cost) = astar(
+            &(start_tile.clone(), (0, 0)),
+            |(tile, parent_direction)| search.successors(tile, *parent_direction),
+            |(tile, _)| search.heuristic(tile),
+            |(tile, _)| search.is_goal(tile),
+        )?;
+        Some(
+            path.into_iter()
+                .skip(1)
+                .map(|(tile, _)| {
+                    if tile == goal_tile {
+                        goal.clone()
+                    } else {
+                        tile_center(&tile)
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+
+/// tile and the unit step which led to it
+type JumpNode = (Pos, (i32, i32));
+
+struct JumpPointSearch<'a> {
+    grid: &'a WalkGrid,
+    goal: Position,
+    goal_tile: Pos,
+    radius: f64,
+}
+
+impl JumpPointSearch<'_> {
+    fn is_goal(&self, tile: &Pos) -> bool {
+        *tile == self.goal_tile || tile_center(tile).distance(&self.goal) <= self.radius
+    }
+
+    fn heuristic(&self, tile: &Pos) -> u32 {
+        octile_cost(tile, &self.goal_tile)
+            .saturating_sub((self.radius * STRAIGHT_COST as f64) as u32)
+    }
+
+    /// jump points reachable from `tile`, `parent_direction` is the unit step
+    /// which led to `tile` or `(0, 0)` for the start
+    fn successors(&self, tile: &Pos, parent_direction: (i32, i32)) -> Vec<(JumpNode, u32)> {
+        self.directions(tile, parent_direction)
+            .into_iter()
+            .filter_map(|(dx, dy)| {
+                self.jump(tile.0 + dx, tile.1 + dy, dx, dy)
+                    .map(|jump_point| {
+                        let cost = octile_cost(tile, &jump_point);
+                        ((jump_point, (dx, dy)), cost)
+                    })
+            })
+            .collect()
+    }
+
+    /// natural and forced neighbour directions of `tile`
+    fn directions(&self, tile: &Pos, (dx, dy): (i32, i32)) -> Vec<(i32, i32)> {
+        let free = |x: i32, y: i32| self.grid.is_free(tile.0 + x, tile.1 + y);
+        let mut directions = vec![];
+        if dx == 0 && dy == 0 {
+            for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                if free(dx, dy) {
+                    directions.push((dx, dy));
+                }
+            }
+            for (dx, dy) in [(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+                if free(dx, 0) && free(0, dy) && free(dx, dy) {
+                    directions.push((dx, dy));
+                }
+            }
+        } else if dx != 0 && dy != 0 {
+            if free(0, dy) {
+                directions.push((0, dy));
+            }
+            if free(dx, 0) {
+                directions.push((dx, 0));
+            }
+            if free(0, dy) && free(dx, 0) {
+                directions.push((dx, dy));
+            }
+        } else if dx != 0 {
+            let (next, below, above) = (free(dx, 0), free(0, 1), free(0, -1));
+            if next {
+                directions.push((dx, 0));
+                if below {
+                    directions.push((dx, 1));
+                }
+                if above {
+                    directions.push((dx, -1));
+                }
+            }
+            if below {
+                directions.push((0, 1));
+            }
+            if above {
+                directions.push((0, -1));
+            }
+        } else {
+            let (next, right, left) = (free(0, dy), free(1, 0), free(-1, 0));
+            if next {
+                directions.push((0, dy));
+                if right {
+                    directions.push((1, dy));
+                }
+                if left {
+                    directions.push((-1, dy));
+                }
+            }
+            if right {
+                directions.push((1, 0));
+            }
+            if left {
+                directions.push((-1, 0));
+            }
+        }
+        directions
+    }
+
+    /// follows the direction from `(x, y)` until it reaches the goal, a tile with
+    /// forced neighbours or an obstacle
+    fn jump(&self, mut x: i32, mut y: i32, dx: i32, dy: i32) -> Option<Pos> {
+        let free = |x: i32, y: i32| self.grid.is_free(x, y);
+        loop {
+            if !free(x, y) {
+                return None;
+            }
+            if self.is_goal(&Pos(x, y)) {
+                return Some(Pos(x, y));
+            }
+            if dx != 0 && dy != 0 {
+                if self.jump(x + dx, y, dx, 0).is_some() || self.jump(x, y + dy, 0, dy).is_some() {
+                    return Some(Pos(x, y));
+                }
+                if !free(x + dx, y) || !free(x, y + dy) {
+                    return None;
+                }
+            } else if dx != 0 {
+                if (free(x, y - 1) && !free(x - dx, y - 1))
+                    || (free(x, y + 1) && !free(x - dx, y + 1))
+                {
+                    return Some(Pos(x, y));
+                }
+            } else if (free(x - 1, y) && !free(x - 1, y - dy))
+                || (free(x + 1, y) && !free(x + 1, y - dy))
+            {
+                return Some(Pos(x, y));
+            }
+            x += dx;
+            y += dy;
+        }
+    }
+}
+
+fn tile_center(tile: &Pos) -> Position {
+    Position::new(tile.0 as f64 + 0.5, tile.1 as f64 + 0.5)
+}
+
+fn octile_cost(from: &Pos, to: &Pos) -> u32 {
+    let dx = (from.0 - to.0).unsigned_abs();
+    let dy = (from.1 - to.1).unsigned_abs();
+    let (short, long) = if dx < dy { (dx, dy) } else { (dy, dx) };
+    short * DIAGONAL_COST + (long - short) * STRAIGHT_COST
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::entity_graph_from;
+    use crate::types::{Direction, FactorioEntity};
+
+    /// every tile a path walks over, assuming straight or diagonal segments
+    fn walked_tiles(start: &Position, path: &[Position]) -> Vec<Pos> {
+        let mut tiles = vec![];
+        let mut current: Pos = start.into();
+        for waypoint in path {
+            let next: Pos = waypoint.into();
+            let (dx, dy) = ((next.0 - current.0).signum(), (next.1 - current.1).signum());
+            while current != next {
+                current = Pos(current.0 + dx, current.1 + dy);
+                tiles.push(current.clone());
+            }
+        }
+        tiles
+    }
+
+    #[test]
+    fn test_walk_around_wall() {
+        let entity_graph = entity_graph_from(
+            (-5..=5)
+                .map(|x| {
+                    FactorioEntity::new_stone_furnace(
+                        &Position::new(x as f64 * 2., 0.),
+                        Direction::North,
+                    )
+                })
+                .collect(),
+        )
+        .unwrap();
+        let start = Position::new(0.5, -5.5);
+        let goal = Position::new(0.5, 5.5);
+        let blocked_tree = entity_graph.blocked_tree();
+        let path = find_walk_path(&blocked_tree, &start, &goal, None, &[]).unwrap();
+        assert_eq!(path.last(), Some(&goal));
+        let grid = WalkGrid::new(
+            &blocked_tree,
+            &Rect::new(&Position::new(-20., -20.), &Position::new(20., 20.)),
+            &[],
+        );
+        let tiles = walked_tiles(&start, &path);
+        assert!(tiles.iter().all(|tile| grid.is_walkable(tile)));
+        assert!(tiles.iter().any(|tile| tile.0 < -11 || tile.0 > 10));
+
+        let path = find_walk_path(&blocked_tree, &start, &goal, Some(8.), &[]).unwrap();
+        assert!(path.last().unwrap().distance(&goal) <= 8.);
+        assert!(walked_tiles(&start, &path).len() < tiles.len());
+
+        // a danger zone around the western end leaves only the eastern detour
+        let zone = DangerZone {
+            name: String::from("small-worm-turret"),
+            position: Position::new(-12., 0.),
+            radius: 8.,
+        };
+        let path = find_walk_path(
+            &blocked_tree,
+            &start,
+            &goal,
+            None,
+            std::slice::from_ref(&zone),
+        )
+        .unwrap();
+        let tiles = walked_tiles(&start, &path);
+        assert!(tiles.iter().all(|tile| !zone.contains(&tile_center(tile))));
+        assert!(tiles.iter().any(|tile| tile.0 > 10));
+    }
+
+    #[test]
+    fn test_walk_path_not_found() {
+        let entity_graph = entity_graph_from(
+            [
+                (-2., -2.),
+                (0., -2.),
+                (2., -2.),
+                (2., 0.),
+                (2., 2.),
+                (0., 2.),
+                (-2., 2.),
+                (-2., 0.),
+            ]
+            .iter()
+            .map(|(x, y)| {
+                FactorioEntity::new_stone_furnace(&Position::new(*x, *y), Direction::North)
+            })
+            .collect(),
+        )
+        .unwrap();
+        let result = find_walk_path(
+            &entity_graph.blocked_tree(),
+            &Position::new(10.5, 10.5),
+            &Position::new(0.5, 0.5),
+            None,
+            &[],
+        );
+        assert!(result.is_err());
+    }
+}
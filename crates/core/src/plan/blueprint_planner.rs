@@ -0,0 +1,516 @@
+use crate::errors::{BlueprintBookUnsupported, BlueprintNoPlayers};
+use crate::factorio::util::calculate_distance;
+use crate::factorio::world::FactorioWorld;
+use crate::factorio_blueprint::{BlueprintCodec, Container};
+use crate::plan::plan_builder::PlanBuilder;
+use crate::types::{
+    item_key, split_item_key, EntityType, FactorioEntity, FactorioPlayer, InventoryItem,
+    InventoryLocation, PlayerId, Position, Rect,
+};
+use miette::{IntoDiagnostic, Result};
+use num_traits::FromPrimitive;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// tiles per side of the grid cells entities are clustered by
+const CLUSTER_SIZE: f64 = 8.;
+/// tiles a bot walks in about the time it takes to place one entity
+const PLACE_WALK_TILES: f64 = 9.;
+/// largest distance from an entity position to the edge of its bounding box
+const MAX_ENTITY_RADIUS: f64 = 5.;
+
+/// Parts of a blueprint in the order they are built. Poles come before their consumers,
+/// belts and pipes before the machines and the inserters between them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BuildStage {
+    Power,
+    Transport,
+    Machines,
+    Inserters,
+}
+
+impl BuildStage {
+    pub fn of(entity: &FactorioEntity) -> BuildStage {
+        match EntityType::from_str(&entity.entity_type) {
+            Ok(EntityType::ElectricPole) => BuildStage::Power,
+            Ok(
+                EntityType::TransportBelt
+                | EntityType::UndergroundBelt
+                | EntityType::Splitter
+                | EntityType::Pipe
+                | EntityType::PipeToGround,
+            ) => BuildStage::Transport,
+            Ok(EntityType::Inserter) => BuildStage::Inserters,
+            _ => BuildStage::Machines,
+        }
+    }
+}
+
+impl fmt::Display for BuildStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BuildStage::Power => "power",
+            BuildStage::Transport => "transport",
+            BuildStage::Machines => "machines",
+            BuildStage::Inserters => "inserters",
+        })
+    }
+}
+
+/// Chest bots take items from which they are missing to build
+#[derive(Debug, Clone)]
+pub struct BuildSupply {
+    pub location: InventoryLocation,
+    /// available items by item key
+    pub items: BTreeMap<String, u32>,
+}
+
+/// Entities built or mined by one bot in one go
+#[derive(Debug, Clone)]
+struct Cluster {
+    center: Position,
+    entities: Vec<FactorioEntity>,
+}
+
+/// Splits a blueprint into tasks for several bots.
+///
+/// Entities in the way are mined first, then every `BuildStage` is a task graph group so
+/// all bots finish a stage before the next one starts. Within a stage entities are
+/// clustered by area and clusters handed to the bot which can build them soonest, judged
+/// by walking distance, work already assigned and items in inventory. Missing items are
+/// fetched from the `BuildSupply` chest if there is one.
+pub struct BlueprintPlanner {
+    builder: PlanBuilder,
+    world: Arc<FactorioWorld>,
+    supply: Option<BuildSupply>,
+}
+
+impl BlueprintPlanner {
+    pub fn new(builder: PlanBuilder, world: Arc<FactorioWorld>) -> BlueprintPlanner {
+        BlueprintPlanner {
+            builder,
+            world,
+            supply: None,
+        }
+    }
+
+    pub fn with_supply(mut self, supply: BuildSupply) -> BlueprintPlanner {
+        self.supply = Some(supply);
+        self
+    }
+
+    /// Plans building `blueprint` centered at `position`, see `plan_entities`
+    pub fn plan_blueprint(
+        &mut self,
+        player_ids: &[PlayerId],
+        blueprint: &str,
+        position: &Position,
+        obstructions: &[FactorioEntity],
+    ) -> Result<Vec<FactorioEntity>> {
+        let blueprint = match BlueprintCodec::decode_string(blueprint).into_diagnostic()? {
+            Container::Blueprint(blueprint) => blueprint,
+            Container::BlueprintBook(_) => return Err(BlueprintBookUnsupported {}.into()),
+        };
+        let entities = blueprint
+            .entities
+            .into_iter()
+            .map(|entity| {
+                let entity = FactorioEntity::from_blueprint_entity(
+                    entity,
+                    self.world.entity_prototypes.clone(),
+                )?;
+                self.moved(entity, position)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.plan_entities(player_ids, entities, obstructions)
+    }
+
+    /// Plans mining `obstructions` and entities of the world in the way, then placing
+    /// `entities` which are not built yet. Returns the entities to be placed.
+    pub fn plan_entities(
+        &mut self,
+        player_ids: &[PlayerId],
+        entities: Vec<FactorioEntity>,
+        obstructions: &[FactorioEntity],
+    ) -> Result<Vec<FactorioEntity>> {
+        if player_ids.is_empty() {
+            return Err(BlueprintNoPlayers {}.into());
+        }
+        let surface = self.player(player_ids[0]).surface;
+        let entity_graph = self.world.surface(surface.as_deref()).entity_graph.clone();
+        let mut in_the_way: Vec<FactorioEntity> = vec![];
+        let mut to_build: Vec<FactorioEntity> = vec![];
+        for entity in entities {
+            let area = Rect::new(
+                &Position::new(
+                    entity.bounding_box.left_top.x() - MAX_ENTITY_RADIUS,
+                    entity.bounding_box.left_top.y() - MAX_ENTITY_RADIUS,
+                ),
+                &Position::new(
+                    entity.bounding_box.right_bottom.x() + MAX_ENTITY_RADIUS,
+                    entity.bounding_box.right_bottom.y() + MAX_ENTITY_RADIUS,
+                ),
+            );
+            let mut built = false;
+            for other in entity_graph.entities_in(&area).iter().chain(obstructions) {
+                if other.entity_type == EntityType::Resource.to_string()
+                    || other.entity_type == EntityType::Character.to_string()
+                    || !overlaps(&entity.bounding_box, &other.bounding_box)
+                {
+                    continue;
+                }
+                if other.name == entity.name
+                    && other.position == entity.position
+                    && other.direction == entity.direction
+                {
+                    built = true;
+                } else if !in_the_way
+                    .iter()
+                    .any(|e| e.name == other.name && e.position == other.position)
+                {
+                    in_the_way.push(other.clone());
+                }
+            }
+            if !built {
+                to_build.push(entity);
+            }
+        }
+
+        if !in_the_way.is_empty() {
+            let assignment = self.assign(player_ids, clusters(in_the_way), false);
+            self.builder.group_start("clear build area");
+            for (player_id, entities) in assignment {
+                for entity in self.tour(player_id, entities) {
+                    self.builder
+                        .mine(player_id, entity.position.clone(), &entity.name, 1)?;
+                }
+            }
+            self.builder.group_end();
+        }
+
+        let mut stages: BTreeMap<BuildStage, Vec<FactorioEntity>> = BTreeMap::new();
+        for entity in to_build {
+            stages
+                .entry(BuildStage::of(&entity))
+                .or_default()
+                .push(entity);
+        }
+        let mut placed: Vec<FactorioEntity> = vec![];
+        for (stage, entities) in stages {
+            let assignment = self.assign(player_ids, clusters(entities), true);
+            self.builder.group_start(&format!("build {}", stage));
+            for (player_id, entities) in assignment {
+                self.supply_items(player_id, &entities)?;
+                for entity in self.tour(player_id, entities) {
+                    placed.push(self.builder.add_place(player_id, entity)?);
+                }
+            }
+            self.builder.group_end();
+        }
+        Ok(placed)
+    }
+
+    /// Hands out clusters, largest first, to the bot with the lowest cost. Bots which
+    /// would lack items even with the supply chest are only picked if all do.
+    fn assign(
+        &self,
+        player_ids: &[PlayerId],
+        mut clusters: Vec<Cluster>,
+        with_items: bool,
+    ) -> BTreeMap<PlayerId, Vec<FactorioEntity>> {
+        struct Bot {
+            position: Position,
+            inventory: BTreeMap<String, u32>,
+            load: usize,
+        }
+        let mut bots: BTreeMap<PlayerId, Bot> = player_ids
+            .iter()
+            .map(|player_id| {
+                let player = self.player(*player_id);
+                (
+                    *player_id,
+                    Bot {
+                        position: player.position,
+                        inventory: player.main_inventory,
+                        load: 0,
+                    },
+                )
+            })
+            .collect();
+        let mut supply_items = self
+            .supply
+            .as_ref()
+            .map(|supply| supply.items.clone())
+            .unwrap_or_default();
+        let mut assignment: BTreeMap<PlayerId, Vec<FactorioEntity>> = BTreeMap::new();
+        clusters.sort_by_key(|cluster| Reverse(cluster.entities.len()));
+        for cluster in clusters {
+            let needed = if with_items {
+                needed_items(&cluster.entities)
+            } else {
+                BTreeMap::new()
+            };
+            let (player_id, _) = bots
+                .iter()
+                .map(|(player_id, bot)| {
+                    let missing = missing_items(&needed, &bot.inventory);
+                    let unavailable: u32 = missing_items(&missing, &supply_items).values().sum();
+                    let walk = match self.supply.as_ref() {
+                        Some(supply) if !missing.is_empty() => {
+                            calculate_distance(&bot.position, &supply.location.position)
+                                + calculate_distance(&supply.location.position, &cluster.center)
+                        }
+                        _ => calculate_distance(&bot.position, &cluster.center),
+                    };
+                    let cost = walk + bot.load as f64 * PLACE_WALK_TILES;
+                    (*player_id, (unavailable, cost))
+                })
+                .min_by(|(_, a), (_, b)| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)))
+                .expect("at least one player");
+            let bot = bots.get_mut(&player_id).unwrap();
+            for (key, count) in needed {
+                let in_inventory = bot.inventory.entry(key.clone()).or_insert(0);
+                let taken = (*in_inventory).min(count);
+                *in_inventory -= taken;
+                if let Some(in_supply) = supply_items.get_mut(&key) {
+                    *in_supply -= (*in_supply).min(count - taken);
+                }
+            }
+            bot.position = cluster.center;
+            bot.load += cluster.entities.len();
+            assignment
+                .entry(player_id)
+                .or_default()
+                .extend(cluster.entities);
+        }
+        assignment
+    }
+
+    /// Takes items missing to place `entities` out of the supply chest
+    fn supply_items(&mut self, player_id: PlayerId, entities: &[FactorioEntity]) -> Result<()> {
+        let missing = missing_items(
+            &needed_items(entities),
+            &self.player(player_id).main_inventory,
+        );
+        let Some(supply) = self.supply.as_mut() else {
+            return Ok(());
+        };
+        for (key, count) in missing {
+            let in_supply = supply.items.entry(key.clone()).or_insert(0);
+            let count = count.min(*in_supply);
+            if count == 0 {
+                continue;
+            }
+            *in_supply -= count;
+            let (name, quality) = split_item_key(&key);
+            self.builder.add_remove_from_inventory(
+                player_id,
+                supply.location.clone(),
+                InventoryItem::with_quality(name, count, quality),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Orders entities by always going to the nearest one next
+    fn tour(&self, player_id: PlayerId, mut entities: Vec<FactorioEntity>) -> Vec<FactorioEntity> {
+        let mut position = self.player(player_id).position;
+        let mut ordered = Vec::with_capacity(entities.len());
+        while !entities.is_empty() {
+            let (index, _) = entities
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| {
+                    calculate_distance(&position, &a.position)
+                        .total_cmp(&calculate_distance(&position, &b.position))
+                })
+                .unwrap();
+            let entity = entities.swap_remove(index);
+            position = entity.position.clone();
+            ordered.push(entity);
+        }
+        ordered
+    }
+
+    /// Blueprint entity moved from blueprint coordinates to `position`
+    fn moved(&self, entity: FactorioEntity, position: &Position) -> Result<FactorioEntity> {
+        let mut moved = FactorioEntity::from_prototype(
+            &entity.name,
+            entity.position.add(position),
+            FromPrimitive::from_u8(entity.direction),
+            entity.pickup_position.map(|p| p.add(position)),
+            entity.drop_position.map(|p| p.add(position)),
+            self.world.entity_prototypes.clone(),
+        )?;
+        moved.recipe = entity.recipe;
+        moved.belt_to_ground_type = entity.belt_to_ground_type;
+        moved.modules = entity.modules;
+        Ok(moved)
+    }
+
+    fn player(&self, player_id: PlayerId) -> FactorioPlayer {
+        self.world
+            .players
+            .get(&player_id)
+            .expect("failed to find player")
+            .clone()
+    }
+}
+
+fn clusters(entities: Vec<FactorioEntity>) -> Vec<Cluster> {
+    let mut cells: BTreeMap<(i64, i64), Vec<FactorioEntity>> = BTreeMap::new();
+    for entity in entities {
+        let cell = (
+            (entity.position.x() / CLUSTER_SIZE).floor() as i64,
+            (entity.position.y() / CLUSTER_SIZE).floor() as i64,
+        );
+        cells.entry(cell).or_default().push(entity);
+    }
+    cells
+        .into_values()
+        .map(|entities| {
+            let count = entities.len() as f64;
+            let center = Position::new(
+                entities.iter().map(|e| e.position.x()).sum::<f64>() / count,
+                entities.iter().map(|e| e.position.y()).sum::<f64>() / count,
+            );
+            Cluster { center, entities }
+        })
+        .collect()
+}
+
+fn needed_items(entities: &[FactorioEntity]) -> BTreeMap<String, u32> {
+    let mut needed: BTreeMap<String, u32> = BTreeMap::new();
+    for entity in entities {
+        *needed
+            .entry(item_key(&entity.name, entity.quality()))
+            .or_insert(0) += 1;
+    }
+    needed
+}
+
+fn missing_items(
+    needed: &BTreeMap<String, u32>,
+    available: &BTreeMap<String, u32>,
+) -> BTreeMap<String, u32> {
+    needed
+        .iter()
+        .filter_map(|(key, count)| {
+            let missing = count.saturating_sub(*available.get(key).unwrap_or(&0));
+            (missing > 0).then(|| (key.clone(), missing))
+        })
+        .collect()
+}
+
+fn overlaps(a: &Rect, b: &Rect) -> bool {
+    a.left_top.x() < b.right_bottom.x()
+        && b.left_top.x() < a.right_bottom.x()
+        && a.left_top.y() < b.right_bottom.y()
+        && b.left_top.y() < a.right_bottom.y()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::task_graph::{TaskData, TaskGraph};
+    use crate::test_utils::fixture_world;
+    use crate::types::{Direction, PlayerChangedMainInventoryEvent, PlayerChangedPositionEvent};
+    use parking_lot::RwLock;
+
+    #[test]
+    fn test_plan_entities() {
+        let world = Arc::new(fixture_world());
+        for (player_id, x, items) in [
+            (
+                1,
+                0.,
+                vec![
+                    ("small-electric-pole", 1),
+                    ("transport-belt", 2),
+                    ("inserter", 1),
+                    ("assembling-machine-1", 1),
+                ],
+            ),
+            (2, 60., vec![]),
+        ] {
+            world
+                .player_changed_main_inventory(PlayerChangedMainInventoryEvent::from_btreemap(
+                    player_id,
+                    items.into_iter().map(|(k, v)| (k.to_owned(), v)).collect(),
+                ))
+                .unwrap();
+            world
+                .player_changed_position(PlayerChangedPositionEvent {
+                    player_id,
+                    position: Position::new(x, 0.),
+                    surface: None,
+                })
+                .unwrap();
+        }
+        let entity = |name: &str, x: f64, y: f64| {
+            FactorioEntity::from_prototype(
+                name,
+                Position::new(x, y),
+                Some(Direction::East),
+                None,
+                None,
+                world.entity_prototypes.clone(),
+            )
+            .unwrap()
+        };
+        let entities = vec![
+            entity("transport-belt", 1.5, 1.5),
+            entity("transport-belt", 2.5, 1.5),
+            entity("inserter", 2.5, 2.5),
+            entity("assembling-machine-1", 2.5, 4.5),
+            entity("small-electric-pole", 0.5, 3.5),
+            entity("small-electric-pole", 60.5, 3.5),
+            entity("transport-belt", 61.5, 1.5),
+        ];
+        let obstructions = vec![FactorioEntity::new_rock(
+            &Position::new(2.5, 4.5),
+            "rock-huge",
+        )];
+        let graph = Arc::new(RwLock::new(TaskGraph::new()));
+        let builder = PlanBuilder::new(graph.clone(), world.clone());
+        let placed = BlueprintPlanner::new(builder, world.clone())
+            .with_supply(BuildSupply {
+                location: InventoryLocation {
+                    entity_name: "wooden-chest".into(),
+                    position: Position::new(60.5, 6.5),
+                    inventory_type: 1,
+                },
+                items: BTreeMap::from([
+                    ("small-electric-pole".into(), 5),
+                    ("transport-belt".into(), 5),
+                ]),
+            })
+            .plan_entities(&[1, 2], entities, &obstructions)
+            .unwrap();
+        assert_eq!(placed.len(), 7);
+
+        let graph = graph.read();
+        let nodes: Vec<_> = graph
+            .node_indices()
+            .filter_map(|index| graph.node_weight(index))
+            .collect();
+        let group = |label: &str| nodes.iter().position(|n| n.name == label).unwrap();
+        assert!(group("Start: clear build area") < group("Start: build power"));
+        assert!(group("Start: build power") < group("Start: build transport"));
+        assert!(group("Start: build transport") < group("Start: build machines"));
+        assert!(group("Start: build machines") < group("Start: build inserters"));
+        assert!(nodes.iter().any(|n| n.name == "Mining rock-huge"));
+        assert!(nodes
+            .iter()
+            .any(|n| n.player_id == Some(2) && n.name.starts_with("Remove small-electric-pole")));
+        for node in nodes {
+            if let Some(TaskData::PlaceEntity(entity)) = &node.data {
+                let player_id = if entity.position.x() > 30. { 2 } else { 1 };
+                assert_eq!(node.player_id, Some(player_id), "{}", node.name);
+            }
+        }
+    }
+}
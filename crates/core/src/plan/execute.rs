@@ -1,10 +1,13 @@
+use crate::errors::BotKilled;
 use crate::graph::task_graph::{TaskData, TaskStatus};
 use crate::plan::planner::Planner;
+use crate::types::{EntityAttackedEvent, PlayerId};
 use futures::future::join_all;
 use miette::Result;
 use petgraph::visit::EdgeRef;
 use petgraph::Direction;
 use std::time::Duration;
+use tokio::sync::broadcast::{error::TryRecvError, Receiver};
 
 #[allow(dead_code)]
 async fn execute(planner: &Planner) -> Result<()> {
@@ -28,6 +31,7 @@ async fn execute(planner: &Planner) -> Result<()> {
 async fn execute_single(planner: &Planner, player_id: u8) -> Result<()> {
     let graph = planner.graph.read();
     let mut cursor = graph.start_node;
+    let mut attacks = planner.real_world.subscribe_attacks();
 
     while cursor != graph.end_node {
         let node = graph
@@ -143,6 +147,7 @@ async fn execute_single(planner: &Planner, player_id: u8) -> Result<()> {
                             location.inventory_type,
                             item.name.clone(),
                             item.count,
+                            Some(item.quality.clone()),
                             &planner.real_world,
                         )
                         .await?;
@@ -159,6 +164,7 @@ async fn execute_single(planner: &Planner, player_id: u8) -> Result<()> {
                             location.inventory_type,
                             item.name.clone(),
                             item.count,
+                            Some(item.quality.clone()),
                             &planner.real_world,
                         )
                         .await?;
@@ -173,6 +179,7 @@ async fn execute_single(planner: &Planner, player_id: u8) -> Result<()> {
                             entity.name.clone(),
                             entity.position.clone(),
                             entity.direction,
+                            entity.quality.clone(),
                             &planner.real_world,
                         )
                         .await?;
@@ -199,6 +206,9 @@ async fn execute_single(planner: &Planner, player_id: u8) -> Result<()> {
             }
         }
 
+        // Bots may get attacked while working, stop this bot once it died
+        check_attacks(&mut attacks, player_id)?;
+
         let cursor_copy = cursor;
         for edge in graph.edges_directed(cursor, Direction::Outgoing) {
             let target_idx = edge.target();
@@ -217,6 +227,25 @@ async fn execute_single(planner: &Planner, player_id: u8) -> Result<()> {
     Ok(())
 }
 
+fn check_attacks(attacks: &mut Receiver<EntityAttackedEvent>, player_id: PlayerId) -> Result<()> {
+    loop {
+        match attacks.try_recv() {
+            Ok(event) if event.player_id == Some(player_id) => {
+                let cause = event.cause.unwrap_or_else(|| "unknown".into());
+                if event.died {
+                    return Err(BotKilled { player_id, cause }.into());
+                }
+                warn!(
+                    "bot {} took {} damage from {} at {}, {} health left",
+                    player_id, event.damage, cause, event.position, event.health
+                );
+            }
+            Ok(_) | Err(TryRecvError::Lagged(_)) => continue,
+            Err(_) => return Ok(()),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::factorio::rcon::MockFactorioRcon;
@@ -280,10 +309,10 @@ mod tests {
     6 [ label = "Mining rock-huge" ]
     7 [ label = "End" ]
     0 -> 2 [ label = "0" ]
-    2 -> 3 [ label = "45" ]
-    2 -> 5 [ label = "48" ]
+    2 -> 3 [ label = "5" ]
+    2 -> 5 [ label = "6" ]
     3 -> 4 [ label = "3" ]
-    4 -> 7 [ label = "3" ]
+    4 -> 7 [ label = "1" ]
     5 -> 6 [ label = "3" ]
     6 -> 7 [ label = "0" ]
     7 -> 1 [ label = "0" ]
@@ -292,4 +321,26 @@ mod tests {
         );
         execute(&planner).await.expect("failed to execute");
     }
+
+    #[test]
+    fn test_killed_bot_stops() {
+        let world = fixture_world();
+        let mut attacks = world.subscribe_attacks();
+        let attack = EntityAttackedEvent {
+            name: "character".into(),
+            entity_type: "character".into(),
+            position: Position::new(1., 2.),
+            surface: None,
+            player_id: Some(2),
+            cause: Some("small-biter".into()),
+            damage: 15.,
+            health: 0.,
+            died: true,
+        };
+        world.on_entity_attacked(attack.clone()).unwrap();
+        assert!(check_attacks(&mut attacks, 1).is_ok());
+        world.on_entity_attacked(attack).unwrap();
+        let err = check_attacks(&mut attacks, 2).unwrap_err();
+        assert_eq!(format!("{}", err), "bot 2 was killed by small-biter");
+    }
 }
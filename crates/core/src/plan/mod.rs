@@ -1,3 +1,5 @@
+pub mod blueprint_planner;
 pub mod execute;
 pub mod plan_builder;
 pub mod planner;
+pub mod walk_cost;
@@ -3,7 +3,7 @@ use crate::factorio::util::calculate_distance;
 use crate::factorio::world::FactorioWorld;
 use crate::graph::task_graph::TaskGraph;
 use crate::types::{
-    FactorioEntity, FactorioPlayer, InventoryItem, InventoryLocation, MineTarget,
+    item_key, FactorioEntity, FactorioPlayer, InventoryItem, InventoryLocation, MineTarget,
     PlayerChangedMainInventoryEvent, PlayerChangedPositionEvent, PlayerId, Position,
     PositionRadius,
 };
@@ -73,12 +73,18 @@ impl PlanBuilder {
         Ok(())
     }
 
-    fn distance(&self, player_id: PlayerId, position: &Position) -> f64 {
-        calculate_distance(
-            &self.world.players.get(&player_id).unwrap().position,
-            position,
-        )
-        .ceil()
+    /// seconds to walk to `goal`, see `WalkCostModel`
+    fn walk_time(&self, player_id: PlayerId, goal: &PositionRadius) -> f64 {
+        let player = self.player(player_id);
+        self.world
+            .walk_time(
+                player_id,
+                player.surface.as_deref(),
+                &player.position,
+                &goal.position,
+                Some(goal.radius),
+            )
+            .ceil()
     }
 
     fn player(&self, player_id: PlayerId) -> FactorioPlayer {
@@ -97,14 +103,15 @@ impl PlanBuilder {
     // }
 
     pub fn add_walk(&self, player_id: PlayerId, goal: PositionRadius) -> Result<()> {
-        let distance = self.distance(player_id, &goal.position);
+        let cost = self.walk_time(player_id, &goal);
         self.world
             .player_changed_position(PlayerChangedPositionEvent {
                 player_id,
                 position: goal.position.clone(),
+                surface: self.player(player_id).surface,
             })?;
         let mut graph = self.graph.write();
-        graph.add_walk_node(player_id, distance, goal);
+        graph.add_walk_node(player_id, cost, goal);
         Ok(())
     }
 
@@ -119,17 +126,18 @@ impl PlanBuilder {
             )?;
         }
         let mut inventory = self.player(player_id).main_inventory;
-        let inventory_item_count = *inventory.get(&entity.name).unwrap_or(&0);
+        let key = item_key(&entity.name, entity.quality());
+        let inventory_item_count = *inventory.get(&key).unwrap_or(&0);
         if inventory_item_count < 1 {
             return Err(PlayerMissingItem {
                 player_id,
-                item: entity.name,
+                item: key,
             }
             .into());
         }
         let mut graph = self.graph.write();
         graph.add_place_node(player_id, 1., entity.clone());
-        inventory.insert(entity.name.clone(), inventory_item_count - 1);
+        inventory.insert(key, inventory_item_count - 1);
         self.world.player_changed_main_inventory(
             PlayerChangedMainInventoryEvent::from_btreemap(player_id, inventory),
         )?;
@@ -153,11 +161,12 @@ impl PlanBuilder {
             )?;
         }
         let mut inventory = self.player(player_id).main_inventory;
-        let inventory_item_count = *inventory.get(&item.name).unwrap_or(&0);
+        let key = item.key();
+        let inventory_item_count = *inventory.get(&key).unwrap_or(&0);
         if inventory_item_count < item.count {
             return Err(PlayerMissingItem {
                 player_id,
-                item: item.name,
+                item: key,
             }
             .into());
         }
@@ -166,7 +175,34 @@ impl PlanBuilder {
         drop(graph);
 
         // Update player inventory to reflect items inserted into target inventory
-        inventory.insert(item.name.clone(), inventory_item_count - item.count);
+        inventory.insert(key, inventory_item_count - item.count);
+        self.world.player_changed_main_inventory(
+            PlayerChangedMainInventoryEvent::from_btreemap(player_id, inventory),
+        )?;
+        Ok(())
+    }
+
+    pub fn add_remove_from_inventory(
+        &self,
+        player_id: PlayerId,
+        location: InventoryLocation,
+        item: InventoryItem,
+    ) -> Result<()> {
+        let player = self.player(player_id);
+        let distance = calculate_distance(&player.position, &location.position);
+        let reach_distance = player.reach_distance as f64;
+        if distance > reach_distance {
+            self.add_walk(
+                player_id,
+                PositionRadius::from_position(&location.position, reach_distance),
+            )?;
+        }
+        let mut graph = self.graph.write();
+        graph.add_remove_from_inventory_node(player_id, 1., location, item.clone());
+        drop(graph);
+
+        let mut inventory = self.player(player_id).main_inventory;
+        *inventory.entry(item.key()).or_insert(0) += item.count;
         self.world.player_changed_main_inventory(
             PlayerChangedMainInventoryEvent::from_btreemap(player_id, inventory),
         )?;
@@ -2,6 +2,7 @@
 use crate::factorio::rcon::FactorioRcon;
 use crate::factorio::world::FactorioWorld;
 use crate::graph::task_graph::TaskGraph;
+use crate::process::server_registry::SharedFactorioServerRegistry;
 use crate::types::{EntityName, PlayerChangedMainInventoryEvent};
 use parking_lot::RwLock;
 use petgraph::Direction;
@@ -14,6 +15,8 @@ pub struct Planner {
     pub real_world: Arc<FactorioWorld>,
     pub plan_world: Arc<FactorioWorld>,
     pub graph: Arc<RwLock<TaskGraph>>,
+    /// other servers scripts may target, see `with_servers`
+    pub servers: Option<SharedFactorioServerRegistry>,
 }
 
 impl Planner {
@@ -25,9 +28,16 @@ impl Planner {
             rcon,
             real_world: world,
             plan_world: Arc::new(plan_world),
+            servers: None,
         }
     }
 
+    /// Lets scripts reach every server of given registry, not only the planned one
+    pub fn with_servers(mut self, servers: SharedFactorioServerRegistry) -> Planner {
+        self.servers = Some(servers);
+        self
+    }
+
     pub fn reset(&mut self) {
         let plan_world = (*self.real_world).clone();
         self.plan_world = Arc::new(plan_world);
@@ -0,0 +1,219 @@
+use crate::factorio::util::calculate_distance;
+use crate::factorio::world::FactorioWorld;
+use crate::graph::entity_graph::TileQuadTree;
+use crate::types::{EntityType, FactorioTilePrototype, PlayerId, Position, Rect};
+use dashmap::DashMap;
+use std::collections::HashMap;
+
+/// vanilla character in tiles per tick, used if the prototype has no running speed
+const CHARACTER_RUNNING_SPEED: f64 = 0.15;
+/// position changes further apart are not treated as one continuous walk
+const MAX_SAMPLE_TICKS: u64 = 30;
+/// faster samples are teleports or vehicles, not walking
+const MAX_SAMPLE_SPEED_FACTOR: f64 = 3.;
+
+/// Measured versus predicted walking speed of all recorded position changes
+#[derive(Debug, Clone, Default)]
+pub struct WalkCalibration {
+    last_samples: HashMap<PlayerId, (u64, Position)>,
+    measured_distance: f64,
+    predicted_distance: f64,
+}
+
+impl WalkCalibration {
+    /// Records the position of a player at `tick`, `speed` is the uncalibrated
+    /// prediction in tiles per tick at the previous position.
+    pub fn record(&mut self, player_id: PlayerId, tick: u64, position: &Position, speed: f64) {
+        if let Some((last_tick, last_position)) = self
+            .last_samples
+            .insert(player_id, (tick, position.clone()))
+        {
+            let ticks = tick.saturating_sub(last_tick);
+            if ticks == 0 || ticks > MAX_SAMPLE_TICKS {
+                return;
+            }
+            let measured = calculate_distance(&last_position, position);
+            let predicted = ticks as f64 * speed;
+            if measured > predicted * MAX_SAMPLE_SPEED_FACTOR {
+                return;
+            }
+            self.measured_distance += measured;
+            self.predicted_distance += predicted;
+        }
+    }
+
+    /// measured divided by predicted speed, 1 until something was recorded
+    pub fn factor(&self) -> f64 {
+        if self.measured_distance > 0. && self.predicted_distance > 0. {
+            self.measured_distance / self.predicted_distance
+        } else {
+            1.
+        }
+    }
+}
+
+/// Travel time model of a character.
+///
+/// Factorio moves characters with `running_speed * (1 + movement_bonus)` multiplied with
+/// the walking speed modifier of the tile below. Diagonal steps are as fast as straight
+/// ones, so walking a diagonal tile takes √2 times longer.
+#[derive(Debug, Clone)]
+pub struct WalkCostModel<'a> {
+    /// tiles per tick
+    running_speed: f64,
+    movement_bonus: f64,
+    calibration: f64,
+    tile_prototypes: &'a DashMap<String, FactorioTilePrototype>,
+}
+
+impl<'a> WalkCostModel<'a> {
+    pub fn new(world: &'a FactorioWorld, player_id: PlayerId) -> Self {
+        WalkCostModel {
+            running_speed: world
+                .entity_prototypes
+                .get(&EntityType::Character.to_string())
+                .and_then(|prototype| prototype.running_speed)
+                .unwrap_or(CHARACTER_RUNNING_SPEED),
+            movement_bonus: world
+                .players
+                .get(&player_id)
+                .map(|player| player.movement_bonus)
+                .unwrap_or_default(),
+            calibration: world.walk_calibration.read().factor(),
+            tile_prototypes: &world.tile_prototypes,
+        }
+    }
+
+    /// uncalibrated tiles per tick on given tile
+    pub fn predicted_speed(&self, tile: Option<&str>) -> f64 {
+        let modifier = tile
+            .and_then(|tile| self.tile_prototypes.get(tile))
+            .map(|prototype| prototype.walking_speed_modifier)
+            .unwrap_or(1.);
+        self.running_speed * (1. + self.movement_bonus) * modifier
+    }
+
+    /// uncalibrated tiles per tick on the tile at `position`
+    pub fn predicted_speed_at(&self, tiles: &TileQuadTree, position: &Position) -> f64 {
+        self.predicted_speed(tile_at(tiles, position).as_deref())
+    }
+
+    /// tiles per second on given tile, calibrated with recorded walks
+    pub fn speed(&self, tile: Option<&str>) -> f64 {
+        self.predicted_speed(tile) * 60. * self.calibration
+    }
+
+    /// Seconds to walk from `start` along the waypoints of `path`
+    pub fn walk_time(&self, tiles: &TileQuadTree, start: &Position, path: &[Position]) -> f64 {
+        let mut seconds = 0.;
+        let mut from = start.clone();
+        for to in path {
+            let length = calculate_distance(&from, to);
+            let steps = length.ceil().max(1.);
+            for step in 0..steps as u32 {
+                let t = (step as f64 + 0.5) / steps;
+                let position = Position::new(
+                    from.x() + (to.x() - from.x()) * t,
+                    from.y() + (to.y() - from.y()) * t,
+                );
+                seconds += length / steps / self.speed(tile_at(tiles, &position).as_deref());
+            }
+            from = to.clone();
+        }
+        seconds
+    }
+}
+
+/// name of the known tile containing `position`
+fn tile_at(tiles: &TileQuadTree, position: &Position) -> Option<String> {
+    let center = Position::new(position.x().floor() + 0.5, position.y().floor() + 0.5);
+    let rect = Rect::new(
+        &Position::new(center.x() - 0.1, center.y() - 0.1),
+        &Position::new(center.x() + 0.1, center.y() + 0.1),
+    );
+    tiles
+        .query(rect.into())
+        .first()
+        .map(|(tile, _, _)| tile.name.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixture_world;
+    use crate::types::{FactorioTile, FactorioTilePrototype, PlayerChangedDistanceEvent};
+
+    #[test]
+    fn test_walk_time() {
+        let world = fixture_world();
+        world
+            .update_tile_prototypes(vec![FactorioTilePrototype {
+                name: String::from("refined-concrete"),
+                walking_speed_modifier: 1.5,
+            }])
+            .unwrap();
+        world
+            .entity_graph
+            .add_tiles(
+                (0..10)
+                    .map(|x| FactorioTile {
+                        name: String::from("refined-concrete"),
+                        player_collidable: false,
+                        position: Position::new(x as f64 + 0.5, 0.5),
+                        color: None,
+                        surface: None,
+                    })
+                    .collect(),
+                None,
+            )
+            .unwrap();
+        let model = WalkCostModel::new(&world, 1);
+        let tiles = world.entity_graph.tile_tree();
+        let start = Position::new(0.5, 0.5);
+        // 9 tiles per second on grass, 13.5 on refined concrete
+        let grass = model.walk_time(
+            &tiles,
+            &Position::new(0.5, 2.5),
+            &[Position::new(0.5, 11.5)],
+        );
+        assert!((grass - 1.).abs() < 1e-9);
+        let concrete = model.walk_time(&tiles, &start, &[Position::new(9.5, 0.5)]);
+        assert!((concrete - 9. / 13.5).abs() < 1e-9);
+        let diagonal = model.walk_time(
+            &tiles,
+            &Position::new(0.5, 2.5),
+            &[Position::new(9.5, 11.5)],
+        );
+        assert!((diagonal - 2f64.sqrt()).abs() < 1e-9);
+        drop(tiles);
+
+        world
+            .player_changed_distance(PlayerChangedDistanceEvent {
+                player_id: 1,
+                build_distance: 10,
+                reach_distance: 10,
+                drop_item_distance: 10,
+                item_pickup_distance: 1,
+                loot_pickup_distance: 2,
+                resource_reach_distance: 3,
+                movement_bonus: 0.3,
+            })
+            .unwrap();
+        let model = WalkCostModel::new(&world, 1);
+        assert!((model.speed(None) - 9. * 1.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_walk_calibration() {
+        let mut calibration = WalkCalibration::default();
+        assert_eq!(calibration.factor(), 1.);
+        // walks 0.12 instead of 0.15 tiles per tick
+        for step in 0..10u64 {
+            calibration.record(1, step * 5, &Position::new(step as f64 * 0.6, 0.), 0.15);
+        }
+        // standing still for a while and teleporting are ignored
+        calibration.record(1, 1000, &Position::new(10., 0.), 0.15);
+        calibration.record(1, 1001, &Position::new(100., 0.), 0.15);
+        assert!((calibration.factor() - 0.8).abs() < 1e-9);
+    }
+}
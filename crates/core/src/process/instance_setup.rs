@@ -165,7 +165,9 @@ pub async fn setup_factorio_instance(
                         );
                         return Err(FactorioBinaryNotFound {}.into());
                     }
+                    #[cfg(target_os = "macos")]
                     let mods_path = instance_path.join("mods");
+                    #[cfg(target_os = "macos")]
                     let mods_path_str = mods_path.to_str().unwrap().to_string();
                     let mut args = vec!["--create", saves_level_path.to_str().unwrap()];
                     if let Some(seed) = seed.as_ref() {
@@ -222,7 +224,9 @@ pub async fn setup_factorio_instance(
                 );
                 return Err(FactorioBinaryNotFound {}.into());
             }
+            #[cfg(target_os = "macos")]
             let mods_path = instance_path.join("mods");
+            #[cfg(target_os = "macos")]
             let mods_path_str = mods_path.to_str().unwrap().to_string();
             let mut args = vec!["--create", saves_level_path.to_str().unwrap()];
             if let Some(seed) = &seed {
@@ -370,10 +374,13 @@ pub async fn update_map_gen_settings(
             MAP_SETTINGS_FILENAME, MAP_GEN_SETTINGS_FILENAME
         ));
     }
+    #[cfg(target_os = "macos")]
     let mods_path = instance_path.join("mods");
+    #[cfg(target_os = "macos")]
     let mods_path_str = mods_path.to_str().unwrap().to_string();
     let port_str = factorio_port.unwrap_or(34197).to_string();
     let rcon_port_str = rcon_settings.port.to_string();
+    #[cfg_attr(not(target_os = "macos"), allow(unused_mut))]
     let mut args = vec![
         "--start-server",
         saves_level_path.to_str().unwrap(),
@@ -402,6 +409,8 @@ pub async fn update_map_gen_settings(
         false,
         Arc::new(RwLock::new(true)),
         FactorioStartCondition::Initialized,
+        None,
+        None,
     )
     .await?;
     rcon.parse_map_exchange_string(MAP_GEN_SETTINGS_FILENAME, map_exchange_string)
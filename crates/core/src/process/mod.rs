@@ -4,6 +4,7 @@ pub mod io_utils;
 pub mod output_parser;
 pub mod output_reader;
 pub mod process_control;
+pub mod server_registry;
 
 mod interactive_process;
 pub use interactive_process::InteractiveProcess;
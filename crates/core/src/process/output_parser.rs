@@ -5,12 +5,107 @@ use crate::factorio::world::FactorioWorld;
 //     FactorioWebSocketServer, PlayerChangedMainInventoryMessage, PlayerChangedPositionMessage,
 //     PlayerDistanceChangedMessage, PlayerLeftMessage, ResearchCompletedMessage,
 // };
+use crate::errors::{OutputParseFailed, OutputUnknownAction};
 use crate::types::{
-    ChunkPosition, FactorioEntity, FactorioEntityPrototype, FactorioForce, FactorioGraphic,
-    FactorioItemPrototype, FactorioRecipe, FactorioTile, PlayerChangedDistanceEvent,
-    PlayerChangedMainInventoryEvent, PlayerChangedPositionEvent, PlayerId, Pos, Position, Rect,
+    ChunkPosition, EntityAttackedEvent, EntityStateChangedEvent, FactorioEntity,
+    FactorioEntityPrototype, FactorioForce, FactorioGraphic, FactorioItemPrototype, FactorioRecipe,
+    FactorioTile, FactorioTilePrototype, PlayerChangedDistanceEvent,
+    PlayerChangedMainInventoryEvent, PlayerChangedPositionEvent, PlayerId, PollutionChangedEvent,
+    Pos, Position, Rect,
 };
-use miette::{IntoDiagnostic, Result};
+use dashmap::DashMap;
+use miette::{miette, IntoDiagnostic, Result};
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+
+/// max length of line excerpts in parse diagnostics
+const EXCERPT_LENGTH: usize = 120;
+
+/// Number of parsed, failed and unknown messages of one action
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OutputActionCounter {
+    pub parsed: u64,
+    pub failed: u64,
+    pub unknown: u64,
+}
+
+/// Counters of all BotBridge messages seen by the `OutputParser`, by action
+#[derive(Debug, Default)]
+pub struct OutputParserMetrics {
+    counters: DashMap<String, OutputActionCounter>,
+}
+
+impl OutputParserMetrics {
+    fn count(&self, action: &str, update: impl FnOnce(&mut OutputActionCounter)) {
+        match self.counters.get_mut(action) {
+            Some(mut counter) => update(&mut counter),
+            None => update(&mut self.counters.entry(action.to_owned()).or_default()),
+        }
+    }
+
+    /// Counters sorted by action
+    pub fn report(&self) -> BTreeMap<String, OutputActionCounter> {
+        self.counters
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect()
+    }
+
+    pub fn total_failed(&self) -> u64 {
+        self.counters.iter().map(|counter| counter.failed).sum()
+    }
+
+    pub fn total_unknown(&self) -> u64 {
+        self.counters.iter().map(|counter| counter.unknown).sum()
+    }
+}
+
+fn excerpt(line: &str) -> String {
+    if line.chars().count() > EXCERPT_LENGTH {
+        let excerpt: String = line.chars().take(EXCERPT_LENGTH).collect();
+        excerpt + "..."
+    } else {
+        line.to_owned()
+    }
+}
+
+fn from_json<T: DeserializeOwned>(json: &str) -> Result<T> {
+    serde_json::from_str(json).map_err(|err| miette!("{} in '{}'", err, excerpt(json)))
+}
+
+/// Splits the optional `@surface` suffix off a chunk header like `0,0;32,32@vulcanus`
+fn split_surface(header: &str) -> (&str, Option<String>) {
+    match header.split_once('@') {
+        Some((rect, surface)) => (rect, Some(surface.trim().to_owned())),
+        None => (header, None),
+    }
+}
+
+/// Deserializes every `$` separated part of `rest`. Parts which fail to deserialize
+/// are skipped so one broken prototype does not hide all others.
+fn from_json_list<T: DeserializeOwned>(rest: &str) -> (Vec<T>, Option<miette::Report>) {
+    let mut items = vec![];
+    let mut failed = 0;
+    let mut first_error = None;
+    for part in rest.split('$') {
+        match from_json(part) {
+            Ok(item) => items.push(item),
+            Err(err) => {
+                failed += 1;
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+    let error = first_error.map(|err| {
+        miette!(
+            "{} of {} failed, first: {}",
+            failed,
+            failed + items.len(),
+            err
+        )
+    });
+    (items, error)
+}
 
 pub struct OutputParser {
     world: Arc<FactorioWorld>,
@@ -18,32 +113,75 @@ pub struct OutputParser {
 }
 
 impl OutputParser {
-    pub fn parse(&mut self, _tick: u64, action: &str, rest: &str) -> Result<()> {
+    /// Parses one BotBridge message and updates the world.
+    ///
+    /// Never panics on malformed input: failures are returned as `OutputParseFailed`
+    /// naming the action and an excerpt of the message, unknown actions as
+    /// `OutputUnknownAction`. Both are counted in `FactorioWorld::parser_metrics`.
+    pub fn parse(&mut self, tick: u64, action: &str, rest: &str) -> Result<()> {
+        let metrics = self.world.parser_metrics.clone();
+        match self.parse_action(tick, action, rest) {
+            Ok(true) => {
+                metrics.count(action, |counter| counter.parsed += 1);
+                Ok(())
+            }
+            Ok(false) => {
+                metrics.count(action, |counter| counter.unknown += 1);
+                Err(OutputUnknownAction {
+                    action: action.to_owned(),
+                    excerpt: excerpt(rest),
+                }
+                .into())
+            }
+            Err(err) => {
+                metrics.count(action, |counter| counter.failed += 1);
+                Err(OutputParseFailed {
+                    action: action.to_owned(),
+                    excerpt: excerpt(rest),
+                    reason: format!("{}", err),
+                }
+                .into())
+            }
+        }
+    }
+
+    /// returns false for unknown actions
+    fn parse_action(&mut self, tick: u64, action: &str, rest: &str) -> Result<bool> {
         match action {
             "entities" => {
-                let colon_pos = rest.find(':').unwrap();
-                let rect: Rect = rest[0..colon_pos].parse()?;
-                let pos: Pos = (&rect.left_top).into();
-                let _chunk_position: ChunkPosition = (&pos).into();
-                let mut entities = &rest[colon_pos + 1..];
-                if entities == "{}" {
-                    entities = "[]"
-                }
-                let entities: Vec<FactorioEntity> =
-                    serde_json::from_str(entities).into_diagnostic()?;
-                self.world.update_chunk_entities(entities)?;
+                let (rect, entities) = rest
+                    .split_once(':')
+                    .ok_or_else(|| miette!("missing ':' after rect"))?;
+                let (rect, surface) = split_surface(rect);
+                let rect: Rect = rect.parse()?;
+                let entities = if entities == "{}" { "[]" } else { entities };
+                let entities: Vec<FactorioEntity> = from_json(entities)?;
+                // chunks are written again on rediscovery so they replace what we know
+                self.world
+                    .replace_chunk_entities(surface.as_deref(), &rect, entities)?;
             }
             "tiles" => {
-                let colon_pos = rest.find(':').unwrap();
-                let rect: Rect = rest[0..colon_pos].parse()?;
+                let (rect, tiles) = rest
+                    .split_once(':')
+                    .ok_or_else(|| miette!("missing ':' after rect"))?;
+                let (rect, surface) = split_surface(rect);
+                let rect: Rect = rect.parse()?;
                 let pos: Pos = (&rect.left_top).into();
                 let chunk_position: ChunkPosition = (&pos).into();
-                let tiles: Vec<FactorioTile> = rest[colon_pos + 1..]
+                let tiles: Vec<FactorioTile> = tiles
                     .split(',')
                     .enumerate()
                     .map(|(index, tile)| {
-                        let parts: Vec<&str> = tile.split(':').collect();
-                        let name: String = parts[0].trim().into();
+                        let (name, player_collidable) = tile
+                            .split_once(':')
+                            .ok_or_else(|| miette!("missing ':' in tile '{}'", tile))?;
+                        let name: String = name.trim().into();
+                        let player_collidable: u8 = player_collidable
+                            .split(':')
+                            .next()
+                            .unwrap_or_default()
+                            .parse()
+                            .map_err(|_| miette!("invalid player_collidable in tile '{}'", tile))?;
                         let color_name = match name.find('-') {
                             Some(pos) => {
                                 if &name[0..pos] == "red" {
@@ -57,7 +195,7 @@ impl OutputParser {
                             }
                             None => &name,
                         };
-                        FactorioTile {
+                        Ok(FactorioTile {
                             color: match color_name {
                                 "water" => Some([0u8, 162u8, 232u8, 255u8]),
                                 "deepwater" => Some([18u8, 16u8, 254u8, 255u8]),
@@ -78,15 +216,17 @@ impl OutputParser {
                                            // }
                             },
                             name,
-                            player_collidable: parts[1].parse::<u8>().unwrap() == 1,
+                            player_collidable: player_collidable == 1,
                             position: Position::new(
                                 (chunk_position.x * 32 + (index % 32) as i32) as f64,
                                 (chunk_position.y * 32 + (index / 32) as i32) as f64,
                             ),
-                        }
+                            surface: surface.clone(),
+                        })
                     })
-                    .collect();
-                self.world.update_chunk_tiles(tiles)?;
+                    .collect::<Result<_>>()?;
+                self.world
+                    .replace_chunk_tiles(surface.as_deref(), &rect, tiles)?;
             }
             "graphics" => {
                 // 0 graphics: spark-explosion*__core__/graphics/empty.png:1:1:0:0:0:0:1|spark-explosion-higher*__core__/graphics/empty.png:1:1:0:0:0:0:1|
@@ -94,79 +234,83 @@ impl OutputParser {
                     .split('|')
                     .map(|graphic| {
                         let parts: Vec<&str> = graphic.split(':').collect();
-                        let parts2: Vec<&str> = parts[0].split('*').collect();
-                        FactorioGraphic {
-                            entity_name: parts2[0].into(),
-                            image_path: parts2[1].into(),
-                            width: parts[1].parse().unwrap(),
-                            height: parts[1].parse().unwrap(),
-                        }
+                        let (entity_name, image_path) = parts[0]
+                            .split_once('*')
+                            .ok_or_else(|| miette!("missing '*' in graphic '{}'", graphic))?;
+                        let width = parts
+                            .get(1)
+                            .and_then(|width| width.parse().ok())
+                            .ok_or_else(|| miette!("invalid width in graphic '{}'", graphic))?;
+                        Ok(FactorioGraphic {
+                            entity_name: entity_name.into(),
+                            image_path: image_path.into(),
+                            width,
+                            height: width,
+                        })
                     })
-                    .collect();
+                    .collect::<Result<_>>()?;
                 self.world.update_graphics(graphics)?;
             }
             "entity_prototypes" => {
-                let entity_prototypes: Vec<FactorioEntityPrototype> = rest
-                    .split('$')
-                    .map(|entity_prototype| {
-                        serde_json::from_str(entity_prototype).unwrap_or_else(|err| {
-                            panic!(
-                                "failed to deserialize entity prototype: {:?} '{}'",
-                                err, entity_prototype
-                            )
-                        })
-                    })
-                    .collect();
+                let (entity_prototypes, error) = from_json_list::<FactorioEntityPrototype>(rest);
                 self.world.update_entity_prototypes(entity_prototypes)?;
+                if let Some(error) = error {
+                    return Err(error);
+                }
             }
             "item_prototypes" => {
-                let item_prototypes: Vec<FactorioItemPrototype> = rest
-                    .split('$')
-                    .map(|item_prototype| {
-                        serde_json::from_str(item_prototype).unwrap_or_else(|err| {
-                            panic!(
-                                "failed to deserialize item prototype: {:?} '{}'",
-                                err, item_prototype
-                            )
-                        })
-                    })
-                    .collect();
+                let (item_prototypes, error) = from_json_list::<FactorioItemPrototype>(rest);
                 self.world.update_item_prototypes(item_prototypes)?;
+                if let Some(error) = error {
+                    return Err(error);
+                }
+            }
+            "tile_prototypes" => {
+                let (tile_prototypes, error) = from_json_list::<FactorioTilePrototype>(rest);
+                self.world.update_tile_prototypes(tile_prototypes)?;
+                if let Some(error) = error {
+                    return Err(error);
+                }
             }
             "recipes" => {
-                let recipes: Vec<FactorioRecipe> = rest
-                    .split('$')
-                    .map(|recipe| {
-                        serde_json::from_str(recipe).unwrap_or_else(|err| {
-                            panic!("failed to deserialize recipe: {:?} '{}'", err, recipe)
-                        })
-                    })
-                    .collect();
+                let (recipes, error) = from_json_list::<FactorioRecipe>(rest);
                 self.world.update_recipes(recipes)?;
+                if let Some(error) = error {
+                    return Err(error);
+                }
             }
             "action_completed" => {
-                if let Some(pos) = rest.find(' ') {
-                    let action_status = &rest[0..pos];
-                    let rest = &rest[pos + 1..];
-                    let action_id: u32 = match rest.find(' ') {
-                        Some(pos) => rest[0..pos].parse().into_diagnostic()?,
-                        None => rest.parse().into_diagnostic()?,
+                if let Some((action_status, rest)) = rest.split_once(' ') {
+                    let (action_id, message) = match rest.split_once(' ') {
+                        Some((action_id, message)) => (action_id, Some(message)),
+                        None => (rest, None),
                     };
-                    let result = match action_status {
-                        "ok" => "ok",
-                        "fail" => {
-                            let pos = rest.find(' ').unwrap();
-                            &rest[pos + 1..]
-                        }
-                        _ => panic!("unexpected action_completed {}", action_status),
+                    let action_id: u32 = action_id.parse().into_diagnostic()?;
+                    let result = match (action_status, message) {
+                        ("ok", _) => "ok",
+                        ("fail", Some(message)) => message,
+                        ("fail", None) => return Err(miette!("missing failure message")),
+                        _ => return Err(miette!("unexpected status {}", action_status)),
                     };
                     self.world.actions.insert(action_id, String::from(result));
                 }
             }
             "on_script_path_request_finished" => {
-                let parts: Vec<&str> = rest.split('#').collect();
-                let id: u32 = parts[0].parse().into_diagnostic()?;
-                self.world.path_requests.insert(id, String::from(parts[1]));
+                let (id, path) = rest
+                    .split_once('#')
+                    .ok_or_else(|| miette!("missing '#' after request id"))?;
+                let id: u32 = id.parse().into_diagnostic()?;
+                let path = path.split('#').next().unwrap_or_default();
+                self.world.path_requests.insert(id, String::from(path));
+            }
+            "on_throughput_sampled" => {
+                let (id, sampling) = rest
+                    .split_once('#')
+                    .ok_or_else(|| miette!("missing '#' after request id"))?;
+                let id: u32 = id.parse().into_diagnostic()?;
+                self.world
+                    .throughput_samplings
+                    .insert(id, String::from(sampling));
             }
             "STATIC_DATA_END" => {
                 // handled by OutputReader
@@ -186,32 +330,39 @@ impl OutputParser {
                 // }
             }
             "force" => {
-                let force: FactorioForce = serde_json::from_str(rest).unwrap_or_else(|err| {
-                    panic!("failed to deserialize force: {:?} '{}'", err, rest)
-                });
+                let force: FactorioForce = from_json(rest)?;
                 self.world.update_force(force)?;
             }
             "on_some_entity_created" => {
-                let entity: FactorioEntity = serde_json::from_str(rest).unwrap_or_else(|err| {
-                    panic!("failed to deserialize entity: {:?} '{}'", err, rest)
-                });
+                let entity: FactorioEntity = from_json(rest)?;
                 self.world.on_some_entity_created(entity)?;
             }
             "on_some_entity_updated" => {
-                let entity: FactorioEntity = serde_json::from_str(rest).unwrap_or_else(|err| {
-                    panic!("failed to deserialize entity: {:?} '{}'", err, rest)
-                });
+                let entity: FactorioEntity = from_json(rest)?;
                 self.world.on_some_entity_updated(entity)?;
             }
             "on_some_entity_deleted" => {
-                let entity: FactorioEntity = serde_json::from_str(rest).unwrap_or_else(|err| {
-                    panic!("failed to deserialize entity: {:?} '{}'", err, rest)
-                });
+                let entity: FactorioEntity = from_json(rest)?;
                 self.world.on_some_entity_deleted(entity)?;
             }
+            "on_entity_state_changed" => {
+                let event: EntityStateChangedEvent = from_json(rest)?;
+                self.world.on_entity_state_changed(tick, event)?;
+            }
+            "on_enemy_built" => {
+                let entity: FactorioEntity = from_json(rest)?;
+                self.world.on_some_entity_created(entity)?;
+            }
+            "on_entity_attacked" => {
+                let event: EntityAttackedEvent = from_json(rest)?;
+                self.world.on_entity_attacked(event)?;
+            }
+            "on_pollution_changed" => {
+                let event: PollutionChangedEvent = from_json(rest)?;
+                self.world.on_pollution_changed(event)?;
+            }
             "on_player_main_inventory_changed" => {
-                let event: PlayerChangedMainInventoryEvent =
-                    serde_json::from_str(rest).into_diagnostic()?;
+                let event: PlayerChangedMainInventoryEvent = from_json(rest)?;
                 let _player_id = event.player_id;
                 self.world.player_changed_main_inventory(event)?;
                 // if let Some(websocket_server) = self.websocket_server.as_ref() {
@@ -223,9 +374,9 @@ impl OutputParser {
                 // }
             }
             "on_player_changed_position" => {
-                let event: PlayerChangedPositionEvent =
-                    serde_json::from_str(rest).into_diagnostic()?;
+                let event: PlayerChangedPositionEvent = from_json(rest)?;
                 let _player_id = event.player_id;
+                self.world.record_walk_sample(tick, &event);
                 self.world.player_changed_position(event)?;
                 // if let Some(websocket_server) = self.websocket_server.as_ref() {
                 //     websocket_server
@@ -236,8 +387,7 @@ impl OutputParser {
                 // }
             }
             "on_player_changed_distance" => {
-                let event: PlayerChangedDistanceEvent =
-                    serde_json::from_str(rest).into_diagnostic()?;
+                let event: PlayerChangedDistanceEvent = from_json(rest)?;
                 let _player_id = event.player_id;
                 self.world.player_changed_distance(event)?;
                 // if let Some(websocket_server) = self.websocket_server.as_ref() {
@@ -254,17 +404,13 @@ impl OutputParser {
             "tick" => {
                 // info!("tick!");
             }
-            _ => {
-                error!("<red>unexpected action</>: <bright-blue>{}</>", action);
-            }
+            _ => return Ok(false),
         };
-        Ok(())
+        Ok(true)
     }
 
     pub fn on_init(&self) -> Result<()> {
-        self.world.entity_graph.connect()?;
-        self.world.flow_graph.update()?;
-        Ok(())
+        self.world.connect_surfaces()
     }
 
     #[allow(clippy::new_without_default)]
@@ -279,3 +425,178 @@ impl OutputParser {
         self.world.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixture_entity_prototypes;
+
+    #[test]
+    fn test_malformed_lines_fail_without_panic() {
+        let mut parser = OutputParser::new();
+        assert!(parser.parse(1, "tiles", "0,0;32,32:grass-1:x").is_err());
+        assert!(parser.parse(1, "tiles", "no rect here").is_err());
+        assert!(parser.parse(1, "graphics", "missing-star.png:1:1").is_err());
+        assert!(parser.parse(1, "action_completed", "maybe 12").is_err());
+        assert!(parser.parse(1, "force", "{not json").is_err());
+        assert!(parser
+            .parse(1, "on_script_path_request_finished", "1")
+            .is_err());
+        assert!(parser.parse(1, "action_completed", "ok 12").is_ok());
+        assert!(parser.parse(1, "mystery", "").is_err());
+
+        let report = parser.world().parser_metrics.report();
+        assert_eq!(report["tiles"].failed, 2);
+        assert_eq!(report["action_completed"].failed, 1);
+        assert_eq!(report["action_completed"].parsed, 1);
+        assert_eq!(report["mystery"].unknown, 1);
+        assert_eq!(parser.world().parser_metrics.total_failed(), 6);
+        assert_eq!(parser.world().parser_metrics.total_unknown(), 1);
+    }
+
+    #[test]
+    fn test_chunks_are_routed_by_surface() {
+        let mut parser = OutputParser::new();
+        let entity = FactorioEntity::new_stone_furnace(
+            &Position::new(1.0, 1.0),
+            crate::types::Direction::North,
+        );
+        let entities = serde_json::to_string(&vec![entity]).unwrap();
+        parser
+            .parse(1, "entities", &format!("0,0;32,32@vulcanus:{}", entities))
+            .unwrap();
+        parser
+            .parse(
+                1,
+                "tiles",
+                &format!("0,0;32,32@vulcanus: {}", ["lava:1"; 1024].join(",")),
+            )
+            .unwrap();
+        parser.on_init().unwrap();
+
+        let world = parser.world();
+        assert_eq!(world.surface_names(), vec!["nauvis", "vulcanus"]);
+        let vulcanus = world.surface(Some("vulcanus"));
+        let found =
+            vulcanus
+                .entity_graph
+                .find_entities_in_radius(Position::new(1.0, 1.0), 2.0, None, None);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].surface(), "vulcanus");
+        assert!(world
+            .entity_graph
+            .find_entities_in_radius(Position::new(1.0, 1.0), 2.0, None, None)
+            .is_empty());
+        let tiles = vulcanus.entity_graph.tile_tree();
+        let (_, (tile, _)) = tiles.iter().next().unwrap();
+        assert_eq!(tile.surface(), "vulcanus");
+        assert!(world.entity_graph.tile_tree().iter().next().is_none());
+    }
+
+    #[test]
+    fn test_entity_state_is_tracked() {
+        let mut parser = OutputParser::new();
+        parser
+            .parse(
+                7,
+                "on_entity_state_changed",
+                r#"{"name":"stone-furnace","position":{"x":1,"y":1},"surface":"nauvis","status":"full_output","inventories":{"furnace_result":[{"name":"iron-plate","quality":"normal","count":100},{"name":"iron-plate","quality":"rare","count":2}],"fuel":[{"name":"coal","quality":"normal","count":3}]}}"#,
+            )
+            .unwrap();
+        parser
+            .parse(
+                8,
+                "on_entity_state_changed",
+                r#"{"name":"lab","position":{"x":5.5,"y":5.5},"status":"the_future","inventories":{}}"#,
+            )
+            .unwrap();
+
+        let world = parser.world();
+        let state = world.entity_state(None, &Position::new(1.0, 1.0)).unwrap();
+        assert_eq!(state.tick, 7);
+        assert!(state.status.unwrap().is_output_blocked());
+        assert_eq!(state.inventories["furnace_result"]["iron-plate"], 100);
+        assert_eq!(state.item_count("iron-plate@rare"), 2);
+        assert_eq!(state.item_count("coal"), 3);
+        let lab = world.entity_state(None, &Position::new(5.5, 5.5)).unwrap();
+        assert_eq!(lab.status, Some(crate::types::EntityStatus::Other));
+        assert!(world
+            .entity_state(Some("vulcanus"), &Position::new(1.0, 1.0))
+            .is_none());
+    }
+
+    #[test]
+    fn test_enemies_and_attacks_are_tracked() {
+        let mut parser = OutputParser::new();
+        let world = parser.world();
+        let mut attacks = world.subscribe_attacks();
+        parser
+            .parse(
+                1,
+                "on_enemy_built",
+                r#"{"name":"biter-spawner","entity_type":"unit-spawner","position":{"x":100,"y":0},"bounding_box":{"left_top":{"x":98,"y":-2},"right_bottom":{"x":102,"y":2}},"direction":0}"#,
+            )
+            .unwrap();
+        parser
+            .parse(
+                2,
+                "on_pollution_changed",
+                r#"{"surface":"nauvis","chunks":[{"x":-1,"y":0,"pollution":12.5}]}"#,
+            )
+            .unwrap();
+        parser
+            .parse(
+                3,
+                "on_entity_attacked",
+                r#"{"name":"character","entity_type":"character","position":{"x":90,"y":0},"player_id":1,"cause":"small-biter","damage":7,"health":243,"died":false}"#,
+            )
+            .unwrap();
+
+        assert_eq!(world.enemies.len(), 1);
+        assert!(world.entity_graph.inner_graph().node_count() == 0);
+        let zone = world.danger_zone_at(None, &Position::new(90., 0.)).unwrap();
+        assert_eq!(zone.name, "biter-spawner");
+        assert!(world
+            .danger_zone_at(Some("vulcanus"), &Position::new(90., 0.))
+            .is_none());
+        assert!(world
+            .avoid_danger(None, vec![Position::new(0., 0.), Position::new(200., 0.)])
+            .is_err());
+        assert!(world
+            .avoid_danger(None, vec![Position::new(0., 0.), Position::new(0., 200.)])
+            .is_ok());
+        assert_eq!(world.pollution_at(None, &Position::new(-5., 5.)), 12.5);
+        assert_eq!(world.pollution_at(None, &Position::new(5., 5.)), 0.);
+        let attack = attacks.try_recv().unwrap();
+        assert_eq!(attack.player_id, Some(1));
+        assert!(!attack.died);
+
+        parser
+            .parse(
+                4,
+                "on_some_entity_deleted",
+                r#"{"name":"biter-spawner","entity_type":"unit-spawner","position":{"x":100,"y":0},"bounding_box":{"left_top":{"x":98,"y":-2},"right_bottom":{"x":102,"y":2}},"direction":0}"#,
+            )
+            .unwrap();
+        assert!(world.danger_zones(None).is_empty());
+    }
+
+    #[test]
+    fn test_broken_prototype_is_skipped() {
+        let mut parser = OutputParser::new();
+        let prototype = fixture_entity_prototypes()
+            .iter()
+            .next()
+            .map(|entry| serde_json::to_string(entry.value()).unwrap())
+            .unwrap();
+        let err = parser
+            .parse(
+                1,
+                "entity_prototypes",
+                &format!("{}${{\"name\":1}}", prototype),
+            )
+            .unwrap_err();
+        assert!(format!("{}", err).contains("entity_prototypes"));
+        assert_eq!(parser.world().entity_prototypes.len(), 1);
+    }
+}
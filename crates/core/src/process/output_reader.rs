@@ -7,6 +7,7 @@ use std::{fs::File, sync::Arc};
 // use tokio::sync::mpsc::channel;
 
 use crate::factorio::rcon::{FactorioRcon, RconSettings};
+use crate::factorio::rcon_trace::RconRecorder;
 use crate::factorio::world::FactorioWorld;
 use crate::process::output_parser::OutputParser;
 use crate::process::process_control::FactorioStartCondition;
@@ -15,6 +16,7 @@ use miette::{miette, IntoDiagnostic, Result};
 use parking_lot::{Mutex, RwLock};
 use std::sync::mpsc;
 
+#[allow(clippy::too_many_arguments)]
 pub async fn read_output(
     cmd: Command,
     log_path: PathBuf,
@@ -22,6 +24,8 @@ pub async fn read_output(
     write_logs: bool,
     silent: Arc<RwLock<bool>>,
     wait_until: FactorioStartCondition,
+    recorder: Option<Arc<RconRecorder>>,
+    snapshot: Option<FactorioWorld>,
 ) -> Result<(Arc<FactorioWorld>, InteractiveProcess, FactorioRcon)> {
     let log_file = Mutex::new(match write_logs {
         true => Some(File::create(log_path).into_diagnostic()?),
@@ -56,6 +60,9 @@ pub async fn read_output(
                         && (line.contains("initial discovery done") || line.contains("(100% done)"))
                     {
                         *initialized = true;
+                        if let Some(recorder) = recorder.as_ref() {
+                            recorder.record_initialized();
+                        }
                         output_parser.on_init().unwrap();
                         tx2.send(()).expect("failed to send");
                     }
@@ -71,7 +78,7 @@ pub async fn read_output(
 
                         if let Some(stripped) = line.strip_prefix('§') {
                             if let Some(pos) = stripped.find('§') {
-                                let tick: u64 = stripped[..pos].parse().unwrap();
+                                let tick: u64 = stripped[..pos].parse().unwrap_or_default();
                                 let rest = &stripped[pos + 2..];
                                 if let Some(pos) = rest.find('§') {
                                     let action = &rest[0..pos];
@@ -80,11 +87,15 @@ pub async fn read_output(
                                         match action {
                                             "on_player_changed_position"
                                             | "on_player_main_inventory_changed"
+                                            | "on_entity_state_changed"
+                                            | "on_pollution_changed"
+                                            | "on_throughput_sampled"
                                             | "on_player_changed_distance"
                                             | "entity_prototypes"
                                             | "recipes"
                                             | "force"
                                             | "item_prototypes"
+                                            | "tile_prototypes"
                                             | "graphics"
                                             | "tiles"
                                             | "STATIC_DATA_END"
@@ -98,6 +109,9 @@ pub async fn read_output(
                                         }
                                     }
 
+                                    if let Some(recorder) = recorder.as_ref() {
+                                        recorder.record_event(tick, action, rest);
+                                    }
                                     // println!("get output_parser.lock {tick}: {action}");
                                     let result = output_parser.parse(tick, action, rest);
                                     // println!("output_parser.lock gotten");
@@ -157,7 +171,11 @@ pub async fn read_output(
     let rcon = FactorioRcon::new(rcon_settings, _silent)
         .await
         .expect("failed to rcon");
-    rcon.initialize_server().await?;
+    let skip_discovery = snapshot.is_some();
+    if let Some(snapshot) = snapshot {
+        _world.restore(snapshot)?;
+    }
+    rcon.initialize_server(skip_discovery).await?;
     if wait_until == FactorioStartCondition::DiscoveryComplete {
         rx2.recv().into_diagnostic()?;
     }
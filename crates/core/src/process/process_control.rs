@@ -1,7 +1,9 @@
 use crate::constants::SERVER_SETTINGS_FILENAME;
 use crate::errors::*;
 use crate::factorio::rcon::{FactorioRcon, RconSettings};
+use crate::factorio::rcon_trace::{RconRecorder, RconReplayer};
 use crate::factorio::world::FactorioWorld;
+use crate::factorio::world_snapshot::load_snapshot;
 use crate::process::arrange_windows::arrange_windows;
 use crate::process::instance_setup::setup_factorio_instance;
 use crate::process::output_reader::read_output;
@@ -38,11 +40,17 @@ pub struct FactorioParams {
     pub recreate: bool,
     pub instance_name: Option<String>,
     pub factorio_port: Option<u16>,
+    pub rcon_port: Option<u16>,
     pub map_exchange_string: Option<String>,
     pub seed: Option<String>,
     pub write_logs: bool,
     pub silent: bool,
     pub wait_until: FactorioStartCondition,
+    /// record rcon traffic and server events to this file, see `RconRecorder`
+    pub trace_path: Option<PathBuf>,
+    /// load the world from this snapshot instead of discovering every chunk again,
+    /// see `world_snapshot::save_snapshot`
+    pub snapshot_path: Option<PathBuf>,
 }
 
 impl Default for FactorioParams {
@@ -53,11 +61,14 @@ impl Default for FactorioParams {
             recreate: false,
             instance_name: None,
             factorio_port: None,
+            rcon_port: None,
             map_exchange_string: None,
             seed: None,
             write_logs: false,
             silent: true,
             wait_until: FactorioStartCondition::Initialized,
+            trace_path: None,
+            snapshot_path: None,
         }
     }
 }
@@ -76,11 +87,15 @@ impl FactorioInstance {
         let silent = Arc::new(parking_lot::RwLock::new(params.silent));
         let instance_name = params.instance_name.unwrap_or_else(|| "server".to_owned());
         let rcon_settings = RconSettings::new(
-            settings.rcon_port,
+            params.rcon_port.unwrap_or(settings.rcon_port),
             &settings.rcon_pass,
             params.server_host.clone(),
         );
         let mut factorio_port = params.factorio_port;
+        let recorder = match params.trace_path.as_ref() {
+            Some(trace_path) => Some(Arc::new(RconRecorder::create(trace_path)?)),
+            None => None,
+        };
         if params.server_host.is_none() {
             setup_factorio_instance(
                 &settings.workspace_path,
@@ -98,7 +113,7 @@ impl FactorioInstance {
         }
         let settings = settings.clone();
         for instance_number in 0..params.client_count {
-            let instance_name = format!("client{}", instance_number + 1);
+            let instance_name = client_instance_name(&instance_name, instance_number + 1);
             if let Err(err) = setup_factorio_instance(
                 &settings.workspace_path,
                 &settings.factorio_archive_path,
@@ -120,18 +135,25 @@ impl FactorioInstance {
         let mut server_child = None;
         let mut client_children = vec![];
 
+        let server_instance_name = instance_name.clone();
         let rcon = match params.server_host {
             None => {
+                let snapshot = match params.snapshot_path.as_ref() {
+                    Some(snapshot_path) => Some(load_snapshot(snapshot_path)?),
+                    None => None,
+                };
                 let started = Instant::now();
                 let (_world, rcon, child, used_factorio_port) = Self::start_server(
                     &settings.workspace_path,
                     &rcon_settings,
-                    None,
+                    factorio_port,
                     &instance_name,
                     // websocket_server,
                     params.write_logs,
                     silent.clone(),
                     params.wait_until,
+                    recorder.clone(),
+                    snapshot,
                 )
                 .await?;
                 factorio_port = Some(used_factorio_port);
@@ -154,7 +176,7 @@ impl FactorioInstance {
         };
         // Spawn all clients first
         for instance_number in 0..params.client_count {
-            let instance_name = format!("client{}", instance_number + 1);
+            let instance_name = client_instance_name(&server_instance_name, instance_number + 1);
             let instance_path = Path::new(settings.workspace_path.as_ref()).join(PathBuf::from(&instance_name));
             let lock_path = instance_path.join(PathBuf::from(".lock"));
 
@@ -170,6 +192,7 @@ impl FactorioInstance {
                 &settings,
                 instance_name.clone(),
                 params.server_host.clone(),
+                factorio_port,
                 params.write_logs,
                 true,
             )
@@ -235,7 +258,7 @@ impl FactorioInstance {
 
         // Register client names with BotBridge after they're connected
         for instance_number in 0..params.client_count {
-            let instance_name = format!("client{}", instance_number + 1);
+            let instance_name = client_instance_name(&server_instance_name, instance_number + 1);
             rcon.whoami(&instance_name).await.unwrap();
             // Execute a dummy command to silence the warning about "using commands will
             // disable achievements". If we don't do this, the first command will be lost
@@ -243,6 +266,10 @@ impl FactorioInstance {
         }
 
         arrange_windows(params.client_count).await?;
+        // only commands sent by scripts are recorded, startup depends on timing
+        if let Some(recorder) = recorder {
+            rcon.record_to(recorder);
+        }
         Ok(FactorioInstance {
             client_processes: client_children,
             server_process: server_child,
@@ -258,6 +285,26 @@ impl FactorioInstance {
         })
     }
 
+    /// Creates an instance without any factorio process which replays a trace
+    /// recorded with `FactorioParams::trace_path`
+    pub fn replay(trace_path: &Path, silent: bool) -> Result<FactorioInstance> {
+        let silent = Arc::new(parking_lot::RwLock::new(silent));
+        let replayer = Arc::new(RconReplayer::load(trace_path)?);
+        Ok(FactorioInstance {
+            client_processes: vec![],
+            server_process: None,
+            world: Some(replayer.world()),
+            rcon: Arc::new(FactorioRcon::new_replay(replayer, silent.clone())),
+            silent,
+            map_exchange_string: None,
+            seed: None,
+            server_host: None,
+            server_port: None,
+            rcon_port: 0,
+            client_count: 0,
+        })
+    }
+
     #[allow(clippy::too_many_arguments)]
     async fn start_server(
         workspace_path: &str,
@@ -267,6 +314,8 @@ impl FactorioInstance {
         write_logs: bool,
         silent: Arc<parking_lot::RwLock<bool>>,
         wait_until: FactorioStartCondition,
+        recorder: Option<Arc<RconRecorder>>,
+        snapshot: Option<FactorioWorld>,
     ) -> Result<(
         Arc<FactorioWorld>,
         Arc<FactorioRcon>,
@@ -327,10 +376,13 @@ impl FactorioInstance {
         let factorio_port = factorio_port.unwrap_or(34197);
         let factorio_port_str = factorio_port.to_string();
         let rcon_port_str = rcon_settings.port.to_string();
+        #[cfg(target_os = "macos")]
         let mods_path = instance_path.join("mods");
+        #[cfg(target_os = "macos")]
         let mods_path_str = mods_path.to_str().unwrap();
         let config_path = instance_path.join("config").join("config.ini");
         let config_path_str = config_path.to_str().unwrap().to_string();
+        #[cfg_attr(not(target_os = "macos"), allow(unused_mut))]
         let mut args = vec![
             "--start-server",
             saves_level_path.to_str().unwrap(),
@@ -369,6 +421,8 @@ impl FactorioInstance {
             write_logs,
             silent.clone(),
             wait_until,
+            recorder,
+            snapshot,
         )
         .await?;
         info!("waiting finished");
@@ -381,6 +435,7 @@ impl FactorioInstance {
         settings: &FactorioSettings,
         instance_name: String,
         server_host: Option<String>,
+        server_port: Option<u16>,
         _write_logs: bool,
         silent: bool,
     ) -> Result<InteractiveProcess> {
@@ -415,9 +470,12 @@ impl FactorioInstance {
         let mods_path_str = mods_path.to_str().unwrap().to_string();
         let config_path = instance_path.join("config").join("config.ini");
         let config_path_str = config_path.to_str().unwrap().to_string();
-        let server_host_str = server_host
+        let mut server_host_str = server_host
             .clone()
             .unwrap_or_else(|| "localhost".to_owned());
+        if let Some(server_port) = server_port {
+            server_host_str = format!("{server_host_str}:{server_port}");
+        }
         let args = &[
             "--mp-connect",
             &server_host_str,
@@ -477,6 +535,16 @@ impl FactorioInstance {
     }
 }
 
+/// Name of the client instance `number` belonging to the server instance `server_instance_name`.
+/// Clients of the default `server` instance keep their plain `clientN` names.
+pub fn client_instance_name(server_instance_name: &str, number: u8) -> String {
+    if server_instance_name == "server" {
+        format!("client{number}")
+    } else {
+        format!("{server_instance_name}-client{number}")
+    }
+}
+
 #[derive(PartialEq, Clone)]
 pub enum FactorioStartCondition {
     Initialized,
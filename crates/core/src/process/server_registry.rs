@@ -0,0 +1,248 @@
+use crate::errors::{ServerNotFound, ServerNotStarted};
+use crate::factorio::rcon::FactorioRcon;
+use crate::factorio::world::FactorioWorld;
+use crate::process::process_control::{FactorioInstance, FactorioParams, SharedFactorioInstance};
+use crate::settings::FactorioSettings;
+use dashmap::DashMap;
+use miette::Result;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::Arc;
+
+/// name of the server every api falls back to if no server is given
+pub const DEFAULT_SERVER_NAME: &str = "default";
+
+const DEFAULT_FACTORIO_PORT: u16 = 34197;
+
+pub type SharedFactorioServerRegistry = Arc<FactorioServerRegistry>;
+
+struct FactorioServer {
+    /// port offset of this server, the default server always uses 0
+    index: u16,
+    instance: SharedFactorioInstance,
+}
+
+/// Named factorio servers controlled by this process.
+///
+/// Every server has its own `FactorioInstance` and with it its own rcon connection
+/// and world. The `default` server always exists and is used whenever no server
+/// name is given.
+pub struct FactorioServerRegistry {
+    servers: DashMap<String, FactorioServer>,
+    next_index: AtomicU16,
+}
+
+impl FactorioServerRegistry {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> FactorioServerRegistry {
+        let servers = DashMap::new();
+        servers.insert(
+            DEFAULT_SERVER_NAME.to_owned(),
+            FactorioServer {
+                index: 0,
+                instance: FactorioInstance::new_shared(),
+            },
+        );
+        FactorioServerRegistry {
+            servers,
+            next_index: AtomicU16::new(1),
+        }
+    }
+
+    pub fn new_shared() -> SharedFactorioServerRegistry {
+        Arc::new(Self::new())
+    }
+
+    /// Registers a server with given name if missing and returns its instance slot
+    pub fn get_or_insert(&self, name: &str) -> SharedFactorioInstance {
+        self.servers
+            .entry(name.to_owned())
+            .or_insert_with(|| FactorioServer {
+                index: self.next_index.fetch_add(1, Ordering::SeqCst),
+                instance: FactorioInstance::new_shared(),
+            })
+            .instance
+            .clone()
+    }
+
+    /// Instance slot of given server, `None` selects the default server
+    pub fn instance(&self, name: Option<&str>) -> Option<SharedFactorioInstance> {
+        self.servers
+            .get(name.unwrap_or(DEFAULT_SERVER_NAME))
+            .map(|server| server.instance.clone())
+    }
+
+    pub fn default_instance(&self) -> SharedFactorioInstance {
+        self.instance(None).expect("default server always exists")
+    }
+
+    /// Names of all registered servers, sorted
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.servers.iter().map(|s| s.key().clone()).collect();
+        names.sort();
+        names
+    }
+
+    /// Unregisters given server and returns its instance slot. The default server can't be removed.
+    pub fn remove(&self, name: &str) -> Option<SharedFactorioInstance> {
+        if name == DEFAULT_SERVER_NAME {
+            return None;
+        }
+        self.servers.remove(name).map(|(_, server)| server.instance)
+    }
+
+    /// Adjusts start parameters so multiple servers don't collide: every non-default
+    /// server gets its own instance folder, rcon port and factorio port, offset by
+    /// the order in which the servers were registered.
+    pub fn server_params(
+        &self,
+        name: &str,
+        settings: &FactorioSettings,
+        params: FactorioParams,
+    ) -> FactorioParams {
+        self.get_or_insert(name);
+        let index = self.servers.get(name).map_or(0, |server| server.index);
+        if index == 0 {
+            return params;
+        }
+        Self::params_with_offset(name, index, settings, params)
+    }
+
+    /// Like `server_params` for a temporary server which is not registered, like the
+    /// servers of a seed roll. It takes its own port offset, so it neither collides with
+    /// registered servers nor shows up in `names`.
+    pub fn unregistered_server_params(
+        &self,
+        name: &str,
+        settings: &FactorioSettings,
+        params: FactorioParams,
+    ) -> FactorioParams {
+        let index = self.next_index.fetch_add(1, Ordering::SeqCst);
+        Self::params_with_offset(name, index, settings, params)
+    }
+
+    fn params_with_offset(
+        name: &str,
+        index: u16,
+        settings: &FactorioSettings,
+        params: FactorioParams,
+    ) -> FactorioParams {
+        FactorioParams {
+            instance_name: Some(params.instance_name.unwrap_or_else(|| name.to_owned())),
+            rcon_port: Some(params.rcon_port.unwrap_or(settings.rcon_port + index)),
+            factorio_port: Some(
+                params
+                    .factorio_port
+                    .unwrap_or(DEFAULT_FACTORIO_PORT + index),
+            ),
+            ..params
+        }
+    }
+
+    pub async fn rcon(&self, name: Option<&str>) -> Result<Arc<FactorioRcon>> {
+        let name = name.unwrap_or(DEFAULT_SERVER_NAME);
+        let instance = self.instance(Some(name)).ok_or_else(|| ServerNotFound {
+            name: name.to_owned(),
+        })?;
+        let instance = instance.read().await;
+        match instance.as_ref() {
+            Some(instance) => Ok(instance.rcon.clone()),
+            None => Err(ServerNotStarted {
+                name: name.to_owned(),
+            }
+            .into()),
+        }
+    }
+
+    pub async fn world(&self, name: Option<&str>) -> Result<Arc<FactorioWorld>> {
+        let name = name.unwrap_or(DEFAULT_SERVER_NAME);
+        let instance = self.instance(Some(name)).ok_or_else(|| ServerNotFound {
+            name: name.to_owned(),
+        })?;
+        let instance = instance.read().await;
+        match instance
+            .as_ref()
+            .and_then(|instance| instance.world.clone())
+        {
+            Some(world) => Ok(world),
+            None => Err(ServerNotStarted {
+                name: name.to_owned(),
+            }
+            .into()),
+        }
+    }
+
+    /// Stops every running server
+    pub async fn stop_all(&self) -> Result<()> {
+        let instances: Vec<SharedFactorioInstance> = self
+            .servers
+            .iter()
+            .map(|server| server.instance.clone())
+            .collect();
+        for instance in instances {
+            if let Some(instance) = instance.write().await.take() {
+                instance.stop()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_params() {
+        let registry = FactorioServerRegistry::new();
+        let settings = FactorioSettings::default();
+
+        let params =
+            registry.server_params(DEFAULT_SERVER_NAME, &settings, FactorioParams::default());
+        assert_eq!(params.instance_name, None);
+        assert_eq!(params.rcon_port, None);
+
+        let params = registry.server_params("roll1", &settings, FactorioParams::default());
+        assert_eq!(params.instance_name, Some("roll1".to_owned()));
+        assert_eq!(params.rcon_port, Some(settings.rcon_port + 1));
+        assert_eq!(params.factorio_port, Some(34198));
+
+        let params = registry.server_params("roll2", &settings, FactorioParams::default());
+        assert_eq!(params.rcon_port, Some(settings.rcon_port + 2));
+        // registering again keeps the offset
+        let params = registry.server_params("roll1", &settings, FactorioParams::default());
+        assert_eq!(params.rcon_port, Some(settings.rcon_port + 1));
+
+        assert_eq!(registry.names(), vec!["default", "roll1", "roll2"]);
+        assert!(registry.remove(DEFAULT_SERVER_NAME).is_none());
+        assert!(registry.remove("roll2").is_some());
+        assert!(registry.instance(Some("roll2")).is_none());
+    }
+
+    #[test]
+    fn test_unregistered_server_params() {
+        let registry = FactorioServerRegistry::new();
+        let settings = FactorioSettings::default();
+
+        registry.server_params("other", &settings, FactorioParams::default());
+        let roll1 =
+            registry.unregistered_server_params("roll1", &settings, FactorioParams::default());
+        let roll2 =
+            registry.unregistered_server_params("roll2", &settings, FactorioParams::default());
+        assert_eq!(roll1.instance_name, Some("roll1".to_owned()));
+        assert_eq!(roll1.rcon_port, Some(settings.rcon_port + 2));
+        assert_eq!(roll2.rcon_port, Some(settings.rcon_port + 3));
+        assert_eq!(roll2.factorio_port, Some(34200));
+        assert_eq!(registry.names(), vec!["default", "other"]);
+
+        // servers registered later don't reuse the ports of the roll servers
+        let params = registry.server_params("late", &settings, FactorioParams::default());
+        assert_eq!(params.rcon_port, Some(settings.rcon_port + 4));
+    }
+
+    #[tokio::test]
+    async fn test_not_started() {
+        let registry = FactorioServerRegistry::new();
+        assert!(registry.rcon(None).await.is_err());
+        assert!(registry.world(Some("missing")).await.is_err());
+    }
+}
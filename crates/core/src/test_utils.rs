@@ -120,6 +120,7 @@ pub fn spawn_water(tiles: &mut Vec<FactorioTile>, rect: Rect) {
             name: EntityName::Water.to_string(),
             player_collidable: true,
             color: None,
+            surface: None,
         });
     }
 }
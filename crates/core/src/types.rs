@@ -5,12 +5,13 @@ use std::sync::Arc;
 
 use dashmap::DashMap;
 use euclid::{Point2D, Size2D};
-use factorio_blueprint::objects::Entity;
+use factorio_blueprint::objects::{Entity, EntityType as BlueprintEntityType, ItemRequest};
 use noisy_float::prelude::*;
 use num_traits::ToPrimitive;
 use serde_json::Value;
 use typescript_definitions::TypeScriptify;
 
+use crate::constants::{DEFAULT_QUALITY, DEFAULT_SURFACE};
 use crate::errors::RectInvalid;
 use crate::factorio::util::{add_to_rect, add_to_rect_turned, calculate_distance, rect_floor_ceil};
 use crate::graph::entity_graph::QuadTreeRect;
@@ -73,7 +74,7 @@ mod deserialize_helpers {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct FactorioRecipe {
     pub name: String,
@@ -84,13 +85,219 @@ pub struct FactorioRecipe {
     #[serde(deserialize_with = "deserialize_helpers::vec_or_empty_map")]
     pub products: Vec<FactorioProduct>,
     pub hidden: bool,
+    #[schemars(with = "f64")]
     pub energy: Box<R64>,
     pub order: String,
     pub group: String,
     pub subgroup: String,
 }
 
-#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WireColor {
+    Red,
+    Green,
+}
+
+/// Circuit wire between two entities of a blueprint, by index into its entity list
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct BlueprintWire {
+    pub from: usize,
+    pub to: usize,
+    pub color: WireColor,
+}
+
+/// Requirements for a build site, see `find_sites`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct SiteConstraints {
+    /// resource the site has to cover, the search is limited to its patches
+    #[serde(default)]
+    pub resource: Option<String>,
+    /// minimum number of `resource` tiles covered
+    #[serde(default)]
+    pub min_resource_tiles: u32,
+    /// maximum distance in tiles from the site to the nearest water tile
+    #[serde(default)]
+    pub max_water_distance: Option<f64>,
+    /// allow trees and rocks on the site, they have to be mined first
+    #[serde(default)]
+    pub allow_trees: bool,
+    /// sites closer to this position rank higher, like the base centre
+    #[serde(default)]
+    pub near: Option<Position>,
+    /// maximum distance of the site centre from `near`
+    #[serde(default)]
+    pub max_distance: Option<f64>,
+    /// also try the footprint turned by 90 degrees
+    #[serde(default)]
+    pub rotations: bool,
+}
+
+/// Entities of one chunk, `ring` is the chunk distance from the centre chunk
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ChunkEntities {
+    pub chunk: ChunkPosition,
+    pub ring: u32,
+    pub entities: Vec<FactorioEntity>,
+}
+
+/// Place found for a footprint, lower scores are better
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct SiteCandidate {
+    /// centre of the site, for blueprint sites the blueprint origin
+    pub position: Position,
+    /// North, or East if the footprint is turned
+    pub direction: u8,
+    pub rect: Rect,
+    pub resource_tiles: u32,
+    /// trees and rocks on the site
+    pub obstructions: u32,
+    pub water_distance: Option<f64>,
+    pub score: f64,
+}
+
+/// How mining drills are spaced along a row
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OutpostStrategy {
+    /// drills side by side, mining areas overlap for the most ore per minute
+    #[default]
+    Overlap,
+    /// drills spaced by their mining area, every tile is mined by one drill
+    Coverage,
+}
+
+/// Options for `plan_outpost`, names default to the early game entities
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct OutpostOptions {
+    /// `electric-mining-drill` or `burner-mining-drill`
+    #[serde(default)]
+    pub drill: Option<String>,
+    /// belt tier between the drill rows
+    #[serde(default)]
+    pub belt: Option<String>,
+    /// drills insert directly into furnaces instead of onto belts
+    #[serde(default)]
+    pub furnace: Option<String>,
+    /// powers electric drills
+    #[serde(default)]
+    pub pole: Option<String>,
+    #[serde(default)]
+    pub strategy: OutpostStrategy,
+}
+
+/// Generated mining outpost
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct OutpostLayout {
+    pub entities: Vec<FactorioEntity>,
+    pub blueprint: String,
+    pub drill_count: u32,
+    /// ore output of all drills as modelled by the flow graph
+    pub ore_per_minute: f64,
+}
+
+/// Options for `smelting_column`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct SmeltingColumnOptions {
+    /// item to smelt like `iron-ore`
+    pub input: String,
+    /// number of furnaces
+    pub count: u32,
+    #[serde(default)]
+    pub furnace: Option<String>,
+    #[serde(default)]
+    pub belt: Option<String>,
+    /// the slowest vanilla inserter which keeps up if unset
+    #[serde(default)]
+    pub inserter: Option<String>,
+    #[serde(default)]
+    pub pole: Option<String>,
+}
+
+/// Options for `assembler_line`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct AssemblerLineOptions {
+    pub recipe: String,
+    /// target output of the recipe's main product
+    pub items_per_minute: f64,
+    #[serde(default)]
+    pub machine: Option<String>,
+    #[serde(default)]
+    pub belt: Option<String>,
+    /// the slowest vanilla inserter which keeps up if unset
+    #[serde(default)]
+    pub inserter: Option<String>,
+    #[serde(default)]
+    pub pole: Option<String>,
+}
+
+/// Generated production block, checked against the flow graph
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ProductionBlock {
+    pub entities: Vec<FactorioEntity>,
+    pub blueprint: String,
+    pub machine_count: u32,
+    /// first tile of the input belt, ingredients and fuel are fed in here
+    pub input_position: Position,
+    /// last tile of the output belt
+    pub output_position: Position,
+    /// items per minute the machines consume
+    pub inputs: BTreeMap<String, f64>,
+    /// items per minute reaching the output belt as predicted by the flow graph
+    pub outputs: BTreeMap<String, f64>,
+}
+
+/// Options for `plan_power_plant`, names default to the early game entities
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PowerPlantOptions {
+    /// boilers to build, defaults to 1, each gets as many steam engines as it feeds
+    #[serde(default)]
+    pub boilers: Option<u32>,
+    #[serde(default)]
+    pub offshore_pump: Option<String>,
+    #[serde(default)]
+    pub boiler: Option<String>,
+    #[serde(default)]
+    pub steam_engine: Option<String>,
+    /// pipe between the offshore pump and the boilers
+    #[serde(default)]
+    pub pipe: Option<String>,
+    #[serde(default)]
+    pub pipe_to_ground: Option<String>,
+    /// powers the steam engines and connects them to the base
+    #[serde(default)]
+    pub pole: Option<String>,
+    /// maximum distance of the boilers from the offshore pump
+    #[serde(default)]
+    pub max_distance: Option<f64>,
+}
+
+/// Generated power plant
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PowerPlantLayout {
+    pub entities: Vec<FactorioEntity>,
+    pub blueprint: String,
+    pub offshore_pump_position: Position,
+    pub boiler_count: u32,
+    pub steam_engine_count: u32,
+    /// watts the steam engines produce at full load as predicted by the flow graph
+    pub power: f64,
+    /// coal per minute burned by the boilers at full load
+    pub fuel_per_minute: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct FactorioBlueprintInfo {
     pub label: String,
@@ -101,7 +308,7 @@ pub struct FactorioBlueprintInfo {
     pub data: Value,
 }
 
-#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct FactorioIngredient {
     pub name: String,
@@ -110,13 +317,14 @@ pub struct FactorioIngredient {
     pub amount: u32,
 }
 
-#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct FactorioProduct {
     pub name: String,
     #[serde(default)]
     pub product_type: String,
     pub amount: u32,
+    #[schemars(with = "f64")]
     pub probability: Box<R64>,
 }
 
@@ -135,6 +343,12 @@ pub struct FactorioPlayer {
     pub item_pickup_distance: u64,    // not in use, for picking up items from the ground
     pub loot_pickup_distance: u64, // not in use, for picking up items from the ground automatically
     pub resource_reach_distance: u64, // for mine
+    /// name of the surface, `None` means the default surface
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub surface: Option<String>,
+    /// running speed bonus of the character, e.g. 0.3 per exoskeleton
+    #[serde(default)]
+    pub movement_bonus: f64,
 }
 
 impl Default for FactorioPlayer {
@@ -149,10 +363,18 @@ impl Default for FactorioPlayer {
             item_pickup_distance: 1,
             loot_pickup_distance: 2,
             resource_reach_distance: 3,
+            surface: None,
+            movement_bonus: 0.,
         }
     }
 }
 
+impl FactorioPlayer {
+    pub fn surface(&self) -> &str {
+        self.surface.as_deref().unwrap_or(DEFAULT_SURFACE)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct RequestEntity {
@@ -169,7 +391,7 @@ pub struct InventoryResponse {
     pub fuel_inventory: Box<Option<Vec<InventoryItemWithQuality>>>,
 }
 
-#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, JsonSchema, Hash, Eq)]
 #[serde(rename_all = "snake_case")]
 pub struct ChunkPosition {
     pub x: i32,
@@ -418,6 +640,15 @@ pub struct FactorioTile {
     pub player_collidable: bool,
     pub position: Position,
     pub color: Option<[u8; 4]>,
+    /// name of the surface, `None` means the default surface
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub surface: Option<String>,
+}
+
+impl FactorioTile {
+    pub fn surface(&self) -> &str {
+        self.surface.as_deref().unwrap_or(DEFAULT_SURFACE)
+    }
 }
 
 #[derive(Debug, Clone, Default, PartialEq, TypeScriptify, Serialize, Deserialize)]
@@ -445,7 +676,7 @@ pub struct ChunkResource {
     pub position: Position,
 }
 
-#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct FactorioTechnology {
     pub name: String,
@@ -456,13 +687,14 @@ pub struct FactorioTechnology {
     #[serde(deserialize_with = "deserialize_helpers::vec_or_empty_map")]
     pub research_unit_ingredients: Vec<FactorioIngredient>,
     pub research_unit_count: u64,
+    #[schemars(with = "f64")]
     pub research_unit_energy: Box<R64>,
     pub order: String,
     pub level: u32,
     pub valid: bool,
 }
 
-#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct FactorioForce {
     pub name: String,
@@ -470,6 +702,7 @@ pub struct FactorioForce {
     // The current technology in research, or None if no research is currently ongoing.
     pub current_research: Option<String>,
     // Progress of current research, as a number in range [0, 1].
+    #[schemars(with = "Option<f64>")]
     pub research_progress: Option<Box<R64>>,
     pub technologies: Box<BTreeMap<String, FactorioTechnology>>,
 }
@@ -553,6 +786,28 @@ pub struct FactorioEntityPrototype {
     pub crafting_speed: Option<f64>,
     pub max_underground_distance: Option<u8>,
     pub fluidbox_prototypes: Option<Vec<FactorioFluidBoxPrototype>>,
+    /// only electric poles, half the width of the supplied square
+    pub supply_area_distance: Option<f64>,
+    /// only electric poles
+    pub max_wire_distance: Option<f64>,
+    /// electric, burner, heat, fluid or void
+    pub energy_source: Option<String>,
+    /// in watts
+    pub energy_usage: Option<f64>,
+    /// in watts, only generators and solar panels
+    pub max_energy_production: Option<f64>,
+    /// in joules, only accumulators
+    pub electric_buffer_size: Option<f64>,
+    /// in fluid units per second, only pumps and offshore pumps
+    pub pumping_speed: Option<f64>,
+    /// in fluid units per second, only generators
+    pub fluid_usage: Option<f64>,
+    /// in tiles per tick, only characters
+    pub running_speed: Option<f64>,
+    /// in turns per tick, only inserters
+    pub rotation_speed: Option<f64>,
+    /// items an inserter hand holds beyond one, only inserters
+    pub inserter_stack_size_bonus: Option<u32>,
 }
 
 #[derive(Debug, Clone, Default, PartialEq, TypeScriptify, Serialize, Deserialize, JsonSchema)]
@@ -566,11 +821,26 @@ pub struct FactorioEntity {
     pub drop_position: Option<Position>,
     pub pickup_position: Option<Position>, // only type = inserter
     pub output_inventory: Option<Vec<InventoryItemWithQuality>>, // Factorio 2.0 format
-    pub fuel_inventory: Option<Vec<InventoryItemWithQuality>>,   // Factorio 2.0 format
-    pub amount: Option<u32>,        // only type = resource
-    pub recipe: Option<String>,     // only CraftingMachines
-    pub ghost_name: Option<String>, // only type = entity-ghost
-    pub ghost_type: Option<String>, // only type = entity-ghost
+    pub fuel_inventory: Option<Vec<InventoryItemWithQuality>>, // Factorio 2.0 format
+    pub amount: Option<u32>,               // only type = resource
+    pub recipe: Option<String>,            // only CraftingMachines
+    pub ghost_name: Option<String>,        // only type = entity-ghost
+    pub ghost_type: Option<String>,        // only type = entity-ghost
+    /// `input` or `output`, only type = underground-belt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub belt_to_ground_type: Option<String>,
+    /// modules inserted or requested by name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modules: Option<BTreeMap<String, u32>>,
+    /// name of the surface, `None` means the default surface
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub surface: Option<String>,
+    /// quality of the entity, `None` means normal quality
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quality: Option<String>,
+    /// name of the owning force like player, enemy or neutral, `None` if unknown
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub force: Option<String>,
 }
 
 impl crate::aabb_quadtree::Spatial<Rect> for FactorioEntity {
@@ -580,6 +850,14 @@ impl crate::aabb_quadtree::Spatial<Rect> for FactorioEntity {
 }
 
 impl FactorioEntity {
+    pub fn surface(&self) -> &str {
+        self.surface.as_deref().unwrap_or(DEFAULT_SURFACE)
+    }
+
+    pub fn quality(&self) -> &str {
+        self.quality.as_deref().unwrap_or(DEFAULT_QUALITY)
+    }
+
     pub fn from_blueprint_entity(
         entity: Entity,
         prototypes: Arc<DashMap<String, FactorioEntityPrototype>>,
@@ -588,14 +866,28 @@ impl FactorioEntity {
         let direction: Option<Direction> = entity
             .direction
             .map(|d| Direction::from_u8(d % 8).expect("should always work"));
-        Self::from_prototype(
+        let mut result = Self::from_prototype(
             &entity.name,
             position,
             direction,
             entity.pickup_position.map(|p| p.into()),
             entity.drop_position.map(|p| p.into()),
             prototypes,
-        )
+        )?;
+        result.recipe = entity.recipe;
+        result.belt_to_ground_type = entity.type_.and_then(|type_| match type_ {
+            BlueprintEntityType::Input => Some("input".to_owned()),
+            BlueprintEntityType::Output => Some("output".to_owned()),
+            BlueprintEntityType::Item => None,
+        });
+        result.modules = entity.items.map(|items| match items {
+            ItemRequest::Compact(items) => items.into_iter().collect(),
+            ItemRequest::Verbose(items) => items
+                .into_iter()
+                .map(|item| (item.item, item.count))
+                .collect(),
+        });
+        Ok(result)
     }
 
     pub fn from_prototype(
@@ -732,6 +1024,46 @@ impl FactorioEntity {
         }
     }
 
+    pub fn new_pipe(position: &Position) -> FactorioEntity {
+        FactorioEntity {
+            name: EntityName::Pipe.to_string(),
+            entity_type: EntityType::Pipe.to_string(),
+            position: position.clone(),
+            bounding_box: add_to_rect(&Rect::from_wh(0.58, 0.58), position),
+            ..Default::default()
+        }
+    }
+    pub fn new_offshore_pump(position: &Position, direction: Direction) -> FactorioEntity {
+        FactorioEntity {
+            name: EntityName::OffshorePump.to_string(),
+            entity_type: EntityType::OffshorePump.to_string(),
+            position: position.clone(),
+            bounding_box: add_to_rect_turned(&Rect::from_wh(0.8, 0.8), position, direction),
+            direction: direction.to_u8().unwrap(),
+            ..Default::default()
+        }
+    }
+    pub fn new_boiler(position: &Position, direction: Direction) -> FactorioEntity {
+        FactorioEntity {
+            name: EntityName::Boiler.to_string(),
+            entity_type: EntityType::Boiler.to_string(),
+            position: position.clone(),
+            bounding_box: add_to_rect_turned(&Rect::from_wh(2.58, 1.58), position, direction),
+            direction: direction.to_u8().unwrap(),
+            ..Default::default()
+        }
+    }
+    pub fn new_steam_engine(position: &Position, direction: Direction) -> FactorioEntity {
+        FactorioEntity {
+            name: EntityName::SteamEngine.to_string(),
+            entity_type: EntityType::Generator.to_string(),
+            position: position.clone(),
+            bounding_box: add_to_rect_turned(&Rect::from_wh(2.5, 4.7), position, direction),
+            direction: direction.to_u8().unwrap(),
+            ..Default::default()
+        }
+    }
+
     pub fn is_minable(&self) -> bool {
         self.entity_type == EntityType::Tree.to_string()
             || self.entity_type == EntityType::SimpleEntity.to_string()
@@ -759,11 +1091,20 @@ pub struct FactorioItemPrototype {
     pub subgroup: String,
 }
 
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct FactorioTilePrototype {
+    pub name: String,
+    /// multiplies the running speed of characters on this tile, e.g. 1.5 on refined concrete
+    pub walking_speed_modifier: f64,
+}
+
 #[derive(EnumString, Display, Debug)]
 #[strum(serialize_all = "kebab-case")]
 pub enum EntityName {
     // raw resources
     Water,
+    Steam,
     Wood,
     Stone,
     Coal,
@@ -786,12 +1127,17 @@ pub enum EntityName {
     Splitter,
     ElectricMiningDrill,
     Pumpjack,
+    Pipe,
+    OffshorePump,
+    Boiler,
+    SteamEngine,
 }
 
 #[derive(EnumString, Display, Debug, PartialEq, Clone, Serialize, Deserialize)]
 #[strum(serialize_all = "kebab-case")]
 pub enum EntityType {
     AssemblingMachine,
+    Character,
     LogisticContainer,
     Boiler,
     Lab,
@@ -813,14 +1159,28 @@ pub enum EntityType {
     StraightRail,
     CurvedRail,
     Fish,
+    ElectricPole,
+    Generator,
+    SolarPanel,
+    Accumulator,
+    Radar,
+    Pump,
+    UnitSpawner,
+    Turret,
+    Unit,
 }
 
 impl EntityType {
+    /// types of spawners, worms and biters. Own turrets may share them,
+    /// so check the force of an entity as well
+    pub fn is_enemy(&self) -> bool {
+        *self == EntityType::UnitSpawner || *self == EntityType::Turret || *self == EntityType::Unit
+    }
+    /// pipes and tanks, machines connect through their fluid box prototypes instead
     pub fn is_fluid_input(&self) -> bool {
         *self == EntityType::Pipe
             || *self == EntityType::StorageTank
             || *self == EntityType::PipeToGround
-            || *self == EntityType::Boiler
     }
 }
 
@@ -890,19 +1250,69 @@ impl ResourcePatch {
     }
 }
 
+/// Builtin qualities of Factorio 2.0, mods may add more
+#[derive(EnumString, Display, Debug, Clone, Copy, PartialEq, Eq)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Quality {
+    Normal,
+    Uncommon,
+    Rare,
+    Epic,
+    Legendary,
+}
+
+impl Quality {
+    /// https://wiki.factorio.com/Quality
+    /// every quality level adds 30% crafting speed, legendary is level 5
+    pub fn crafting_speed_multiplier(self) -> f64 {
+        match self {
+            Quality::Normal => 1.0,
+            Quality::Uncommon => 1.3,
+            Quality::Rare => 1.6,
+            Quality::Epic => 1.9,
+            Quality::Legendary => 2.5,
+        }
+    }
+}
+
+/// Inventory key of an item: its name for normal quality, `name@quality` otherwise.
+/// Keeps inventories keyed by plain item names working for normal items.
+pub fn item_key(name: &str, quality: &str) -> String {
+    if quality == DEFAULT_QUALITY {
+        name.to_owned()
+    } else {
+        format!("{}@{}", name, quality)
+    }
+}
+
+/// Splits an inventory key built by `item_key` into name and quality
+pub fn split_item_key(key: &str) -> (&str, &str) {
+    key.split_once('@').unwrap_or((key, DEFAULT_QUALITY))
+}
+
 #[derive(Debug, Clone)]
 pub struct InventoryItem {
     pub name: String,
     pub count: u32,
+    pub quality: String,
 }
 
 impl InventoryItem {
     pub fn new(name: &str, count: u32) -> InventoryItem {
+        Self::with_quality(name, count, DEFAULT_QUALITY)
+    }
+
+    pub fn with_quality(name: &str, count: u32, quality: &str) -> InventoryItem {
         InventoryItem {
             name: name.into(),
             count,
+            quality: quality.into(),
         }
     }
+
+    pub fn key(&self) -> String {
+        item_key(&self.name, &self.quality)
+    }
 }
 
 /// Inventory item with quality field (Factorio 2.0 format)
@@ -914,6 +1324,12 @@ pub struct InventoryItemWithQuality {
     pub count: u32,
 }
 
+impl InventoryItemWithQuality {
+    pub fn key(&self) -> String {
+        item_key(&self.name, &self.quality)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct InventoryLocation {
     pub entity_name: String,
@@ -977,7 +1393,7 @@ pub struct PlaceEntitiesResult {
     pub entities: Vec<FactorioEntity>,
 }
 
-#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
+#[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct PlayerChangedDistanceEvent {
     pub player_id: PlayerId,
@@ -987,6 +1403,26 @@ pub struct PlayerChangedDistanceEvent {
     pub item_pickup_distance: u64,
     pub loot_pickup_distance: u64,
     pub resource_reach_distance: u64,
+    /// running speed modifier of the character plus the movement bonus of its equipment
+    #[serde(default)]
+    pub movement_bonus: f64,
+}
+
+// the mod only sends finite movement bonuses, so hashing the bits (with -0 folded into 0) agrees
+// with the derived PartialEq and keeps the event usable as a set or map key
+impl Eq for PlayerChangedDistanceEvent {}
+
+impl std::hash::Hash for PlayerChangedDistanceEvent {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.player_id.hash(state);
+        self.build_distance.hash(state);
+        self.reach_distance.hash(state);
+        self.drop_item_distance.hash(state);
+        self.item_pickup_distance.hash(state);
+        self.loot_pickup_distance.hash(state);
+        self.resource_reach_distance.hash(state);
+        (self.movement_bonus + 0.).to_bits().hash(state);
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize)]
@@ -994,6 +1430,9 @@ pub struct PlayerChangedDistanceEvent {
 pub struct PlayerChangedPositionEvent {
     pub player_id: PlayerId,
     pub position: Position,
+    /// name of the surface, `None` means the default surface
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub surface: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
@@ -1005,14 +1444,17 @@ pub struct PlayerChangedMainInventoryEvent {
 }
 
 impl PlayerChangedMainInventoryEvent {
-    /// Create an event from a BTreeMap (for internal use/simulation)
+    /// Create an event from a BTreeMap keyed by `item_key` (for internal use/simulation)
     pub fn from_btreemap(player_id: PlayerId, inventory: BTreeMap<String, u32>) -> Self {
         let main_inventory = inventory
-            .into_iter()
-            .map(|(name, count)| InventoryItemWithQuality {
-                name,
-                quality: "normal".to_string(),
-                count,
+            .iter()
+            .map(|(key, count)| {
+                let (name, quality) = split_item_key(key);
+                InventoryItemWithQuality {
+                    name: name.to_owned(),
+                    quality: quality.to_owned(),
+                    count: *count,
+                }
             })
             .collect();
         Self {
@@ -1022,6 +1464,243 @@ impl PlayerChangedMainInventoryEvent {
     }
 }
 
+/// Machine status as reported by `LuaEntity::status`, see `defines.entity_status`
+#[derive(Display, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum EntityStatus {
+    Working,
+    Normal,
+    NoPower,
+    LowPower,
+    NoFuel,
+    NoRecipe,
+    NoIngredients,
+    NoInputFluid,
+    NoResearchInProgress,
+    NoMinableResources,
+    MissingSciencePacks,
+    ItemIngredientShortage,
+    FluidIngredientShortage,
+    WaitingForSourceItems,
+    FullOutput,
+    FullBurntResultOutput,
+    WaitingForSpaceInDestination,
+    DisabledByControlBehavior,
+    DisabledByScript,
+    MarkedForDeconstruction,
+    Frozen,
+    #[serde(other)]
+    Other,
+}
+
+impl EntityStatus {
+    pub fn is_working(self) -> bool {
+        self == EntityStatus::Working
+    }
+
+    /// waiting for ingredients, fuel, ore or science packs
+    pub fn is_starved(self) -> bool {
+        matches!(
+            self,
+            EntityStatus::NoFuel
+                | EntityStatus::NoIngredients
+                | EntityStatus::NoInputFluid
+                | EntityStatus::NoMinableResources
+                | EntityStatus::MissingSciencePacks
+                | EntityStatus::ItemIngredientShortage
+                | EntityStatus::FluidIngredientShortage
+                | EntityStatus::WaitingForSourceItems
+        )
+    }
+
+    /// output is full and has to be emptied before work continues
+    pub fn is_output_blocked(self) -> bool {
+        matches!(
+            self,
+            EntityStatus::FullOutput
+                | EntityStatus::FullBurntResultOutput
+                | EntityStatus::WaitingForSpaceInDestination
+        )
+    }
+}
+
+/// Sent by BotBridge when inventories or status of a tracked entity changed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct EntityStateChangedEvent {
+    pub name: String,
+    pub position: Position,
+    /// name of the surface, `None` means the default surface
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub surface: Option<String>,
+    #[serde(default)]
+    pub status: Option<EntityStatus>,
+    /// contents by inventory name, empty inventories are left out
+    #[serde(default)]
+    pub inventories: BTreeMap<String, Vec<InventoryItemWithQuality>>,
+}
+
+/// Last known inventories and status of a machine or container
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct FactorioEntityState {
+    pub name: String,
+    pub position: Position,
+    pub status: Option<EntityStatus>,
+    /// item counts keyed by `item_key` per inventory name, e.g. `furnace_result`
+    pub inventories: BTreeMap<String, BTreeMap<String, u32>>,
+    /// tick of the last change
+    pub tick: u64,
+}
+
+impl FactorioEntityState {
+    /// count of given item key over all inventories
+    pub fn item_count(&self, key: &str) -> u32 {
+        self.inventories
+            .values()
+            .filter_map(|inventory| inventory.get(key))
+            .sum()
+    }
+}
+
+/// Sent by BotBridge when an entity got damaged or killed by the enemy force
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct EntityAttackedEvent {
+    pub name: String,
+    pub entity_type: String,
+    pub position: Position,
+    /// name of the surface, `None` means the default surface
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub surface: Option<String>,
+    /// only set if the attacked entity is the character of a bot
+    #[serde(default)]
+    pub player_id: Option<PlayerId>,
+    /// name of the attacking entity, `None` if it is gone already
+    #[serde(default)]
+    pub cause: Option<String>,
+    #[serde(default)]
+    pub damage: f64,
+    /// health left after the attack
+    #[serde(default)]
+    pub health: f64,
+    #[serde(default)]
+    pub died: bool,
+}
+
+/// Sent by BotBridge periodically, chunks without pollution are left out
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct PollutionChangedEvent {
+    /// name of the surface, `None` means the default surface
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub surface: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_helpers::vec_or_empty_map")]
+    pub chunks: Vec<ChunkPollution>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ChunkPollution {
+    /// chunk coordinates, not tile positions
+    pub x: i32,
+    pub y: i32,
+    pub pollution: f64,
+}
+
+/// Circle around an enemy structure or unit which bots should stay out of
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct DangerZone {
+    /// name of the enemy entity
+    pub name: String,
+    pub position: Position,
+    pub radius: f64,
+}
+
+impl DangerZone {
+    pub fn contains(&self, position: &Position) -> bool {
+        let dx = position.x() - self.position.x();
+        let dy = position.y() - self.position.y();
+        dx * dx + dy * dy <= self.radius * self.radius
+    }
+
+    /// true if the straight line from `from` to `to` touches the zone
+    pub fn intersects_line(&self, from: &Position, to: &Position) -> bool {
+        let (dx, dy) = (to.x() - from.x(), to.y() - from.y());
+        let length = dx * dx + dy * dy;
+        let t = if length == 0. {
+            0.
+        } else {
+            (((self.position.x() - from.x()) * dx + (self.position.y() - from.y()) * dy) / length)
+                .clamp(0., 1.)
+        };
+        self.contains(&Position::new(from.x() + t * dx, from.y() + t * dy))
+    }
+
+    pub fn intersects_rect(&self, rect: &Rect) -> bool {
+        let closest = Position::new(
+            self.position
+                .x()
+                .clamp(rect.left_top.x(), rect.right_bottom.x()),
+            self.position
+                .y()
+                .clamp(rect.left_top.y(), rect.right_bottom.y()),
+        );
+        self.contains(&closest)
+    }
+}
+
+/// Items BotBridge saw leaving belts and inserters of an area during a sampling window
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ThroughputSampling {
+    /// name of the surface, `None` means the default surface
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub surface: Option<String>,
+    /// length of the sampling window
+    pub ticks: u32,
+    #[serde(default, deserialize_with = "deserialize_helpers::vec_or_empty_map")]
+    pub entities: Vec<EntityThroughput>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct EntityThroughput {
+    pub name: String,
+    pub position: Position,
+    /// item counts moved by the entity during the sampling window
+    #[serde(default)]
+    pub items: BTreeMap<String, u32>,
+}
+
+impl EntityThroughput {
+    pub fn items_per_minute(&self, ticks: u32) -> Vec<(String, f64)> {
+        self.items
+            .iter()
+            .map(|(name, count)| (name.clone(), *count as f64 * 3600. / ticks.max(1) as f64))
+            .collect()
+    }
+}
+
+/// Differences between the known state of an area and a fresh scan of it.
+/// Entities are matched by name and position.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct EntityDiff {
+    pub added: Vec<FactorioEntity>,
+    pub removed: Vec<FactorioEntity>,
+    /// entities which were rotated, got another recipe or quality
+    pub changed: Vec<FactorioEntity>,
+}
+
+impl EntityDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, TypeScriptify, Serialize, Deserialize, Hash, Eq)]
 #[serde(rename_all = "snake_case")]
 pub struct PlayerLeftEvent {
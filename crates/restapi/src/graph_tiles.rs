@@ -1,35 +1,61 @@
-use std::sync::Arc;
-
-// use actix_web::{web, HttpResponse};
+use crate::error::ErrorResponse;
+use factorio_bot_core::draw::{arrow_mut, draw_blocked_rects_mut, draw_resource_rects_mut};
+use factorio_bot_core::factorio::util::scaled_draw_rect;
+use factorio_bot_core::factorio::world::FactorioWorld;
+use factorio_bot_core::graph::power_graph::{PowerEdge, PowerRole};
+use factorio_bot_core::petgraph;
+use factorio_bot_core::petgraph::visit::{EdgeRef, IntoEdgeReferences};
+use factorio_bot_core::process::server_registry::SharedFactorioServerRegistry;
+use factorio_bot_core::types::{Pos, Position, Rect};
 use image::{DynamicImage, ImageFormat, RgbaImage};
 use imageproc::drawing::{draw_filled_rect_mut, draw_hollow_rect_mut};
-use petgraph::visit::EdgeRef;
-
-use crate::draw::{draw_arrow_mut, draw_blocked_rects_mut, draw_resource_rects_mut};
-use crate::factorio::util::scaled_draw_rect;
-use crate::factorio::world::FactorioWorld;
-use crate::types::{Position, Rect};
+use rocket::http::ContentType;
+use rocket::response::status::BadRequest;
+use rocket::serde::json::Json;
+use rocket::State;
 use std::collections::HashMap;
-
-// use std::time::Instant;
+use std::io::Cursor;
+use std::sync::Arc;
 
 const TILE_WIDTH: u32 = 256;
 const TILE_HEIGHT: u32 = 256;
 
+/// png image of one map tile
+pub type TileResult = Result<(ContentType, Vec<u8>), BadRequest<Json<ErrorResponse>>>;
+
+/// World of given server, the default server if none given
+async fn server_world(
+    servers: &SharedFactorioServerRegistry,
+    server: Option<String>,
+) -> Result<Arc<FactorioWorld>, BadRequest<Json<ErrorResponse>>> {
+    servers
+        .world(server.as_deref())
+        .await
+        .map_err(|err| ErrorResponse::new(format!("{}", err), 2))
+}
+
+/// tiles of the map colored like in game
+#[openapi(skip)]
+#[get("/mapTiles/<z>/<x>/<y>?<surface>&<server>")]
 pub async fn map_tiles(
-    world: web::Data<Arc<FactorioWorld>>,
-    info: web::Path<(i32, i32, i32)>,
-) -> Result<HttpResponse, actix_web::Error> {
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    z: i32,
+    x: i32,
+    y: i32,
+    surface: Option<String>,
+) -> TileResult {
+    let world = server_world(servers, server).await?;
+    let surface = world.surface(surface.as_deref());
     let mut buffer = create_tile();
     for (_x, _y, pixel) in buffer.enumerate_pixels_mut() {
         *pixel = image::Rgba([255, 255, 255, 255u8]);
     }
-    let (tile_z, tile_x, tile_y) = info.into_inner();
-    let bounding_box = tile_boundaries(tile_z, tile_x, tile_y);
+    let bounding_box = tile_boundaries(z, x, y);
     let base_x = bounding_box.left_top.x();
     let base_y = bounding_box.left_top.y();
     let scaling_factor = TILE_WIDTH as f64 / bounding_box.width();
-    for (tile, rect, _id) in world.entity_graph.tile_tree().query(bounding_box.into()) {
+    for (tile, rect, _id) in surface.entity_graph.tile_tree().query(bounding_box.into()) {
         if let Some(color) = tile.color {
             let width = (rect.size.width as f64 * scaling_factor).round() as u32;
             let height = (rect.size.height as f64 * scaling_factor).round() as u32;
@@ -52,38 +78,44 @@ pub async fn map_tiles(
             // }
         }
     }
-    Ok(HttpResponse::Ok()
-        .content_type("image/png")
-        .body(build_image_body(buffer)))
+    Ok((ContentType::PNG, build_image_body(buffer)))
 }
 
+/// entities and their connections in the entity graph, entities missing from the graph in red
+#[openapi(skip)]
+#[get("/entityGraphTiles/<z>/<x>/<y>?<surface>&<server>")]
 pub async fn entity_graph_tiles(
-    world: web::Data<Arc<FactorioWorld>>,
-    info: web::Path<(i32, i32, i32)>,
-) -> Result<HttpResponse, actix_web::Error> {
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    z: i32,
+    x: i32,
+    y: i32,
+    surface: Option<String>,
+) -> TileResult {
+    let world = server_world(servers, server).await?;
+    let surface = world.surface(surface.as_deref());
     let mut buffer = create_tile();
-    let (tile_z, tile_x, tile_y) = info.into_inner();
-    let bounding_box = tile_boundaries(tile_z, tile_x, tile_y);
+    let bounding_box = tile_boundaries(z, x, y);
     let base_x = bounding_box.left_top.x();
     let base_y = bounding_box.left_top.y();
     let scaling_factor = TILE_WIDTH as f64 / bounding_box.width();
-    for (entity, rect, id) in world
+    for (entity, rect, id) in surface
         .entity_graph
         .inner_tree()
         .query(bounding_box.clone().into())
     {
         if let Some(draw_rect) = scaled_draw_rect(&bounding_box, rect, scaling_factor) {
-            match world.entity_graph.node_by_id(&id) {
+            match surface.entity_graph.node_by_id(&id) {
                 Some(node_id) => {
                     draw_hollow_rect_mut(
                         &mut buffer,
                         draw_rect,
                         image::Rgba([3u8, 169u8, 244u8, 255u8]),
                     );
-                    let graph = world.entity_graph.inner_graph();
+                    let graph = surface.entity_graph.inner_graph();
                     for edge in graph.edges_directed(node_id, petgraph::Direction::Outgoing) {
                         if let Some(node) = graph.node_weight(edge.target()) {
-                            draw_arrow_mut(
+                            arrow_mut(
                                 &mut buffer,
                                 (
                                     ((entity.position.x() - base_x) * scaling_factor) as f32,
@@ -100,7 +132,7 @@ pub async fn entity_graph_tiles(
                     }
                     for edge in graph.edges_directed(node_id, petgraph::Direction::Incoming) {
                         if let Some(node) = graph.node_weight(edge.source()) {
-                            draw_arrow_mut(
+                            arrow_mut(
                                 &mut buffer,
                                 (
                                     ((node.position.x() - base_x) * scaling_factor) as f32,
@@ -127,39 +159,51 @@ pub async fn entity_graph_tiles(
         }
     }
 
-    Ok(HttpResponse::Ok()
-        .content_type("image/png")
-        .body(build_image_body(buffer)))
+    Ok((ContentType::PNG, build_image_body(buffer)))
 }
 
+/// blocked rects, minable ones like trees and rocks in green
+#[openapi(skip)]
+#[get("/blockedTiles/<z>/<x>/<y>?<surface>&<server>")]
 pub async fn blocked_tiles(
-    world: web::Data<Arc<FactorioWorld>>,
-    info: web::Path<(i32, i32, i32)>,
-) -> Result<HttpResponse, actix_web::Error> {
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    z: i32,
+    x: i32,
+    y: i32,
+    surface: Option<String>,
+) -> TileResult {
+    let world = server_world(servers, server).await?;
+    let surface = world.surface(surface.as_deref());
     let mut buffer = create_tile();
-    let (tile_z, tile_x, tile_y) = info.into_inner();
-    let bounding_box = tile_boundaries(tile_z, tile_x, tile_y);
+    let bounding_box = tile_boundaries(z, x, y);
     let scaling_factor = TILE_WIDTH as f64 / bounding_box.width();
     draw_blocked_rects_mut(
         &mut buffer,
-        world.entity_graph.blocked_tree(),
+        surface.entity_graph.blocked_tree(),
         &bounding_box,
         scaling_factor,
         image::Rgba([76u8, 175u8, 80u8, 255u8]),
         image::Rgba([255u8, 0u8, 0u8, 255u8]),
     );
-    Ok(HttpResponse::Ok()
-        .content_type("image/png")
-        .body(build_image_body(buffer)))
+    Ok((ContentType::PNG, build_image_body(buffer)))
 }
 
+/// resource tiles colored by resource
+#[openapi(skip)]
+#[get("/resourceTiles/<z>/<x>/<y>?<surface>&<server>")]
 pub async fn resource_tiles(
-    world: web::Data<Arc<FactorioWorld>>,
-    info: web::Path<(i32, i32, i32)>,
-) -> Result<HttpResponse, actix_web::Error> {
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    z: i32,
+    x: i32,
+    y: i32,
+    surface: Option<String>,
+) -> TileResult {
+    let world = server_world(servers, server).await?;
+    let surface = world.surface(surface.as_deref());
     let mut buffer = create_tile();
-    let (tile_z, tile_x, tile_y) = info.into_inner();
-    let bounding_box = tile_boundaries(tile_z, tile_x, tile_y);
+    let bounding_box = tile_boundaries(z, x, y);
     let scaling_factor = TILE_WIDTH as f64 / bounding_box.width();
     let resource_colors: HashMap<&str, image::Rgba<_>> = [
         ("iron-ore", image::Rgba([0u8, 140u8, 255u8, 255u8])),
@@ -174,44 +218,69 @@ pub async fn resource_tiles(
     .collect();
     draw_resource_rects_mut(
         &mut buffer,
-        world.entity_graph.resource_tree(),
+        surface.entity_graph.resource_tree(),
         &bounding_box,
         scaling_factor,
         resource_colors,
         image::Rgba([255u8, 0u8, 0u8, 255u8]),
     );
-    Ok(HttpResponse::Ok()
-        .content_type("image/png")
-        .body(build_image_body(buffer)))
+    Ok((ContentType::PNG, build_image_body(buffer)))
 }
 
+/// flow graph edges colored by measured versus theoretical throughput, see `throughput_color`
+#[openapi(skip)]
+#[get("/flowGraphTiles/<z>/<x>/<y>?<surface>&<server>")]
 pub async fn flow_graph_tiles(
-    world: web::Data<Arc<FactorioWorld>>,
-    info: web::Path<(i32, i32, i32)>,
-) -> Result<HttpResponse, actix_web::Error> {
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    z: i32,
+    x: i32,
+    y: i32,
+    surface: Option<String>,
+) -> TileResult {
+    let world = server_world(servers, server).await?;
+    let surface = world.surface(surface.as_deref());
     let mut buffer = create_tile();
-    let (tile_z, tile_x, tile_y) = info.into_inner();
-    let bounding_box = tile_boundaries(tile_z, tile_x, tile_y);
+    let bounding_box = tile_boundaries(z, x, y);
     let base_x = bounding_box.left_top.x();
     let base_y = bounding_box.left_top.y();
     let scaling_factor = TILE_WIDTH as f64 / bounding_box.width();
-    for (entity, rect, _id) in world
+    let utilization: HashMap<(Pos, Pos), Option<f64>> = surface
+        .flow_graph
+        .throughput()
+        .iter()
+        .map(|edge| {
+            (
+                ((&edge.source).into(), (&edge.target).into()),
+                edge.utilization(),
+            )
+        })
+        .collect();
+    let edge_color = |source: &Position, target: &Position| {
+        throughput_color(
+            utilization
+                .get(&(source.into(), target.into()))
+                .copied()
+                .flatten(),
+        )
+    };
+    for (entity, rect, _id) in surface
         .entity_graph
         .inner_tree()
         .query(bounding_box.clone().into())
     {
         if let Some(draw_rect) = scaled_draw_rect(&bounding_box, rect, scaling_factor) {
-            match world.flow_graph.node_at(&entity.position) {
+            match surface.flow_graph.node_at(&entity.position) {
                 Some(node_id) => {
                     draw_hollow_rect_mut(
                         &mut buffer,
                         draw_rect,
                         image::Rgba([3u8, 169u8, 244u8, 255u8]),
                     );
-                    let graph = world.flow_graph.inner_graph();
+                    let graph = surface.flow_graph.inner_graph();
                     for edge in graph.edges_directed(node_id, petgraph::Direction::Outgoing) {
                         if let Some(node) = graph.node_weight(edge.target()) {
-                            draw_arrow_mut(
+                            arrow_mut(
                                 &mut buffer,
                                 (
                                     ((entity.position.x() - base_x) * scaling_factor) as f32,
@@ -221,14 +290,14 @@ pub async fn flow_graph_tiles(
                                     ((node.position.x() - base_x) * scaling_factor) as f32,
                                     ((node.position.y() - base_y) * scaling_factor) as f32,
                                 ),
-                                image::Rgba([76u8, 175u8, 80u8, 255u8]),
+                                edge_color(&entity.position, &node.position),
                                 scaling_factor / 10.,
                             );
                         }
                     }
                     for edge in graph.edges_directed(node_id, petgraph::Direction::Incoming) {
                         if let Some(node) = graph.node_weight(edge.source()) {
-                            draw_arrow_mut(
+                            arrow_mut(
                                 &mut buffer,
                                 (
                                     ((node.position.x() - base_x) * scaling_factor) as f32,
@@ -238,7 +307,7 @@ pub async fn flow_graph_tiles(
                                     ((entity.position.x() - base_x) * scaling_factor) as f32,
                                     ((entity.position.y() - base_y) * scaling_factor) as f32,
                                 ),
-                                image::Rgba([76u8, 175u8, 80u8, 255u8]),
+                                edge_color(&node.position, &entity.position),
                                 scaling_factor / 10.,
                             );
                         }
@@ -254,9 +323,74 @@ pub async fn flow_graph_tiles(
             }
         }
     }
-    Ok(HttpResponse::Ok()
-        .content_type("image/png")
-        .body(build_image_body(buffer)))
+    Ok((ContentType::PNG, build_image_body(buffer)))
+}
+
+/// measured versus theoretical throughput of a flow edge: green at 90% or more,
+/// yellow at 50% or more, red below, gray if the edge was never sampled
+fn throughput_color(utilization: Option<f64>) -> image::Rgba<u8> {
+    match utilization {
+        Some(utilization) if utilization >= 0.9 => image::Rgba([76u8, 175u8, 80u8, 255u8]),
+        Some(utilization) if utilization >= 0.5 => image::Rgba([255u8, 193u8, 7u8, 255u8]),
+        Some(_) => image::Rgba([244u8, 67u8, 54u8, 255u8]),
+        None => image::Rgba([158u8, 158u8, 158u8, 255u8]),
+    }
+}
+
+/// poles and wires in blue, powered entities in green, unpowered consumers in red
+#[openapi(skip)]
+#[get("/powerGraphTiles/<z>/<x>/<y>?<surface>&<server>")]
+pub async fn power_graph_tiles(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    z: i32,
+    x: i32,
+    y: i32,
+    surface: Option<String>,
+) -> TileResult {
+    let world = server_world(servers, server).await?;
+    let surface = world.surface(surface.as_deref());
+    let mut buffer = create_tile();
+    let bounding_box = tile_boundaries(z, x, y);
+    let base_x = bounding_box.left_top.x();
+    let base_y = bounding_box.left_top.y();
+    let scaling_factor = TILE_WIDTH as f64 / bounding_box.width();
+    let networks = surface.power_graph.networks();
+    let graph = surface.power_graph.inner_graph();
+    for node in graph.node_weights() {
+        let rect = node.bounding_box.clone().into();
+        if let Some(draw_rect) = scaled_draw_rect(&bounding_box, rect, scaling_factor) {
+            let powered = node
+                .network
+                .map(|network| networks[network].supply > 0.)
+                .unwrap_or(false);
+            let color = match (node.role, powered) {
+                (PowerRole::Pole, _) => image::Rgba([3u8, 169u8, 244u8, 255u8]),
+                (_, true) => image::Rgba([76u8, 175u8, 80u8, 255u8]),
+                (_, false) => image::Rgba([255u8, 0u8, 0u8, 255u8]),
+            };
+            draw_hollow_rect_mut(&mut buffer, draw_rect, color);
+        }
+    }
+    for edge in (&*graph).edge_references() {
+        if let PowerEdge::Wire(_) = edge.weight() {
+            let (from, to) = (&graph[edge.source()], &graph[edge.target()]);
+            arrow_mut(
+                &mut buffer,
+                (
+                    ((from.position.x() - base_x) * scaling_factor) as f32,
+                    ((from.position.y() - base_y) * scaling_factor) as f32,
+                ),
+                (
+                    ((to.position.x() - base_x) * scaling_factor) as f32,
+                    ((to.position.y() - base_y) * scaling_factor) as f32,
+                ),
+                image::Rgba([3u8, 169u8, 244u8, 255u8]),
+                scaling_factor / 10.,
+            );
+        }
+    }
+    Ok((ContentType::PNG, build_image_body(buffer)))
 }
 
 pub fn tile_boundaries(z: i32, x: i32, y: i32) -> Rect {
@@ -267,12 +401,12 @@ pub fn tile_boundaries(z: i32, x: i32, y: i32) -> Rect {
     let y = y as f64;
     let zoom_width = map_size_chunks / 2.0f64.powi(z);
     let top_left = (
-        (-map_size_chunks_half + (zoom_width * x)) as f64,
-        (-map_size_chunks_half + (zoom_width * y)) as f64,
+        (-map_size_chunks_half + (zoom_width * x)),
+        (-map_size_chunks_half + (zoom_width * y)),
     );
     let bottom_right = (
-        (-map_size_chunks_half + (zoom_width * (x + 1.0f64))) as f64,
-        (-map_size_chunks_half + (zoom_width * (y + 1.0f64))) as f64,
+        (-map_size_chunks_half + (zoom_width * (x + 1.0f64))),
+        (-map_size_chunks_half + (zoom_width * (y + 1.0f64))),
     );
     Rect::new(
         &Position::new(top_left.0 * 32., top_left.1 * 32.),
@@ -280,6 +414,19 @@ pub fn tile_boundaries(z: i32, x: i32, y: i32) -> Rect {
     )
 }
 
+pub fn create_tile() -> RgbaImage {
+    image::ImageBuffer::new(TILE_WIDTH, TILE_HEIGHT)
+}
+
+pub fn build_image_body(buffer: RgbaImage) -> Vec<u8> {
+    let dynamic = DynamicImage::ImageRgba8(buffer);
+    let mut buf = Cursor::new(Vec::new());
+    dynamic
+        .write_to(&mut buf, ImageFormat::Png)
+        .expect("failed to write image");
+    buf.into_inner()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -304,16 +451,3 @@ mod tests {
         assert_eq!(right_bottom, Position::new(0.0, 0.0));
     }
 }
-
-pub fn create_tile() -> RgbaImage {
-    image::ImageBuffer::new(TILE_WIDTH, TILE_HEIGHT)
-}
-
-pub fn build_image_body(buffer: RgbaImage) -> Vec<u8> {
-    let dynamic = DynamicImage::ImageRgba8(buffer);
-    let mut buf: Vec<u8> = Vec::new();
-    dynamic
-        .write_to(&mut buf, ImageFormat::Png)
-        .expect("failed to write image");
-    buf
-}
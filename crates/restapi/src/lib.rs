@@ -1,4 +1,5 @@
 pub mod error;
+pub mod graph_tiles;
 pub mod restapi;
 pub mod settings;
 pub mod webserver;
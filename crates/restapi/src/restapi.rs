@@ -1,15 +1,26 @@
 use crate::error::{ErrorResponse, RestApiResult};
 
+use factorio_bot_core::factorio::rcon_lanes::RconLaneMetrics;
+use factorio_bot_core::factorio::util::{blueprint_build_area, position_in_area};
+use factorio_bot_core::factorio_blueprint::BlueprintCodec;
+use factorio_bot_core::graph::flow_graph::EdgeThroughput;
+use factorio_bot_core::graph::power_graph::PowerReport;
 use factorio_bot_core::process::process_control::SharedFactorioInstance;
+use factorio_bot_core::process::server_registry::SharedFactorioServerRegistry;
 use factorio_bot_core::types::{
-    AreaFilter, Direction, FactorioEntity, FactorioEntityPrototype, FactorioItemPrototype,
-    FactorioPlayer, FactorioTile, InventoryResponse, PlaceEntityResult, PlayerId, Position,
-    RequestEntity,
+    AreaFilter, ChunkEntities, Direction, FactorioBlueprintInfo, FactorioEntity,
+    FactorioEntityPrototype, FactorioForce, FactorioItemPrototype, FactorioPlayer, FactorioRecipe,
+    FactorioTile, InventoryResponse, PlaceEntitiesResult, PlaceEntityResult, PlayerId, Position,
+    Rect, RequestEntity, SiteCandidate, SiteConstraints,
 };
 use num_traits::cast::FromPrimitive;
+use rocket::response::status::BadRequest;
 use rocket::serde::json::Json;
 use rocket::State;
+use serde_json::Value;
 use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
 use std::time::Duration;
 use tokio::time::sleep;
 
@@ -19,22 +30,56 @@ pub struct OperationResult {
     pub success: bool,
 }
 
+/// Resolves the instance of given server, the default server if none given
+fn server_instance(
+    servers: &SharedFactorioServerRegistry,
+    server: Option<String>,
+) -> Result<SharedFactorioInstance, BadRequest<Json<ErrorResponse>>> {
+    servers.instance(server.as_deref()).ok_or_else(|| {
+        ErrorResponse::new(format!("unknown server: {}", server.unwrap_or_default()), 3)
+    })
+}
+
+/// Parses a query parameter, `what` names it in the error
+fn parse_param<T: FromStr>(value: &str, what: &str) -> Result<T, BadRequest<Json<ErrorResponse>>> {
+    value
+        .parse()
+        .map_err(|_| ErrorResponse::new(format!("invalid {}", what), 1))
+}
+
+/// Error response for failed rcon commands, like missing items or players out of reach
+fn rcon_error(err: impl Display) -> BadRequest<Json<ErrorResponse>> {
+    ErrorResponse::new(format!("{}", err), 4)
+}
+
+/// Lists names of all registered servers
+#[openapi(tag = "Servers")]
+#[get("/servers")]
+pub async fn list_servers(
+    servers: &State<SharedFactorioServerRegistry>,
+) -> RestApiResult<Vec<String>> {
+    Ok(Json(servers.names()))
+}
+
 /// Finds entities in given area/radius
 #[openapi(tag = "Query")]
-#[get("/findEntities?<area>&<position>&<radius>&<name>&<entity_type>")]
+#[get("/findEntities?<area>&<position>&<radius>&<name>&<entity_type>&<surface>&<server>")]
+#[allow(clippy::too_many_arguments)]
 pub async fn find_entities(
     area: Option<String>,
     position: Option<String>,
     radius: Option<f64>,
     name: Option<String>,
     entity_type: Option<String>,
-    instance_state: &State<SharedFactorioInstance>,
+    surface: Option<String>,
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
 ) -> RestApiResult<Vec<FactorioEntity>> {
     let area_filter = match &area {
-        Some(area) => AreaFilter::Rect(area.parse().unwrap()),
+        Some(area) => AreaFilter::Rect(parse_param(area, "area")?),
         None => {
             if let Some(position) = &position {
-                AreaFilter::PositionRadius((position.parse().unwrap(), radius))
+                AreaFilter::PositionRadius((parse_param(position, "position")?, radius))
             } else {
                 return Err(ErrorResponse::new(
                     "area or position + optional radius needed".into(),
@@ -44,14 +89,15 @@ pub async fn find_entities(
         }
     };
 
+    let instance_state = server_instance(servers, server)?;
     let instance_state = instance_state.read().await;
     if let Some(instance_state) = &*instance_state {
         Ok(Json(
             instance_state
                 .rcon
-                .find_entities_filtered(&area_filter, name.clone(), entity_type.clone())
+                .find_entities_filtered(&area_filter, name.clone(), entity_type.clone(), surface)
                 .await
-                .unwrap(),
+                .map_err(rcon_error)?,
         ))
     } else {
         Err(ErrorResponse::new("not started".into(), 2))
@@ -60,10 +106,11 @@ pub async fn find_entities(
 
 /// Plan path from one position to another
 #[openapi(tag = "Query")]
-#[get("/planPath?<entity_name>&<entity_type>&<underground_entity_name>&<underground_entity_type>&<underground_max>&<from_position>&<to_position>&<to_direction>")]
+#[get("/planPath?<entity_name>&<entity_type>&<underground_entity_name>&<underground_entity_type>&<underground_max>&<from_position>&<to_position>&<to_direction>&<surface>&<server>")]
 #[allow(clippy::too_many_arguments)]
 pub async fn plan_path(
-    instance_state: &State<SharedFactorioInstance>,
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
     entity_name: String,
     entity_type: String,
     underground_entity_name: String,
@@ -72,7 +119,9 @@ pub async fn plan_path(
     from_position: String,
     to_position: String,
     to_direction: u8,
+    surface: Option<String>,
 ) -> RestApiResult<Vec<FactorioEntity>> {
+    let instance_state = server_instance(servers, server)?;
     let instance_state = instance_state.read().await;
     if let Some(instance_state) = &*instance_state {
         Ok(Json(
@@ -85,12 +134,14 @@ pub async fn plan_path(
                     &underground_entity_name,
                     &underground_entity_type,
                     underground_max,
-                    &from_position.parse().unwrap(),
-                    &to_position.parse().unwrap(),
-                    Direction::from_u8(to_direction).unwrap(),
+                    &parse_param(&from_position, "from position")?,
+                    &parse_param(&to_position, "to position")?,
+                    Direction::from_u8(to_direction)
+                        .ok_or_else(|| ErrorResponse::new("invalid direction".into(), 1))?,
+                    surface,
                 )
                 .await
-                .unwrap(),
+                .map_err(rcon_error)?,
         ))
     } else {
         Err(ErrorResponse::new("not started".into(), 2))
@@ -99,19 +150,22 @@ pub async fn plan_path(
 
 /// Finds tiles in given area/radius
 #[openapi(tag = "Query")]
-#[get("/findTiles?<area>&<position>&<radius>&<name>")]
+#[get("/findTiles?<area>&<position>&<radius>&<name>&<surface>&<server>")]
+#[allow(clippy::too_many_arguments)]
 pub async fn find_tiles(
-    instance_state: &State<SharedFactorioInstance>,
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
     area: Option<String>,
     position: Option<String>,
     radius: Option<f64>,
     name: Option<String>,
+    surface: Option<String>,
 ) -> RestApiResult<Vec<FactorioTile>> {
     let area_filter = match &area {
-        Some(area) => AreaFilter::Rect(area.parse().unwrap()),
+        Some(area) => AreaFilter::Rect(parse_param(area, "area")?),
         None => {
             if let Some(position) = &position {
-                AreaFilter::PositionRadius((position.parse().unwrap(), radius))
+                AreaFilter::PositionRadius((parse_param(position, "position")?, radius))
             } else {
                 return Err(ErrorResponse::new(
                     "area or position + optional radius needed".into(),
@@ -121,14 +175,15 @@ pub async fn find_tiles(
         }
     };
 
+    let instance_state = server_instance(servers, server)?;
     let instance_state = instance_state.read().await;
     if let Some(instance_state) = &*instance_state {
         Ok(Json(
             instance_state
                 .rcon
-                .find_tiles_filtered(&area_filter, name)
+                .find_tiles_filtered(&area_filter, name, surface)
                 .await
-                .unwrap(),
+                .map_err(rcon_error)?,
         ))
     } else {
         Err(ErrorResponse::new("not started".into(), 2))
@@ -137,31 +192,34 @@ pub async fn find_tiles(
 
 /// List inventory contents at position
 #[openapi(tag = "Query")]
-#[get("/inventoryContentsAt?<query>")]
+#[get("/inventoryContentsAt?<query>&<surface>&<server>")]
 pub async fn inventory_contents_at(
-    instance_state: &State<SharedFactorioInstance>,
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
     query: String,
+    surface: Option<String>,
 ) -> RestApiResult<Vec<Option<InventoryResponse>>> {
     let parts: Vec<&str> = query.split(';').collect();
     let entities: Vec<RequestEntity> = parts
         .iter()
-        .map(|part| {
-            let parts: Vec<&str> = part.split('@').collect();
-            RequestEntity {
-                name: String::from(parts[0]),
-                position: parts[1].parse().unwrap(),
-            }
+        .map(|part| match part.split_once('@') {
+            Some((name, position)) => Ok(RequestEntity {
+                name: String::from(name),
+                position: parse_param(position, "position")?,
+            }),
+            None => Err(ErrorResponse::new("invalid query".into(), 1)),
         })
-        .collect();
+        .collect::<Result<_, _>>()?;
 
+    let instance_state = server_instance(servers, server)?;
     let instance_state = instance_state.read().await;
     if let Some(instance_state) = &*instance_state {
         Ok(Json(
             instance_state
                 .rcon
-                .inventory_contents_at(entities)
+                .inventory_contents_at(entities, surface)
                 .await
-                .unwrap(),
+                .map_err(rcon_error)?,
         ))
     } else {
         Err(ErrorResponse::new("not started".into(), 2))
@@ -170,15 +228,17 @@ pub async fn inventory_contents_at(
 
 /// Move player to position
 #[openapi(tag = "Control")]
-#[get("/movePlayer?<player_id>&<goal>&<radius>")]
+#[get("/movePlayer?<player_id>&<goal>&<radius>&<server>")]
 pub async fn move_player(
-    instance_state: &State<SharedFactorioInstance>,
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
     player_id: PlayerId,
     goal: String,
     radius: Option<f64>,
 ) -> RestApiResult<FactorioPlayer> {
-    let goal: Position = goal.parse().unwrap();
+    let goal: Position = parse_param(&goal, "goal")?;
 
+    let instance_state = server_instance(servers, server)?;
     let instance_state = instance_state.read().await;
     if let Some(instance_state) = &*instance_state {
         let world = &instance_state.world.as_ref().unwrap().clone();
@@ -186,7 +246,7 @@ pub async fn move_player(
             .rcon
             .move_player(world, player_id, &goal, radius)
             .await
-            .unwrap();
+            .map_err(rcon_error)?;
 
         let player = world.players.get(&player_id);
         match player {
@@ -200,11 +260,13 @@ pub async fn move_player(
 
 /// Player Information
 #[openapi(tag = "Query")]
-#[get("/playerInfo?<player_id>")]
+#[get("/playerInfo?<player_id>&<server>")]
 pub async fn player_info(
-    instance_state: &State<SharedFactorioInstance>,
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
     player_id: PlayerId,
 ) -> RestApiResult<FactorioPlayer> {
+    let instance_state = server_instance(servers, server)?;
     let instance_state = instance_state.read().await;
     if let Some(instance_state) = &*instance_state {
         let player = instance_state
@@ -224,14 +286,18 @@ pub async fn player_info(
 
 /// Place entity by given player
 #[openapi(tag = "Place")]
-#[get("/placeEntity?<player_id>&<item>&<position>&<direction>")]
+#[get("/placeEntity?<player_id>&<item>&<position>&<direction>&<quality>&<server>")]
+#[allow(clippy::too_many_arguments)]
 pub async fn place_entity(
-    instance_state: &State<SharedFactorioInstance>,
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
     player_id: PlayerId,
     item: String,
     position: String,
     direction: u8,
+    quality: Option<String>,
 ) -> RestApiResult<PlaceEntityResult> {
+    let instance_state = server_instance(servers, server)?;
     let instance_state = instance_state.read().await;
     if let Some(instance_state) = &*instance_state {
         let world = &instance_state.world.as_ref().unwrap().clone();
@@ -240,12 +306,13 @@ pub async fn place_entity(
             .place_entity(
                 player_id,
                 item.clone(),
-                position.parse().unwrap(),
+                parse_param(&position, "position")?,
                 direction,
+                quality,
                 world,
             )
             .await
-            .unwrap();
+            .map_err(rcon_error)?;
         sleep(Duration::from_millis(50)).await;
         let player = world.players.get(&player_id);
         match player {
@@ -262,21 +329,24 @@ pub async fn place_entity(
 
 /// Cheat items and give them to player
 #[openapi(tag = "Cheat")]
-#[get("/cheatItem?<name>&<count>&<player_id>")]
+#[get("/cheatItem?<name>&<count>&<player_id>&<quality>&<server>")]
 pub async fn cheat_item(
-    instance_state: &State<SharedFactorioInstance>,
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
     name: String,
     count: u32,
     player_id: PlayerId,
+    quality: Option<String>,
 ) -> RestApiResult<FactorioPlayer> {
+    let instance_state = server_instance(servers, server)?;
     let instance_state = instance_state.read().await;
     if let Some(instance_state) = &*instance_state {
         let world = &instance_state.world.as_ref().unwrap().clone();
         instance_state
             .rcon
-            .cheat_item(player_id, &name, count)
+            .cheat_item(player_id, &name, count, quality)
             .await
-            .unwrap();
+            .map_err(rcon_error)?;
         sleep(Duration::from_millis(50)).await;
         let player = world.players.get(&player_id);
         match player {
@@ -290,14 +360,20 @@ pub async fn cheat_item(
 
 /// Cheat Technology
 #[openapi(tag = "Cheat")]
-#[get("/cheatTechnology?<tech>")]
+#[get("/cheatTechnology?<tech>&<server>")]
 pub async fn cheat_technology(
-    instance_state: &State<SharedFactorioInstance>,
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
     tech: String,
 ) -> RestApiResult<OperationResult> {
+    let instance_state = server_instance(servers, server)?;
     let instance_state = instance_state.read().await;
     if let Some(instance_state) = &*instance_state {
-        instance_state.rcon.cheat_technology(&tech).await.unwrap();
+        instance_state
+            .rcon
+            .cheat_technology(&tech)
+            .await
+            .map_err(rcon_error)?;
         Ok(Json(OperationResult { success: true }))
     } else {
         Err(ErrorResponse::new("not started".into(), 2))
@@ -306,13 +382,19 @@ pub async fn cheat_technology(
 
 /// Cheat Technology
 #[openapi(tag = "Cheat")]
-#[get("/cheatAllTechnologies")]
+#[get("/cheatAllTechnologies?<server>")]
 pub async fn cheat_all_technologies(
-    instance_state: &State<SharedFactorioInstance>,
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
 ) -> RestApiResult<OperationResult> {
+    let instance_state = server_instance(servers, server)?;
     let instance_state = instance_state.read().await;
     if let Some(instance_state) = &*instance_state {
-        instance_state.rcon.cheat_all_technologies().await.unwrap();
+        instance_state
+            .rcon
+            .cheat_all_technologies()
+            .await
+            .map_err(rcon_error)?;
         Ok(Json(OperationResult { success: true }))
     } else {
         Err(ErrorResponse::new("not started".into(), 2))
@@ -322,17 +404,21 @@ pub async fn cheat_all_technologies(
 /// Insert items into inventory
 #[openapi(tag = "Inventory")]
 #[get(
-    "/insertToInventory?<player_id>&<entity_name>&<entity_position>&<inventory_type>&<item_name>&<item_count>"
+    "/insertToInventory?<player_id>&<entity_name>&<entity_position>&<inventory_type>&<item_name>&<item_count>&<quality>&<server>"
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn insert_to_inventory(
-    instance_state: &State<SharedFactorioInstance>,
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
     player_id: PlayerId,
     entity_name: String,
     entity_position: String,
     inventory_type: u32,
     item_name: String,
     item_count: u32,
+    quality: Option<String>,
 ) -> RestApiResult<FactorioPlayer> {
+    let instance_state = server_instance(servers, server)?;
     let instance_state = instance_state.read().await;
     if let Some(instance_state) = &*instance_state {
         let world = &instance_state.world.as_ref().unwrap().clone();
@@ -341,14 +427,15 @@ pub async fn insert_to_inventory(
             .insert_to_inventory(
                 player_id,
                 entity_name.clone(),
-                entity_position.parse().unwrap(),
+                parse_param(&entity_position, "entity position")?,
                 inventory_type,
                 item_name.clone(),
                 item_count,
+                quality,
                 world,
             )
             .await
-            .unwrap();
+            .map_err(rcon_error)?;
         sleep(Duration::from_millis(50)).await;
         let player = world.players.get(&player_id);
         match player {
@@ -363,17 +450,21 @@ pub async fn insert_to_inventory(
 /// Remove items from inventory
 #[openapi(tag = "Inventory")]
 #[get(
-"/removeToInventory?<player_id>&<entity_name>&<entity_position>&<inventory_type>&<item_name>&<item_count>"
+"/removeToInventory?<player_id>&<entity_name>&<entity_position>&<inventory_type>&<item_name>&<item_count>&<quality>&<server>"
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn remove_from_inventory(
-    instance_state: &State<SharedFactorioInstance>,
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
     player_id: PlayerId,
     entity_name: String,
     entity_position: String,
     inventory_type: u32,
     item_name: String,
     item_count: u32,
+    quality: Option<String>,
 ) -> RestApiResult<FactorioPlayer> {
+    let instance_state = server_instance(servers, server)?;
     let instance_state = instance_state.read().await;
     if let Some(instance_state) = &*instance_state {
         let world = &instance_state.world.as_ref().unwrap().clone();
@@ -382,14 +473,15 @@ pub async fn remove_from_inventory(
             .remove_from_inventory(
                 player_id,
                 entity_name.clone(),
-                entity_position.parse().unwrap(),
+                parse_param(&entity_position, "entity position")?,
                 inventory_type,
                 item_name.clone(),
                 item_count,
+                quality,
                 world,
             )
             .await
-            .unwrap();
+            .map_err(rcon_error)?;
         sleep(Duration::from_millis(50)).await;
         let player = world.players.get(&player_id);
         match player {
@@ -403,10 +495,12 @@ pub async fn remove_from_inventory(
 
 /// List all connected Players
 #[openapi(tag = "Query")]
-#[get("/allPlayers")]
+#[get("/allPlayers?<server>")]
 pub async fn all_players(
-    instance_state: &State<SharedFactorioInstance>,
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
 ) -> RestApiResult<Vec<FactorioPlayer>> {
+    let instance_state = server_instance(servers, server)?;
     let instance_state = instance_state.read().await;
     if let Some(instance_state) = &*instance_state {
         let mut all_players: Vec<FactorioPlayer> = Vec::new();
@@ -420,12 +514,92 @@ pub async fn all_players(
     }
 }
 
+/// List names of all surfaces known to the world
+#[openapi(tag = "Query")]
+#[get("/surfaces?<server>")]
+pub async fn surfaces(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+) -> RestApiResult<Vec<String>> {
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        let world = &instance_state.world.as_ref().unwrap().clone();
+        Ok(Json(world.surface_names()))
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// Electric networks with expected supply and demand in watts, and all unpowered consumers
+#[openapi(tag = "Query")]
+#[get("/powerNetworks?<surface>&<server>")]
+pub async fn power_networks(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    surface: Option<String>,
+) -> RestApiResult<PowerReport> {
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        let world = &instance_state.world.as_ref().unwrap().clone();
+        let surface = world.surface(surface.as_deref());
+        surface
+            .power_graph
+            .update()
+            .map_err(|err| ErrorResponse::new(format!("{}", err), 4))?;
+        Ok(Json(surface.power_graph.report()))
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// Counts the items belts, splitters and inserters in area move during given seconds and
+/// returns the theoretical and measured items per minute of the flow graph edges starting in area
+#[openapi(tag = "Query")]
+#[get("/sampleThroughput?<area>&<seconds>&<surface>&<server>")]
+pub async fn sample_throughput(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    area: String,
+    seconds: u32,
+    surface: Option<String>,
+) -> RestApiResult<Vec<EdgeThroughput>> {
+    let area: Rect = match area.parse() {
+        Ok(area) => area,
+        Err(_) => return Err(ErrorResponse::new("invalid area".into(), 1)),
+    };
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        let world = instance_state.world.as_ref().unwrap().clone();
+        let sampling = instance_state
+            .rcon
+            .sample_throughput(&world, &area, seconds, surface)
+            .await
+            .map_err(rcon_error)?;
+        Ok(Json(
+            world
+                .surface(sampling.surface.as_deref())
+                .flow_graph
+                .throughput()
+                .into_iter()
+                .filter(|edge| position_in_area(&edge.source, &area))
+                .collect(),
+        ))
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
 /// List all ItemPrototypes
 #[openapi(tag = "Query")]
-#[get("/itemPrototypes")]
+#[get("/itemPrototypes?<server>")]
 pub async fn item_prototypes(
-    instance_state: &State<SharedFactorioInstance>,
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
 ) -> RestApiResult<HashMap<String, FactorioItemPrototype>> {
+    let instance_state = server_instance(servers, server)?;
     let instance_state = instance_state.read().await;
     if let Some(instance_state) = &*instance_state {
         let world = &instance_state.world.as_ref().unwrap().clone();
@@ -441,10 +615,12 @@ pub async fn item_prototypes(
 
 /// List all EntityPrototypes
 #[openapi(tag = "Query")]
-#[get("/entityPrototypes")]
+#[get("/entityPrototypes?<server>")]
 pub async fn entity_prototypes(
-    instance_state: &State<SharedFactorioInstance>,
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
 ) -> RestApiResult<HashMap<String, FactorioEntityPrototype>> {
+    let instance_state = server_instance(servers, server)?;
     let instance_state = instance_state.read().await;
     if let Some(instance_state) = &*instance_state {
         let world = &instance_state.world.as_ref().unwrap().clone();
@@ -460,314 +636,724 @@ pub async fn entity_prototypes(
 
 /// Server Save
 #[openapi(tag = "Admin")]
-#[get("/serverSave")]
+#[get("/serverSave?<server>")]
 pub async fn server_save(
-    instance_state: &State<SharedFactorioInstance>,
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
 ) -> RestApiResult<OperationResult> {
+    let instance_state = server_instance(servers, server)?;
     let instance_state = instance_state.read().await;
     if let Some(instance_state) = &*instance_state {
-        instance_state.rcon.server_save().await.unwrap();
+        instance_state
+            .rcon
+            .server_save()
+            .await
+            .map_err(rcon_error)?;
         Ok(Json(OperationResult { success: true }))
     } else {
         Err(ErrorResponse::new("not started".into(), 2))
     }
 }
 
+/// Queueing metrics of the rcon priority classes
+#[openapi(tag = "Admin")]
+#[get("/rconLanes?<server>")]
+pub async fn rcon_lanes(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+) -> RestApiResult<Vec<RconLaneMetrics>> {
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        Ok(Json(instance_state.rcon.lane_metrics()))
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
 /// Add Research to Queue
 #[openapi(tag = "Research")]
-#[get("/addResearch?<tech>")]
+#[get("/addResearch?<tech>&<server>")]
 pub async fn add_research(
-    instance_state: &State<SharedFactorioInstance>,
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
     tech: String,
 ) -> RestApiResult<OperationResult> {
+    let instance_state = server_instance(servers, server)?;
     let instance_state = instance_state.read().await;
     if let Some(instance_state) = &*instance_state {
-        instance_state.rcon.add_research(&tech).await.unwrap();
+        instance_state
+            .rcon
+            .add_research(&tech)
+            .await
+            .map_err(rcon_error)?;
+        Ok(Json(OperationResult { success: true }))
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// Store arbitrary json data in the map (persists in savegame)
+#[openapi(tag = "Admin")]
+#[post(
+    "/storeMapData?<key>&<server>",
+    format = "application/json",
+    data = "<value>"
+)]
+pub async fn store_map_data(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    key: String,
+    value: Json<Value>,
+) -> RestApiResult<OperationResult> {
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        instance_state
+            .rcon
+            .store_map_data(&key, value.into_inner())
+            .await
+            .map_err(rcon_error)?;
+        Ok(Json(OperationResult { success: true }))
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// Evaluate lua snippet on the server and return its result as json.
+/// The snippet is run as function body, so it needs to `return` something.
+#[openapi(tag = "Admin")]
+#[post("/eval?<server>", data = "<lua_snippet>")]
+pub async fn eval(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    lua_snippet: String,
+) -> RestApiResult<Value> {
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        match instance_state.rcon.eval(&lua_snippet).await {
+            Ok(result) => Ok(Json(result)),
+            Err(err) => Err(ErrorResponse::new(format!("{}", err), 4)),
+        }
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// Retrieve json data stored in the map, null if missing
+#[openapi(tag = "Admin")]
+#[get("/retrieveMapData?<key>&<server>")]
+pub async fn retrieve_map_data(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    key: String,
+) -> RestApiResult<Value> {
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        let result = instance_state
+            .rcon
+            .retrieve_map_data(&key)
+            .await
+            .map_err(rcon_error)?;
+        Ok(Json(result.unwrap_or(Value::Null)))
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// Place blueprint by given player, optionally taking items from other players
+#[openapi(tag = "Place")]
+#[get("/placeBlueprint?<player_id>&<blueprint>&<position>&<direction>&<force_build>&<only_ghosts>&<inventory_player_ids>&<server>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn place_blueprint(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    player_id: PlayerId,
+    blueprint: String,
+    position: String,
+    direction: Option<u8>,
+    force_build: Option<bool>,
+    only_ghosts: Option<bool>,
+    inventory_player_ids: Option<String>,
+) -> RestApiResult<PlaceEntitiesResult> {
+    let inventory_player_ids: Vec<PlayerId> = match inventory_player_ids {
+        Some(inventory_player_ids) => inventory_player_ids
+            .split(',')
+            .map(|id| parse_param(id, "player id"))
+            .collect::<Result<_, _>>()?,
+        None => vec![],
+    };
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        let world = &instance_state.world.as_ref().unwrap().clone();
+        let entities = instance_state
+            .rcon
+            .place_blueprint(
+                player_id,
+                blueprint,
+                &parse_param(&position, "position")?,
+                direction.unwrap_or(0),
+                force_build.unwrap_or(false),
+                only_ghosts.unwrap_or(false),
+                inventory_player_ids,
+                world,
+            )
+            .await
+            .map_err(rcon_error)?;
+        sleep(Duration::from_millis(50)).await;
+        let player = world.players.get(&player_id);
+        match player {
+            Some(player) => Ok(Json(PlaceEntitiesResult {
+                player: player.clone(),
+                entities,
+            })),
+            None => Err(ErrorResponse::new("player not found".into(), 2)),
+        }
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// Revive ghost entity by given player
+#[openapi(tag = "Place")]
+#[get("/reviveGhost?<player_id>&<name>&<position>&<server>")]
+pub async fn revive_ghost(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    player_id: PlayerId,
+    name: String,
+    position: String,
+) -> RestApiResult<PlaceEntityResult> {
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        let world = &instance_state.world.as_ref().unwrap().clone();
+        let entity = instance_state
+            .rcon
+            .revive_ghost(
+                player_id,
+                &name,
+                &parse_param(&position, "position")?,
+                world,
+            )
+            .await
+            .map_err(rcon_error)?;
+        sleep(Duration::from_millis(50)).await;
+        let player = world.players.get(&player_id);
+        match player {
+            Some(player) => Ok(Json(PlaceEntityResult {
+                player: player.clone(),
+                entity,
+            })),
+            None => Err(ErrorResponse::new("player not found".into(), 2)),
+        }
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// Cheat blueprint entities into the world without using items
+#[openapi(tag = "Cheat")]
+#[get("/cheatBlueprint?<player_id>&<blueprint>&<position>&<direction>&<force_build>&<server>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn cheat_blueprint(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    player_id: PlayerId,
+    blueprint: String,
+    position: String,
+    direction: Option<u8>,
+    force_build: Option<bool>,
+) -> RestApiResult<PlaceEntitiesResult> {
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        let world = &instance_state.world.as_ref().unwrap().clone();
+        let entities = instance_state
+            .rcon
+            .cheat_blueprint(
+                player_id,
+                blueprint,
+                &parse_param(&position, "position")?,
+                direction.unwrap_or(0),
+                force_build.unwrap_or(false),
+            )
+            .await
+            .map_err(rcon_error)?;
+        sleep(Duration::from_millis(50)).await;
+        let player = world.players.get(&player_id);
+        match player {
+            Some(player) => Ok(Json(PlaceEntitiesResult {
+                player: player.clone(),
+                entities,
+            })),
+            None => Err(ErrorResponse::new("player not found".into(), 2)),
+        }
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// Parse blueprint string and calculate its build area
+#[openapi(tag = "Query")]
+#[get("/parseBlueprint?<blueprint>&<label>&<server>")]
+pub async fn parse_blueprint(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    blueprint: String,
+    label: Option<String>,
+) -> RestApiResult<FactorioBlueprintInfo> {
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        let world = &instance_state.world.as_ref().unwrap().clone();
+        let decoded = match BlueprintCodec::decode_string(&blueprint) {
+            Ok(decoded) => decoded,
+            Err(_) => return Err(ErrorResponse::new("invalid blueprint".into(), 1)),
+        };
+        let rect = blueprint_build_area(world.entity_prototypes.clone(), &blueprint)
+            .map_err(|err| ErrorResponse::new(format!("{}", err), 1))?;
+        Ok(Json(FactorioBlueprintInfo {
+            rect: rect.clone(),
+            label: label.unwrap_or_default(),
+            blueprint,
+            width: rect.width() as u16,
+            height: rect.height() as u16,
+            data: serde_json::to_value(decoded).map_err(rcon_error)?,
+        }))
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// List all Recipes
+#[openapi(tag = "Query")]
+#[get("/recipes?<server>")]
+pub async fn all_recipes(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+) -> RestApiResult<HashMap<String, FactorioRecipe>> {
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        let world = &instance_state.world.as_ref().unwrap().clone();
+        let mut data: HashMap<String, FactorioRecipe> = HashMap::new();
+        for recipe in world.recipes.iter() {
+            data.insert(recipe.name.clone(), recipe.clone());
+        }
+        Ok(Json(data))
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// Fetch the player force including technologies from the server
+#[openapi(tag = "Query")]
+#[get("/playerForce?<server>")]
+pub async fn player_force(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+) -> RestApiResult<FactorioForce> {
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        Ok(Json(
+            instance_state
+                .rcon
+                .player_force()
+                .await
+                .map_err(rcon_error)?,
+        ))
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// List all known Forces
+#[openapi(tag = "Query")]
+#[get("/allForces?<server>")]
+pub async fn all_forces(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+) -> RestApiResult<Vec<FactorioForce>> {
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        let world = &instance_state.world.as_ref().unwrap().clone();
+        let mut forces: Vec<FactorioForce> = vec![];
+        for force in world.forces.iter() {
+            forces.push(force.clone());
+        }
+        Ok(Json(forces))
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// Mine entity at position with player
+#[openapi(tag = "Control")]
+#[get("/mine?<player_id>&<name>&<position>&<count>&<server>")]
+pub async fn mine(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    player_id: PlayerId,
+    name: String,
+    position: String,
+    count: u32,
+) -> RestApiResult<FactorioPlayer> {
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        let world = &instance_state.world.as_ref().unwrap().clone();
+        instance_state
+            .rcon
+            .player_mine(
+                world,
+                player_id,
+                &name,
+                &parse_param(&position, "position")?,
+                count,
+            )
+            .await
+            .map_err(rcon_error)?;
+        sleep(Duration::from_millis(50)).await;
+        let player = world.players.get(&player_id);
+        match player {
+            Some(player) => Ok(Json(player.clone())),
+            None => Err(ErrorResponse::new("player not found".into(), 2)),
+        }
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// Craft recipe with player
+#[openapi(tag = "Control")]
+#[get("/craft?<player_id>&<recipe>&<count>&<server>")]
+pub async fn craft(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    player_id: PlayerId,
+    recipe: String,
+    count: u32,
+) -> RestApiResult<FactorioPlayer> {
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        let world = &instance_state.world.as_ref().unwrap().clone();
+        instance_state
+            .rcon
+            .player_craft(world, player_id, &recipe, count)
+            .await
+            .map_err(rcon_error)?;
+        sleep(Duration::from_millis(50)).await;
+        let player = world.players.get(&player_id);
+        match player {
+            Some(player) => Ok(Json(player.clone())),
+            None => Err(ErrorResponse::new("player not found".into(), 2)),
+        }
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// Find positions near search center where an offshore pump can be placed
+#[openapi(tag = "Query")]
+#[get("/findOffshorePumpPlacementOptions?<search_center>&<pump_direction>&<surface>&<server>")]
+pub async fn find_offshore_pump_placement_options(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    search_center: String,
+    pump_direction: u8,
+    surface: Option<String>,
+) -> RestApiResult<Vec<Position>> {
+    let pump_direction = match Direction::from_u8(pump_direction) {
+        Some(pump_direction) => pump_direction,
+        None => return Err(ErrorResponse::new("invalid direction".into(), 1)),
+    };
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        let world = &instance_state.world.as_ref().unwrap().clone();
+        Ok(Json(
+            instance_state
+                .rcon
+                .find_offshore_pump_placement_options(
+                    world,
+                    parse_param(&search_center, "search center")?,
+                    pump_direction,
+                    surface,
+                )
+                .await
+                .map_err(rcon_error)?
+                .iter()
+                .map(|pos| pos.into())
+                .collect(),
+        ))
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// Find ranked sites for a build area of given size or the build area of a blueprint
+///
+/// Positions of blueprint sites are the blueprint origin, `rotations` only applies to sizes
+#[openapi(tag = "Query")]
+#[get("/findSites?<width>&<height>&<blueprint>&<resource>&<min_resource_tiles>&<max_water_distance>&<allow_trees>&<near>&<max_distance>&<rotations>&<limit>&<surface>&<server>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn find_sites(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    blueprint: Option<String>,
+    resource: Option<String>,
+    min_resource_tiles: Option<u32>,
+    max_water_distance: Option<f64>,
+    allow_trees: Option<bool>,
+    near: Option<String>,
+    max_distance: Option<f64>,
+    rotations: Option<bool>,
+    limit: Option<usize>,
+    surface: Option<String>,
+) -> RestApiResult<Vec<SiteCandidate>> {
+    let near = match near.map(|near| near.parse::<Position>()).transpose() {
+        Ok(near) => near,
+        Err(_) => return Err(ErrorResponse::new("invalid near position".into(), 1)),
+    };
+    let constraints = SiteConstraints {
+        resource,
+        min_resource_tiles: min_resource_tiles.unwrap_or_default(),
+        max_water_distance,
+        allow_trees: allow_trees.unwrap_or_default(),
+        near,
+        max_distance,
+        rotations: rotations.unwrap_or_default(),
+    };
+    let limit = limit.unwrap_or(10);
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        let world = &instance_state.world.as_ref().unwrap().clone();
+        match (blueprint, width, height) {
+            (Some(blueprint), _, _) => world
+                .find_blueprint_sites(surface.as_deref(), &blueprint, &constraints, limit)
+                .map(Json)
+                .map_err(|err| ErrorResponse::new(format!("{}", err), 1)),
+            (None, Some(width), Some(height)) => Ok(Json(world.find_sites(
+                surface.as_deref(),
+                width,
+                height,
+                &constraints,
+                limit,
+            ))),
+            _ => Err(ErrorResponse::new(
+                "blueprint or width + height needed".into(),
+                1,
+            )),
+        }
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// Find the entities or resource tiles nearest to a position, nearest first
+#[openapi(tag = "Query")]
+#[get("/nearest?<position>&<name>&<entity_type>&<n>&<surface>&<server>")]
+pub async fn nearest(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    position: String,
+    name: Option<String>,
+    entity_type: Option<String>,
+    n: Option<usize>,
+    surface: Option<String>,
+) -> RestApiResult<Vec<FactorioEntity>> {
+    let position = match position.parse::<Position>() {
+        Ok(position) => position,
+        Err(_) => return Err(ErrorResponse::new("invalid position".into(), 1)),
+    };
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        let world = &instance_state.world.as_ref().unwrap().clone();
+        Ok(Json(world.nearest(
+            surface.as_deref(),
+            &position,
+            n.unwrap_or(1),
+            name.as_deref(),
+            entity_type.as_deref(),
+        )))
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// Checks if no building, tree, rock or water blocks the straight line between two positions
+#[openapi(tag = "Query")]
+#[get("/lineOfSight?<from>&<to>&<surface>&<server>")]
+pub async fn line_of_sight(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    from: String,
+    to: String,
+    surface: Option<String>,
+) -> RestApiResult<bool> {
+    let (from, to) = match (from.parse::<Position>(), to.parse::<Position>()) {
+        (Ok(from), Ok(to)) => (from, to),
+        _ => return Err(ErrorResponse::new("invalid position".into(), 1)),
+    };
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        let world = &instance_state.world.as_ref().unwrap().clone();
+        Ok(Json(world.line_of_sight(surface.as_deref(), &from, &to)))
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// Find the entities crossed by the straight line between two positions, in order along it
+#[openapi(tag = "Query")]
+#[get("/entitiesAlong?<from>&<to>&<surface>&<server>")]
+pub async fn entities_along(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    from: String,
+    to: String,
+    surface: Option<String>,
+) -> RestApiResult<Vec<FactorioEntity>> {
+    let (from, to) = match (from.parse::<Position>(), to.parse::<Position>()) {
+        (Ok(from), Ok(to)) => (from, to),
+        _ => return Err(ErrorResponse::new("invalid position".into(), 1)),
+    };
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        let world = &instance_state.world.as_ref().unwrap().clone();
+        Ok(Json(world.entities_along(surface.as_deref(), &from, &to)))
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// List entities by chunk for the chunk containing a position and `radius` rings around it
+#[openapi(tag = "Query")]
+#[get("/chunkRings?<position>&<radius>&<surface>&<server>")]
+pub async fn chunk_rings(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    position: String,
+    radius: Option<u32>,
+    surface: Option<String>,
+) -> RestApiResult<Vec<ChunkEntities>> {
+    let position = match position.parse::<Position>() {
+        Ok(position) => position,
+        Err(_) => return Err(ErrorResponse::new("invalid position".into(), 1)),
+    };
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        let world = &instance_state.world.as_ref().unwrap().clone();
+        Ok(Json(world.chunk_rings(
+            surface.as_deref(),
+            &position,
+            radius.unwrap_or_default(),
+        )))
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// Checks if given area/radius has no entities and no player collidable tiles
+#[openapi(tag = "Query")]
+#[get("/isAreaEmpty?<area>&<position>&<radius>&<surface>&<server>")]
+pub async fn is_area_empty(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    area: Option<String>,
+    position: Option<String>,
+    radius: Option<f64>,
+    surface: Option<String>,
+) -> RestApiResult<bool> {
+    let area_filter = match &area {
+        Some(area) => AreaFilter::Rect(parse_param(area, "area")?),
+        None => {
+            if let Some(position) = &position {
+                AreaFilter::PositionRadius((parse_param(position, "position")?, radius))
+            } else {
+                return Err(ErrorResponse::new(
+                    "area or position + optional radius needed".into(),
+                    1,
+                ));
+            }
+        }
+    };
+
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        Ok(Json(
+            instance_state
+                .rcon
+                .is_area_empty(&area_filter, surface)
+                .await
+                .map_err(rcon_error)?,
+        ))
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// Parse map exchange string and store map gen settings under given name
+#[openapi(tag = "Admin")]
+#[get("/parseMapExchangeString?<name>&<map_exchange_string>&<server>")]
+pub async fn parse_map_exchange_string(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    name: String,
+    map_exchange_string: String,
+) -> RestApiResult<OperationResult> {
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        instance_state
+            .rcon
+            .parse_map_exchange_string(&name, &map_exchange_string)
+            .await
+            .map_err(rcon_error)?;
+        Ok(Json(OperationResult { success: true }))
+    } else {
+        Err(ErrorResponse::new("not started".into(), 2))
+    }
+}
+
+/// Take a screenshot on the server, written to its script-output folder
+#[openapi(tag = "Admin")]
+#[get("/screenshot?<width>&<height>&<depth>&<server>")]
+pub async fn screenshot(
+    servers: &State<SharedFactorioServerRegistry>,
+    server: Option<String>,
+    width: i16,
+    height: i16,
+    depth: i8,
+) -> RestApiResult<OperationResult> {
+    let instance_state = server_instance(servers, server)?;
+    let instance_state = instance_state.read().await;
+    if let Some(instance_state) = &*instance_state {
+        instance_state
+            .rcon
+            .screenshot(width, height, depth)
+            .await
+            .map_err(rcon_error)?;
         Ok(Json(OperationResult { success: true }))
     } else {
         Err(ErrorResponse::new("not started".into(), 2))
     }
 }
-//
-// #[derive(Deserialize)]
-// #[serde(rename_all = "camelCase")]
-// pub struct StoreMapDataQueryParams {
-//     key: String,
-// }
-//
-// // #[post("/storeMapData?<key>", format = "application/json", data = "<value>")]
-// pub async fn store_map_data(
-//     rcon: web::Data<Arc<FactorioRcon>>,
-//     data: Json<Value>,
-//     info: actix_web::web::Query<StoreMapDataQueryParams>,
-// ) -> RestApiResult<OperationResult> {
-//     rcon.store_map_data(&info.key, data.into_inner()).await?;
-//     Ok(Json(json!({"status": "ok"})))
-// }
-// // #[get("/retrieveMapData?<key>")]
-// pub async fn retrieve_map_data(
-//     rcon: web::Data<Arc<FactorioRcon>>,
-//     info: actix_web::web::Query<StoreMapDataQueryParams>,
-// ) -> Result<Json<Value>, ActixAnyhowError> {
-//     let res = rcon.retrieve_map_data(&info.key).await?;
-//     match res {
-//         Some(result) => Ok(Json(result)),
-//         None => Ok(Json(json!(null))),
-//     }
-// }
-//
-// #[derive(Deserialize)]
-// #[serde(rename_all = "camelCase")]
-// pub struct PlaceBlueprintQueryParams {
-//     blueprint: String,
-//     position: String,
-//     direction: Option<u8>,
-//     force_build: Option<bool>,
-//     only_ghosts: Option<bool>,
-//     inventory_player_ids: Option<String>,
-// }
-// // #[get("/<player_id>/placeBlueprint?<position>&<direction>&<force_build>&<blueprint>&<only_ghosts>")]
-// // #[allow(clippy::too_many_arguments)]
-// pub async fn place_blueprint(
-//     world: web::Data<Arc<FactorioWorld>>,
-//     rcon: web::Data<Arc<FactorioRcon>>,
-//     path: PathInfo<u32>,
-//     info: actix_web::web::Query<PlaceBlueprintQueryParams>,
-// ) -> Result<Json<PlaceEntitiesResult>, ActixAnyhowError> {
-//     let player_id = *path;
-//     let inventory_player_ids: Vec<u32> = match info.inventory_player_ids.as_ref() {
-//         Some(inventory_player_ids) => inventory_player_ids
-//             .split(',')
-//             .map(|id| id.parse().unwrap())
-//             .collect(),
-//         None => vec![],
-//     };
-//     let entities = rcon
-//         .place_blueprint(
-//             player_id,
-//             info.blueprint.clone(),
-//             &info.position.parse()?,
-//             info.direction.unwrap_or(0),
-//             info.force_build.unwrap_or(false),
-//             info.only_ghosts.unwrap_or(false),
-//             inventory_player_ids,
-//             &world,
-//         )
-//         .await?;
-//     async_std::task::sleep(Duration::from_millis(50)).await;
-//     let player = world.players.get(&player_id);
-//     match player {
-//         Some(player) => Ok(Json(PlaceEntitiesResult {
-//             player: player.clone(),
-//             entities,
-//         })),
-//         None => Err(ActixAnyhowError::from(anyhow!("player not found"))),
-//     }
-// }
-//
-// #[derive(Deserialize)]
-// #[serde(rename_all = "camelCase")]
-// pub struct ReviveGhostQueryParams {
-//     name: String,
-//     position: String,
-// }
-// // #[get("/<player_id>/reviveGhost?<position>&<name>")]
-// // #[allow(clippy::too_many_arguments)]
-// pub async fn revive_ghost(
-//     info: actix_web::web::Query<ReviveGhostQueryParams>,
-//     path: PathInfo<u32>,
-//     world: web::Data<Arc<FactorioWorld>>,
-//     rcon: web::Data<Arc<FactorioRcon>>,
-// ) -> Result<Json<PlaceEntityResult>, ActixAnyhowError> {
-//     let player_id = *path;
-//     let entity = rcon
-//         .revive_ghost(player_id, &info.name, &info.position.parse()?, &world)
-//         .await?;
-//     async_std::task::sleep(Duration::from_millis(50)).await;
-//     let player = world.players.get(&player_id);
-//     match player {
-//         Some(player) => Ok(Json(PlaceEntityResult {
-//             player: player.clone(),
-//             entity,
-//         })),
-//         None => Err(ActixAnyhowError::from(anyhow!("player not found"))),
-//     }
-// }
-//
-// #[derive(Deserialize)]
-// #[serde(rename_all = "camelCase")]
-// pub struct CheatBlueprintQueryParams {
-//     blueprint: String,
-//     position: String,
-//     direction: Option<u8>,
-//     force_build: Option<bool>,
-// }
-// // #[get("/<player_id>/cheatBlueprint?<position>&<direction>&<force_build>&<blueprint>")]
-// pub async fn cheat_blueprint(
-//     world: web::Data<Arc<FactorioWorld>>,
-//     rcon: web::Data<Arc<FactorioRcon>>,
-//     info: actix_web::web::Query<CheatBlueprintQueryParams>,
-//     path: PathInfo<u32>,
-// ) -> Result<Json<PlaceEntitiesResult>, ActixAnyhowError> {
-//     let player_id = *path;
-//     let entities = rcon
-//         .cheat_blueprint(
-//             player_id,
-//             info.blueprint.clone(),
-//             &info.position.parse()?,
-//             info.direction.unwrap_or(0),
-//             info.force_build.unwrap_or(false),
-//         )
-//         .await?;
-//     async_std::task::sleep(Duration::from_millis(50)).await;
-//     let player = world.players.get(&player_id);
-//     match player {
-//         Some(player) => Ok(Json(PlaceEntitiesResult {
-//             player: player.clone(),
-//             entities,
-//         })),
-//         None => Err(ActixAnyhowError::from(anyhow!("player not found"))),
-//     }
-// }
-//
-// #[derive(Deserialize)]
-// #[serde(rename_all = "camelCase")]
-// pub struct ParseBlueprintQueryParams {
-//     label: String,
-//     blueprint: String,
-// }
-//
-// // #[get("/parseBlueprint?<blueprint>")]
-// pub async fn parse_blueprint(
-//     world: web::Data<Arc<FactorioWorld>>,
-//     info: actix_web::web::Query<ParseBlueprintQueryParams>,
-// ) -> Result<Json<FactorioBlueprintInfo>, ActixAnyhowError> {
-//     let decoded =
-//         BlueprintCodec::decode_string(&info.blueprint).expect("failed to parse blueprint");
-//     let rect = blueprint_build_area(world.entity_prototypes.clone(), &info.blueprint);
-//     let response = FactorioBlueprintInfo {
-//         rect: rect.clone(),
-//         label: info.label.clone(),
-//         blueprint: info.blueprint.clone(),
-//         width: rect.width() as u16,
-//         height: rect.height() as u16,
-//         data: serde_json::to_value(decoded).unwrap(),
-//     };
-//     Ok(Json(response))
-// }
-//
-// // #[get("/recipes")]
-// pub async fn all_recipes(
-//     world: web::Data<Arc<FactorioWorld>>,
-// ) -> Result<Json<HashMap<String, FactorioRecipe>>, ActixAnyhowError> {
-//     let mut map: HashMap<String, FactorioRecipe> = HashMap::new();
-//     for recipe in world.recipes.iter() {
-//         map.insert(recipe.name.clone(), recipe.clone());
-//     }
-//     Ok(Json(map))
-// }
-// // #[get("/playerForce")]
-// pub async fn player_force(
-//     world: web::Data<Arc<FactorioWorld>>,
-// ) -> Result<Json<FactorioForce>, ActixAnyhowError> {
-//     Ok(Json(
-//         world
-//             .forces
-//             .get("player")
-//             .expect("player force not found")
-//             .clone(),
-//     ))
-// }
-// pub async fn all_forces(
-//     world: web::Data<Arc<FactorioWorld>>,
-// ) -> Result<Json<Vec<FactorioForce>>, ActixAnyhowError> {
-//     let mut forces: Vec<FactorioForce> = vec![];
-//     for force in world.forces.iter() {
-//         forces.push(force.clone());
-//     }
-//     Ok(Json(forces))
-// }
-//
-// // #[get("/<player_id>/mine?<name>&<position>&<count>")]
-//
-// #[derive(Deserialize)]
-// #[serde(rename_all = "camelCase")]
-// pub struct MineQueryParams {
-//     name: String,
-//     position: String,
-//     count: u32,
-// }
-// pub async fn mine(
-//     info: actix_web::web::Query<MineQueryParams>,
-//     path: PathInfo<u32>,
-//     rcon: web::Data<Arc<FactorioRcon>>,
-//     world: web::Data<Arc<FactorioWorld>>,
-// ) -> Result<Json<FactorioPlayer>, ActixAnyhowError> {
-//     let player_id = *path;
-//     rcon.player_mine(
-//         &world,
-//         player_id,
-//         &info.name,
-//         &info.position.parse()?,
-//         info.count,
-//     )
-//     .await?;
-//     async_std::task::sleep(Duration::from_millis(50)).await;
-//     let player = world.players.get(&player_id);
-//     match player {
-//         Some(player) => Ok(Json(player.clone())),
-//         None => Err(ActixAnyhowError::from(anyhow!("player not found"))),
-//     }
-// }
-//
-// // #[get("/<player_id>/craft?<recipe>&<count>")]
-//
-// #[derive(Deserialize)]
-// #[serde(rename_all = "camelCase")]
-// pub struct CraftQueryParams {
-//     recipe: String,
-//     count: u32,
-// }
-// pub async fn craft(
-//     info: actix_web::web::Query<CraftQueryParams>,
-//     path: PathInfo<u32>,
-//     rcon: web::Data<Arc<FactorioRcon>>,
-//     world: web::Data<Arc<FactorioWorld>>,
-// ) -> Result<Json<FactorioPlayer>, ActixAnyhowError> {
-//     let player_id = *path;
-//     rcon.player_craft(&world, player_id, &info.recipe, info.count)
-//         .await?;
-//     async_std::task::sleep(Duration::from_millis(50)).await;
-//     let player = world.players.get(&player_id);
-//     match player {
-//         Some(player) => Ok(Json(player.clone())),
-//         None => Err(ActixAnyhowError::from(anyhow!("player not found"))),
-//     }
-// }
-//
-// #[derive(Deserialize)]
-// #[serde(rename_all = "camelCase")]
-// pub struct FindOffshorePumpPlacementOptionsQueryParams {
-//     search_center: String,
-//     pump_direction: u8,
-// }
-// pub async fn find_offshore_pump_placement_options(
-//     info: actix_web::web::Query<FindOffshorePumpPlacementOptionsQueryParams>,
-//     rcon: web::Data<Arc<FactorioRcon>>,
-//     world: web::Data<Arc<FactorioWorld>>,
-// ) -> Result<Json<Vec<Position>>, ActixAnyhowError> {
-//     Ok(Json(
-//         rcon.find_offshore_pump_placement_options(
-//             &world,
-//             info.search_center.parse()?,
-//             Direction::from_u8(info.pump_direction).expect("invalid direction"),
-//         )
-//         .await?
-//         .iter()
-//         .map(|pos| pos.into())
-//         .collect(),
-//     ))
-// }
 //
 // #[derive(Deserialize)]
 // #[serde(rename_all = "camelCase")]
@@ -856,3 +1442,46 @@ pub async fn add_research(
 //     let dot = world.flow_graph.graphviz_dot_condensed();
 //     Ok(dot)
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use factorio_bot_core::process::server_registry::FactorioServerRegistry;
+    use rocket::http::Status;
+    use rocket::local::asynchronous::Client;
+
+    async fn client() -> Client {
+        let rocket = rocket::build()
+            .manage(FactorioServerRegistry::new_shared())
+            .mount("/api", rocket::routes![find_entities, move_player]);
+        Client::tracked(rocket).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_invalid_queries_are_bad_requests() {
+        let client = client().await;
+
+        let response = client
+            .get("/api/findEntities?position=north&radius=5")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+        let error: Value = response.into_json().await.unwrap();
+        assert_eq!(error["message"], "invalid position");
+
+        let response = client
+            .get("/api/movePlayer?player_id=1&goal=1,2")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+        let error: Value = response.into_json().await.unwrap();
+        assert_eq!(error["message"], "not started");
+
+        let response = client
+            .get("/api/movePlayer?player_id=1&goal=1,2&server=other")
+            .dispatch()
+            .await;
+        let error: Value = response.into_json().await.unwrap();
+        assert_eq!(error["code"], 3);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::settings::RestApiSettings;
-use factorio_bot_core::process::process_control::SharedFactorioInstance;
+use factorio_bot_core::process::server_registry::SharedFactorioServerRegistry;
 use miette::{IntoDiagnostic, Result};
 use rocket::data::{Limits, ToByteUnit};
 use rocket::http::Status;
@@ -28,23 +28,67 @@ fn general_not_found() -> String {
 //     RawHtml(include_str!("rapidoc.html"))
 // }
 
-pub async fn start(
-    settings: RestApiSettings,
-    instance_state: SharedFactorioInstance,
-) -> Result<()> {
+pub async fn start(settings: RestApiSettings, servers: SharedFactorioServerRegistry) -> Result<()> {
     let port = settings.port;
     let figment = rocket::Config::figment()
         .merge(("port", port))
         .merge(("limits", Limits::new().limit("json", 2.mebibytes())));
     let _rocket = rocket::custom(figment)
         .manage(Arc::new(RwLock::new(settings)))
-        .manage(instance_state)
+        .manage(servers)
         // .mount("/", rocket::routes![index])
         .mount(
             "/",
             rocket_okapi::openapi_get_routes![
+                crate::restapi::list_servers,
                 crate::restapi::find_entities,
-                crate::restapi::plan_path
+                crate::restapi::plan_path,
+                crate::restapi::find_tiles,
+                crate::restapi::inventory_contents_at,
+                crate::restapi::move_player,
+                crate::restapi::player_info,
+                crate::restapi::place_entity,
+                crate::restapi::cheat_item,
+                crate::restapi::cheat_technology,
+                crate::restapi::cheat_all_technologies,
+                crate::restapi::insert_to_inventory,
+                crate::restapi::remove_from_inventory,
+                crate::restapi::all_players,
+                crate::restapi::surfaces,
+                crate::restapi::power_networks,
+                crate::restapi::sample_throughput,
+                crate::restapi::item_prototypes,
+                crate::restapi::entity_prototypes,
+                crate::restapi::server_save,
+                crate::restapi::rcon_lanes,
+                crate::restapi::add_research,
+                crate::restapi::store_map_data,
+                crate::restapi::retrieve_map_data,
+                crate::restapi::eval,
+                crate::restapi::place_blueprint,
+                crate::restapi::revive_ghost,
+                crate::restapi::cheat_blueprint,
+                crate::restapi::parse_blueprint,
+                crate::restapi::all_recipes,
+                crate::restapi::player_force,
+                crate::restapi::all_forces,
+                crate::restapi::mine,
+                crate::restapi::craft,
+                crate::restapi::find_offshore_pump_placement_options,
+                crate::restapi::is_area_empty,
+                crate::restapi::find_sites,
+                crate::restapi::nearest,
+                crate::restapi::line_of_sight,
+                crate::restapi::entities_along,
+                crate::restapi::chunk_rings,
+                crate::restapi::parse_map_exchange_string,
+                crate::restapi::screenshot,
+                crate::graph_tiles::map_tiles,
+                crate::graph_tiles::entity_graph_tiles,
+                crate::graph_tiles::blocked_tiles,
+                crate::graph_tiles::resource_tiles,
+                crate::graph_tiles::flow_graph_tiles,
+                crate::graph_tiles::power_graph_tiles,
             ],
         )
         .mount(
@@ -3,4 +3,5 @@ mod globals;
 pub use globals::create_lua_globals;
 pub(crate) mod plan;
 pub(crate) mod rcon;
+pub(crate) mod servers;
 pub(crate) mod world;
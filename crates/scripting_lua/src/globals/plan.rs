@@ -3,10 +3,39 @@ use factorio_bot_core::graph::task_graph::TaskGraph;
 use factorio_bot_core::mlua::prelude::*;
 use factorio_bot_core::num_traits::FromPrimitive;
 use factorio_bot_core::parking_lot::RwLock;
+use factorio_bot_core::plan::blueprint_planner::{BlueprintPlanner, BuildSupply};
 use factorio_bot_core::plan::plan_builder::PlanBuilder;
-use factorio_bot_core::types::{Direction, FactorioEntity, PlayerId, Position, PositionRadius};
+use factorio_bot_core::types::{
+    Direction, FactorioEntity, InventoryItem, InventoryLocation, PlayerId, Position,
+    PositionRadius, PowerPlantOptions,
+};
 use std::sync::Arc;
 
+/// chest table given as `supply` to the build methods
+fn build_supply_from_lua(supply: &LuaTable) -> LuaResult<BuildSupply> {
+    let position: LuaTable = supply.get("position")?;
+    Ok(BuildSupply {
+        location: InventoryLocation {
+            entity_name: supply.get("entity_name")?,
+            position: Position::new(position.get("x")?, position.get("y")?),
+            inventory_type: supply.get("inventory_type")?,
+        },
+        items: supply.get("items")?,
+    })
+}
+
+/// player ids, pump options, pump direction, base, options, obstructions, supply and surface
+type BuildPowerPlantArgs = (
+    Vec<PlayerId>,
+    LuaValue,
+    u8,
+    LuaValue,
+    Option<LuaValue>,
+    Option<LuaValue>,
+    Option<LuaTable>,
+    Option<String>,
+);
+
 pub fn create_lua_plan_builder(
     lua: &Lua,
     graph: Arc<RwLock<TaskGraph>>,
@@ -64,8 +93,7 @@ end
         )?,
     )?;
     let plan_builder = _plan_builder.clone();
-    // let world = _world.clone();
-    let world = _world;
+    let world = _world.clone();
     map_table.set(
         "__doc_entry_place",
         String::from(
@@ -76,8 +104,9 @@ end
 -- @string entity_name name of item to place
 -- @param position `types.Position` 
 -- @param[opt] direction `types.Direction` 
+-- @string[opt] quality quality of item to place, defaults to normal
 -- @return `types.FactorioEntity`
-function plan.place(player_id, entity_name, position, direction)
+function plan.place(player_id, entity_name, position, direction, quality)
 end
 "#,
         ),
@@ -86,13 +115,14 @@ end
         "place",
         lua.create_function(
             move |_lua,
-                  (player_id, entity_name, position, direction): (
+                  (player_id, entity_name, position, direction, quality): (
                 PlayerId,
                 String,
                 LuaTable,
                 Option<u8>,
+                Option<String>,
             )| {
-                let entity = FactorioEntity::from_prototype(
+                let mut entity = FactorioEntity::from_prototype(
                     &entity_name,
                     Position::new(position.get("x").unwrap(), position.get("y").unwrap()),
                     direction.map(|d| Direction::from_u8(d).expect("invalid direction")),
@@ -101,6 +131,7 @@ end
                     world.entity_prototypes.clone(),
                 )
                 .expect("failed to build entity");
+                entity.quality = quality;
                 let entity = plan_builder.add_place(player_id, entity).unwrap();
                 Ok(entity)
             },
@@ -138,6 +169,172 @@ end
             },
         )?,
     )?;
+    map_table.set(
+        "__doc_entry_insert_into_inventory",
+        String::from(
+            r#"
+--- adds an INSERT node to graph
+-- If required first a WALK node is inserted to walk near the target entity
+-- @number player_id id of player
+-- @string entity_name name of entity to insert into
+-- @param position `types.Position` of entity
+-- @number inventory_type which inventory of the entity to insert into
+-- @string item_name name of item to insert
+-- @number count how many items to insert
+-- @string[opt] quality quality of items to insert, defaults to normal
+function plan.insert_into_inventory(player_id, entity_name, position, inventory_type, item_name, count, quality)
+end
+"#,
+        ),
+    )?;
+    let plan_builder = _plan_builder.clone();
+    map_table.set(
+        "insert_into_inventory",
+        lua.create_function(
+            move |_lua,
+                  (player_id, entity_name, position, inventory_type, item_name, count, quality): (
+                PlayerId,
+                String,
+                LuaTable,
+                u32,
+                String,
+                u32,
+                Option<String>,
+            )| {
+                let location = InventoryLocation {
+                    entity_name,
+                    position: Position::new(position.get("x")?, position.get("y")?),
+                    inventory_type,
+                };
+                let item = match quality {
+                    Some(quality) => InventoryItem::with_quality(&item_name, count, &quality),
+                    None => InventoryItem::new(&item_name, count),
+                };
+                plan_builder
+                    .add_insert_into_inventory(player_id, location, item)
+                    .map_err(|err| LuaError::RuntimeError(format!("{}", err)))
+            },
+        )?,
+    )?;
+    map_table.set(
+        "__doc_entry_build_blueprint",
+        String::from(
+            r#"
+--- adds tasks for several bots to build a blueprint
+-- Entities in the way are mined first, then poles, belts and pipes, machines and inserters
+-- are built in one group each. Bots get the entities closest to them which they have items for,
+-- missing items are taken out of the supply chest if given.
+-- @param player_ids {number} ids of players to build with
+-- @string blueprint blueprint string
+-- @param position `types.Position` center of the blueprint
+-- @param[opt] obstructions {`types.FactorioEntity`} entities which may be in the way, like trees and rocks
+-- @param[opt] supply table with `entity_name`, `position` and `inventory_type` of a chest and its `items` by name
+-- @return {`types.FactorioEntity`} entities to be placed
+function plan.build_blueprint(player_ids, blueprint, position, obstructions, supply)
+end
+"#,
+        ),
+    )?;
+    let plan_builder = _plan_builder.clone();
+    let world = _world.clone();
+    map_table.set(
+        "build_blueprint",
+        lua.create_function(
+            move |lua,
+                  (player_ids, blueprint, position, obstructions, supply): (
+                Vec<PlayerId>,
+                String,
+                LuaTable,
+                Option<LuaValue>,
+                Option<LuaTable>,
+            )| {
+                let obstructions: Vec<FactorioEntity> = match obstructions {
+                    Some(obstructions) => lua.from_value(obstructions)?,
+                    None => vec![],
+                };
+                let mut planner = BlueprintPlanner::new((*plan_builder).clone(), world.clone());
+                if let Some(supply) = supply {
+                    planner = planner.with_supply(build_supply_from_lua(&supply)?);
+                }
+                planner
+                    .plan_blueprint(
+                        &player_ids,
+                        &blueprint,
+                        &Position::new(position.get("x")?, position.get("y")?),
+                        &obstructions,
+                    )
+                    .map_err(|err| LuaError::RuntimeError(format!("{}", err)))
+            },
+        )?,
+    )?;
+    map_table.set(
+        "__doc_entry_build_power_plant",
+        String::from(
+            r#"
+--- adds tasks for several bots to build a steam power plant
+-- The layout comes from world.plan_power_plant and is built like plan.build_blueprint.
+-- @param player_ids {number} ids of players to build with
+-- @param pump_options {`types.Position`} from rcon.find_offshore_pump_placement_options
+-- @number pump_direction direction given to rcon.find_offshore_pump_placement_options
+-- @param base `types.Position` where the pole line should end
+-- @param[opt] options `types.PowerPlantOptions`
+-- @param[opt] obstructions {`types.FactorioEntity`} entities which may be in the way, like trees and rocks
+-- @param[opt] supply table with `entity_name`, `position` and `inventory_type` of a chest and its `items` by name
+-- @string[opt] surface name of surface to plan on, defaults to nauvis
+-- @return `types.PowerPlantLayout` the planned power plant
+function plan.build_power_plant(player_ids, pump_options, pump_direction, base, options, obstructions, supply, surface)
+end
+"#,
+        ),
+    )?;
+    let plan_builder = _plan_builder.clone();
+    let world = _world;
+    map_table.set(
+        "build_power_plant",
+        lua.create_function(
+            move |lua,
+                  (
+                player_ids,
+                pump_options,
+                pump_direction,
+                base,
+                options,
+                obstructions,
+                supply,
+                surface,
+            ): BuildPowerPlantArgs| {
+                let pump_options: Vec<Position> = lua.from_value(pump_options)?;
+                let pump_direction = Direction::from_u8(pump_direction)
+                    .ok_or_else(|| LuaError::RuntimeError("invalid direction".into()))?;
+                let base: Position = lua.from_value(base)?;
+                let options: PowerPlantOptions = match options {
+                    Some(options) => lua.from_value(options)?,
+                    None => PowerPlantOptions::default(),
+                };
+                let obstructions: Vec<FactorioEntity> = match obstructions {
+                    Some(obstructions) => lua.from_value(obstructions)?,
+                    None => vec![],
+                };
+                let layout = world
+                    .plan_power_plant(
+                        surface.as_deref(),
+                        &pump_options,
+                        pump_direction,
+                        &base,
+                        &options,
+                    )
+                    .map_err(|err| LuaError::RuntimeError(format!("{}", err)))?;
+                let mut planner = BlueprintPlanner::new((*plan_builder).clone(), world.clone());
+                if let Some(supply) = supply {
+                    planner = planner.with_supply(build_supply_from_lua(&supply)?);
+                }
+                planner
+                    .plan_entities(&player_ids, layout.entities.clone(), &obstructions)
+                    .map_err(|err| LuaError::RuntimeError(format!("{}", err)))?;
+                lua.to_value(&layout)
+            },
+        )?,
+    )?;
     let graph = _graph.clone();
     map_table.set(
         "__doc_entry_task_graph_graphviz",
@@ -1,9 +1,28 @@
 use factorio_bot_core::factorio::rcon::FactorioRcon;
+use factorio_bot_core::factorio::router::{BeltLane, RouteEndpoint, RouteRequest};
+use factorio_bot_core::factorio::util::position_in_area;
 use factorio_bot_core::factorio::world::FactorioWorld;
+use factorio_bot_core::graph::flow_graph::EdgeThroughput;
 use factorio_bot_core::mlua::prelude::*;
-use factorio_bot_core::types::{AreaFilter, PlayerId, Position, RequestEntity};
+use factorio_bot_core::num_traits::FromPrimitive;
+use factorio_bot_core::serde_json::Value;
+use factorio_bot_core::types::{AreaFilter, Direction, PlayerId, Position, Rect, RequestEntity};
 use std::sync::Arc;
 
+/// entity name, entity type, underground entity name, underground entity type,
+/// underground max, from position, to position, to direction and surface
+type PlanPathArgs = (
+    String,
+    String,
+    String,
+    String,
+    u8,
+    LuaTable,
+    LuaTable,
+    u8,
+    Option<String>,
+);
+
 pub fn create_lua_rcon(
     lua: &Lua,
     _rcon: Arc<FactorioRcon>,
@@ -35,8 +54,9 @@ local rcon = {}
 -- @number radius searches in circular radius around search_center
 -- @string[opt] search_name name of entity to find
 -- @string[opt] search_type type of entity to find
+-- @string[opt] surface name of surface to search, defaults to nauvis
 -- @return {`types.FactorioEntity`}
-function rcon.find_entities_in_radius(search_center, radius, search_name, search_type)
+function rcon.find_entities_in_radius(search_center, radius, search_name, search_type, surface)
 end
 "#,
         ),
@@ -45,11 +65,12 @@ end
         "find_entities_in_radius",
         lua.create_async_function(
             move |lua,
-                  (search_center, radius, search_name, search_type): (
+                  (search_center, radius, search_name, search_type, surface): (
                 LuaTable,
                 f64,
                 Option<String>,
                 Option<String>,
+                Option<String>,
             )| {
                 let _rcon = rcon.clone();
                 let _lua = lua;
@@ -61,7 +82,7 @@ end
                     let filter = AreaFilter::PositionRadius((search_center, Some(radius)));
                     let result = _rcon
                         .as_ref()
-                        .find_entities_filtered(&filter, search_name, search_type)
+                        .find_entities_filtered(&filter, search_name, search_type, surface)
                         .await
                         .unwrap();
                     _lua.to_value(&result)
@@ -178,7 +199,8 @@ end
 -- @number player_id id of player to give the item to
 -- @string name item name
 -- @number count how many items to give player
-function rcon.cheat_item(player_id, name, count)
+-- @string[opt] quality quality of the items, defaults to normal
+function rcon.cheat_item(player_id, name, count, quality)
 end
 "#,
         ),
@@ -186,12 +208,12 @@ end
     map_table.set(
         "cheat_item",
         lua.create_async_function(
-            move |_lua, (player_id, name, count): (PlayerId, String, u32)| {
+            move |_lua, (player_id, name, count, quality): (PlayerId, String, u32, Option<String>)| {
                 let _rcon = rcon.clone();
                 async move {
                     _rcon
                         .as_ref()
-                        .cheat_item(player_id, name.as_str(), count)
+                        .cheat_item(player_id, name.as_str(), count, quality)
                         .await
                         .unwrap();
                     Ok(())
@@ -454,39 +476,42 @@ end
 --- Craft an item with player
 -- Sends /silent-command remote.call('action_start_crafting', ...)
 -- @param inventories table list of `types.Position` to check
+-- @string[opt] surface name of surface, defaults to nauvis
 -- @return {[string]=number,...}
-function rcon.inventory_contents_at(inventories)
+function rcon.inventory_contents_at(inventories, surface)
 end
 "#,
         ),
     )?;
     map_table.set(
         "inventory_contents_at",
-        lua.create_async_function(move |lua, inventories: LuaTable| {
-            let _rcon = rcon.clone();
-            let _lua = lua;
-            let request_entities: Vec<LuaResult<RequestEntity>> = inventories
-                .pairs::<u32, LuaTable>()
-                .map(|a| {
-                    let t: LuaTable = a?.1;
-                    let position = Position::new(t.get("x")?, t.get("y")?);
-                    Ok(RequestEntity {
-                        name: t.get("name")?,
-                        position,
+        lua.create_async_function(
+            move |lua, (inventories, surface): (LuaTable, Option<String>)| {
+                let _rcon = rcon.clone();
+                let _lua = lua;
+                let request_entities: Vec<LuaResult<RequestEntity>> = inventories
+                    .pairs::<u32, LuaTable>()
+                    .map(|a| {
+                        let t: LuaTable = a?.1;
+                        let position = Position::new(t.get("x")?, t.get("y")?);
+                        Ok(RequestEntity {
+                            name: t.get("name")?,
+                            position,
+                        })
                     })
-                })
-                .collect();
-            let request_entities: LuaResult<Vec<RequestEntity>> =
-                request_entities.into_iter().collect();
-            async move {
-                let res = _rcon
-                    .as_ref()
-                    .inventory_contents_at(request_entities?)
-                    .await
-                    .unwrap();
-                _lua.to_value(&res)
-            }
-        })?,
+                    .collect();
+                let request_entities: LuaResult<Vec<RequestEntity>> =
+                    request_entities.into_iter().collect();
+                async move {
+                    let res = _rcon
+                        .as_ref()
+                        .inventory_contents_at(request_entities?, surface)
+                        .await
+                        .map_err(|err| LuaError::RuntimeError(format!("{}", err)))?;
+                    _lua.to_value(&res)
+                }
+            },
+        )?,
     )?;
     let rcon = _rcon.clone();
     let world = _world.clone();
@@ -500,8 +525,9 @@ end
 -- @string name name of item to craft
 -- @param position  `types.Position`
 -- @number direction direction of placed entity
+-- @string[opt] quality quality of the item to place, defaults to normal
 -- @return `types.FactorioEntity`
-function rcon.place_entity(player_id, name, position, direction)
+function rcon.place_entity(player_id, name, position, direction, quality)
 end
 "#,
         ),
@@ -509,7 +535,14 @@ end
     map_table.set(
         "place_entity",
         lua.create_async_function(
-            move |lua, (player_id, name, position, direction): (PlayerId, String, LuaTable, u8)| {
+            move |lua,
+                  (player_id, name, position, direction, quality): (
+                PlayerId,
+                String,
+                LuaTable,
+                u8,
+                Option<String>,
+            )| {
                 let _rcon = rcon.clone();
                 let _world = world.clone();
                 let _lua = lua;
@@ -519,7 +552,7 @@ end
                 async move {
                     let result = _rcon
                         .as_ref()
-                        .place_entity(player_id, name, position, direction, &_world)
+                        .place_entity(player_id, name, position, direction, quality, &_world)
                         .await
                         .unwrap();
                     _lua.to_value(&result)
@@ -541,7 +574,8 @@ end
 -- @string inventory_type which type of inventory to place in
 -- @string item_name which item to insert
 -- @number item_count how many items to insert
-function rcon.insert_to_inventory(player_id, entity_name, position, inventory_type, item_name, item_count)
+-- @string[opt] quality quality of the items, defaults to normal
+function rcon.insert_to_inventory(player_id, entity_name, position, inventory_type, item_name, item_count, quality)
 end
 "#,
         ),
@@ -550,14 +584,15 @@ end
         "insert_to_inventory",
         lua.create_async_function(
             move |_lua,
-                  (player_id, entity_name, position, inventory_type, item_name, item_count): (
-                PlayerId,
-                String,
-                LuaTable,
-                u32,
-                String,
-                u32,
-            )| {
+                  (
+                player_id,
+                entity_name,
+                position,
+                inventory_type,
+                item_name,
+                item_count,
+                quality,
+            ): (PlayerId, String, LuaTable, u32, String, u32, Option<String>)| {
                 let _rcon = rcon.clone();
                 let _world = world.clone();
                 let position =
@@ -572,6 +607,7 @@ end
                             inventory_type,
                             item_name,
                             item_count,
+                            quality,
                             &_world,
                         )
                         .await
@@ -581,6 +617,536 @@ end
             },
         )?,
     )?;
+    let rcon = _rcon.clone();
+    map_table.set(
+        "__doc_entry_find_tiles_in_radius",
+        String::from(
+            r#"
+--- find tiles at given position/radius with optional name filter
+-- Sends /silent-command remote.call('find_tiles_filtered', ...)
+-- @param search_center `types.Position`
+-- @number radius searches in circular radius around search_center
+-- @string[opt] search_name name of tile to find
+-- @string[opt] surface name of surface to search, defaults to nauvis
+-- @return {`types.FactorioTile`}
+function rcon.find_tiles_in_radius(search_center, radius, search_name, surface)
+end
+"#,
+        ),
+    )?;
+    map_table.set(
+        "find_tiles_in_radius",
+        lua.create_async_function(
+            move |lua,
+                  (search_center, radius, search_name, surface): (
+                LuaTable,
+                f64,
+                Option<String>,
+                Option<String>,
+            )| {
+                let _rcon = rcon.clone();
+                let _lua = lua;
+                let search_center = Position::new(
+                    search_center.get("x").unwrap(),
+                    search_center.get("y").unwrap(),
+                );
+                async move {
+                    let filter = AreaFilter::PositionRadius((search_center, Some(radius)));
+                    let result = _rcon
+                        .as_ref()
+                        .find_tiles_filtered(&filter, search_name, surface)
+                        .await
+                        .unwrap();
+                    _lua.to_value(&result)
+                }
+            },
+        )?,
+    )?;
+    let rcon = _rcon.clone();
+    map_table.set(
+        "__doc_entry_is_area_empty",
+        String::from(
+            r#"
+--- checks if area contains neither entities nor player collidable tiles
+-- Sends /silent-command remote.call('find_entities_filtered', ...) and remote.call('find_tiles_filtered', ...)
+-- @param area `types.Rect`
+-- @string[opt] surface name of surface to check, defaults to nauvis
+-- @return boolean
+function rcon.is_area_empty(area, surface)
+end
+"#,
+        ),
+    )?;
+    map_table.set(
+        "is_area_empty",
+        lua.create_async_function(move |lua, (area, surface): (LuaValue, Option<String>)| {
+            let _rcon = rcon.clone();
+            let area: LuaResult<Rect> = lua.from_value(area);
+            async move {
+                let filter = AreaFilter::Rect(area?);
+                let result = _rcon
+                    .as_ref()
+                    .is_area_empty(&filter, surface)
+                    .await
+                    .unwrap();
+                Ok(result)
+            }
+        })?,
+    )?;
+    let rcon = _rcon.clone();
+    let world = _world.clone();
+    map_table.set(
+        "__doc_entry_rescan",
+        String::from(
+            r#"
+--- scans area again and replaces what the world knows about it
+-- Use after the map was changed outside of the bots, e.g. by manual play.
+-- Sends /silent-command remote.call('find_entities_filtered', ...) and remote.call('find_tiles_filtered', ...)
+-- @param area `types.Rect`
+-- @string[opt] surface name of surface to scan, defaults to nauvis
+-- @return `types.EntityDiff` with added, removed and changed entities
+function rcon.rescan(area, surface)
+end
+"#,
+        ),
+    )?;
+    map_table.set(
+        "rescan",
+        lua.create_async_function(move |lua, (area, surface): (LuaValue, Option<String>)| {
+            let _rcon = rcon.clone();
+            let world = world.clone();
+            let area: LuaResult<Rect> = lua.from_value(area);
+            async move {
+                let diff = _rcon
+                    .as_ref()
+                    .rescan(&world, &area?, surface)
+                    .await
+                    .map_err(|err| LuaError::RuntimeError(format!("{}", err)))?;
+                lua.to_value(&diff)
+            }
+        })?,
+    )?;
+    let rcon = _rcon.clone();
+    let world = _world.clone();
+    map_table.set(
+        "__doc_entry_sample_throughput",
+        String::from(
+            r#"
+--- counts the items belts, splitters and inserters in area move during given seconds
+-- The measurement is attached to the flow graph, so it can be compared to the theoretical flow.
+-- Sends /silent-command remote.call('async_sample_throughput', ...) and waits for the result
+-- @param area `types.Rect`
+-- @int seconds length of the sampling window
+-- @string[opt] surface name of surface to sample, defaults to nauvis
+-- @return list of `types.EdgeThroughput` starting in area, with theoretical and measured items per minute
+function rcon.sample_throughput(area, seconds, surface)
+end
+"#,
+        ),
+    )?;
+    map_table.set(
+        "sample_throughput",
+        lua.create_async_function(
+            move |lua, (area, seconds, surface): (LuaValue, u32, Option<String>)| {
+                let _rcon = rcon.clone();
+                let world = world.clone();
+                let area: LuaResult<Rect> = lua.from_value(area);
+                async move {
+                    let area = area?;
+                    let sampling = _rcon
+                        .as_ref()
+                        .sample_throughput(&world, &area, seconds, surface)
+                        .await
+                        .map_err(|err| LuaError::RuntimeError(format!("{}", err)))?;
+                    let throughput: Vec<EdgeThroughput> = world
+                        .surface(sampling.surface.as_deref())
+                        .flow_graph
+                        .throughput()
+                        .into_iter()
+                        .filter(|edge| position_in_area(&edge.source, &area))
+                        .collect();
+                    lua.to_value(&throughput)
+                }
+            },
+        )?,
+    )?;
+    let rcon = _rcon.clone();
+    let world = _world.clone();
+    map_table.set(
+        "__doc_entry_find_offshore_pump_placement_options",
+        String::from(
+            r#"
+--- find positions near search_center where an offshore pump can be placed
+-- Sends /silent-command remote.call('find_tiles_filtered', ...) with increasing radius
+-- @param search_center `types.Position`
+-- @number pump_direction direction the placed pump should face
+-- @string[opt] surface name of surface to search, defaults to nauvis
+-- @return {`types.Position`}
+function rcon.find_offshore_pump_placement_options(search_center, pump_direction, surface)
+end
+"#,
+        ),
+    )?;
+    map_table.set(
+        "find_offshore_pump_placement_options",
+        lua.create_async_function(
+            move |lua, (search_center, pump_direction, surface): (LuaTable, u8, Option<String>)| {
+                let _rcon = rcon.clone();
+                let _world = world.clone();
+                let _lua = lua;
+                let search_center = Position::new(
+                    search_center.get("x").unwrap(),
+                    search_center.get("y").unwrap(),
+                );
+                async move {
+                    let pump_direction = Direction::from_u8(pump_direction)
+                        .ok_or_else(|| LuaError::RuntimeError("invalid direction".into()))?;
+                    let result: Vec<Position> = _rcon
+                        .as_ref()
+                        .find_offshore_pump_placement_options(
+                            &_world,
+                            search_center,
+                            pump_direction,
+                            surface,
+                        )
+                        .await
+                        .unwrap()
+                        .iter()
+                        .map(|pos| pos.into())
+                        .collect();
+                    _lua.to_value(&result)
+                }
+            },
+        )?,
+    )?;
+    let rcon = _rcon.clone();
+    let world = _world.clone();
+    map_table.set(
+        "__doc_entry_plan_path",
+        String::from(
+            r#"
+--- plan a belt/pipe path between two positions, using undergrounds where needed
+-- Sends /silent-command remote.call('find_entities_filtered', ...) and remote.call('find_tiles_filtered', ...)
+-- @string entity_name name of entity to build path with
+-- @string entity_type type of entity to build path with
+-- @string underground_entity_name name of underground entity
+-- @string underground_entity_type type of underground entity
+-- @number underground_max max length of underground connections
+-- @param from_position `types.Position`
+-- @param to_position `types.Position`
+-- @number to_direction direction at the end of the path
+-- @string[opt] surface name of surface to build on, defaults to nauvis
+-- @return {`types.FactorioEntity`}
+function rcon.plan_path(entity_name, entity_type, underground_entity_name, underground_entity_type, underground_max, from_position, to_position, to_direction, surface)
+end
+"#,
+        ),
+    )?;
+    map_table.set(
+        "plan_path",
+        lua.create_async_function(
+            move |lua,
+                  (
+                entity_name,
+                entity_type,
+                underground_entity_name,
+                underground_entity_type,
+                underground_max,
+                from_position,
+                to_position,
+                to_direction,
+                surface,
+            ): PlanPathArgs| {
+                let _rcon = rcon.clone();
+                let _world = world.clone();
+                let _lua = lua;
+                let from_position = Position::new(
+                    from_position.get("x").unwrap(),
+                    from_position.get("y").unwrap(),
+                );
+                let to_position =
+                    Position::new(to_position.get("x").unwrap(), to_position.get("y").unwrap());
+                async move {
+                    let to_direction = Direction::from_u8(to_direction)
+                        .ok_or_else(|| LuaError::RuntimeError("invalid direction".into()))?;
+                    let result = _rcon
+                        .as_ref()
+                        .plan_path(
+                            &_world,
+                            &entity_name,
+                            &entity_type,
+                            &underground_entity_name,
+                            &underground_entity_type,
+                            underground_max,
+                            &from_position,
+                            &to_position,
+                            to_direction,
+                            surface,
+                        )
+                        .await
+                        .unwrap();
+                    _lua.to_value(&result)
+                }
+            },
+        )?,
+    )?;
+    let rcon = _rcon.clone();
+    let world = _world.clone();
+    map_table.set(
+        "__doc_entry_plan_routes",
+        String::from(
+            r#"
+--- plan parallel belt/pipe lines like a main bus, using undergrounds to cross other lines
+-- Each route is a table `{from = endpoint, to = endpoint}`, an endpoint is a table
+-- `{position = types.Position, direction = number, lane = "left"|"right"}` with optional direction and lane.
+-- At the start direction is where items move into position, a lane sideloads them onto that lane of the first belt.
+-- At the end the last belt faces direction, with a lane position is an existing belt to sideload onto.
+-- Sends /silent-command remote.call('find_entities_filtered', ...) and remote.call('find_tiles_filtered', ...)
+-- @string entity_name name of entity to build lines with, like transport-belt or pipe
+-- @string underground_entity_name name of underground entity, like underground-belt or pipe-to-ground
+-- @param routes list of routes
+-- @param[opt] reserved_zones list of `types.Rect` the lines must not be built in
+-- @string[opt] surface name of surface to build on, defaults to nauvis
+-- @return list of {`types.FactorioEntity`} per route
+function rcon.plan_routes(entity_name, underground_entity_name, routes, reserved_zones, surface)
+end
+"#,
+        ),
+    )?;
+    map_table.set(
+        "plan_routes",
+        lua.create_async_function(
+            move |lua,
+                  (entity_name, underground_entity_name, routes, reserved_zones, surface): (
+                String,
+                String,
+                Vec<LuaTable>,
+                Option<LuaValue>,
+                Option<String>,
+            )| {
+                let _rcon = rcon.clone();
+                let world = world.clone();
+                let requests: LuaResult<Vec<RouteRequest>> = routes
+                    .into_iter()
+                    .map(|route| {
+                        Ok(RouteRequest {
+                            from: route_endpoint_from_lua(&lua, route.get("from")?)?,
+                            to: route_endpoint_from_lua(&lua, route.get("to")?)?,
+                        })
+                    })
+                    .collect();
+                let reserved_zones: LuaResult<Vec<Rect>> = match reserved_zones {
+                    Some(zones) => lua.from_value(zones),
+                    None => Ok(vec![]),
+                };
+                async move {
+                    let result = _rcon
+                        .as_ref()
+                        .plan_routes(
+                            &world,
+                            &entity_name,
+                            &underground_entity_name,
+                            &requests?,
+                            &reserved_zones?,
+                            surface,
+                        )
+                        .await
+                        .map_err(|err| LuaError::RuntimeError(format!("{}", err)))?;
+                    lua.to_value(&result)
+                }
+            },
+        )?,
+    )?;
+    let rcon = _rcon.clone();
+    map_table.set(
+        "__doc_entry_player_force",
+        String::from(
+            r#"
+--- fetch the player force including all technologies
+-- Sends /silent-command remote.call('player_force')
+-- @return `types.FactorioForce`
+function rcon.player_force()
+end
+"#,
+        ),
+    )?;
+    map_table.set(
+        "player_force",
+        lua.create_async_function(move |lua, ()| {
+            let _rcon = rcon.clone();
+            let _lua = lua;
+            async move {
+                let result = _rcon.as_ref().player_force().await.unwrap();
+                _lua.to_value(&result)
+            }
+        })?,
+    )?;
+    let rcon = _rcon.clone();
+    map_table.set(
+        "__doc_entry_eval",
+        String::from(
+            r#"
+--- evaluate lua snippet on the server and return its result
+-- The snippet is run as function body, so it needs to `return` a value.
+-- The result is serialized with helpers.table_to_json, so entities and other
+-- LuaObjects need to be converted to plain tables first.
+-- Sends /silent-command with the wrapped snippet
+-- @string lua_snippet
+-- @return result of snippet, tables come back as tables
+-- @usage local tick = rcon.eval("return game.tick")
+function rcon.eval(lua_snippet)
+end
+"#,
+        ),
+    )?;
+    map_table.set(
+        "eval",
+        lua.create_async_function(move |lua, lua_snippet: String| {
+            let _rcon = rcon.clone();
+            let _lua = lua;
+            async move {
+                let result = _rcon
+                    .as_ref()
+                    .eval(&lua_snippet)
+                    .await
+                    .map_err(|err| LuaError::RuntimeError(format!("{}", err)))?;
+                if result.is_null() {
+                    Ok(LuaValue::Nil)
+                } else {
+                    _lua.to_value(&result)
+                }
+            }
+        })?,
+    )?;
+    let rcon = _rcon.clone();
+    map_table.set(
+        "__doc_entry_store_map_data",
+        String::from(
+            r#"
+--- store data in the map, persists in the savegame
+-- Sends /silent-command remote.call('store_map_data', key, value)
+-- @string key
+-- @param value any json serializable value
+function rcon.store_map_data(key, value)
+end
+"#,
+        ),
+    )?;
+    map_table.set(
+        "store_map_data",
+        lua.create_async_function(move |lua, (key, value): (String, LuaValue)| {
+            let _rcon = rcon.clone();
+            let value: LuaResult<Value> = lua.from_value(value);
+            async move {
+                _rcon.as_ref().store_map_data(&key, value?).await.unwrap();
+                Ok(())
+            }
+        })?,
+    )?;
+    let rcon = _rcon.clone();
+    map_table.set(
+        "__doc_entry_retrieve_map_data",
+        String::from(
+            r#"
+--- retrieve data stored in the map
+-- Sends /silent-command remote.call('retrieve_map_data', key)
+-- @string key
+-- @return value or nil if nothing is stored under key
+function rcon.retrieve_map_data(key)
+end
+"#,
+        ),
+    )?;
+    map_table.set(
+        "retrieve_map_data",
+        lua.create_async_function(move |lua, key: String| {
+            let _rcon = rcon.clone();
+            let _lua = lua;
+            async move {
+                let result = _rcon.as_ref().retrieve_map_data(&key).await.unwrap();
+                _lua.to_value(&result)
+            }
+        })?,
+    )?;
+    let rcon = _rcon.clone();
+    map_table.set(
+        "__doc_entry_parse_map_exchange_string",
+        String::from(
+            r#"
+--- parse map exchange string on the server and store its settings under name
+-- Sends /silent-command remote.call('parse_map_exchange_string', name, map_exchange_string)
+-- @string name
+-- @string map_exchange_string
+function rcon.parse_map_exchange_string(name, map_exchange_string)
+end
+"#,
+        ),
+    )?;
+    map_table.set(
+        "parse_map_exchange_string",
+        lua.create_async_function(move |_lua, (name, map_exchange_string): (String, String)| {
+            let _rcon = rcon.clone();
+            async move {
+                _rcon
+                    .as_ref()
+                    .parse_map_exchange_string(&name, &map_exchange_string)
+                    .await
+                    .unwrap();
+                Ok(())
+            }
+        })?,
+    )?;
+    let rcon = _rcon.clone();
+    map_table.set(
+        "__doc_entry_server_save",
+        String::from(
+            r#"
+--- save the current game on the server
+-- Sends /server-save
+function rcon.server_save()
+end
+"#,
+        ),
+    )?;
+    map_table.set(
+        "server_save",
+        lua.create_async_function(move |_lua, ()| {
+            let _rcon = rcon.clone();
+            async move {
+                _rcon.as_ref().server_save().await.unwrap();
+                Ok(())
+            }
+        })?,
+    )?;
+    let rcon = _rcon.clone();
+    map_table.set(
+        "__doc_entry_screenshot",
+        String::from(
+            r#"
+--- take a screenshot, written to script-output of the server
+-- Sends /screenshot width height depth
+-- @number width
+-- @number height
+-- @number depth
+function rcon.screenshot(width, height, depth)
+end
+"#,
+        ),
+    )?;
+    map_table.set(
+        "screenshot",
+        lua.create_async_function(move |_lua, (width, height, depth): (i16, i16, i8)| {
+            let _rcon = rcon.clone();
+            async move {
+                _rcon
+                    .as_ref()
+                    .screenshot(width, height, depth)
+                    .await
+                    .unwrap();
+                Ok(())
+            }
+        })?,
+    )?;
     let rcon = _rcon;
     let world = _world;
     map_table.set(
@@ -595,7 +1161,8 @@ end
 -- @string inventory_type which type of inventory to remove from
 -- @string item_name which item to remove
 -- @number item_count how many items to remove
-function rcon.remove_from_inventory(player_id, entity_name, position, inventory_type, item_name, item_count)
+-- @string[opt] quality quality of the items, defaults to normal
+function rcon.remove_from_inventory(player_id, entity_name, position, inventory_type, item_name, item_count, quality)
 end
 "#,
         ),
@@ -604,14 +1171,15 @@ end
         "remove_from_inventory",
         lua.create_async_function(
             move |_lua,
-                  (player_id, entity_name, position, inventory_type, item_name, item_count): (
-                PlayerId,
-                String,
-                LuaTable,
-                u32,
-                String,
-                u32,
-            )| {
+                  (
+                player_id,
+                entity_name,
+                position,
+                inventory_type,
+                item_name,
+                item_count,
+                quality,
+            ): (PlayerId, String, LuaTable, u32, String, u32, Option<String>)| {
                 let _rcon = rcon.clone();
                 let _world = world.clone();
                 let position =
@@ -626,6 +1194,7 @@ end
                             inventory_type,
                             item_name,
                             item_count,
+                            quality,
                             &_world,
                         )
                         .await
@@ -637,3 +1206,23 @@ end
     )?;
     Ok(map_table)
 }
+
+fn route_endpoint_from_lua(lua: &Lua, endpoint: LuaTable) -> LuaResult<RouteEndpoint> {
+    let position: Position = lua.from_value(endpoint.get("position")?)?;
+    let direction = match endpoint.get::<Option<u8>>("direction")? {
+        Some(direction) => Some(
+            Direction::from_u8(direction)
+                .ok_or_else(|| LuaError::RuntimeError("invalid direction".into()))?,
+        ),
+        None => None,
+    };
+    let lane = match endpoint.get::<Option<String>>("lane")? {
+        Some(lane) => Some(lane.parse::<BeltLane>().map_err(LuaError::RuntimeError)?),
+        None => None,
+    };
+    Ok(RouteEndpoint {
+        position,
+        direction,
+        lane,
+    })
+}
@@ -0,0 +1,101 @@
+use crate::globals::rcon::create_lua_rcon;
+use crate::globals::world::create_lua_world;
+use factorio_bot_core::mlua::prelude::*;
+use factorio_bot_core::process::server_registry::SharedFactorioServerRegistry;
+use std::path::PathBuf;
+
+pub fn create_lua_servers(
+    lua: &Lua,
+    _servers: SharedFactorioServerRegistry,
+    cwd: PathBuf,
+) -> LuaResult<LuaTable> {
+    let map_table = lua.create_table()?;
+    map_table.set(
+        "__doc__header",
+        String::from(
+            r#"
+--- Server registry
+-- methods for targeting other factorio servers than the one the script runs against
+--
+-- @module servers
+
+local servers = {}
+    "#,
+        ),
+    )?;
+    map_table.set("__doc__footer", String::from(r#"return servers"#))?;
+    map_table.set(
+        "__doc_entry_names",
+        String::from(
+            r#"
+--- names of all registered servers
+-- @return {string} sorted names, the default server is called default
+function servers.names()
+end
+"#,
+        ),
+    )?;
+    let servers = _servers.clone();
+    map_table.set(
+        "names",
+        lua.create_function(move |_lua, ()| Ok(servers.names()))?,
+    )?;
+    map_table.set(
+        "__doc_entry_rcon",
+        String::from(
+            r#"
+--- rcon interface of given server
+-- @string[opt] name name of server, defaults to the default server
+-- @return rcon table with the same methods as the rcon global
+function servers.rcon(name)
+end
+"#,
+        ),
+    )?;
+    let servers = _servers.clone();
+    map_table.set(
+        "rcon",
+        lua.create_async_function(move |lua, name: Option<String>| {
+            let servers = servers.clone();
+            async move {
+                let rcon = servers
+                    .rcon(name.as_deref())
+                    .await
+                    .map_err(|err| LuaError::RuntimeError(format!("{}", err)))?;
+                let world = servers
+                    .world(name.as_deref())
+                    .await
+                    .map_err(|err| LuaError::RuntimeError(format!("{}", err)))?;
+                create_lua_rcon(&lua, rcon, world)
+            }
+        })?,
+    )?;
+    map_table.set(
+        "__doc_entry_world",
+        String::from(
+            r#"
+--- world interface of given server
+-- @string[opt] name name of server, defaults to the default server
+-- @return world table with the same methods as the world global, reading the live world of that server
+function servers.world(name)
+end
+"#,
+        ),
+    )?;
+    let servers = _servers;
+    map_table.set(
+        "world",
+        lua.create_async_function(move |lua, name: Option<String>| {
+            let servers = servers.clone();
+            let cwd = cwd.clone();
+            async move {
+                let world = servers
+                    .world(name.as_deref())
+                    .await
+                    .map_err(|err| LuaError::RuntimeError(format!("{}", err)))?;
+                create_lua_world(&lua, world, cwd)
+            }
+        })?,
+    )?;
+    Ok(map_table)
+}
@@ -1,13 +1,24 @@
+use factorio_bot_core::constants::DEFAULT_QUALITY;
 use factorio_bot_core::factorio::util::blueprint_build_area;
 use factorio_bot_core::factorio::world::FactorioWorld;
 use factorio_bot_core::factorio_blueprint::BlueprintCodec;
 use factorio_bot_core::mlua::prelude::*;
+use factorio_bot_core::num_traits::FromPrimitive;
 use factorio_bot_core::serde_json;
 use factorio_bot_core::test_utils::draw_world;
-use factorio_bot_core::types::{FactorioBlueprintInfo, PlayerId, Position, Rect};
+use factorio_bot_core::types::{
+    item_key, AssemblerLineOptions, BlueprintWire, Direction, FactorioBlueprintInfo,
+    FactorioEntity, OutpostOptions, PlayerId, Position, PowerPlantOptions, Rect, SiteConstraints,
+    SmeltingColumnOptions,
+};
 use std::path::PathBuf;
 use std::sync::Arc;
 
+/// sites returned by `find_sites` if no limit is given
+const DEFAULT_SITE_LIMIT: usize = 10;
+/// entities returned by `nearest` if no count is given
+const DEFAULT_NEAREST_COUNT: usize = 1;
+
 pub fn create_lua_world(
     lua: &Lua,
     _world: Arc<FactorioWorld>,
@@ -85,9 +96,11 @@ end
 -- @string ore_name name of item to craft
 -- @number width name of item to craft
 -- @number height name of item to craft
+-- Rectangles inside danger zones of enemies are skipped.
 -- @param near `types.Position`
+-- @string[opt] surface name of surface to search, defaults to nauvis
 -- @return `types.FactorioPlayer`
-function world.find_free_resource_rect(ore_name, width, height, near)
+function world.find_free_resource_rect(ore_name, width, height, near, surface)
 end
 "#,
         ),
@@ -95,13 +108,25 @@ end
     map_table.set(
         "find_free_resource_rect",
         lua.create_function(
-            move |_lua, (ore_name, width, height, near): (String, u32, u32, LuaTable)| {
-                let patches = world.entity_graph.resource_patches(ore_name.as_str());
+            move |_lua,
+                  (ore_name, width, height, near, surface): (
+                String,
+                u32,
+                u32,
+                LuaTable,
+                Option<String>,
+            )| {
+                let patches = world
+                    .surface(surface.as_deref())
+                    .entity_graph
+                    .resource_patches(ore_name.as_str());
                 let near = Position::new(near.get("x").unwrap(), near.get("y").unwrap());
                 for patch in patches {
                     let rect = patch.find_free_rect(width, height, &near);
                     if let Some(rect) = rect {
-                        return Ok(rect);
+                        if !world.is_rect_dangerous(surface.as_deref(), &rect) {
+                            return Ok(rect);
+                        }
                     }
                 }
                 Ok(Rect::default())
@@ -109,6 +134,96 @@ end
         )?,
     )?;
 
+    map_table.set(
+        "__doc_entry_find_sites",
+        String::from(
+            r#"
+--- find ranked places for a build area
+-- Sites never cover water or buildings and do not overlap each other, the best comes first.
+-- @number width width of the build area
+-- @number height height of the build area
+-- @param[opt] constraints `types.SiteConstraints` like resource, min_resource_tiles, max_water_distance, allow_trees, near, max_distance and rotations
+-- @number[opt] limit maximum number of sites, defaults to 10
+-- @string[opt] surface name of surface to search, defaults to nauvis
+-- @return {`types.SiteCandidate`}
+function world.find_sites(width, height, constraints, limit, surface)
+end
+"#,
+        ),
+    )?;
+    let world = _world.clone();
+    map_table.set(
+        "find_sites",
+        lua.create_function(
+            move |lua,
+                  (width, height, constraints, limit, surface): (
+                u32,
+                u32,
+                Option<LuaValue>,
+                Option<usize>,
+                Option<String>,
+            )| {
+                let constraints: SiteConstraints = match constraints {
+                    Some(constraints) => lua.from_value(constraints)?,
+                    None => SiteConstraints::default(),
+                };
+                let sites = world.find_sites(
+                    surface.as_deref(),
+                    width,
+                    height,
+                    &constraints,
+                    limit.unwrap_or(DEFAULT_SITE_LIMIT),
+                );
+                lua.to_value(&sites)
+            },
+        )?,
+    )?;
+
+    map_table.set(
+        "__doc_entry_find_blueprint_sites",
+        String::from(
+            r#"
+--- find ranked places to put a blueprint
+-- Like `find_sites` with the build area of the blueprint. Positions are the blueprint origin,
+-- where to place it unrotated, turned footprints are not tried.
+-- @string blueprint blueprint string
+-- @param[opt] constraints `types.SiteConstraints`
+-- @number[opt] limit maximum number of sites, defaults to 10
+-- @string[opt] surface name of surface to search, defaults to nauvis
+-- @return {`types.SiteCandidate`}
+function world.find_blueprint_sites(blueprint, constraints, limit, surface)
+end
+"#,
+        ),
+    )?;
+    let world = _world.clone();
+    map_table.set(
+        "find_blueprint_sites",
+        lua.create_function(
+            move |lua,
+                  (blueprint, constraints, limit, surface): (
+                String,
+                Option<LuaValue>,
+                Option<usize>,
+                Option<String>,
+            )| {
+                let constraints: SiteConstraints = match constraints {
+                    Some(constraints) => lua.from_value(constraints)?,
+                    None => SiteConstraints::default(),
+                };
+                let sites = world
+                    .find_blueprint_sites(
+                        surface.as_deref(),
+                        &blueprint,
+                        &constraints,
+                        limit.unwrap_or(DEFAULT_SITE_LIMIT),
+                    )
+                    .map_err(|err| LuaError::RuntimeError(format!("{}", err)))?;
+                lua.to_value(&sites)
+            },
+        )?,
+    )?;
+
     map_table.set(
         "__doc_entry_parse_blueprint",
         String::from(
@@ -125,9 +240,10 @@ end
     map_table.set(
         "parse_blueprint",
         lua.create_function(move |lua, (blueprint, label): (String, String)| {
-            let decoded =
-                BlueprintCodec::decode_string(&blueprint).expect("failed to parse blueprint");
-            let rect = blueprint_build_area(world.entity_prototypes.clone(), &blueprint);
+            let decoded = BlueprintCodec::decode_string(&blueprint)
+                .map_err(|err| LuaError::RuntimeError(format!("{}", err)))?;
+            let rect = blueprint_build_area(world.entity_prototypes.clone(), &blueprint)
+                .map_err(|err| LuaError::RuntimeError(format!("{}", err)))?;
             let response = FactorioBlueprintInfo {
                 rect: rect.clone(),
                 label,
@@ -140,6 +256,347 @@ end
         })?,
     )?;
 
+    map_table.set(
+        "__doc_entry_make_blueprint",
+        String::from(
+            r#"
+--- Encode entities as blueprint string
+-- Entity directions, recipes, modules and underground belt types are kept,
+-- electric poles get connected to the nearest poles in reach.
+-- Uses the Factorio 1.1 format, so quality is not kept.
+-- @param entities {`types.FactorioEntity`} entities to put into the blueprint
+-- @string[opt] label blueprint label
+-- @param[opt] wires {`types.BlueprintWire`} circuit wires between entities, by 0-based index
+-- @return string blueprint string
+function world.make_blueprint(entities, label, wires)
+end
+"#,
+        ),
+    )?;
+    let world = _world.clone();
+    map_table.set(
+        "make_blueprint",
+        lua.create_function(
+            move |lua, (entities, label, wires): (LuaValue, Option<String>, Option<LuaValue>)| {
+                let entities: Vec<FactorioEntity> = lua.from_value(entities)?;
+                let wires: Vec<BlueprintWire> = match wires {
+                    Some(wires) => lua.from_value(wires)?,
+                    None => vec![],
+                };
+                world
+                    .make_blueprint(label.as_deref().unwrap_or_default(), &entities, &wires)
+                    .map_err(|err| LuaError::RuntimeError(format!("{}", err)))
+            },
+        )?,
+    )?;
+
+    map_table.set(
+        "__doc_entry_plan_outpost",
+        String::from(
+            r#"
+--- Generate a mining outpost layout for a resource patch
+-- Drills are placed in rows around belts or insert directly into furnaces, electric drills get poles.
+-- @string resource name of the resource like iron-ore
+-- @param[opt] near `types.Position` use the patch nearest to this position instead of the largest one
+-- @param[opt] options `types.OutpostOptions` like drill, belt, furnace, pole and strategy (overlap or coverage)
+-- @string[opt] surface name of surface to search, defaults to nauvis
+-- @return `types.OutpostLayout` entities, blueprint string and expected ore per minute
+function world.plan_outpost(resource, near, options, surface)
+end
+"#,
+        ),
+    )?;
+    let world = _world.clone();
+    map_table.set(
+        "plan_outpost",
+        lua.create_function(
+            move |lua,
+                  (resource, near, options, surface): (
+                String,
+                Option<LuaValue>,
+                Option<LuaValue>,
+                Option<String>,
+            )| {
+                let near: Option<Position> = match near {
+                    Some(near) => Some(lua.from_value(near)?),
+                    None => None,
+                };
+                let options: OutpostOptions = match options {
+                    Some(options) => lua.from_value(options)?,
+                    None => OutpostOptions::default(),
+                };
+                let layout = world
+                    .plan_outpost(surface.as_deref(), &resource, near.as_ref(), &options)
+                    .map_err(|err| LuaError::RuntimeError(format!("{}", err)))?;
+                lua.to_value(&layout)
+            },
+        )?,
+    )?;
+
+    map_table.set(
+        "__doc_entry_plan_power_plant",
+        String::from(
+            r#"
+--- Generate a steam power plant layout next to water
+-- The offshore pump on the option closest to base feeds a row of boilers through a pipe,
+-- each boiler drives as many steam engines as its steam output allows and poles run towards base.
+-- @param pump_options {`types.Position`} from rcon.find_offshore_pump_placement_options
+-- @number pump_direction direction given to rcon.find_offshore_pump_placement_options
+-- @param base `types.Position` where the pole line should end
+-- @param[opt] options `types.PowerPlantOptions` like boilers, entity names and max_distance from the pump
+-- @string[opt] surface name of surface to search, defaults to nauvis
+-- @return `types.PowerPlantLayout` entities, blueprint string, power in watts and coal per minute
+function world.plan_power_plant(pump_options, pump_direction, base, options, surface)
+end
+"#,
+        ),
+    )?;
+    let world = _world.clone();
+    map_table.set(
+        "plan_power_plant",
+        lua.create_function(
+            move |lua,
+                  (pump_options, pump_direction, base, options, surface): (
+                LuaValue,
+                u8,
+                LuaValue,
+                Option<LuaValue>,
+                Option<String>,
+            )| {
+                let pump_options: Vec<Position> = lua.from_value(pump_options)?;
+                let pump_direction = Direction::from_u8(pump_direction)
+                    .ok_or_else(|| LuaError::RuntimeError("invalid direction".into()))?;
+                let base: Position = lua.from_value(base)?;
+                let options: PowerPlantOptions = match options {
+                    Some(options) => lua.from_value(options)?,
+                    None => PowerPlantOptions::default(),
+                };
+                let layout = world
+                    .plan_power_plant(
+                        surface.as_deref(),
+                        &pump_options,
+                        pump_direction,
+                        &base,
+                        &options,
+                    )
+                    .map_err(|err| LuaError::RuntimeError(format!("{}", err)))?;
+                lua.to_value(&layout)
+            },
+        )?,
+    )?;
+
+    map_table.set(
+        "__doc_entry_nearest",
+        String::from(
+            r#"
+--- find the entities nearest to a position
+-- Resource tiles are included, each as its own entity.
+-- @string[opt] name entity name like iron-ore, nil for any
+-- @param position `types.Position` position to search around
+-- @number[opt] n maximum number of entities, defaults to 1
+-- @string[opt] surface name of surface to search, defaults to nauvis
+-- @return {`types.FactorioEntity`} nearest first
+function world.nearest(name, position, n, surface)
+end
+"#,
+        ),
+    )?;
+    let world = _world.clone();
+    map_table.set(
+        "nearest",
+        lua.create_function(
+            move |lua,
+                  (name, position, n, surface): (
+                Option<String>,
+                LuaValue,
+                Option<usize>,
+                Option<String>,
+            )| {
+                let position: Position = lua.from_value(position)?;
+                let entities = world.nearest(
+                    surface.as_deref(),
+                    &position,
+                    n.unwrap_or(DEFAULT_NEAREST_COUNT),
+                    name.as_deref(),
+                    None,
+                );
+                lua.to_value(&entities)
+            },
+        )?,
+    )?;
+
+    map_table.set(
+        "__doc_entry_nearest_of_type",
+        String::from(
+            r#"
+--- find the entities of a type nearest to a position
+-- @string entity_type entity type like assembling-machine or resource
+-- @param position `types.Position` position to search around
+-- @number[opt] n maximum number of entities, defaults to 1
+-- @string[opt] surface name of surface to search, defaults to nauvis
+-- @return {`types.FactorioEntity`} nearest first
+function world.nearest_of_type(entity_type, position, n, surface)
+end
+"#,
+        ),
+    )?;
+    let world = _world.clone();
+    map_table.set(
+        "nearest_of_type",
+        lua.create_function(
+            move |lua,
+                  (entity_type, position, n, surface): (
+                String,
+                LuaValue,
+                Option<usize>,
+                Option<String>,
+            )| {
+                let position: Position = lua.from_value(position)?;
+                let entities = world.nearest(
+                    surface.as_deref(),
+                    &position,
+                    n.unwrap_or(DEFAULT_NEAREST_COUNT),
+                    None,
+                    Some(&entity_type),
+                );
+                lua.to_value(&entities)
+            },
+        )?,
+    )?;
+
+    map_table.set(
+        "__doc_entry_line_of_sight",
+        String::from(
+            r#"
+--- checks if the straight line between two positions is free
+-- Buildings, trees, rocks and water block the line, anything covering either end is ignored.
+-- @param from `types.Position`
+-- @param to `types.Position`
+-- @string[opt] surface name of surface, defaults to nauvis
+-- @return bool
+function world.line_of_sight(from, to, surface)
+end
+"#,
+        ),
+    )?;
+    let world = _world.clone();
+    map_table.set(
+        "line_of_sight",
+        lua.create_function(
+            move |lua, (from, to, surface): (LuaValue, LuaValue, Option<String>)| {
+                let from: Position = lua.from_value(from)?;
+                let to: Position = lua.from_value(to)?;
+                Ok(world.line_of_sight(surface.as_deref(), &from, &to))
+            },
+        )?,
+    )?;
+
+    map_table.set(
+        "__doc_entry_entities_along",
+        String::from(
+            r#"
+--- find the entities crossed by the straight line between two positions
+-- @param from `types.Position`
+-- @param to `types.Position`
+-- @string[opt] surface name of surface, defaults to nauvis
+-- @return {`types.FactorioEntity`} in order from `from` to `to`
+function world.entities_along(from, to, surface)
+end
+"#,
+        ),
+    )?;
+    let world = _world.clone();
+    map_table.set(
+        "entities_along",
+        lua.create_function(
+            move |lua, (from, to, surface): (LuaValue, LuaValue, Option<String>)| {
+                let from: Position = lua.from_value(from)?;
+                let to: Position = lua.from_value(to)?;
+                lua.to_value(&world.entities_along(surface.as_deref(), &from, &to))
+            },
+        )?,
+    )?;
+
+    map_table.set(
+        "__doc_entry_chunk_rings",
+        String::from(
+            r#"
+--- list entities by chunk around a position
+-- The chunk containing the position comes first, followed by the rings of chunks around it.
+-- @param position `types.Position`
+-- @number radius number of rings around the centre chunk
+-- @string[opt] surface name of surface, defaults to nauvis
+-- @return {`types.ChunkEntities`}
+function world.chunk_rings(position, radius, surface)
+end
+"#,
+        ),
+    )?;
+    let world = _world.clone();
+    map_table.set(
+        "chunk_rings",
+        lua.create_function(
+            move |lua, (position, radius, surface): (LuaValue, u32, Option<String>)| {
+                let position: Position = lua.from_value(position)?;
+                lua.to_value(&world.chunk_rings(surface.as_deref(), &position, radius))
+            },
+        )?,
+    )?;
+
+    map_table.set(
+        "__doc_entry_plan_smelting_column",
+        String::from(
+            r#"
+--- Generate a column of furnaces with input and output belts, inserters and poles
+-- Furnaces are stacked southwards, the input belt runs on the west side and the output belt on the east side.
+-- @param position `types.Position` left top corner of the column
+-- @param options `types.SmeltingColumnOptions` input to smelt, furnace count and optionally furnace, belt, inserter and pole
+-- @return `types.ProductionBlock` entities, blueprint string and items per minute in and out
+function world.plan_smelting_column(position, options)
+end
+"#,
+        ),
+    )?;
+    let world = _world.clone();
+    map_table.set(
+        "plan_smelting_column",
+        lua.create_function(move |lua, (position, options): (LuaValue, LuaValue)| {
+            let position: Position = lua.from_value(position)?;
+            let options: SmeltingColumnOptions = lua.from_value(options)?;
+            let block = world
+                .plan_smelting_column(&position, &options)
+                .map_err(|err| LuaError::RuntimeError(format!("{}", err)))?;
+            lua.to_value(&block)
+        })?,
+    )?;
+
+    map_table.set(
+        "__doc_entry_plan_assembler_line",
+        String::from(
+            r#"
+--- Generate a line of assembling machines for a recipe at a target rate
+-- The machine count follows from recipe time and crafting speed, up to two ingredients share the input belt.
+-- @param position `types.Position` left top corner of the line
+-- @param options `types.AssemblerLineOptions` recipe, items_per_minute and optionally machine, belt, inserter and pole
+-- @return `types.ProductionBlock` entities, blueprint string and items per minute in and out
+function world.plan_assembler_line(position, options)
+end
+"#,
+        ),
+    )?;
+    let world = _world.clone();
+    map_table.set(
+        "plan_assembler_line",
+        lua.create_function(move |lua, (position, options): (LuaValue, LuaValue)| {
+            let position: Position = lua.from_value(position)?;
+            let options: AssemblerLineOptions = lua.from_value(options)?;
+            let block = world
+                .plan_assembler_line(&position, &options)
+                .map_err(|err| LuaError::RuntimeError(format!("{}", err)))?;
+            lua.to_value(&block)
+        })?,
+    )?;
+
     let world = _world.clone();
     map_table.set(
         "__doc_entry_find_entities_in_radius",
@@ -151,8 +608,9 @@ end
 -- @number radius searches in circular radius around search_center
 -- @string[opt] search_name name of entity to find
 -- @string[opt] search_type type of entity to find
+-- @string[opt] surface name of surface to search, defaults to nauvis
 -- @return {`types.FactorioEntity`}
-function world.find_entities_in_radius(search_center, radius, search_name, search_type)
+function world.find_entities_in_radius(search_center, radius, search_name, search_type, surface)
 end
 "#,
         ),
@@ -161,22 +619,21 @@ end
         "find_entities_in_radius",
         lua.create_function(
             move |_lua,
-                  (search_center, radius, search_name, search_type): (
+                  (search_center, radius, search_name, search_type, surface): (
                 LuaTable,
                 f64,
                 Option<String>,
                 Option<String>,
+                Option<String>,
             )| {
                 let search_center = Position::new(
                     search_center.get("x").unwrap(),
                     search_center.get("y").unwrap(),
                 );
-                let entities = world.entity_graph.find_entities_in_radius(
-                    search_center,
-                    radius,
-                    search_name,
-                    search_type,
-                );
+                let entities = world
+                    .surface(surface.as_deref())
+                    .entity_graph
+                    .find_entities_in_radius(search_center, radius, search_name, search_type);
                 Ok(entities)
             },
         )?,
@@ -202,6 +659,162 @@ end
         })?,
     )?;
 
+    let world = _world.clone();
+    map_table.set(
+        "__doc_entry_surfaces",
+        String::from(
+            r#"
+--- names of all known surfaces, nauvis first
+-- @return {string}
+function world.surfaces()
+end
+"#,
+        ),
+    )?;
+    map_table.set(
+        "surfaces",
+        lua.create_function(move |_lua, ()| Ok(world.surface_names()))?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "__doc_entry_entity_inventory",
+        String::from(
+            r#"
+--- last known inventory contents of the machine or container at given position
+-- Updated by BotBridge whenever contents change, no rcon roundtrip needed.
+-- Items of other than normal quality are keyed as `name@quality`.
+-- @param position `types.Position` of or inside the entity
+-- @string[opt] surface name of surface, defaults to nauvis
+-- @return table of inventory name to table of item to count, nil if entity is not tracked
+function world.entity_inventory(position, surface)
+end
+"#,
+        ),
+    )?;
+    map_table.set(
+        "entity_inventory",
+        lua.create_function(
+            move |lua, (position, surface): (LuaTable, Option<String>)| {
+                let position = Position::new(position.get("x")?, position.get("y")?);
+                match world.entity_state(surface.as_deref(), &position) {
+                    Some(state) => lua.to_value(&state.inventories),
+                    None => Ok(LuaValue::Nil),
+                }
+            },
+        )?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "__doc_entry_entity_status",
+        String::from(
+            r#"
+--- last known status of the machine at given position
+-- One of the `defines.entity_status` names like working, no_ingredients or full_output.
+-- @param position `types.Position` of or inside the entity
+-- @string[opt] surface name of surface, defaults to nauvis
+-- @return string, nil if entity is not tracked or has no status
+function world.entity_status(position, surface)
+end
+"#,
+        ),
+    )?;
+    map_table.set(
+        "entity_status",
+        lua.create_function(
+            move |_lua, (position, surface): (LuaTable, Option<String>)| {
+                let position = Position::new(position.get("x")?, position.get("y")?);
+                Ok(world
+                    .entity_state(surface.as_deref(), &position)
+                    .and_then(|state| state.status)
+                    .map(|status| status.to_string()))
+            },
+        )?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "__doc_entry_danger_zones",
+        String::from(
+            r#"
+--- areas around known spawners, worms and biters
+-- Paths of bots through these areas are rejected.
+-- @string[opt] surface name of surface, defaults to nauvis
+-- @return list of `types.DangerZone`
+function world.danger_zones(surface)
+end
+"#,
+        ),
+    )?;
+    map_table.set(
+        "danger_zones",
+        lua.create_function(move |lua, surface: Option<String>| {
+            lua.to_value(&world.danger_zones(surface.as_deref()))
+        })?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "__doc_entry_walk_path",
+        String::from(
+            r#"
+--- walking path computed from the known obstacles, without asking factorio
+-- Avoids danger zones and returns within milliseconds, useful for comparing walk costs.
+-- @param start `types.Position`
+-- @param goal `types.Position`
+-- @number[opt] radius how close the path needs to get to goal
+-- @string[opt] surface name of surface, defaults to nauvis
+-- @return list of `types.Position` waypoints, without start
+function world.walk_path(start, goal, radius, surface)
+end
+"#,
+        ),
+    )?;
+    map_table.set(
+        "walk_path",
+        lua.create_function(
+            move |lua,
+                  (start, goal, radius, surface): (
+                LuaValue,
+                LuaValue,
+                Option<f64>,
+                Option<String>,
+            )| {
+                let start: Position = lua.from_value(start)?;
+                let goal: Position = lua.from_value(goal)?;
+                let path = world
+                    .walk_path(surface.as_deref(), &start, &goal, radius)
+                    .map_err(|err| LuaError::RuntimeError(format!("{}", err)))?;
+                lua.to_value(&path)
+            },
+        )?,
+    )?;
+
+    let world = _world.clone();
+    map_table.set(
+        "__doc_entry_pollution",
+        String::from(
+            r#"
+--- last known pollution of the chunk containing given position
+-- @param position `types.Position`
+-- @string[opt] surface name of surface, defaults to nauvis
+-- @return number
+function world.pollution(position, surface)
+end
+"#,
+        ),
+    )?;
+    map_table.set(
+        "pollution",
+        lua.create_function(
+            move |_lua, (position, surface): (LuaTable, Option<String>)| {
+                let position = Position::new(position.get("x")?, position.get("y")?);
+                Ok(world.pollution_at(surface.as_deref(), &position))
+            },
+        )?,
+    )?;
+
     let world = _world;
     map_table.set(
         "__doc_entry_inventory",
@@ -211,23 +824,27 @@ end
 -- The ...
 -- @number player_id id of player
 -- @string item_name name of item
+-- @string[opt] quality quality of item, defaults to normal
 -- @return {`types.FactorioEntity`}
-function world.inventory(player_id, item_name)
+function world.inventory(player_id, item_name, quality)
 end
 "#,
         ),
     )?;
     map_table.set(
         "inventory",
-        lua.create_function(move |_lua, (player_id, item_name): (PlayerId, String)| {
-            match world.players.get(&player_id) {
-                Some(player) => match player.main_inventory.get(&item_name) {
-                    Some(cnt) => Ok(*cnt),
-                    None => Ok(0),
-                },
-                None => Err(LuaError::RuntimeError("player not found".into())),
-            }
-        })?,
+        lua.create_function(
+            move |_lua, (player_id, item_name, quality): (PlayerId, String, Option<String>)| {
+                let key = item_key(&item_name, quality.as_deref().unwrap_or(DEFAULT_QUALITY));
+                match world.players.get(&player_id) {
+                    Some(player) => match player.main_inventory.get(&key) {
+                        Some(cnt) => Ok(*cnt),
+                        None => Ok(0),
+                    },
+                    None => Err(LuaError::RuntimeError("player not found".into())),
+                }
+            },
+        )?,
     )?;
 
     Ok(map_table)
@@ -1,12 +1,14 @@
 use crate::globals::create_lua_globals;
 use crate::globals::plan::create_lua_plan_builder;
 use crate::globals::rcon::create_lua_rcon;
+use crate::globals::servers::create_lua_servers;
 use crate::globals::world::create_lua_world;
 use factorio_bot_core::factorio::rcon::FactorioRcon;
 use factorio_bot_core::factorio::world::FactorioWorld;
 use factorio_bot_core::mlua::prelude::*;
 use factorio_bot_core::parking_lot::Mutex;
 use factorio_bot_core::plan::planner::Planner;
+use factorio_bot_core::process::server_registry::FactorioServerRegistry;
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
@@ -24,6 +26,11 @@ pub fn write_lua_docs(target_path: PathBuf) -> LuaResult<()> {
     let plan_table =
         create_lua_plan_builder(&lua, planner.graph.clone(), planner.plan_world.clone())?;
     let rcon_table = create_lua_rcon(&lua, rcon, planner.real_world)?;
+    let servers_table = create_lua_servers(
+        &lua,
+        FactorioServerRegistry::new_shared(),
+        cwd.to_path_buf(),
+    )?;
     let code_by_path: HashMap<String, String> = HashMap::new();
     let code_by_path: Arc<Mutex<HashMap<String, String>>> = Arc::new(Mutex::new(code_by_path));
     create_lua_globals(
@@ -39,6 +46,7 @@ pub fn write_lua_docs(target_path: PathBuf) -> LuaResult<()> {
     write_lua_doc(target_path.join("world.lua"), &world_table);
     write_lua_doc(target_path.join("plan.lua"), &plan_table);
     write_lua_doc(target_path.join("rcon.lua"), &rcon_table);
+    write_lua_doc(target_path.join("servers.lua"), &servers_table);
     Ok(())
 }
 
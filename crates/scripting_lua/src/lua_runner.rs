@@ -1,6 +1,7 @@
 use crate::globals::create_lua_globals;
 use crate::globals::plan::create_lua_plan_builder;
 use crate::globals::rcon::create_lua_rcon;
+use crate::globals::servers::create_lua_servers;
 use crate::globals::world::create_lua_world;
 use factorio_bot_core::mlua::prelude::*;
 use factorio_bot_core::mlua::LuaSerdeExt;
@@ -43,6 +44,7 @@ pub async fn run_lua(
     let graph = planner.graph.clone();
     let real_world = planner.real_world.clone();
     let rcon = planner.rcon.clone();
+    let servers = planner.servers.clone();
     let cwd_buf = cwd.to_path_buf();
 
     let thread_stdout = stdout.clone();
@@ -51,7 +53,7 @@ pub async fn run_lua(
     let result = thread::spawn(move || {
         let lua = Lua::new();
         let _code_by_path = code_by_path.clone();
-        let world = create_lua_world(&lua, plan_world.clone(), cwd_buf).unwrap();
+        let world = create_lua_world(&lua, plan_world.clone(), cwd_buf.clone()).unwrap();
         let plan = create_lua_plan_builder(&lua, graph, plan_world).unwrap();
         create_lua_globals(
             &lua,
@@ -70,6 +72,10 @@ pub async fn run_lua(
             let rcon = create_lua_rcon(&lua, rcon.clone(), real_world.clone()).unwrap();
             globals.set("rcon", rcon).unwrap();
         }
+        if let Some(servers) = servers {
+            let servers = create_lua_servers(&lua, servers, cwd_buf).unwrap();
+            globals.set("servers", servers).unwrap();
+        }
 
         let rt: Runtime = Runtime::new().unwrap();
         rt.block_on(async {
@@ -6,6 +6,8 @@ use factorio_bot_core::miette::{IntoDiagnostic, Result};
 use factorio_bot_core::paris::{error, info};
 use factorio_bot_core::plan::planner::Planner;
 use factorio_bot_core::process::instance_setup::setup_factorio_instance;
+use factorio_bot_core::process::process_control::FactorioParams;
+use factorio_bot_core::process::server_registry::SharedFactorioServerRegistry;
 use factorio_bot_core::settings::FactorioSettings;
 use factorio_bot_core::tokio::sync::Mutex;
 use factorio_bot_core::types::{AreaFilter, FactorioEntity, PlayerId, Position};
@@ -23,6 +25,7 @@ pub enum RollSeedLimit {
 }
 #[allow(unused_mut, unused_variables)]
 pub async fn roll_seed(
+    servers: SharedFactorioServerRegistry,
     settings: FactorioSettings,
     map_exchange_string: String,
     limit: RollSeedLimit,
@@ -36,22 +39,39 @@ pub async fn roll_seed(
     let factorio_archive_path: Arc<String> = Arc::new(settings.factorio_archive_path.to_string());
     let map_exchange_string = Arc::new(map_exchange_string);
 
+    // roll servers only borrow port offsets, they are never registered as named servers
+    let roll_servers: Vec<(String, FactorioParams)> = (0..parallel)
+        .map(|p| {
+            let instance_name = format!("roll{}", p + 1);
+            let params = servers.unregistered_server_params(
+                &instance_name,
+                &settings,
+                FactorioParams::default(),
+            );
+            (instance_name, params)
+        })
+        .collect();
+
     let mut join_handles: Vec<JoinHandle<()>> = vec![];
     info!("preparing instances ...");
     for p in 0..parallel {
-        let instance_name = format!("roll{}", p + 1);
+        let (instance_name, params) = &roll_servers[p as usize];
         let rcon_settings = RconSettings {
             host: None,
             pass: "roll".into(),
-            port: 1234 + p as u16,
+            port: params
+                .rcon_port
+                .expect("roll servers have their own rcon port"),
         };
-        let factorio_port: u16 = 2345 + p as u16;
+        let factorio_port: u16 = params
+            .factorio_port
+            .expect("roll servers have their own factorio port");
         setup_factorio_instance(
             &workspace_path,
             &factorio_archive_path,
             &rcon_settings,
             Some(factorio_port),
-            &instance_name,
+            instance_name,
             true,
             true,
             Some(map_exchange_string.to_string()),
@@ -64,13 +84,17 @@ pub async fn roll_seed(
     info!("finished preparing. spawning {} instances", parallel);
     let started = Instant::now();
     for p in 0..parallel {
-        let instance_name = format!("roll{}", p + 1);
+        let (instance_name, params) = &roll_servers[p as usize];
         let rcon_settings = RconSettings {
             host: None,
             pass: "roll".into(),
-            port: 1234 + p as u16,
+            port: params
+                .rcon_port
+                .expect("roll servers have their own rcon port"),
         };
-        let factorio_port: u16 = 2345 + p as u16;
+        let factorio_port: u16 = params
+            .factorio_port
+            .expect("roll servers have their own factorio port");
         let best_seed_with_score = best_seed_with_score.clone();
         let workspace_path = workspace_path.clone();
         let factorio_archive_path = factorio_archive_path.clone();
@@ -224,6 +248,7 @@ pub async fn find_nearest_entities(
             &AreaFilter::PositionRadius((search_center.clone(), Some(search_radius))),
             name,
             entity_type,
+            None,
         )
         .await?;
     entities.sort_by(|a, b| {
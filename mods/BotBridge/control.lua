@@ -63,6 +63,32 @@ local recent_item_additions   = {} -- recent_item_additions[character_index].{ti
 
 local tile_chunks = {}
 
+-- entity types whose inventories and status are streamed via on_entity_state_changed
+local tracked_entity_types = {
+	["container"] = true,
+	["logistic-container"] = true,
+	["furnace"] = true,
+	["assembling-machine"] = true,
+	["lab"] = true,
+	["mining-drill"] = true,
+	["boiler"] = true
+}
+-- tracked entities are checked in buckets, one bucket per tick
+local ENTITY_STATE_BUCKETS = 30
+-- pollution of all chunks is written out every 10 seconds
+local POLLUTION_INTERVAL = 600
+-- an entity under attack is reported at most once per second
+local ATTACK_REPORT_INTERVAL = 60
+local last_attack_reports = {}
+-- belts and inserters of running throughput samplings are checked every 5 ticks
+local THROUGHPUT_SAMPLE_INTERVAL = 5
+local throughput_sampled_types = {"transport-belt", "underground-belt", "splitter", "inserter"}
+
+local entity_status_names = {}
+for name, value in pairs(defines.entity_status) do
+	entity_status_names[value] = name
+end
+
 function inventory_type_name(invtype, enttype)
 	local burner = {
 		[defines.inventory.fuel] = "fuel",
@@ -219,6 +245,7 @@ function on_init()
 	storage.pathfinding = {}
 	storage.pathfinding.map = {}
 	storage.n_clients = 1
+	storage.tracked_entities = {}
 end
 
 function pos_str(pos)
@@ -251,6 +278,7 @@ function writeout_initial_stuff()
 	writeout_pictures()
 	writeout_entity_prototypes()
 	writeout_item_prototypes()
+	writeout_tile_prototypes()
 	writeout_recipes()
 	writeout_forces()
 	writeout(0, "STATIC_DATA_END", "done")
@@ -479,6 +507,14 @@ function writeout_item_prototypes()
 	writeout(0, "item_prototypes", table.concat(lines,"$"))
 end
 
+function writeout_tile_prototypes()
+	local lines = {}
+	for name, prot in pairs(prototypes.tile) do
+		table.insert(lines, helpers.table_to_json(serialize_tile_prototype(prot)))
+	end
+	writeout(0, "tile_prototypes", table.concat(lines,"$"))
+end
+
 function simplify_amount(prod)
 	if prod.amount ~= nil then
 		return prod.amount
@@ -504,12 +540,24 @@ function writeout_forces()
 	end
 end
 
-function on_whoami()
-	if client_local_data.whoami == "server" then
+function on_whoami(skip_discovery)
+	if client_local_data.whoami == "server" and skip_discovery then
+		-- the world was loaded from a snapshot, only entity state tracking needs to be set up
+		local types = {}
+		for entity_type, _ in pairs(tracked_entity_types) do table.insert(types, entity_type) end
+		for _, surface in pairs(game.surfaces) do
+			for _, ent in pairs(surface.find_entities_filtered{type=types}) do
+				track_entity(ent)
+			end
+		end
+		print("initial discovery done (skipped, using snapshot)")
+	elseif client_local_data.whoami == "server" then
 		client_local_data.initial_discovery={}
 		client_local_data.initial_discovery.chunks = {}
-		for chunk in game.surfaces[1].get_chunks() do
-			table.insert(client_local_data.initial_discovery.chunks, chunk)
+		for _, surface in pairs(game.surfaces) do
+			for chunk in surface.get_chunks() do
+				table.insert(client_local_data.initial_discovery.chunks, {x=chunk.x, y=chunk.y, surface=surface})
+			end
 		end
 		client_local_data.initial_discovery.n = #client_local_data.initial_discovery.chunks
 		client_local_data.initial_discovery.idx = 1
@@ -535,7 +583,7 @@ function on_tick(event)
 		end
 		for i = id.idx, maxi do
 			local chunk = id.chunks[i]
-			on_chunk_generated({tick=event.tick, area={left_top={x=chunk.x*32, y=chunk.y*32}, right_bottom={x=32*chunk.x+32, y=32*chunk.y+32}}, surface=game.surfaces[1]})
+			on_chunk_generated({tick=event.tick, area={left_top={x=chunk.x*32, y=chunk.y*32}, right_bottom={x=32*chunk.x+32, y=32*chunk.y+32}}, surface=chunk.surface})
 		end
 
 		id.idx = maxi+1
@@ -674,6 +722,16 @@ function on_tick(event)
 		writeout_initial_stuff()
 	end
 
+	writeout_entity_states(event.tick)
+
+	if event.tick % POLLUTION_INTERVAL == 0 then
+		writeout_pollution(event.tick)
+	end
+
+	if event.tick % THROUGHPUT_SAMPLE_INTERVAL == 0 then
+		sample_throughput(event.tick)
+	end
+
 	if event.tick % 120 == 0 then
 		local who = "?"
 		if client_local_data.whoami then who = client_local_data.whoami end
@@ -796,10 +854,7 @@ function on_chunk_generated(event)
 	local chunk_xend = area.right_bottom.x
 	local chunk_yend = area.right_bottom.y
 
-	if surface ~= game.surfaces['nauvis'] then -- TODO we only support one surface
-		print("unknown surface")
-		return
-	end
+	if not surface.valid then return end
 
 	if chunk_x < -512 then return end
 	if chunk_y < -512 then return end
@@ -812,7 +867,7 @@ function on_chunk_generated(event)
 	if chunk_yend > storage.map_area.y2 then storage.map_area.y2 = chunk_yend end
 
 	writeout_entities(event.tick, surface, area)
-	local chunk_id = chunk_x .. "/" .. chunk_y
+	local chunk_id = surface.name .. "@" .. chunk_x .. "/" .. chunk_y
 	if tile_chunks[chunk_id] == nil then
 		tile_chunks[chunk_id] = true
 		writeout_tiles(event.tick, surface, area)
@@ -855,7 +910,7 @@ end
 
 function writeout_tiles(tick, surface, area) -- SLOW! beastie can do ~2.8 per tick
 	--if my_client_id ~= 1 then return end
-	local header = area.left_top.x..","..area.left_top.y..";"..area.right_bottom.x..","..area.right_bottom.y..": "
+	local header = area.left_top.x..","..area.left_top.y..";"..area.right_bottom.x..","..area.right_bottom.y.."@"..surface.name..": "
 	local tile = nil
 	local line = {}
 	for y = area.left_top.y, area.right_bottom.y-1 do
@@ -902,17 +957,140 @@ end
 
 function writeout_entities(tick, surface, area)
 	--if my_client_id ~= 1 then return end
-	local header = area.left_top.x..","..area.left_top.y..";"..area.right_bottom.x..","..area.right_bottom.y..":"
+	local header = area.left_top.x..","..area.left_top.y..";"..area.right_bottom.x..","..area.right_bottom.y.."@"..surface.name..":"
 	local objects = {}
 	for idx, ent in pairs(surface.find_entities(area)) do
 		if ent.type ~= "character" and area.left_top.x <= ent.position.x and ent.position.x < area.right_bottom.x and area.left_top.y <= ent.position.y and ent.position.y < area.right_bottom.y then
 			table.insert(objects, serialize_entity(ent))
+			track_entity(ent)
 		end
 	end
 	writeout(tick, "entities", header .. helpers.table_to_json(objects))
 	line=nil
 end
 
+function tracked_bucket(unit_number)
+	if storage.tracked_entities == nil then storage.tracked_entities = {} end
+	local idx = unit_number % ENTITY_STATE_BUCKETS
+	if storage.tracked_entities[idx] == nil then storage.tracked_entities[idx] = {} end
+	return storage.tracked_entities[idx]
+end
+
+function track_entity(ent)
+	if tracked_entity_types[ent.type] and ent.unit_number ~= nil then
+		local bucket = tracked_bucket(ent.unit_number)
+		if bucket[ent.unit_number] == nil then
+			bucket[ent.unit_number] = {entity=ent, last=nil}
+		end
+	end
+end
+
+function untrack_entity(ent)
+	if ent.unit_number ~= nil then
+		tracked_bucket(ent.unit_number)[ent.unit_number] = nil
+	end
+end
+
+function serialize_entity_state(ent)
+	local inventories = {}
+	for idx = 1, ent.get_max_inventory_index() do
+		local inventory = ent.get_inventory(idx)
+		if inventory ~= nil and not inventory.is_empty() then
+			local name = inventory.name or inventory_type_name(idx, ent.type) or tostring(idx)
+			inventories[name] = inventory.get_contents()
+		end
+	end
+	local status = nil
+	if ent.status ~= nil then
+		status = entity_status_names[ent.status]
+	end
+	return {
+		name = ent.name,
+		position = ent.position,
+		surface = ent.surface.name,
+		status = status,
+		inventories = inventories
+	}
+end
+
+-- writes out state of tracked entities in this tick's bucket, if it changed since last time
+function writeout_entity_states(tick)
+	local bucket = tracked_bucket(tick % ENTITY_STATE_BUCKETS)
+	for unit_number, tracked in pairs(bucket) do
+		if not tracked.entity.valid then
+			bucket[unit_number] = nil
+		else
+			local state = helpers.table_to_json(serialize_entity_state(tracked.entity))
+			if state ~= tracked.last then
+				tracked.last = state
+				writeout(tick, "on_entity_state_changed", state)
+			end
+		end
+	end
+end
+
+function writeout_pollution(tick)
+	for _, surface in pairs(game.surfaces) do
+		local chunks = {}
+		for chunk in surface.get_chunks() do
+			local pollution = surface.get_pollution({chunk.x * 32 + 16, chunk.y * 32 + 16})
+			if pollution > 0 then
+				table.insert(chunks, {x = chunk.x, y = chunk.y, pollution = pollution})
+			end
+		end
+		writeout(tick, "on_pollution_changed", helpers.table_to_json({surface = surface.name, chunks = chunks}))
+	end
+end
+
+-- counts items which left a belt since the last sample, or were dropped by an inserter
+function sample_entity_throughput(sampled)
+	local entity = sampled.entity
+	if entity.type == "inserter" then
+		local held = nil
+		if entity.held_stack.valid_for_read then
+			held = {name = entity.held_stack.name, count = entity.held_stack.count}
+		end
+		if sampled.held ~= nil and held == nil then
+			sampled.items[sampled.held.name] = (sampled.items[sampled.held.name] or 0) + sampled.held.count
+		end
+		sampled.held = held
+		return
+	end
+	local seen = {}
+	for index = 1, entity.get_max_transport_line_index() do
+		for _, item in pairs(entity.get_transport_line(index).get_detailed_contents()) do
+			seen[item.unique_id] = item.stack.name
+		end
+	end
+	for unique_id, name in pairs(sampled.seen) do
+		if seen[unique_id] == nil then
+			sampled.items[name] = (sampled.items[name] or 0) + 1
+		end
+	end
+	sampled.seen = seen
+end
+
+function sample_throughput(tick)
+	if storage.throughput_samplings == nil then return end
+	for id, sampling in pairs(storage.throughput_samplings) do
+		for _, sampled in pairs(sampling.entities) do
+			if sampled.entity.valid then
+				sample_entity_throughput(sampled)
+			end
+		end
+		if tick >= sampling.until_tick then
+			local entities = {}
+			for _, sampled in pairs(sampling.entities) do
+				if sampled.entity.valid then
+					table.insert(entities, {name = sampled.entity.name, position = sampled.entity.position, items = sampled.items})
+				end
+			end
+			writeout(tick, "on_throughput_sampled", tostring(id) .. "#" .. helpers.table_to_json({ticks = sampling.ticks, entities = entities}))
+			storage.throughput_samplings[id] = nil
+		end
+	end
+end
+
 function writeout(tick, key, value)
 	print("§"..tick.."§"..key.."§"..tostring(value))
 end
@@ -1006,6 +1184,7 @@ function on_some_entity_created(event)
 	end
 
 	writeout(event.tick, "on_some_entity_created", helpers.table_to_json(serialize_entity(ent)))
+	track_entity(ent)
 
 --	if ent.type == "pipe" or ent.type == "pipe-to-ground" or ent.type == "wall" or ent.type == "heat-pipe" then -- HACK to semi-correctly assign an orientation to pipes etc
 --		-- need to write out neighboring entities as well, because they might have changed their orientation by this event
@@ -1026,6 +1205,44 @@ function on_some_entity_updated(event)
 	writeout(event.tick, "on_some_entity_updated", helpers.table_to_json(serialize_entity(ent)))
 end
 
+function on_enemy_built(event)
+	writeout(event.tick, "on_enemy_built", helpers.table_to_json(serialize_entity(event.entity)))
+end
+
+-- reports damage done by the enemy force to anything not belonging to it
+function on_entity_attacked(event, died)
+	local ent = event.entity
+	if ent == nil or not ent.valid or event.force == nil or event.force.name ~= "enemy" or ent.force.name == "enemy" then
+		return
+	end
+	if not died and ent.unit_number ~= nil then
+		local last = last_attack_reports[ent.unit_number]
+		if last ~= nil and event.tick - last < ATTACK_REPORT_INTERVAL then return end
+		last_attack_reports[ent.unit_number] = event.tick
+	elseif ent.unit_number ~= nil then
+		last_attack_reports[ent.unit_number] = nil
+	end
+	local player_id = nil
+	if ent.type == "character" and ent.player ~= nil then
+		player_id = ent.player.index
+	end
+	local cause = nil
+	if event.cause ~= nil and event.cause.valid then
+		cause = event.cause.name
+	end
+	writeout(event.tick, "on_entity_attacked", helpers.table_to_json({
+		name = ent.name,
+		entity_type = ent.type,
+		position = ent.position,
+		surface = ent.surface.name,
+		player_id = player_id,
+		cause = cause,
+		damage = event.final_damage_amount or 0,
+		health = died and 0 or ent.health,
+		died = died
+	}))
+end
+
 function on_some_entity_deleted(event)
 	local ent = event.entity
 	if ent == nil then
@@ -1033,6 +1250,7 @@ function on_some_entity_deleted(event)
 		return
 	end
 	writeout(event.tick, "on_some_entity_deleted", helpers.table_to_json(serialize_entity(ent)))
+	untrack_entity(ent)
 
 --	-- we can't do this now, because the entity still exists at this point. instead, we schedule the writeout for the next tick
 --
@@ -1142,7 +1360,8 @@ function on_player_changed_position(event)
 
 		writeout(event.tick, "on_player_changed_position", helpers.table_to_json({
 			player_id = player_idx,
-			position = position
+			position = position,
+			surface = player.surface.name
 		}))
 	end
 end
@@ -1150,6 +1369,19 @@ function to_i64(a)
 	return math.min(a, 18446744073709541614)
 end
 
+-- running speed modifier of the character plus exoskeletons in its armor
+function player_movement_bonus(player)
+	local ok, bonus = pcall(function()
+		local bonus = player.character_running_speed_modifier
+		local armor = player.get_inventory(defines.inventory.character_armor)
+		if armor ~= nil and not armor.is_empty() and armor[1].grid ~= nil then
+			bonus = bonus + armor[1].grid.movement_bonus
+		end
+		return bonus
+	end)
+	if ok then return bonus else return 0 end
+end
+
 function on_player_changed_distance(event)
 	for idx, player in pairs(game.players) do
 		writeout(event.tick, "on_player_changed_distance", helpers.table_to_json({
@@ -1160,6 +1392,7 @@ function on_player_changed_distance(event)
 			item_pickup_distance = to_i64(math.ceil(player.item_pickup_distance)),
 			loot_pickup_distance = to_i64(math.ceil(player.loot_pickup_distance)),
 			resource_reach_distance = to_i64(math.ceil(player.resource_reach_distance)),
+			movement_bonus = player_movement_bonus(player),
 		}))
 	end
 end
@@ -1191,13 +1424,21 @@ script.on_event(defines.events.on_sector_scanned, on_sector_scanned)
 script.on_event(defines.events.on_chunk_generated, on_chunk_generated)
 script.on_event(defines.events.on_player_mined_item, on_player_mined_item)
 
-script.on_event(defines.events.on_biter_base_built, on_some_entity_created) --entity
+script.on_event(defines.events.on_biter_base_built, on_enemy_built) --entity
 script.on_event(defines.events.on_built_entity, on_some_entity_created) --created_entity
 script.on_event(defines.events.on_robot_built_entity, on_some_entity_created) --created_entity
 script.on_event(defines.events.on_player_rotated_entity, on_some_entity_updated) --entity
 script.on_event(defines.events.on_built_entity, on_some_entity_created) --entity
 
-script.on_event(defines.events.on_entity_died, on_some_entity_deleted) --entity
+script.on_event(defines.events.on_entity_died, function (event) on_entity_attacked(event, true); on_some_entity_deleted(event) end) --entity
+-- enemies damaged by turrets make up most damage events, skip them before they reach lua
+script.on_event(defines.events.on_entity_damaged, function (event) on_entity_attacked(event, false) end, {
+	{filter = "type", type = "unit", invert = true},
+	{filter = "type", type = "unit-spawner", invert = true, mode = "and"},
+	{filter = "type", type = "turret", invert = true, mode = "and"},
+	{filter = "type", type = "tree", invert = true, mode = "and"},
+	{filter = "type", type = "simple-entity", invert = true, mode = "and"},
+}) --entity
 script.on_event(defines.events.on_player_mined_entity, function (event) on_mined_entity(event); on_some_entity_deleted(event) end) --entity
 script.on_event(defines.events.on_robot_mined_entity, on_some_entity_deleted) --entity
 script.on_event(defines.events.on_resource_depleted, on_some_entity_deleted) --entity
@@ -1207,6 +1448,9 @@ script.on_event(defines.events.on_research_finished, on_research_finished)
 
 script.on_event(defines.events.on_player_main_inventory_changed, on_player_main_inventory_changed)
 script.on_event(defines.events.on_player_changed_position, on_player_changed_position)
+script.on_event(defines.events.on_player_armor_inventory_changed, on_player_changed_distance)
+script.on_event(defines.events.on_player_placed_equipment, on_player_changed_distance)
+script.on_event(defines.events.on_player_removed_equipment, on_player_changed_distance)
 --script.on_event(defines.events.on_player_gun_inventory_changed, on_inventory_changed)
 --script.on_event(defines.events.on_player_ammo_inventory_changed, on_inventory_changed)
 --script.on_event(defines.events.on_player_armor_inventory_changed, on_inventory_changed)
@@ -1250,18 +1494,19 @@ function rcon_action_start_mining(action_id, player_id, name, position, count)
 	end
 end
 
-function rcon_place_entity(player_id, item_name, entity_position, direction)
+function rcon_place_entity(player_id, item_name, entity_position, direction, quality)
 	local entproto = prototypes.item[item_name].place_result
 	local player = game.players[player_id]
 	local surface = game.players[player_id].surface
+	quality = quality or "normal"
 
 	if entproto == nil then
 		complain("cannot place item '"..item_name.."' because place_result is nil")
 		return
 	end
 
-	if player.get_item_count(item_name) <= 0 then
-		complain("cannot place item '"..item_name.."' because the player '"..player.name.."' does not have any")
+	if player.get_item_count({name=item_name, quality=quality}) <= 0 then
+		complain("cannot place "..quality.." item '"..item_name.."' because the player '"..player.name.."' does not have any")
 		return
 	end
 
@@ -1279,8 +1524,8 @@ function rcon_place_entity(player_id, item_name, entity_position, direction)
 		return
 	end
 
-	player.remove_item({name=item_name,count=1})
-	result = surface.create_entity{name=entproto.name,position=entity_position,direction=direction,force=player.force, fast_replace=true, player=player, spill=true}
+	player.remove_item({name=item_name,count=1,quality=quality})
+	result = surface.create_entity{name=entproto.name,position=entity_position,direction=direction,quality=quality,force=player.force, fast_replace=true, player=player, spill=true}
 
 	if result == nil then
 		complain("placing item '"..item_name.."' failed, surface.create_entity returned nil :(")
@@ -1328,8 +1573,9 @@ function rcon_insert_to_inventory(player_id, entity_name, entity_pos, inventory_
 
 	local count = 1
 	if items.count ~= nil then count=items.count end
+	local quality = items.quality or "normal"
 
-	local available_count = player.get_item_count(items.name)
+	local available_count = player.get_item_count({name=items.name, quality=quality})
 
 	if available_count < count then
 		complain("cannot insert "..count.."x "..items.name..", because player #"..player_id.." only has "..available_count..". clamping...")
@@ -1337,13 +1583,13 @@ function rcon_insert_to_inventory(player_id, entity_name, entity_pos, inventory_
 	end
 
 	if count > 0 then
-		local real_n = inventory.insert({name=items.name, count=count})
+		local real_n = inventory.insert({name=items.name, count=count, quality=quality})
 
 		if count ~= real_n then
 			complain("tried to insert "..count.."x "..items.name.." but inserted " .. real_n)
 		end
 
-		local check_n = player.remove_item({name=items.name, count=real_n})
+		local check_n = player.remove_item({name=items.name, count=real_n, quality=quality})
 		if check_n ~= real_n then
 			complain("wtf, tried to take "..real_n.."x "..items.name.." from player #"..player_id.." but only got "..check_n..". Isn't supposed to happen?!")
 		end
@@ -1373,7 +1619,7 @@ function rcon_remove_from_inventory(player_id, entity_name, entity_pos, inventor
 	end
 
 	if real_n > 0 then
-		local check_n = player.insert({name=items.name, count=real_n})
+		local check_n = player.insert({name=items.name, count=real_n, quality=items.quality or "normal"})
 
 		if check_n ~= real_n then
 			complain("wtf, couldn't insert "..real_n.."x "..items.name.." into player #"..player_id..", but only "..check_n..". dropping them :(.")
@@ -1381,10 +1627,10 @@ function rcon_remove_from_inventory(player_id, entity_name, entity_pos, inventor
 	end
 end
 
-function rcon_whoami(who)
+function rcon_whoami(who, skip_discovery)
 	if client_local_data.whoami == nil then
 		client_local_data.whoami = who
-		on_whoami()
+		on_whoami(skip_discovery)
 	end
 end
 
@@ -1470,8 +1716,8 @@ function rcon_add_research(technology_name)
 	force.add_research(technology_name)
 end
 
-function rcon_inventory_contents_at(positions)
-	local surface = game.surfaces[1]
+function rcon_inventory_contents_at(positions, surface)
+	local surface = game.surfaces[surface or 1]
 
 	local result = {}
 
@@ -1499,8 +1745,15 @@ function rcon_inventory_contents_at(positions)
 	rcon.print(helpers.table_to_json(result))
 end
 
+-- filters may contain a surface name, defaults to the first surface
+function filtered_surface(filters)
+	local surface = game.surfaces[filters.surface or 1]
+	filters.surface = nil
+	return surface
+end
+
 function rcon_find_entities_filtered(filters)
-	local results = game.surfaces[1].find_entities_filtered(filters)
+	local results = filtered_surface(filters).find_entities_filtered(filters)
 	local lines = {}
 	for k, v in pairs(results) do
 		table.insert(lines, serialize_entity(v))
@@ -1510,7 +1763,7 @@ end
 
 
 function rcon_find_tiles_filtered(filters)
-	local results = game.surfaces[1].find_tiles_filtered(filters)
+	local results = filtered_surface(filters).find_tiles_filtered(filters)
 	local lines = {}
 	for k, v in pairs(results) do
 		table.insert(lines, serialize_tile(v))
@@ -1581,12 +1834,12 @@ function rcon_revive_ghost(player_id, name, x, y)
 	end
 end
 
-function rcon_cheat_item(player_id, item, count)
+function rcon_cheat_item(player_id, item, count, quality)
 	local player = get_player(player_id)
 	if player == nil then
 		return
 	end
-	player.insert{name=item, count=count}
+	player.insert{name=item, count=count, quality=quality or "normal"}
 end
 
 function rcon_cheat_technology(tech)
@@ -1752,8 +2005,8 @@ function rcon_async_request_player_path(player_id, goal, radius)
 	rcon.print(handle)
 end
 
-function rcon_async_request_path(start, goal, radius)
-	local handle = game.surfaces[1].request_path({
+function rcon_async_request_path(start, goal, radius, surface)
+	local handle = game.surfaces[surface or 1].request_path({
 		start = start,
 		goal = goal,
 		force = game.forces[1],
@@ -1766,6 +2019,19 @@ function rcon_async_request_path(start, goal, radius)
 	rcon.print(handle)
 end
 
+function rcon_async_sample_throughput(area, ticks, surface)
+	if storage.throughput_samplings == nil then storage.throughput_samplings = {} end
+	storage.last_throughput_sampling = (storage.last_throughput_sampling or 0) + 1
+	local id = storage.last_throughput_sampling
+	local entities = {}
+	for _, entity in pairs(game.surfaces[surface or 1].find_entities_filtered({area = area, type = throughput_sampled_types})) do
+		table.insert(entities, {entity = entity, items = {}, seen = {}})
+	end
+	-- the sampling ends on the first sample after the window is over
+	storage.throughput_samplings[id] = {until_tick = game.tick + ticks, ticks = ticks, entities = entities}
+	rcon.print(id)
+end
+
 function rcon_test(foo)
 end
 
@@ -1854,6 +2120,7 @@ remote.add_interface("botbridge", {
 	revive_ghost=rcon_revive_ghost,
 	async_request_player_path=rcon_async_request_player_path,
 	async_request_path=rcon_async_request_path,
+	async_sample_throughput=rcon_async_sample_throughput,
 	action_start_walk_waypoints=rcon_action_start_walk_waypoints,
 	action_start_mining=rcon_action_start_mining,
 	action_start_crafting=rcon_action_start_crafting
@@ -51,6 +51,10 @@ function serialize_ingredient(ingredient)
     return table_properties(ingredient, {"name", "type", "amount"}, {type = "ingredient_type"})
 end
 
+function serialize_tile_prototype(tile)
+    return table_properties(tile, {"name", "walking_speed_modifier"})
+end
+
 function serialize_item_prototype(item)
     local record = table_properties(
         item,
@@ -215,6 +219,39 @@ function serialize_entity_prototype(entity)
     if ok then record.mining_speed = val end
     ok, val = pcall(function() return entity.crafting_speed end)
     if ok then record.crafting_speed = val end
+    ok, val = pcall(function() return entity.running_speed end)
+    if ok then record.running_speed = val end
+    ok, val = pcall(function() return entity.get_inserter_rotation_speed() end)
+    if ok then record.rotation_speed = val end
+    ok, val = pcall(function() return entity.inserter_stack_size_bonus end)
+    if ok then record.inserter_stack_size_bonus = val end
+    ok, val = pcall(function() return entity.get_supply_area_distance() end)
+    if ok then record.supply_area_distance = val end
+    ok, val = pcall(function() return entity.get_max_wire_distance() end)
+    if ok then record.max_wire_distance = val end
+    if entity.electric_energy_source_prototype ~= nil then
+        record.energy_source = "electric"
+        ok, val = pcall(function() return entity.electric_energy_source_prototype.buffer_capacity end)
+        if ok and entity.type == "accumulator" then record.electric_buffer_size = val end
+    elseif entity.burner_prototype ~= nil then
+        record.energy_source = "burner"
+    elseif entity.heat_energy_source_prototype ~= nil then
+        record.energy_source = "heat"
+    elseif entity.fluid_energy_source_prototype ~= nil then
+        record.energy_source = "fluid"
+    elseif entity.void_energy_source_prototype ~= nil then
+        record.energy_source = "void"
+    end
+    -- energy is reported per tick, we want watts
+    ok, val = pcall(function() return entity.get_max_energy_usage() end)
+    if ok and val ~= nil and val > 0 then record.energy_usage = val * 60 end
+    ok, val = pcall(function() return entity.get_max_energy_production() end)
+    if ok and val ~= nil and val > 0 then record.max_energy_production = val * 60 end
+    -- fluid speeds are reported per tick, we want units per second
+    ok, val = pcall(function() return entity.pumping_speed end)
+    if ok and val ~= nil and val > 0 then record.pumping_speed = val * 60 end
+    ok, val = pcall(function() return entity.fluid_usage_per_tick end)
+    if ok and val ~= nil and val > 0 then record.fluid_usage = val * 60 end
     record.mine_result = mine_result
     if fluidbox_found then
         record.fluidbox_prototypes = fluidbox_prototypes
@@ -230,6 +267,13 @@ end
 function serialize_entity(entity)
     local record = table_properties(entity, {"name", "direction", "type", "position", "drop_position"}, {type = "entity_type", drop_position = "drop_position"})
     record.bounding_box = table_properties(entity.bounding_box, {"left_top", "right_bottom"}, {left_top = "left_top", right_bottom = "right_bottom"})
+    record.surface = entity.surface.name
+    if entity.force ~= nil then
+        record.force = entity.force.name
+    end
+    if entity.quality ~= nil and entity.quality.name ~= "normal" then
+        record.quality = entity.quality.name
+    end
     local output_inventory = entity.get_output_inventory()
     if output_inventory ~= nil then
         record.output_inventory = output_inventory.get_contents()
@@ -243,6 +287,8 @@ function serialize_entity(entity)
         record.amount = entity.amount
     elseif entity.type == "inserter" then
         record.pickupPosition = entity.pickup_position
+    elseif entity.type == "underground-belt" then
+        record.belt_to_ground_type = entity.belt_to_ground_type
     elseif entity.type == "entity-ghost" then
         record.ghost_name = entity.ghost_name
         record.ghost_type = entity.ghost_type
@@ -258,12 +304,21 @@ function serialize_entity(entity)
             record.recipe = recipe.name
         end
     end
+    local module_inventory = entity.type ~= "entity-ghost" and entity.get_module_inventory() or nil
+    if module_inventory ~= nil and not module_inventory.is_empty() then
+        local modules = {}
+        for _, item in pairs(module_inventory.get_contents()) do
+            modules[item.name] = (modules[item.name] or 0) + item.count
+        end
+        record.modules = modules
+    end
     return record
 end
 
 function serialize_tile(tile)
     local record = table_properties(tile, {"name", "position"})
     record.player_collidable = tile.collides_with('player-layer')
+    record.surface = tile.surface.name
     return record
 end
 